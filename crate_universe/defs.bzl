@@ -15,6 +15,7 @@ load(
 )
 load(
     "//crate_universe/private:generate_utils.bzl",
+    _license_policy = "license_policy",
     _render_config = "render_config",
 )
 load(
@@ -38,5 +39,6 @@ local_crate_mirror = _local_crate_mirror
 
 # Utility Macros
 crate = _crate
+license_policy = _license_policy
 render_config = _render_config
 splicing_config = _splicing_config
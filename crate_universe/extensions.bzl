@@ -399,7 +399,10 @@ load(
     "CRATES_REPOSITORY_ENVIRON",
     "GENERATOR_ENV_VARS",
     "determine_repin",
+    "determine_resolve_conflicts",
     "execute_generator",
+    "execute_resolve_conflicts",
+    "get_lockfile_shards_dir",
     generate_render_config = "render_config",
 )
 load("//crate_universe/private:local_crate_mirror.bzl", "local_crate_mirror")
@@ -418,6 +421,27 @@ _OPT_BOOL_VALUES = {
     "on": True,
 }
 
+def _watch_tree(module_ctx, directory):
+    """Watch a directory and everything beneath it, as `module_ctx` has no `watch_tree`.
+
+    Args:
+        module_ctx (module_ctx): The module context object.
+        directory (path): The directory to watch.
+    """
+    pending = [directory]
+
+    # Starlark has no `while` loops, so bound the walk by a generous number of directories.
+    for _ in range(10000):
+        if not pending:
+            break
+        current = pending.pop()
+        module_ctx.watch(current)
+        for child in current.readdir():
+            if child.is_dir:
+                pending.append(child)
+            else:
+                module_ctx.watch(child)
+
 def _get_or_insert(d, key, value):
     if key not in d:
         d[key] = value
@@ -541,6 +565,7 @@ def _generate_hub_and_spokes(
         skip_cargo_lockfile_overwrite,
         strip_internal_dependencies_from_cargo_lockfile,
         is_root,
+        shard_lockfile = False,
         cargo_lockfile = None,
         manifests = {},
         packages = {}):
@@ -568,6 +593,7 @@ def _generate_hub_and_spokes(
             across rust / cargo / rules_rust versions between the producing module and the consumer,
             and the producer's lockfile typically lives in a read-only bzlmod cache that can't be
             repinned anyway.
+        shard_lockfile (bool): Whether to write each crate in the lockfile to its own file.
         cargo_lockfile (path): Path to Cargo.lock, if we have one.
         manifests (dict): The set of Cargo.toml manifests that apply to this closure, if any, keyed by path.
         packages (dict): The set of extra cargo crate tags that apply to this closure, if any, keyed by package name.
@@ -591,6 +617,15 @@ def _generate_hub_and_spokes(
             workspace_name = cfg.name,
             generate_binaries = cfg.generate_binaries,
             render_config = render_config,
+            license_policy = cfg.license_policy,
+            multiple_versions = cfg.multiple_versions,
+            multiple_versions_skip = cfg.multiple_versions_skip,
+            generate_tests = cfg.generate_tests,
+            cfg_settings = cfg.cfg_settings,
+            feature_resolver = cfg.feature_resolver,
+            prerun_build_scripts = cfg.prerun_build_scripts,
+            prerun_build_scripts_denylist = cfg.prerun_build_scripts_denylist,
+            template_overrides = str(module_ctx.path(cfg.template_overrides).dirname) if cfg.template_overrides else None,
             repository_ctx = module_ctx,
         ),
     )
@@ -603,11 +638,31 @@ def _generate_hub_and_spokes(
             packages = packages,
             splicing_config = splicing_config,
             cargo_config = cfg.cargo_config,
+            custom_targets = cfg.custom_targets,
             manifests = manifests,
             manifest_to_path = module_ctx.path,
         ),
     )
 
+    # The workspace root when one is explicitly provided.
+    # buildifier: disable=canonical-repository
+    nonhermetic_root_bazel_workspace_dir = module_ctx.path(Label("@@//:MODULE.bazel")).dirname
+
+    # Resolve merge conflicts in the lockfiles before checking whether they're up to date
+    if is_root and lockfile and cargo_lockfile and determine_resolve_conflicts(module_ctx, cfg.name):
+        module_ctx.report_progress("Resolving lockfile merge conflicts for `{}`".format(cfg.name))
+        execute_resolve_conflicts(
+            cargo_bazel_fn = cargo_bazel_fn,
+            lockfile_path = lockfile,
+            cargo_lockfile_path = cargo_lockfile,
+            config = config_file,
+            splicing_manifest = splicing_manifest,
+            nonhermetic_root_bazel_workspace_dir = nonhermetic_root_bazel_workspace_dir,
+            skip_cargo_lockfile_overwrite = skip_cargo_lockfile_overwrite,
+            strip_internal_dependencies_from_cargo_lockfile = strip_internal_dependencies_from_cargo_lockfile,
+            shard_lockfile = shard_lockfile,
+        )
+
     # TODO: Repins should never be allowed if the lockfile is not within
     # https://github.com/bazelbuild/rules_rust/issues/1738
 
@@ -631,12 +686,9 @@ def _generate_hub_and_spokes(
             lockfile_path = lockfile,
             config = config_file,
             splicing_manifest = splicing_manifest,
+            cargo_lockfile_path = cargo_lockfile,
         )
 
-    # The workspace root when one is explicitly provided.
-    # buildifier: disable=canonical-repository
-    nonhermetic_root_bazel_workspace_dir = module_ctx.path(Label("@@//:MODULE.bazel")).dirname
-
     # If re-pinning is enabled, gather additional inputs for the generator
     kwargs = dict()
     if repin:
@@ -693,6 +745,7 @@ def _generate_hub_and_spokes(
         hub_packages_output_file = hub_packages_output_file,
         skip_cargo_lockfile_overwrite = skip_cargo_lockfile_overwrite,
         strip_internal_dependencies_from_cargo_lockfile = strip_internal_dependencies_from_cargo_lockfile,
+        shard_lockfile = shard_lockfile,
         **kwargs
     )
 
@@ -731,6 +784,14 @@ def _generate_hub_and_spokes(
 
     contents = json.decode(module_ctx.read(lockfile))
 
+    # The crates of a sharded lockfile are paths, relative to the lockfile, to the file holding each crate.
+    crates = {}
+    for crate_id, crate in contents["crates"].items():
+        if type(crate) == "string":
+            crate = json.decode(module_ctx.read(lockfile.dirname.get_child(crate)))
+        crates[crate_id] = crate
+    contents["crates"] = crates
+
     for crate in contents["crates"].values():
         repo = crate["repository"]
         if repo == None:
@@ -1040,6 +1101,9 @@ def _crate_impl(module_ctx):
             if annotation_dict.pop("gen_all_binaries"):
                 annotation_dict["gen_binaries"] = True
             annotation_dict["gen_build_script"] = _OPT_BOOL_VALUES[annotation_dict["gen_build_script"]]
+            for opt_in_key in ("gen_native_libraries", "gen_tests", "toggleable_features"):
+                if not annotation_dict[opt_in_key]:
+                    annotation_dict.pop(opt_in_key)
 
             # Convert the tri-state string values ("auto"/"on"/"off") into the
             # `int` representation understood by `crate.annotation` (`None`, `1`,
@@ -1153,9 +1217,13 @@ def _crate_impl(module_ctx):
                 module_ctx.watch(cfg.cargo_lockfile)
             if cfg.cargo_config:
                 module_ctx.watch(cfg.cargo_config)
+            for custom_target in cfg.custom_targets:
+                module_ctx.watch(custom_target)
             if hasattr(cfg, "manifests"):
                 for m in cfg.manifests:
                     module_ctx.watch(m)
+            if cfg.template_overrides:
+                _watch_tree(module_ctx, module_ctx.path(cfg.template_overrides).dirname)
             cargo_path, rustc_path = _get_host_cargo_rustc(module_ctx, host_triple, cfg.host_tools)
             cargo_bazel_fn = new_cargo_bazel_fn(
                 repository_ctx = module_ctx,
@@ -1211,6 +1279,7 @@ def _crate_impl(module_ctx):
                 skip_cargo_lockfile_overwrite = cfg.skip_cargo_lockfile_overwrite,
                 strip_internal_dependencies_from_cargo_lockfile = cfg.strip_internal_dependencies_from_cargo_lockfile,
                 is_root = mod.is_root,
+                shard_lockfile = cfg.shard_lockfile,
             )
 
             # Watch cfg.lockfile AFTER generation. The generator may modify it during
@@ -1218,6 +1287,9 @@ def _crate_impl(module_ctx):
             # read, which would crash if the lockfile changed between watch and read.
             if cfg.lockfile:
                 module_ctx.watch(cfg.lockfile)
+                shards_dir = get_lockfile_shards_dir(module_ctx.path(cfg.lockfile).realpath)
+                if cfg.shard_lockfile and shards_dir.exists:
+                    _watch_tree(module_ctx, shards_dir)
 
     metadata_kwargs = {}
     if bazel_features.external_deps.extension_metadata_has_reproducible:
@@ -1228,8 +1300,12 @@ def _crate_impl(module_ctx):
 _FROM_COMMON_ATTRS = {
     "cargo_config": CRATES_VENDOR_ATTRS["cargo_config"],
     "cargo_lockfile": CRATES_VENDOR_ATTRS["cargo_lockfile"],
+    "cfg_settings": CRATES_VENDOR_ATTRS["cfg_settings"],
+    "custom_targets": CRATES_VENDOR_ATTRS["custom_targets"],
+    "feature_resolver": CRATES_VENDOR_ATTRS["feature_resolver"],
     "generate_binaries": CRATES_VENDOR_ATTRS["generate_binaries"],
     "generate_build_scripts": CRATES_VENDOR_ATTRS["generate_build_scripts"],
+    "generate_tests": CRATES_VENDOR_ATTRS["generate_tests"],
     "host_tools": attr.label(
         doc = "The `rust_host_tools` repository to use.",
         default = "@rust_host_tools",
@@ -1244,12 +1320,18 @@ _FROM_COMMON_ATTRS = {
         ),
         default = True,
     ),
+    "license_policy": CRATES_VENDOR_ATTRS["license_policy"],
     "lockfile": attr.label(
         doc = (
             "The path to a file to use for reproducible renderings. " +
             "If set, this file must exist within the workspace (but can be empty) before this rule will work."
         ),
     ),
+    "multiple_versions": CRATES_VENDOR_ATTRS["multiple_versions"],
+    "multiple_versions_skip": CRATES_VENDOR_ATTRS["multiple_versions_skip"],
+    "prerun_build_scripts": CRATES_VENDOR_ATTRS["prerun_build_scripts"],
+    "prerun_build_scripts_denylist": CRATES_VENDOR_ATTRS["prerun_build_scripts_denylist"],
+    "shard_lockfile": CRATES_VENDOR_ATTRS["shard_lockfile"],
     "skip_cargo_lockfile_overwrite": attr.bool(
         doc = (
             "Whether to skip writing the cargo lockfile back after resolving. " +
@@ -1272,6 +1354,7 @@ _FROM_COMMON_ATTRS = {
         doc = "A set of all platform triples to consider when generating dependencies.",
         default = SUPPORTED_PLATFORM_TRIPLES,
     ),
+    "template_overrides": CRATES_VENDOR_ATTRS["template_overrides"],
 }
 
 _from_cargo = tag_class(
@@ -1363,6 +1446,12 @@ _ANNOTATION_NORMAL_ATTRS = {
         values = _OPT_BOOL_VALUES.keys(),
         default = "auto",
     ),
+    "gen_native_libraries": attr.bool(
+        doc = "If true, generates `rust_shared_library` and `rust_static_library` targets for the crate's `cdylib` and `staticlib` crate types.",
+    ),
+    "gen_tests": attr.bool(
+        doc = "If true, generates `rust_test` targets for the crate's unit and integration tests. Tests are skipped for crates with dev dependencies which Cargo did not resolve, i.e. those of non-workspace members, and a warning is printed instead.",
+    ),
     "label_injections": attr.label_keyed_string_dict(
         doc = (
             "A mapping of canonical repository labels to the apparent repository prefix used in the annotation's strings. This is necessary for cases where a `build_script_data` " +
@@ -1396,6 +1485,9 @@ _ANNOTATION_NORMAL_ATTRS = {
     "shallow_since": attr.string(
         doc = "An optional timestamp used for crates originating from a git repository instead of a crate registry. This flag optimizes fetching the source code.",
     ),
+    "toggleable_features": attr.string_list(
+        doc = "A list of the crate's resolved features which can be switched off at build time, eg. `--@crates//serde:derive=false`.",
+    ),
 }
 
 # A list of labels which may be relative (and if so, is within the repo the rule is generated in).
@@ -1573,6 +1665,14 @@ can be found below where the supported keys for each template can be found in th
             doc = "The base template to use for BUILD file names. The available format keys are [`{name}`, {version}`].",
             default = "//:BUILD.{name}-{version}.bazel",
         ),
+        "compress_selects": attr.bool(
+            doc = (
+                "Whether to compress the `select` statements of generated BUILD files. Values selected by every " +
+                "supported platform are moved out of the `select`, and platforms with identical branches share one " +
+                "branch matched by a `selects.config_setting_group` in the hub repository."
+            ),
+            default = False,
+        ),
         "crate_alias_template": attr.string(
             doc = (
                 "The base template to use for crate aliases. The available format keys are " +
@@ -1662,11 +1762,13 @@ Environment Variables:
 
 | variable | usage |
 | --- | --- |
+| `CARGO_BAZEL_CARGO_TREE_CACHE_DIR` | A directory in which to cache the results of `cargo tree` across repins. Entries are keyed by the contents of everything `cargo tree` reads, so unchanged platforms are resolved instantly and stale results are never used. This value does not affect the lockfile digest. |
 | `CARGO_BAZEL_GENERATOR_SHA256` | The sha256 checksum of the file located at `CARGO_BAZEL_GENERATOR_URL` |
 | `CARGO_BAZEL_GENERATOR_URL` | The URL of a cargo-bazel binary. This variable takes precedence over attributes and can use `file://` for local paths |
 | `CARGO_BAZEL_ISOLATED` | An authoritative flag as to whether or not the `CARGO_HOME` environment variable should be isolated from the host configuration |
 | `CARGO_BAZEL_REPIN` | An indicator that the dependencies represented by the rule should be regenerated. `REPIN` may also be used. See [Repinning / Updating Dependencies](crate_universe_workspace.html#repinning--updating-dependencies) for more details. |
 | `CARGO_BAZEL_REPIN_ONLY` | A comma-delimited allowlist for rules to execute repinning. Can be useful if multiple instances of the repository rule are used in a Bazel workspace, but repinning should be limited to one of them. |
+| `CARGO_BAZEL_RESOLVE_CONFLICTS` | An indicator that git merge conflicts in `Cargo.lock` and `lockfile` should be resolved by merging the crates locked on both sides and regenerating the lockfile. `CARGO_BAZEL_REPIN_ONLY` applies to it as well. |
 | `CARGO_BAZEL_TIMEOUT` | An integer value to override the default timeout setting when running the cargo-bazel binary. This value must be in seconds. |
 
 """,
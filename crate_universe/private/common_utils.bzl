@@ -14,6 +14,8 @@ REPIN = "REPIN"
 
 CARGO_BAZEL_REPIN_ONLY = "CARGO_BAZEL_REPIN_ONLY"
 
+CARGO_BAZEL_RESOLVE_CONFLICTS = "CARGO_BAZEL_RESOLVE_CONFLICTS"
+
 REPIN_ENV_VARS = [
     CARGO_BAZEL_REPIN,
     REPIN,
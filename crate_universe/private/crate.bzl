@@ -117,6 +117,8 @@ def _annotation(
         gen_binaries = None,
         disable_pipelining = False,
         gen_build_script = None,
+        gen_native_libraries = None,
+        gen_tests = None,
         patch_args = None,
         patch_tool = None,
         patches = None,
@@ -125,6 +127,7 @@ def _annotation(
         rustc_env_files = None,
         rustc_flags = None,
         shallow_since = None,
+        toggleable_features = None,
         override_targets = None,
         label_injections = None):
     """A collection of extra attributes and settings for a particular crate
@@ -176,6 +179,12 @@ def _annotation(
         disable_pipelining (bool, optional): If True, disables pipelining for library targets for this crate.
         gen_build_script (bool, optional): An authoritative flag to determine whether or not to produce
             `cargo_build_script` targets for the current crate.
+        gen_native_libraries (bool, optional): Whether to produce `rust_shared_library` and `rust_static_library`
+            targets for the crate's `cdylib` and `staticlib` crate types.
+        gen_tests (bool, optional): Whether to produce `rust_test` targets for the crate's unit and integration
+            tests. Tests use the crate's dev dependencies as resolved by Cargo, which only resolves them for
+            workspace members. Tests of other crates with dev dependencies are never generated and a warning
+            is printed instead.
         patch_args (list, optional): The `patch_args` attribute of a Bazel repository rule. See
             [http_archive.patch_args](https://docs.bazel.build/versions/main/repo/http.html#http_archive-patch_args)
         patch_tool (string, optional): The `patch_tool` attribute of a Bazel repository rule. See
@@ -190,8 +199,11 @@ def _annotation(
         rustc_flags (list, optional): A list of strings to set on a crate's `rust_library::rustc_flags` attribute.
         shallow_since (str, optional): An optional timestamp used for crates originating from a git repository
             instead of a crate registry. This flag optimizes fetching the source code.
+        toggleable_features (list, optional): A list of the crate's resolved features which can be switched off at
+            build time, eg. `--@crates//serde:derive=false`. A `bool_flag` is generated in the hub repository for each
+            feature and gates the feature along with the features and optional dependencies only it enables.
         override_targets (dict, optional): A dictionary of alternate targets to use when something depends on this crate to allow
-            the parent repo to provide its own version of this dependency. Keys can be `proc-macro`, `custom-build`, `lib`, `bin`.
+            the parent repo to provide its own version of this dependency. Keys can be `proc-macro`, `custom-build`, `lib`, `bin`, `cdylib`, `staticlib`, `test`.
         label_injections (dict, optional): A mapping of canonical repository prefix to apparent label, applied by the
             `cargo-bazel` generator to every string in this annotation. Populated by `sanitize_label_injections` in
             `crate_universe/private/common_utils.bzl`.
@@ -238,6 +250,8 @@ def _annotation(
             gen_binaries = gen_binaries,
             disable_pipelining = disable_pipelining,
             gen_build_script = gen_build_script,
+            gen_native_libraries = gen_native_libraries,
+            gen_tests = gen_tests,
             patch_args = patch_args,
             patch_tool = patch_tool,
             patches = _stringify_list(patches),
@@ -246,6 +260,7 @@ def _annotation(
             rustc_env_files = _stringify_list(rustc_env_files),
             rustc_flags = rustc_flags,
             shallow_since = shallow_since,
+            toggleable_features = toggleable_features,
             override_targets = override_targets,
             label_injections = label_injections,
         ),
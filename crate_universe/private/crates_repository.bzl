@@ -9,10 +9,14 @@ load(
     "//crate_universe/private:generate_utils.bzl",
     "CRATES_REPOSITORY_ENVIRON",
     "determine_repin",
+    "determine_resolve_conflicts",
     "execute_generator",
+    "execute_resolve_conflicts",
     "generate_config",
     "get_generator",
+    "get_lockfile_shards_dir",
     "get_lockfiles",
+    "get_template_overrides_dir",
 )
 load(
     "//crate_universe/private:splicing_utils.bzl",
@@ -53,8 +57,13 @@ def _crates_repository_impl(repository_ctx):
     repository_ctx.watch(lockfiles.cargo)
     if lockfiles.bazel:
         repository_ctx.watch(lockfiles.bazel)
+        shards_dir = get_lockfile_shards_dir(lockfiles.bazel.realpath)
+        if repository_ctx.attr.shard_lockfile and shards_dir.exists:
+            repository_ctx.watch_tree(shards_dir)
     for m in repository_ctx.attr.manifests:
         repository_ctx.watch(repository_ctx.path(m))
+    if repository_ctx.attr.template_overrides:
+        repository_ctx.watch_tree(get_template_overrides_dir(repository_ctx))
 
     # Locate Rust tools (cargo, rustc)
     tools = get_rust_tools(repository_ctx, host_triple)
@@ -72,6 +81,23 @@ def _crates_repository_impl(repository_ctx):
     # Create a manifest of all dependency inputs
     splicing_manifest = create_splicing_manifest(repository_ctx)
 
+    nonhermetic_root_bazel_workspace_dir = repository_ctx.workspace_root
+
+    # Resolve merge conflicts in the lockfiles before checking whether they're up to date
+    if lockfiles.bazel and determine_resolve_conflicts(repository_ctx, repository_ctx.name):
+        repository_ctx.report_progress("Resolving lockfile merge conflicts.")
+        execute_resolve_conflicts(
+            cargo_bazel_fn = cargo_bazel_fn,
+            lockfile_path = lockfiles.bazel,
+            cargo_lockfile_path = lockfiles.cargo,
+            config = config_path,
+            splicing_manifest = splicing_manifest,
+            nonhermetic_root_bazel_workspace_dir = nonhermetic_root_bazel_workspace_dir,
+            skip_cargo_lockfile_overwrite = repository_ctx.attr.skip_cargo_lockfile_overwrite,
+            strip_internal_dependencies_from_cargo_lockfile = repository_ctx.attr.strip_internal_dependencies_from_cargo_lockfile,
+            shard_lockfile = repository_ctx.attr.shard_lockfile,
+        )
+
     # Determine whether or not to repin depednencies
     repin = determine_repin(
         repository_ctx = repository_ctx,
@@ -80,11 +106,10 @@ def _crates_repository_impl(repository_ctx):
         lockfile_path = lockfiles.bazel,
         config = config_path,
         splicing_manifest = splicing_manifest,
+        cargo_lockfile_path = lockfiles.cargo,
         repin_instructions = repository_ctx.attr.repin_instructions,
     )
 
-    nonhermetic_root_bazel_workspace_dir = repository_ctx.workspace_root
-
     # If re-pinning is enabled, gather additional inputs for the generator
     kwargs = dict()
     if repin:
@@ -132,6 +157,7 @@ def _crates_repository_impl(repository_ctx):
         hub_packages_output_file = hub_packages_output_file,
         skip_cargo_lockfile_overwrite = repository_ctx.attr.skip_cargo_lockfile_overwrite,
         strip_internal_dependencies_from_cargo_lockfile = repository_ctx.attr.strip_internal_dependencies_from_cargo_lockfile,
+        shard_lockfile = repository_ctx.attr.shard_lockfile,
         # sysroot = tools.sysroot,
         **kwargs
     )
@@ -174,11 +200,13 @@ Environment Variables:
 
 | variable | usage |
 | --- | --- |
+| `CARGO_BAZEL_CARGO_TREE_CACHE_DIR` | A directory in which to cache the results of `cargo tree` across repins. Entries are keyed by the contents of everything `cargo tree` reads, so unchanged platforms are resolved instantly and stale results are never used. This value does not affect the lockfile digest. |
 | `CARGO_BAZEL_GENERATOR_SHA256` | The sha256 checksum of the file located at `CARGO_BAZEL_GENERATOR_URL` |
 | `CARGO_BAZEL_GENERATOR_URL` | The URL of a cargo-bazel binary. This variable takes precedence over attributes and can use `file://` for local paths |
 | `CARGO_BAZEL_ISOLATED` | An authoritative flag as to whether or not the `CARGO_HOME` environment variable should be isolated from the host configuration |
 | `CARGO_BAZEL_REPIN` | An indicator that the dependencies represented by the rule should be regenerated. `REPIN` may also be used. See [Repinning / Updating Dependencies](#repinning--updating-dependencies) for more details. |
 | `CARGO_BAZEL_REPIN_ONLY` | A comma-delimited allowlist for rules to execute repinning. Can be useful if multiple instances of the repository rule are used in a Bazel workspace, but repinning should be limited to one of them. |
+| `CARGO_BAZEL_RESOLVE_CONFLICTS` | An indicator that git merge conflicts in `Cargo.lock` and `lockfile` should be resolved by merging the crates locked on both sides and regenerating the lockfile. `CARGO_BAZEL_REPIN_ONLY` applies to it as well. |
 | `CARGO_BAZEL_TIMEOUT` | An integer value to override the default timeout setting when running the cargo-bazel binary. This value must be in seconds. |
 
 Example:
@@ -276,10 +304,36 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             ),
             mandatory = True,
         ),
+        "cfg_settings": attr.label_keyed_string_dict(
+            doc = (
+                "A mapping of `config_setting` labels to the `cfg` atoms they enable (e.g. `target_feature = \"avx2\"`). " +
+                "Dependencies gated on these atoms are selected through `selects.config_setting_group` targets in the " +
+                "generated hub repository, which requires a dependency on `bazel_skylib`. Negated atoms are assumed to be " +
+                "satisfied when the setting is not enabled."
+            ),
+        ),
         "compressed_windows_toolchain_names": attr.bool(
             doc = "Whether or not the toolchain names of windows toolchains are expected to be in a `compressed` format.",
             default = True,
         ),
+        "custom_targets": attr.label_keyed_string_dict(
+            doc = (
+                "A mapping of files describing platforms unknown to `rustc` to the platform triples they describe. " +
+                "Files ending in `.json` are [custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html), " +
+                "all others contain the output of `rustc --print cfg` for the platform. Each triple must also be listed in " +
+                "`supported_platform_triples` and have a matching platform in `render_config.platforms_template`."
+            ),
+            allow_files = True,
+        ),
+        "feature_resolver": attr.string(
+            doc = (
+                "How the features and optional dependencies enabled on each platform are determined. `cargo_tree` runs " +
+                "`cargo tree` for every pair of host and target platforms. `metadata` resolves features in-process from " +
+                "`cargo metadata` output instead. `differential` uses both and fails if their results differ."
+            ),
+            values = ["cargo_tree", "metadata", "differential"],
+            default = "cargo_tree",
+        ),
         "generate_binaries": attr.bool(
             doc = (
                 "Whether to generate `rust_binary` targets for all the binary crates in every package. " +
@@ -298,6 +352,15 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             doc = "DEPRECATED: Moved to `render_config`.",
             default = True,
         ),
+        "generate_tests": attr.bool(
+            doc = (
+                "Whether to generate `rust_test` targets for the unit and integration tests of every package. " +
+                "Tests use the dev dependencies resolved by Cargo, which are only available for workspace members, " +
+                "so tests are skipped for other packages which declare dev dependencies. A warning is printed " +
+                "when a crate annotation's `gen_tests` is set for such a package."
+            ),
+            default = False,
+        ),
         "generator": attr.string(
             doc = (
                 "The absolute label of a generator. Eg. `@cargo_bazel_bootstrap//:cargo-bazel`. " +
@@ -325,6 +388,12 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             ),
             default = True,
         ),
+        "license_policy": attr.string(
+            doc = (
+                "A policy the licenses of third party crates must satisfy. Use `//crate_universe:defs.bzl\\%license_policy` to " +
+                "generate the value for this field. If unset, licenses are not checked."
+            ),
+        ),
         "lockfile": attr.label(
             doc = (
                 "The path to a file to use for reproducible renderings. " +
@@ -336,9 +405,37 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
         "manifests": attr.label_list(
             doc = "A list of Cargo manifests (`Cargo.toml` files).",
         ),
+        "multiple_versions": attr.string(
+            doc = (
+                "How to treat crates which are resolved to more than one version. `warn` reports each duplicated crate, " +
+                "its versions and the workspace members which depend on each version. `deny` fails generation instead."
+            ),
+            values = ["allow", "warn", "deny"],
+            default = "allow",
+        ),
+        "multiple_versions_skip": attr.string_list(
+            doc = (
+                "Crates to ignore when looking for duplicate versions, as `{name} {version requirement}` " +
+                "(e.g. `windows-sys *` or `syn 1`). See `multiple_versions`."
+            ),
+        ),
         "packages": attr.string_dict(
             doc = "A set of crates (packages) specifications to depend on. See [crate.spec](#crate.spec).",
         ),
+        "prerun_build_scripts": attr.bool(
+            doc = (
+                "Whether to run build scripts which only emit `cfg`s and environment variables ahead of time for each " +
+                "supported platform, pinning their outputs in the lockfile as `rustc_flags` and `rustc_env` in place of a " +
+                "`cargo_build_script`. Requires the standard library to be installed for every supported platform."
+            ),
+            default = False,
+        ),
+        "prerun_build_scripts_denylist": attr.string_list(
+            doc = (
+                "Crates whose build scripts are never run ahead of time, as `{name} {version requirement}`. " +
+                "See `prerun_build_scripts`."
+            ),
+        ),
         "quiet": attr.bool(
             doc = "If stdout and stderr should not be printed to the terminal.",
             default = True,
@@ -374,6 +471,14 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             doc = "The version of Rust the currently registered toolchain is using. Eg. `1.56.0`, or `nightly/2021-09-08`",
             default = rust_common.default_version,
         ),
+        "shard_lockfile": attr.bool(
+            doc = (
+                "Whether to write each crate in the `lockfile` to its own file in a directory next to it, named after the " +
+                "`lockfile` (e.g. `cargo-bazel-lock.crates`). The `lockfile` itself then only holds the checksum, workspace " +
+                "members and the paths of each crate's file, so concurrent changes to different dependencies do not conflict."
+            ),
+            default = False,
+        ),
         "skip_cargo_lockfile_overwrite": attr.bool(
             doc = (
                 "Whether to skip writing the cargo lockfile back after resolving. " +
@@ -402,6 +507,14 @@ CARGO_BAZEL_REPIN=1 CARGO_BAZEL_REPIN_ONLY=crate_index bazel sync --only=crate_i
             doc = "A set of all platform triples to consider when generating dependencies.",
             default = SUPPORTED_PLATFORM_TRIPLES,
         ),
+        "template_overrides": attr.label(
+            doc = (
+                "A file, such as a `BUILD.bazel` file, in a directory of [Tera](https://keats.github.io/tera/docs/) " +
+                "templates replacing the built-in templates used to render outputs. See " +
+                "`crates_vendor.template_overrides` for the available templates. The directory is watched for changes."
+            ),
+            allow_single_file = True,
+        ),
     },
     environ = CRATES_REPOSITORY_ENVIRON,
 )
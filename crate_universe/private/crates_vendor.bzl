@@ -1,7 +1,7 @@
 """Rules for vendoring Bazel targets into existing workspaces"""
 
 load("//crate_universe/private:generate_utils.bzl", "compile_config", generate_render_config = "render_config")
-load("//crate_universe/private:splicing_utils.bzl", "kebab_case_keys", generate_splicing_config = "splicing_config")
+load("//crate_universe/private:splicing_utils.bzl", "compile_custom_targets", "kebab_case_keys", generate_splicing_config = "splicing_config")
 load("//crate_universe/private:urls.bzl", "CARGO_BAZEL_LABEL")
 load("//rust/platform:triple_mappings.bzl", "SUPPORTED_PLATFORM_TRIPLES")
 
@@ -213,6 +213,7 @@ def _write_splicing_manifest(ctx):
             packages = ctx.attr.packages,
             splicing_config = splicing_config,
             cargo_config = ctx.attr.cargo_config,
+            custom_targets = ctx.attr.custom_targets,
             manifests = manifests,
             manifest_to_path = _prepare_manifest_path,
         ),
@@ -222,10 +223,10 @@ def _write_splicing_manifest(ctx):
 
     env = [_sys_runfile_env(ctx, "SPLICING_MANIFEST", manifest, is_windows)]
     args = ["--splicing-manifest", _expand_env("SPLICING_MANIFEST", is_windows)]
-    runfiles = [manifest] + ctx.files.manifests + ([ctx.file.cargo_config] if ctx.attr.cargo_config else [])
+    runfiles = [manifest] + ctx.files.manifests + ctx.files.custom_targets + ([ctx.file.cargo_config] if ctx.attr.cargo_config else [])
     return args, env, runfiles
 
-def generate_splicing_manifest(*, packages, splicing_config, cargo_config, manifests, manifest_to_path):
+def generate_splicing_manifest(*, packages, splicing_config, cargo_config, manifests, manifest_to_path, custom_targets = {}):
     # Deserialize information about direct packages
     direct_packages_info = {
         # Ensure the data is using kebab-case as that's what `cargo_toml::DependencyDetail` expects.
@@ -235,6 +236,10 @@ def generate_splicing_manifest(*, packages, splicing_config, cargo_config, manif
 
     splicing_manifest_content = {
         "cargo_config": str(manifest_to_path(cargo_config)) if cargo_config else None,
+        "custom_targets": compile_custom_targets({
+            triple: str(manifest_to_path(target))
+            for (target, triple) in custom_targets.items()
+        }),
         "direct_packages": direct_packages_info,
         "manifests": manifests,
     }
@@ -258,19 +263,28 @@ def _write_config_file(ctx):
             annotations = ctx.attr.annotations,
             generate_binaries = ctx.attr.generate_binaries,
             generate_build_scripts = ctx.attr.generate_build_scripts,
+            generate_tests = ctx.attr.generate_tests,
             generate_target_compatible_with = ctx.attr.generate_target_compatible_with,
             supported_platform_triples = ctx.attr.supported_platform_triples,
             repository_name = ctx.attr.repository_name,
             output_pkg = _get_output_package(ctx),
             workspace_name = workspace_name,
             render_config = dict(json.decode(ctx.attr.render_config)) if ctx.attr.render_config else None,
+            license_policy = ctx.attr.license_policy,
+            multiple_versions = ctx.attr.multiple_versions,
+            multiple_versions_skip = ctx.attr.multiple_versions_skip,
+            cfg_settings = {target.label: atom for (target, atom) in ctx.attr.cfg_settings.items()},
+            feature_resolver = ctx.attr.feature_resolver,
+            prerun_build_scripts = ctx.attr.prerun_build_scripts,
+            prerun_build_scripts_denylist = ctx.attr.prerun_build_scripts_denylist,
+            template_overrides = _prepare_manifest_path(ctx.attr.template_overrides).rpartition("/")[0] if ctx.attr.template_overrides else None,
         ),
     )
 
     is_windows = _is_windows(ctx)
     env = [_sys_runfile_env(ctx, "CONFIG", config, is_windows)]
     args = ["--config", _expand_env("CONFIG", is_windows)]
-    runfiles = [config] + ctx.files.manifests
+    runfiles = [config] + ctx.files.manifests + ctx.files.template_overrides
     return args, env, runfiles
 
 def generate_config_file(
@@ -285,6 +299,15 @@ def generate_config_file(
         output_pkg,
         workspace_name,
         render_config,
+        license_policy = None,
+        multiple_versions = "allow",
+        multiple_versions_skip = [],
+        generate_tests = False,
+        cfg_settings = {},
+        feature_resolver = "cargo_tree",
+        prerun_build_scripts = False,
+        prerun_build_scripts_denylist = [],
+        template_overrides = None,
         repository_ctx = None):
     """Writes the rendering config to cargo-bazel-config.json.
 
@@ -301,6 +324,15 @@ def generate_config_file(
         output_pkg: The path to the package containing the build files.
         workspace_name (str): The name of the workspace.
         render_config: The render config to use.
+        license_policy (str, optional): The json encoded license policy to use.
+        multiple_versions (str, optional): How to treat crates resolved to more than one version.
+        multiple_versions_skip (list, optional): Crates to ignore when looking for duplicate versions.
+        generate_tests (bool, optional): Whether to generate `rust_test` targets for the crates.
+        cfg_settings (dict, optional): A mapping of `config_setting` labels to the `cfg` atoms they enable.
+        feature_resolver (str, optional): How features are resolved for each platform.
+        prerun_build_scripts (bool, optional): Whether to run pure build scripts ahead of time.
+        prerun_build_scripts_denylist (list, optional): Crates whose build scripts are never run ahead of time.
+        template_overrides (str, optional): The path to a directory of templates replacing the built-in templates.
         repository_ctx (repository_ctx, optional): A repository context object
             used for enabling certain functionality.
 
@@ -378,6 +410,15 @@ def generate_config_file(
         render_config = render_config,
         supported_platform_triples = supported_platform_triples,
         repository_name = repository_name or ctx.label.name,
+        license_policy = license_policy,
+        multiple_versions = multiple_versions,
+        multiple_versions_skip = multiple_versions_skip,
+        generate_tests = generate_tests,
+        cfg_settings = cfg_settings,
+        feature_resolver = feature_resolver,
+        prerun_build_scripts = prerun_build_scripts,
+        prerun_build_scripts_denylist = prerun_build_scripts_denylist,
+        template_overrides = template_overrides,
         repository_ctx = repository_ctx,
     )
 
@@ -446,6 +487,8 @@ def _crates_vendor_impl(ctx):
         environ.append(_sys_runfile_env(ctx, "BAZEL_LOCK", ctx.file.lockfile, is_windows))
         args.extend(["--lockfile", _expand_env("BAZEL_LOCK", is_windows)])
         cargo_bazel_runfiles.extend([ctx.file.lockfile])
+        if ctx.attr.shard_lockfile:
+            args.append("--shard-lockfile")
 
     # Determine platform specific settings
     if is_windows:
@@ -512,6 +555,32 @@ CRATES_VENDOR_ATTRS = {
         doc = "The path to an existing `Cargo.lock` file",
         allow_single_file = True,
     ),
+    "cfg_settings": attr.label_keyed_string_dict(
+        doc = (
+            "A mapping of `config_setting` labels to the `cfg` atoms they enable (e.g. `target_feature = \"avx2\"`). " +
+            "Dependencies gated on these atoms are selected through `selects.config_setting_group` targets in the " +
+            "generated hub repository, which requires a dependency on `bazel_skylib`. Negated atoms are assumed to be " +
+            "satisfied when the setting is not enabled."
+        ),
+    ),
+    "custom_targets": attr.label_keyed_string_dict(
+        doc = (
+            "A mapping of files describing platforms unknown to `rustc` to the platform triples they describe. " +
+            "Files ending in `.json` are [custom target specifications](https://doc.rust-lang.org/rustc/targets/custom.html), " +
+            "all others contain the output of `rustc --print cfg` for the platform. Each triple must also be listed in " +
+            "`supported_platform_triples` and have a matching platform in `render_config.platforms_template`."
+        ),
+        allow_files = True,
+    ),
+    "feature_resolver": attr.string(
+        doc = (
+            "How the features and optional dependencies enabled on each platform are determined. `cargo_tree` runs " +
+            "`cargo tree` for every pair of host and target platforms. `metadata` resolves features in-process from " +
+            "`cargo metadata` output instead. `differential` uses both and fails if their results differ."
+        ),
+        values = ["cargo_tree", "metadata", "differential"],
+        default = "cargo_tree",
+    ),
     "generate_binaries": attr.bool(
         doc = (
             "Whether to generate `rust_binary` targets for all the binary crates in every package. " +
@@ -534,6 +603,21 @@ CRATES_VENDOR_ATTRS = {
         doc = "DEPRECATED: Moved to `render_config`.",
         default = True,
     ),
+    "generate_tests": attr.bool(
+        doc = (
+            "Whether to generate `rust_test` targets for the unit and integration tests of every package. " +
+            "Tests use the dev dependencies resolved by Cargo, which are only available for workspace members, " +
+            "so tests are skipped for other packages which declare dev dependencies. A warning is printed " +
+            "when a crate annotation's `gen_tests` is set for such a package."
+        ),
+        default = False,
+    ),
+    "license_policy": attr.string(
+        doc = (
+            "A policy the licenses of third party crates must satisfy. Use `//crate_universe:defs.bzl\\%license_policy` to " +
+            "generate the value for this field. If unset, licenses are not checked."
+        ),
+    ),
     "lockfile": attr.label(
         doc = (
             "The path to a file to write rendering information. It contains the same information as the " +
@@ -550,7 +634,8 @@ CRATES_VENDOR_ATTRS = {
         doc = (
             "Flags determining how crates should be vendored. `local` is where crate source and BUILD files are " +
             "written to the repository. `remote` is where only BUILD files are written and repository rules " +
-            "used to fetch source code."
+            "used to fetch source code. In `local` mode only the directories of crates whose version, source or " +
+            "checksum changed in `cargo_lockfile` are replaced."
         ),
         values = [
             "local",
@@ -558,9 +643,37 @@ CRATES_VENDOR_ATTRS = {
         ],
         default = "remote",
     ),
+    "multiple_versions": attr.string(
+        doc = (
+            "How to treat crates which are resolved to more than one version. `warn` reports each duplicated crate, " +
+            "its versions and the workspace members which depend on each version. `deny` fails generation instead."
+        ),
+        values = ["allow", "warn", "deny"],
+        default = "allow",
+    ),
+    "multiple_versions_skip": attr.string_list(
+        doc = (
+            "Crates to ignore when looking for duplicate versions, as `{name} {version requirement}` " +
+            "(e.g. `windows-sys *` or `syn 1`). See `multiple_versions`."
+        ),
+    ),
     "packages": attr.string_dict(
         doc = "A set of crates (packages) specifications to depend on. See [crate.spec](#crate.spec).",
     ),
+    "prerun_build_scripts": attr.bool(
+        doc = (
+            "Whether to run build scripts which only emit `cfg`s and environment variables ahead of time for each " +
+            "supported platform, pinning their outputs in the lockfile as `rustc_flags` and `rustc_env` in place of a " +
+            "`cargo_build_script`. Requires the standard library to be installed for every supported platform."
+        ),
+        default = False,
+    ),
+    "prerun_build_scripts_denylist": attr.string_list(
+        doc = (
+            "Crates whose build scripts are never run ahead of time, as `{name} {version requirement}`. " +
+            "See `prerun_build_scripts`."
+        ),
+    ),
     "render_config": attr.string(
         doc = (
             "The configuration flags to use for rendering. Use `//crate_universe:defs.bzl\\%render_config` to " +
@@ -570,6 +683,14 @@ CRATES_VENDOR_ATTRS = {
     "repository_name": attr.string(
         doc = "The name of the repository to generate for `remote` vendor modes. If unset, the label name will be used",
     ),
+    "shard_lockfile": attr.bool(
+        doc = (
+            "Whether to write each crate in the `lockfile` to its own file in a directory next to it, named after the " +
+            "`lockfile` (e.g. `cargo-bazel-lock.crates`). The `lockfile` itself then only holds the checksum, workspace " +
+            "members and the paths of each crate's file, so concurrent changes to different dependencies do not conflict."
+        ),
+        default = False,
+    ),
     "splicing_config": attr.string(
         doc = (
             "The configuration flags to use for splicing Cargo manifests. Use `//crate_universe:defs.bzl\\%rsplicing_config` to " +
@@ -580,6 +701,27 @@ CRATES_VENDOR_ATTRS = {
         doc = "A set of all platform triples to consider when generating dependencies.",
         default = SUPPORTED_PLATFORM_TRIPLES,
     ),
+    "template_overrides": attr.label(
+        doc = """\
+A file, such as a `BUILD.bazel` file, in a directory of [Tera](https://keats.github.io/tera/docs/) templates
+replacing the built-in templates of the same name. New partials may be added under `partials/` for the overrides
+to include. Overrides are rendered with an empty lockfile before any files are written, so syntax errors are
+reported up front, but mistakes in the parts of a template which are only rendered for a crate surface when that
+crate is rendered. The built-in templates and the variables available to them are:
+
+| template | variables |
+| --- | --- |
+| `crate_build_file.j2` | `name`, `version`, `content` (the generated `BUILD` file of a crate) |
+| `defs_bzl_shim.j2` | |
+| `module_bzl.j2` | `context` (the lockfile context), `platforms`, `generator` |
+| `partials/header.j2` | |
+| `partials/module/*.j2` | Those of `module_bzl.j2`, see the built-in templates for loop variables |
+
+Every template may use `repository_name`, `regen_command`, `vendor_mode` and `default_package_name`, along with
+the `crate_alias`, `crate_build_file`, `crate_label`, `crate_repository` and `crates_module_label` functions.
+""",
+        allow_single_file = True,
+    ),
     "vendor_path": attr.string(
         doc = "The path to a directory to write files into. Absolute paths will be treated as relative to the workspace root",
         default = "crates",
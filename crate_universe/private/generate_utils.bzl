@@ -4,6 +4,7 @@ load(
     ":common_utils.bzl",
     "CARGO_BAZEL_DEBUG",
     "CARGO_BAZEL_ISOLATED",
+    "CARGO_BAZEL_RESOLVE_CONFLICTS",
     "CARGO_BAZEL_TIMEOUT",
     "REPIN_ALLOWLIST_ENV_VAR",
     "REPIN_ENV_VARS",
@@ -23,6 +24,7 @@ CRATES_REPOSITORY_ENVIRON = GENERATOR_ENV_VARS + REPIN_ENV_VARS + [
     CARGO_BAZEL_ISOLATED,
     CARGO_BAZEL_DEBUG,
     CARGO_BAZEL_TIMEOUT,
+    CARGO_BAZEL_RESOLVE_CONFLICTS,
 ]
 
 def get_generator(repository_ctx, host_triple):
@@ -103,7 +105,8 @@ def render_config(
         regen_command = None,
         vendor_mode = None,
         generate_rules_license_metadata = False,
-        incompatible_no_root_alias_targets = False):
+        incompatible_no_root_alias_targets = False,
+        compress_selects = False):
     """Various settings used to configure rendered outputs
 
     The template parameters each support a select number of format keys. A description of each key
@@ -151,12 +154,17 @@ def render_config(
             subpackages (e.g. `@crate_index//clap`) are always emitted, so flipping this flag on lets users
             keep consuming aliases through the subpackage path while the root version disappears. Planned to
             flip to default-on in a future release.
+        compress_selects (bool, optional): Whether to compress the `select` statements of generated BUILD files.
+            Values selected by every supported platform are moved out of the `select` (and so also apply on
+            unsupported platforms), and platforms with identical branches share one branch matched by a
+            `selects.config_setting_group` in the hub repository, which requires a dependency on `bazel_skylib`.
 
     Returns:
         string: A json encoded struct to match the Rust `config::RenderConfig` struct
     """
     return json.encode(struct(
         build_file_template = build_file_template,
+        compress_selects = compress_selects,
         crate_alias_template = crate_alias_template,
         crate_label_template = crate_label_template,
         crate_repository_template = crate_repository_template,
@@ -172,6 +180,38 @@ def render_config(
         vendor_mode = vendor_mode,
     ))
 
+def license_policy(
+        allow = [],
+        deny = [],
+        exceptions = {},
+        warn_only = False):
+    """A policy the licenses of third party crates must satisfy
+
+    Licenses are SPDX expressions which apply to every license they name, so `MIT OR Apache-2.0`
+    allows (or denies) both. Licenses may be followed by a `WITH` exception
+    (e.g. `Apache-2.0 WITH LLVM-exception`) but not `+` or `-or-later`. Each crate's license
+    expression is evaluated as a whole, so a crate licensed under `MIT OR GPL-3.0` is accepted
+    if `MIT` is allowed. Workspace members are not checked.
+
+    Args:
+        allow (list, optional): Licenses crates may be used under. If empty, any license
+            which is not denied is accepted.
+        deny (list, optional): Licenses crates may never be used under.
+        exceptions (dict, optional): A mapping of `{name} {version requirement}` (e.g. `ring *`)
+            to additional licenses accepted for the matching crates, regardless of `allow` and
+            `deny`. Crates without a license are only accepted if they have an exception.
+        warn_only (bool, optional): Report violations as warnings instead of failing.
+
+    Returns:
+        string: A json encoded struct to match the Rust `config::license_policy::LicensePolicy` struct
+    """
+    return json.encode(struct(
+        allow = allow,
+        deny = deny,
+        exceptions = exceptions,
+        warn_only = warn_only,
+    ))
+
 def _crate_id(name, version):
     """Creates a `cargo_bazel::config::CrateId`.
 
@@ -256,6 +296,15 @@ def compile_config(
         render_config,
         supported_platform_triples,
         repository_name,
+        license_policy = None,
+        multiple_versions = "allow",
+        multiple_versions_skip = [],
+        generate_tests = False,
+        cfg_settings = {},
+        feature_resolver = "cargo_tree",
+        prerun_build_scripts = False,
+        prerun_build_scripts_denylist = [],
+        template_overrides = None,
         repository_ctx = None):
     """Create a config file for generating crate targets
 
@@ -271,6 +320,22 @@ def compile_config(
         render_config (dict): The deserialized dict of the `render_config` function.
         supported_platform_triples (list): A list of platform triples
         repository_name (str): The name of the repository being generated
+        license_policy (str, optional): The json encoded output of the `license_policy` function.
+        multiple_versions (str, optional): How to treat crates resolved to more than one version.
+            One of `allow`, `warn` or `deny`.
+        multiple_versions_skip (list, optional): Crates (`{name} {version requirement}`) to ignore
+            when looking for duplicate versions.
+        generate_tests (bool, optional): Whether to generate `rust_test` targets for all tests.
+        cfg_settings (dict, optional): A mapping of `config_setting` labels to the `cfg` atoms
+            (e.g. `target_feature = "avx2"`) they enable.
+        feature_resolver (str, optional): How features are resolved for each platform. One of
+            `cargo_tree`, `metadata` or `differential`.
+        prerun_build_scripts (bool, optional): Whether to run build scripts which only emit `cfg`s and
+            environment variables ahead of time and pin their outputs.
+        prerun_build_scripts_denylist (list, optional): Crates (`{name} {version requirement}`) whose
+            build scripts are never run ahead of time.
+        template_overrides (str, optional): The path to a directory of templates replacing the
+            built-in templates used to render outputs.
         repository_ctx (repository_ctx, optional): A repository context object used for enabling
             certain functionality.
 
@@ -302,9 +367,13 @@ def compile_config(
         print("DEPRECATED: 'generate_target_compatible_with' has been moved to 'render_config'")
         render_config.update({"generate_target_compatible_with": False})
 
+    if template_overrides:
+        render_config.update({"template_overrides": template_overrides})
+
     config = struct(
         generate_binaries = generate_binaries,
         generate_build_scripts = generate_build_scripts,
+        generate_tests = generate_tests,
         annotations = annotations,
         cargo_config = cargo_config,
         rendering = _update_render_config(
@@ -312,6 +381,16 @@ def compile_config(
             repository_name = repository_name,
         ),
         supported_platform_triples = supported_platform_triples,
+        cfg_settings = {atom: str(label) for (label, atom) in cfg_settings.items()},
+        license_policy = json.decode(license_policy) if license_policy else None,
+        multiple_versions = struct(
+            level = multiple_versions,
+            skip = multiple_versions_skip,
+        ) if multiple_versions != "allow" else None,
+        feature_resolver = feature_resolver,
+        prerun_build_scripts = struct(
+            denylist = prerun_build_scripts_denylist,
+        ) if prerun_build_scripts else None,
     )
 
     return config
@@ -335,6 +414,15 @@ def generate_config(repository_ctx):
         render_config = _get_render_config(repository_ctx),
         supported_platform_triples = repository_ctx.attr.supported_platform_triples,
         repository_name = repository_ctx.name,
+        license_policy = repository_ctx.attr.license_policy,
+        multiple_versions = repository_ctx.attr.multiple_versions,
+        multiple_versions_skip = repository_ctx.attr.multiple_versions_skip,
+        generate_tests = repository_ctx.attr.generate_tests,
+        cfg_settings = repository_ctx.attr.cfg_settings,
+        feature_resolver = repository_ctx.attr.feature_resolver,
+        prerun_build_scripts = repository_ctx.attr.prerun_build_scripts,
+        prerun_build_scripts_denylist = repository_ctx.attr.prerun_build_scripts_denylist,
+        template_overrides = str(get_template_overrides_dir(repository_ctx)) if repository_ctx.attr.template_overrides else None,
         repository_ctx = repository_ctx,
     )
 
@@ -346,6 +434,29 @@ def generate_config(repository_ctx):
 
     return config_path
 
+def get_template_overrides_dir(repository_ctx):
+    """Locate the directory of the `template_overrides` attribute.
+
+    Args:
+        repository_ctx (repository_ctx): The rule's context object.
+
+    Returns:
+        path: The directory containing the file `template_overrides` refers to.
+    """
+    return repository_ctx.path(repository_ctx.attr.template_overrides).dirname
+
+def get_lockfile_shards_dir(lockfile):
+    """Locate the directory holding the crates of a sharded lockfile.
+
+    Args:
+        lockfile (path): The path to the lockfile.
+
+    Returns:
+        path: The directory named after the lockfile, e.g. `cargo-bazel-lock.crates` for `cargo-bazel-lock.json`.
+    """
+    stem = lockfile.basename.rpartition(".")[0] or lockfile.basename
+    return lockfile.dirname.get_child(stem + ".crates")
+
 def get_lockfiles(repository_ctx):
     """_summary_
 
@@ -360,6 +471,95 @@ def get_lockfiles(repository_ctx):
         bazel = repository_ctx.path(repository_ctx.attr.lockfile) if repository_ctx.attr.lockfile else None,
     )
 
+def _is_requested(repository_ctx, env_vars, repository_name):
+    """Determine whether any of `env_vars` requests an action of the repository.
+
+    Args:
+        repository_ctx (repository_ctx): The rule's context object.
+        env_vars (list): The environment variables which request the action.
+        repository_name (str): The name of the repository being generated.
+
+    Returns:
+        bool: True if the action was requested and `CARGO_BAZEL_REPIN_ONLY` doesn't exclude the repository.
+    """
+    for var in env_vars:
+        if var in repository_ctx.os.environ and repository_ctx.os.environ[var].lower() not in ["false", "no", "0", "off"]:
+            # If a repin allowlist is present only request the action if name is in list
+            if REPIN_ALLOWLIST_ENV_VAR in repository_ctx.os.environ:
+                indices_to_repin = repository_ctx.os.environ[REPIN_ALLOWLIST_ENV_VAR].split(",")
+                if repository_name in indices_to_repin:
+                    return True
+            else:
+                return True
+
+    return False
+
+def determine_resolve_conflicts(repository_ctx, repository_name):
+    """Determine whether merge conflicts in the lockfiles should be resolved
+
+    Args:
+        repository_ctx (repository_ctx): The rule's context object.
+        repository_name (str): The name of the repository being generated.
+
+    Returns:
+        bool: True if `CARGO_BAZEL_RESOLVE_CONFLICTS` is set for the repository.
+    """
+    return _is_requested(repository_ctx, [CARGO_BAZEL_RESOLVE_CONFLICTS], repository_name)
+
+def execute_resolve_conflicts(
+        *,
+        cargo_bazel_fn,
+        lockfile_path,
+        cargo_lockfile_path,
+        config,
+        splicing_manifest,
+        nonhermetic_root_bazel_workspace_dir,
+        skip_cargo_lockfile_overwrite,
+        strip_internal_dependencies_from_cargo_lockfile,
+        shard_lockfile = False):
+    """Execute the `cargo-bazel` binary to resolve git merge conflicts in `Cargo.lock` and the lockfile.
+
+    Args:
+        cargo_bazel_fn (callable): A callback for invoking the `cargo-bazel` binary.
+        lockfile_path (path): The path to a "lock" file (file used for reproducible renderings).
+        cargo_lockfile_path (path): The path to a "Cargo.lock" file within the root workspace.
+        config (path): The path to a `cargo-bazel` config file.
+        splicing_manifest (path): The path to a `cargo-bazel` splicing manifest. See `create_splicing_manifest`
+        nonhermetic_root_bazel_workspace_dir (path): The path to the current workspace root
+        skip_cargo_lockfile_overwrite (bool): Whether to skip writing the cargo lockfile back after resolving.
+        strip_internal_dependencies_from_cargo_lockfile (bool): Whether to strip internal dependencies from the cargo lockfile.
+        shard_lockfile (bool): Whether to write each crate in the lockfile to its own file.
+
+    Returns:
+        struct: The results of `repository_ctx.execute`.
+    """
+    args = [
+        "resolve-conflicts",
+        "--cargo-lockfile",
+        cargo_lockfile_path,
+        "--lockfile",
+        lockfile_path,
+        "--config",
+        config,
+        "--splicing-manifest",
+        splicing_manifest,
+        "--nonhermetic-root-bazel-workspace-dir",
+        nonhermetic_root_bazel_workspace_dir,
+    ]
+
+    if skip_cargo_lockfile_overwrite:
+        args.append("--skip-cargo-lockfile-overwrite")
+
+    if strip_internal_dependencies_from_cargo_lockfile:
+        args.append("--strip-internal-dependencies-from-cargo-lockfile")
+
+    if shard_lockfile:
+        args.append("--shard-lockfile")
+
+    return cargo_bazel_fn(
+        args = args,
+    )
+
 def determine_repin(
         *,
         repository_ctx,
@@ -368,6 +568,7 @@ def determine_repin(
         lockfile_path,
         config,
         splicing_manifest,
+        cargo_lockfile_path = None,
         repin_instructions = None):
     """Use the `cargo-bazel` binary to determine whether or not dependencies need to be re-pinned
 
@@ -378,6 +579,7 @@ def determine_repin(
         config (path): The path to a `cargo-bazel` config file. See `generate_config`.
         splicing_manifest (path): The path to a `cargo-bazel` splicing manifest. See `create_splicing_manifest`
         lockfile_path (path): The path to a "lock" file for reproducible outputs.
+        cargo_lockfile_path (path, optional): The path to a `Cargo.lock` file, used to explain why a repin is needed.
         repin_instructions (optional string): Instructions to re-pin dependencies in your repository. Will be shown when re-pinning is required.
 
     Returns:
@@ -385,31 +587,28 @@ def determine_repin(
     """
 
     # If a repin environment variable is set, always repin
-    for var in REPIN_ENV_VARS:
-        if var in repository_ctx.os.environ and repository_ctx.os.environ[var].lower() not in ["false", "no", "0", "off"]:
-            # If a repin allowlist is present only force repin if name is in list
-            if REPIN_ALLOWLIST_ENV_VAR in repository_ctx.os.environ:
-                indices_to_repin = repository_ctx.os.environ[REPIN_ALLOWLIST_ENV_VAR].split(",")
-                if repository_name in indices_to_repin:
-                    return True
-            else:
-                return True
+    if _is_requested(repository_ctx, REPIN_ENV_VARS, repository_name):
+        return True
 
     # If a deterministic lockfile was not added then always repin
     if not lockfile_path:
         return True
 
+    args = [
+        "query",
+        "--lockfile",
+        lockfile_path,
+        "--config",
+        config,
+        "--splicing-manifest",
+        splicing_manifest,
+    ]
+    if cargo_lockfile_path:
+        args.extend(["--cargo-lockfile", cargo_lockfile_path])
+
     # Run the binary to check if a repin is needed
     result = cargo_bazel_fn(
-        args = [
-            "query",
-            "--lockfile",
-            lockfile_path,
-            "--config",
-            config,
-            "--splicing-manifest",
-            splicing_manifest,
-        ],
+        args = args,
         allow_fail = True,
     )
 
@@ -450,6 +649,7 @@ def execute_generator(
         hub_packages_output_file,
         skip_cargo_lockfile_overwrite,
         strip_internal_dependencies_from_cargo_lockfile,
+        shard_lockfile = False,
         metadata = None,
         generator_label = None):
     """Execute the `cargo-bazel` binary to produce `BUILD` and `.bzl` files.
@@ -475,6 +675,7 @@ def execute_generator(
             Bazel only requires external dependencies to be present in the lockfile.
             By removing internal dependencies, the lockfile changes less frequently which reduces merge conflicts
             in other lockfiles where the cargo lockfile's sha is stored.
+        shard_lockfile (bool): Whether to write each crate in the lockfile to its own file.
         generator_label (Label): The label of the `generator` parameter.
         metadata (path, optional): The path to a Cargo metadata json file. If this is set, it indicates to
             the generator that repinning is required. This file must be adjacent to a `Cargo.toml` and
@@ -521,6 +722,9 @@ def execute_generator(
     if strip_internal_dependencies_from_cargo_lockfile:
         args.append("--strip-internal-dependencies-from-cargo-lockfile")
 
+    if shard_lockfile:
+        args.append("--shard-lockfile")
+
     # Some components are not required unless re-pinning is enabled
     if metadata:
         args.extend([
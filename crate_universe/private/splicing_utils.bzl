@@ -33,7 +33,23 @@ def kebab_case_keys(data):
         for (key, val) in data.items()
     }
 
-def compile_splicing_manifest(splicing_config, manifests, cargo_config_path, packages):
+def compile_custom_targets(custom_targets):
+    """Describe custom targets in the form expected by `cargo_bazel::splicing::SplicingManifest`
+
+    Args:
+        custom_targets (dict): A mapping of platform triples to the paths of files describing them.
+            Files ending in `.json` are custom target specifications, all others contain the output
+            of `rustc --print cfg`.
+
+    Returns:
+        dict: A mapping of platform triples to `cargo_bazel::utils::target_triple::CustomTarget`s
+    """
+    return {
+        triple: {("target_json" if path.endswith(".json") else "rustc_cfg"): path}
+        for (triple, path) in custom_targets.items()
+    }
+
+def compile_splicing_manifest(splicing_config, manifests, cargo_config_path, packages, custom_targets = {}):
     """Produce a manifest containing required components for splicing a new Cargo workspace
 
     [cargo_config]: https://doc.rust-lang.org/cargo/reference/config.html
@@ -44,6 +60,7 @@ def compile_splicing_manifest(splicing_config, manifests, cargo_config_path, pac
         manifests (dict): A mapping of paths to Bazel labels which represent [Cargo manifests][cargo_toml].
         cargo_config_path (str): The absolute path to a [Cargo config][cargo_config].
         packages (dict): A set of crates (packages) specifications to depend on
+        custom_targets (dict): A mapping of platform triples to the absolute paths of files describing them.
 
     Returns:
         dict: A dictionary representation of a `cargo_bazel::splicing::SplicingManifest`
@@ -59,6 +76,7 @@ def compile_splicing_manifest(splicing_config, manifests, cargo_config_path, pac
     # Auto-generated splicer manifest values
     splicing_manifest_content = {
         "cargo_config": cargo_config_path,
+        "custom_targets": compile_custom_targets(custom_targets),
         "direct_packages": direct_packages_info,
         "manifests": manifests,
     }
@@ -94,6 +112,11 @@ def create_splicing_manifest(repository_ctx):
     else:
         cargo_config = None
 
+    custom_targets = {
+        triple: str(repository_ctx.path(label))
+        for (label, triple) in repository_ctx.attr.custom_targets.items()
+    }
+
     # Load user configurable splicing settings
     config = json.decode(repository_ctx.attr.splicing_config or splicing_config())
 
@@ -104,6 +127,7 @@ def create_splicing_manifest(repository_ctx):
         manifests = manifests,
         cargo_config_path = cargo_config,
         packages = repository_ctx.attr.packages,
+        custom_targets = custom_targets,
     )
 
     # Serialize information required for splicing
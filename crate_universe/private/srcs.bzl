@@ -9,13 +9,20 @@ CARGO_BAZEL_SRCS = [
     Label("//crate_universe:src/api.rs"),
     Label("//crate_universe:src/api/lockfile.rs"),
     Label("//crate_universe:src/cli.rs"),
+    Label("//crate_universe:src/cli/diff.rs"),
     Label("//crate_universe:src/cli/generate.rs"),
+    Label("//crate_universe:src/cli/outdated.rs"),
     Label("//crate_universe:src/cli/query.rs"),
     Label("//crate_universe:src/cli/render.rs"),
+    Label("//crate_universe:src/cli/resolve_conflicts.rs"),
+    Label("//crate_universe:src/cli/sbom.rs"),
     Label("//crate_universe:src/cli/splice.rs"),
     Label("//crate_universe:src/cli/vendor.rs"),
+    Label("//crate_universe:src/cli/why.rs"),
     Label("//crate_universe:src/config.rs"),
     Label("//crate_universe:src/config/label_injection.rs"),
+    Label("//crate_universe:src/config/license_policy.rs"),
+    Label("//crate_universe:src/config/multiple_versions.rs"),
     Label("//crate_universe:src/context.rs"),
     Label("//crate_universe:src/context/crate_context.rs"),
     Label("//crate_universe:src/context/platforms.rs"),
@@ -23,14 +30,18 @@ CARGO_BAZEL_SRCS = [
     Label("//crate_universe:src/lockfile.rs"),
     Label("//crate_universe:src/main.rs"),
     Label("//crate_universe:src/metadata.rs"),
+    Label("//crate_universe:src/metadata/build_script_prerunner.rs"),
     Label("//crate_universe:src/metadata/cargo_bin.rs"),
+    Label("//crate_universe:src/metadata/cargo_tree_cache.rs"),
     Label("//crate_universe:src/metadata/cargo_tree_resolver.rs"),
     Label("//crate_universe:src/metadata/cargo_tree_rustc_wrapper.bat"),
     Label("//crate_universe:src/metadata/cargo_tree_rustc_wrapper.sh"),
     Label("//crate_universe:src/metadata/dependency.rs"),
     Label("//crate_universe:src/metadata/metadata_annotation.rs"),
+    Label("//crate_universe:src/metadata/metadata_resolver.rs"),
     Label("//crate_universe:src/rendering.rs"),
     Label("//crate_universe:src/rendering/template_engine.rs"),
+    Label("//crate_universe:src/rendering/templates/crate_build_file.j2"),
     Label("//crate_universe:src/rendering/templates/defs_bzl_shim.j2"),
     Label("//crate_universe:src/rendering/templates/module_bzl.j2"),
     Label("//crate_universe:src/rendering/templates/partials/header.j2"),
@@ -46,6 +57,7 @@ CARGO_BAZEL_SRCS = [
     Label("//crate_universe:src/splicing/splicer.rs"),
     Label("//crate_universe:src/test.rs"),
     Label("//crate_universe:src/utils.rs"),
+    Label("//crate_universe:src/utils/merge_conflicts.rs"),
     Label("//crate_universe:src/utils/starlark.rs"),
     Label("//crate_universe:src/utils/starlark/glob.rs"),
     Label("//crate_universe:src/utils/starlark/label.rs"),
@@ -58,4 +70,5 @@ CARGO_BAZEL_SRCS = [
     Label("//crate_universe:src/utils/starlark/target_compatible_with.rs"),
     Label("//crate_universe:src/utils/symlink.rs"),
     Label("//crate_universe:src/utils/target_triple.rs"),
+    Label("//crate_universe:src/utils/text_diff.rs"),
 ]
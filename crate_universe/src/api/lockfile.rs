@@ -1,23 +1,24 @@
 //! The lockfile::public module represents a reasonable stable API for inspecting the contents of a lockfile which others can code against.
 
-use std::collections::BTreeSet;
-use std::fs::File;
-use std::io::BufReader;
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::Path;
 
 use anyhow::Result;
+use camino::Utf8PathBuf;
 use serde::Deserialize;
 
+use crate::config::Commitish;
 pub use crate::config::CrateId;
 use crate::context::crate_context::{CrateDependency, Rule};
-use crate::context::{CommonAttributes, Context};
+use crate::context::{BuildScriptAttributes, CommonAttributes, Context, CrateContext};
+use crate::metadata::SourceAnnotation;
 use crate::select::Select;
 
 /// Parse a lockfile at a path on disk.
+///
+/// Both single file and sharded lockfiles are supported.
 pub fn parse(path: &Path) -> Result<impl CargoBazelLockfile> {
-    let reader = BufReader::new(File::open(path)?);
-    let lockfile: CargoBazelLockfileImpl = serde_json::from_reader(reader)?;
-    Ok(lockfile)
+    Ok(CargoBazelLockfileImpl(Context::try_from_path(path)?))
 }
 
 /// `CargoBazelLockfile` provides a view over `cargo-bazel`'s lockfile format.
@@ -34,6 +35,13 @@ pub trait CargoBazelLockfile {
 
     /// Get information about a specific crate (which may be in the local workspace, or an external dependency).
     fn crate_info(&self, crate_id: &CrateId) -> Option<CrateInfo>;
+
+    /// Iterate over every crate in the lockfile (both workspace members and external dependencies), in order.
+    fn crates(&self) -> Box<dyn Iterator<Item = (CrateId, CrateInfo)> + '_>;
+
+    /// Get the crates which directly depend on a specific crate, on any platform.
+    /// This includes normal, dev, procedural macro and build script dependencies.
+    fn reverse_dependencies(&self, crate_id: &CrateId) -> BTreeSet<CrateId>;
 }
 
 #[derive(Deserialize)]
@@ -46,8 +54,51 @@ impl CargoBazelLockfile for CargoBazelLockfileImpl {
     }
 
     fn crate_info(&self, crate_id: &CrateId) -> Option<CrateInfo> {
-        let crate_context = self.0.crates.get(crate_id)?;
-        Some(CrateInfo {
+        self.0.crates.get(crate_id).map(CrateInfo::from)
+    }
+
+    fn crates(&self) -> Box<dyn Iterator<Item = (CrateId, CrateInfo)> + '_> {
+        Box::new(
+            self.0
+                .crates
+                .iter()
+                .map(|(id, crate_context)| (id.clone(), CrateInfo::from(crate_context))),
+        )
+    }
+
+    fn reverse_dependencies(&self, crate_id: &CrateId) -> BTreeSet<CrateId> {
+        let depends_on = |deps: &Select<BTreeSet<CrateDependency>>| {
+            deps.values().iter().any(|dep| &dep.id == crate_id)
+        };
+
+        self.0
+            .crates
+            .iter()
+            // A crate's library depends on its own build script, which is not interesting here.
+            .filter(|(id, _)| *id != crate_id)
+            .filter(|(_, crate_context)| {
+                let common = &crate_context.common_attrs;
+                depends_on(&common.deps)
+                    || depends_on(&common.deps_dev)
+                    || depends_on(&common.proc_macro_deps)
+                    || depends_on(&common.proc_macro_deps_dev)
+                    || crate_context
+                        .build_script_attrs
+                        .as_ref()
+                        .is_some_and(|attrs| {
+                            depends_on(&attrs.deps)
+                                || depends_on(&attrs.link_deps)
+                                || depends_on(&attrs.proc_macro_deps)
+                        })
+            })
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+impl From<&CrateContext> for CrateInfo {
+    fn from(crate_context: &CrateContext) -> Self {
+        CrateInfo {
             name: crate_context.name.clone(),
             version: crate_context.version.clone(),
             library_target_name: crate_context.library_target_name.clone(),
@@ -55,8 +106,21 @@ impl CargoBazelLockfile for CargoBazelLockfileImpl {
                 .targets
                 .iter()
                 .any(|t| matches!(t, Rule::ProcMacro(_))),
+            binaries: crate_context
+                .targets
+                .iter()
+                .filter_map(|t| match t {
+                    Rule::Binary(attrs) => Some(attrs.crate_name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            package_url: crate_context.package_url.clone(),
+            source: crate_context.repository.as_ref().map(Source::from),
+            license: crate_context.license.clone(),
+            license_ids: crate_context.license_ids.clone(),
             common_attributes: crate_context.common_attrs.clone(),
-        })
+            build_script_attributes: crate_context.build_script_attrs.clone(),
+        }
     }
 }
 
@@ -67,8 +131,14 @@ pub struct CrateInfo {
     version: semver::Version,
     library_target_name: Option<String>,
     is_proc_macro: bool,
+    binaries: BTreeSet<String>,
+    package_url: Option<String>,
+    source: Option<Source>,
+    license: Option<String>,
+    license_ids: BTreeSet<String>,
 
     common_attributes: CommonAttributes,
+    build_script_attributes: Option<BuildScriptAttributes>,
 }
 
 impl CrateInfo {
@@ -112,11 +182,173 @@ impl CrateInfo {
     pub fn proc_macro_dev_deps(&self) -> Select<BTreeSet<CrateDependency>> {
         self.common_attributes.proc_macro_deps_dev.clone()
     }
+
+    /// The Cargo features enabled for the crate.
+    pub fn crate_features(&self) -> Select<BTreeSet<String>> {
+        self.common_attributes.crate_features.clone()
+    }
+
+    /// Environment variables set when compiling the crate.
+    pub fn rustc_env(&self) -> Select<BTreeMap<String, String>> {
+        self.common_attributes.rustc_env.clone()
+    }
+
+    /// Extra flags passed to rustc when compiling the crate.
+    pub fn rustc_flags(&self) -> Select<Vec<String>> {
+        self.common_attributes.rustc_flags.clone()
+    }
+
+    /// The names of the crate's binary targets. This is empty unless binaries were generated for the crate.
+    pub fn binaries(&self) -> &BTreeSet<String> {
+        &self.binaries
+    }
+
+    /// Information about the crate's build script, if it has one.
+    pub fn build_script(&self) -> Option<BuildScriptInfo> {
+        self.build_script_attributes
+            .as_ref()
+            .map(|attributes| BuildScriptInfo {
+                attributes: attributes.clone(),
+            })
+    }
+
+    /// Where the crate's source code is fetched from. Workspace members have no source.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// The [package URL](https://github.com/package-url/purl-spec) of the crate.
+    pub fn package_url(&self) -> Option<&str> {
+        self.package_url.as_deref()
+    }
+
+    /// The license expression declared by the crate.
+    pub fn license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    /// The SPDX license ids parsed from the crate's license expression.
+    pub fn license_ids(&self) -> &BTreeSet<String> {
+        &self.license_ids
+    }
+}
+
+/// Information about a crate's build script.
+#[derive(PartialEq, Eq, Debug)]
+pub struct BuildScriptInfo {
+    attributes: BuildScriptAttributes,
+}
+
+impl BuildScriptInfo {
+    /// Dependencies required to compile the build script.
+    pub fn deps(&self) -> Select<BTreeSet<CrateDependency>> {
+        self.attributes.deps.clone()
+    }
+
+    /// Dependencies of the crate which the build script may need to link against.
+    pub fn link_deps(&self) -> Select<BTreeSet<CrateDependency>> {
+        self.attributes.link_deps.clone()
+    }
+
+    /// Procedural macro dependencies required to compile the build script.
+    pub fn proc_macro_deps(&self) -> Select<BTreeSet<CrateDependency>> {
+        self.attributes.proc_macro_deps.clone()
+    }
+
+    /// Environment variables set when running the build script.
+    pub fn build_script_env(&self) -> Select<BTreeMap<String, String>> {
+        self.attributes.build_script_env.clone()
+    }
+
+    /// Environment variables set when compiling the build script.
+    pub fn rustc_env(&self) -> Select<BTreeMap<String, String>> {
+        self.attributes.rustc_env.clone()
+    }
+
+    /// Extra flags passed to rustc when compiling the build script.
+    pub fn rustc_flags(&self) -> Select<Vec<String>> {
+        self.attributes.rustc_flags.clone()
+    }
+
+    /// The value of the crate's `links` manifest key, if set.
+    pub fn links(&self) -> Option<&str> {
+        self.attributes.links.as_deref()
+    }
+}
+
+/// Where a crate's source code is fetched from.
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
+pub enum Source {
+    /// An archive downloaded over http.
+    Http {
+        /// The urls the archive can be downloaded from.
+        urls: Vec<String>,
+
+        /// The expected sha256 of the archive.
+        sha256: Option<String>,
+    },
+
+    /// A git repository.
+    Git {
+        /// The url of the repository.
+        remote: String,
+
+        /// The revision of the repository to check out.
+        commitish: GitCommitish,
+
+        /// The path within the repository containing the crate.
+        strip_prefix: Option<String>,
+    },
+
+    /// A path relative to the Bazel workspace root.
+    Path {
+        /// The path to the crate's source.
+        path: Utf8PathBuf,
+    },
+}
+
+/// A reference to a revision of a git repository.
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
+pub enum GitCommitish {
+    /// A tag.
+    Tag(String),
+
+    /// The HEAD of a branch.
+    Branch(String),
+
+    /// A specific revision.
+    Rev(String),
+}
+
+impl From<&SourceAnnotation> for Source {
+    fn from(annotation: &SourceAnnotation) -> Self {
+        match annotation {
+            SourceAnnotation::Http { url, sha256, .. } => Source::Http {
+                urls: vec![url.clone()],
+                sha256: sha256.clone(),
+            },
+            SourceAnnotation::Git {
+                remote,
+                commitish,
+                strip_prefix,
+                ..
+            } => Source::Git {
+                remote: remote.clone(),
+                commitish: match commitish {
+                    Commitish::Tag(tag) => GitCommitish::Tag(tag.clone()),
+                    Commitish::Branch(branch) => GitCommitish::Branch(branch.clone()),
+                    Commitish::Rev(rev) => GitCommitish::Rev(rev.clone()),
+                },
+                strip_prefix: strip_prefix.clone(),
+            },
+            SourceAnnotation::Path { path } => Source::Path { path: path.clone() },
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{parse, CargoBazelLockfile};
+    use super::{parse, CargoBazelLockfile, Source};
     use crate::config::CrateId;
     use crate::context::crate_context::CrateDependency;
     use semver::Version;
@@ -190,6 +422,51 @@ mod test {
             ],
         );
 
+        let anyhow = CrateId {
+            name: String::from("anyhow"),
+            version: Version::new(1, 0, 69),
+        };
+        let got_anyhow = parsed.crate_info(&anyhow).unwrap();
+        assert_eq!(
+            got_anyhow.crate_features().values(),
+            vec![String::from("default"), String::from("std")],
+        );
+        assert_eq!(
+            got_anyhow.source(),
+            Some(&Source::Http {
+                urls: vec![String::from(
+                    "https://crates.io/api/v1/crates/anyhow/1.0.69/download"
+                )],
+                sha256: Some(String::from(
+                    "224afbd727c3d6e4b90103ece64b8d1b67fbb1973b1046c2281eed3f3803f800"
+                )),
+            }),
+        );
+        assert_eq!(got_anyhow.license(), Some("MIT OR Apache-2.0"));
+        assert_eq!(
+            got_anyhow.license_ids(),
+            &BTreeSet::from([String::from("Apache-2.0"), String::from("MIT")]),
+        );
+        assert_eq!(
+            got_anyhow.package_url(),
+            Some("https://github.com/dtolnay/anyhow")
+        );
+        assert!(got_anyhow.binaries().is_empty());
+        assert!(got_anyhow.build_script().is_some());
+        assert!(got_pkg_a.build_script().is_none());
+        assert_eq!(got_pkg_a.source(), None);
+        assert_eq!(
+            parsed.reverse_dependencies(&anyhow),
+            BTreeSet::from([pkg_a.clone()]),
+        );
+        assert_eq!(
+            parsed
+                .crates()
+                .find(|(id, _)| id == &anyhow)
+                .map(|(_, info)| info),
+            Some(got_anyhow),
+        );
+
         let async_process = CrateId {
             name: String::from("async-process"),
             version: Version::new(1, 6, 0),
@@ -1,10 +1,15 @@
 //! Command line interface entry points and utilities
 
+mod diff;
 mod generate;
+mod outdated;
 mod query;
 mod render;
+mod resolve_conflicts;
+mod sbom;
 mod splice;
 mod vendor;
+mod why;
 
 use clap::Parser;
 use tracing::Subscriber;
@@ -16,18 +21,28 @@ use tracing_subscriber::FmtSubscriber;
 
 pub use tracing::Level as LogLevel;
 
+pub use self::diff::DiffOptions;
 pub use self::generate::GenerateOptions;
+pub use self::outdated::OutdatedOptions;
 pub use self::query::QueryOptions;
 pub use self::render::RenderOptions;
+pub use self::resolve_conflicts::ResolveConflictsOptions;
+pub use self::sbom::{SbomFormat, SbomOptions};
 pub use self::splice::SpliceOptions;
 pub use self::vendor::VendorOptions;
+pub use self::why::WhyOptions;
 
 // Entrypoints
+pub use diff::diff;
 pub use generate::generate;
+pub use outdated::outdated;
 pub use query::query;
 pub use render::render;
+pub use resolve_conflicts::resolve_conflicts;
+pub use sbom::sbom;
 pub use splice::splice;
 pub use vendor::vendor;
+pub use why::why;
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -50,6 +65,21 @@ pub enum Options {
 
     /// Render a BUILD file for a single crate.
     Render(RenderOptions),
+
+    /// Compare two cargo-bazel lockfiles and report the crates and attributes which changed.
+    Diff(DiffOptions),
+
+    /// Explain why a crate is part of the dependency graph by printing the paths to it from the workspace members.
+    Why(WhyOptions),
+
+    /// Generate a CycloneDX or SPDX Software Bill of Materials from a cargo-bazel lockfile.
+    Sbom(SbomOptions),
+
+    /// Resolve git merge conflicts in `Cargo.lock` and the cargo-bazel lockfile.
+    ResolveConflicts(ResolveConflictsOptions),
+
+    /// Report crates with newer or yanked versions using a local mirror of the registry index.
+    Outdated(OutdatedOptions),
 }
 
 // Convenience wrappers to avoid dependencies in the binary
@@ -59,7 +89,18 @@ pub fn parse_args() -> Options {
     Options::parse()
 }
 
-const EXPECTED_LOGGER_NAMES: [&str; 5] = ["Generate", "Splice", "Query", "Vendor", "Render"];
+const EXPECTED_LOGGER_NAMES: [&str; 10] = [
+    "Generate",
+    "Splice",
+    "Query",
+    "Vendor",
+    "Render",
+    "Diff",
+    "Why",
+    "Sbom",
+    "ResolveConflicts",
+    "Outdated",
+];
 
 /// A wrapper for the tracing-subscriber default [FormatEvent]
 /// that prepends the name of the active CLI option.
@@ -0,0 +1,661 @@
+//! The cli entrypoint for the `diff` subcommand
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context as AnyhowContext, Result};
+use clap::Parser;
+use serde::Serialize;
+
+use crate::config::CrateId;
+use crate::context::{BuildScriptAttributes, Context, CrateContext, CrateDependency};
+use crate::select::{Select, Selectable};
+
+/// Command line options for the `diff` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `diff` subcommand", version)]
+pub struct DiffOptions {
+    /// The path to the original cargo-bazel lockfile
+    #[clap(long)]
+    pub old: PathBuf,
+
+    /// The path to the updated cargo-bazel lockfile
+    #[clap(long)]
+    pub new: PathBuf,
+
+    /// If true, the report will be printed as JSON instead of human-readable text.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Compare two cargo-bazel lockfiles and print a report of what changed between them.
+pub fn diff(opt: DiffOptions) -> Result<()> {
+    let old = Context::try_from_path(&opt.old)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.old.display()))?;
+    let new = Context::try_from_path(&opt.new)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.new.display()))?;
+
+    let report = LockfileDiff::new(&old, &new);
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(())
+}
+
+/// The configuration key used for values which apply to all platforms.
+const COMMON_CONFIGURATION: &str = "common";
+
+/// The values added to and removed from a single `select` configuration.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct ValuesDiff {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    added: BTreeSet<String>,
+
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    removed: BTreeSet<String>,
+}
+
+/// Changes to a [Select] attribute, keyed by configuration.
+type SelectDiff = BTreeMap<String, ValuesDiff>;
+
+/// A crate whose version changed between the two lockfiles.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct VersionChange {
+    name: String,
+    old: semver::Version,
+    new: semver::Version,
+}
+
+/// The attribute changes for a single crate which exists in both lockfiles.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct CrateChanges {
+    name: String,
+    old: semver::Version,
+    new: semver::Version,
+
+    /// Set if a build script was added (`true`) or removed (`false`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_script: Option<bool>,
+
+    /// Changes to attributes, keyed by attribute name.
+    attributes: BTreeMap<String, SelectDiff>,
+}
+
+/// A structured report of the differences between two [Context]s.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct LockfileDiff {
+    added: BTreeSet<CrateId>,
+    removed: BTreeSet<CrateId>,
+    upgraded: Vec<VersionChange>,
+    downgraded: Vec<VersionChange>,
+    changed: Vec<CrateChanges>,
+}
+
+impl LockfileDiff {
+    fn new(old: &Context, new: &Context) -> Self {
+        let mut report = Self::default();
+
+        // Group all versions of each crate by name and semver compatibility so only
+        // versions which Cargo could have updated between are paired up.
+        let mut versions: BTreeMap<_, (BTreeSet<&CrateId>, BTreeSet<&CrateId>)> = BTreeMap::new();
+        for id in old.crates.keys() {
+            versions
+                .entry((id.name.as_str(), semver_compatibility(&id.version)))
+                .or_default()
+                .0
+                .insert(id);
+        }
+        for id in new.crates.keys() {
+            versions
+                .entry((id.name.as_str(), semver_compatibility(&id.version)))
+                .or_default()
+                .1
+                .insert(id);
+        }
+
+        // Versions left unpaired within their compatibility group, by name.
+        let mut unpaired: BTreeMap<&str, (Vec<&CrateId>, Vec<&CrateId>)> = BTreeMap::new();
+        for ((name, _), (old_ids, new_ids)) in &versions {
+            // Versions present in both lockfiles are compared directly.
+            for id in old_ids.intersection(new_ids) {
+                report.compare(&old.crates[*id], &new.crates[*id]);
+            }
+
+            // Remaining versions are paired in order.
+            let mut only_old = old_ids.difference(new_ids);
+            let mut only_new = new_ids.difference(old_ids);
+            loop {
+                match (only_old.next(), only_new.next()) {
+                    (Some(old_id), Some(new_id)) => {
+                        report.change_version(old, new, old_id, new_id);
+                    }
+                    (Some(old_id), None) => {
+                        unpaired.entry(name).or_default().0.push(old_id);
+                    }
+                    (None, Some(new_id)) => {
+                        unpaired.entry(name).or_default().1.push(new_id);
+                    }
+                    (None, None) => break,
+                }
+            }
+        }
+
+        // A single version replaced by another across compatibility groups (e.g. a
+        // major upgrade) is still a version change. Anything else was either added
+        // or removed outright.
+        for (old_ids, new_ids) in unpaired.into_values() {
+            match (old_ids.as_slice(), new_ids.as_slice()) {
+                ([old_id], [new_id]) => report.change_version(old, new, old_id, new_id),
+                _ => {
+                    report.removed.extend(old_ids.into_iter().cloned());
+                    report.added.extend(new_ids.into_iter().cloned());
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Record an upgrade or downgrade between two versions of a crate along with any
+    /// attribute changes.
+    fn change_version(&mut self, old: &Context, new: &Context, old_id: &CrateId, new_id: &CrateId) {
+        let change = VersionChange {
+            name: old_id.name.clone(),
+            old: old_id.version.clone(),
+            new: new_id.version.clone(),
+        };
+        if change.new > change.old {
+            self.upgraded.push(change);
+        } else {
+            self.downgraded.push(change);
+        }
+        self.compare(&old.crates[old_id], &new.crates[new_id]);
+    }
+
+    /// Record any attribute changes between two versions of the same crate.
+    fn compare(&mut self, old: &CrateContext, new: &CrateContext) {
+        let mut attributes = BTreeMap::new();
+
+        let (old_attrs, new_attrs) = (&old.common_attrs, &new.common_attrs);
+        insert_select_diff(
+            &mut attributes,
+            "crate_features",
+            &old_attrs.crate_features,
+            &new_attrs.crate_features,
+            String::clone,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "deps",
+            &old_attrs.deps,
+            &new_attrs.deps,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "deps_dev",
+            &old_attrs.deps_dev,
+            &new_attrs.deps_dev,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "proc_macro_deps",
+            &old_attrs.proc_macro_deps,
+            &new_attrs.proc_macro_deps,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "proc_macro_deps_dev",
+            &old_attrs.proc_macro_deps_dev,
+            &new_attrs.proc_macro_deps_dev,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "rustc_env",
+            &old_attrs.rustc_env,
+            &new_attrs.rustc_env,
+            render_env,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "rustc_flags",
+            &old_attrs.rustc_flags,
+            &new_attrs.rustc_flags,
+            String::clone,
+        );
+
+        let default_build_script_attrs = BuildScriptAttributes::default();
+        let old_build_script = old
+            .build_script_attrs
+            .as_ref()
+            .unwrap_or(&default_build_script_attrs);
+        let new_build_script = new
+            .build_script_attrs
+            .as_ref()
+            .unwrap_or(&default_build_script_attrs);
+        insert_select_diff(
+            &mut attributes,
+            "build_script.deps",
+            &old_build_script.deps,
+            &new_build_script.deps,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "build_script.link_deps",
+            &old_build_script.link_deps,
+            &new_build_script.link_deps,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "build_script.proc_macro_deps",
+            &old_build_script.proc_macro_deps,
+            &new_build_script.proc_macro_deps,
+            render_dep,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "build_script.build_script_env",
+            &old_build_script.build_script_env,
+            &new_build_script.build_script_env,
+            render_env,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "build_script.rustc_env",
+            &old_build_script.rustc_env,
+            &new_build_script.rustc_env,
+            render_env,
+        );
+        insert_select_diff(
+            &mut attributes,
+            "build_script.rustc_flags",
+            &old_build_script.rustc_flags,
+            &new_build_script.rustc_flags,
+            String::clone,
+        );
+
+        let build_script = match (&old.build_script_attrs, &new.build_script_attrs) {
+            (None, Some(_)) => Some(true),
+            (Some(_), None) => Some(false),
+            _ => None,
+        };
+
+        if attributes.is_empty() && build_script.is_none() {
+            return;
+        }
+
+        self.changed.push(CrateChanges {
+            name: new.name.clone(),
+            old: old.version.clone(),
+            new: new.version.clone(),
+            build_script,
+            attributes,
+        });
+    }
+}
+
+/// The leftmost non-zero component of a version, as used by Cargo to decide which
+/// versions are semver compatible with each other.
+fn semver_compatibility(version: &semver::Version) -> (u64, Option<u64>, Option<u64>) {
+    match (version.major, version.minor) {
+        (0, 0) => (0, Some(0), Some(version.patch)),
+        (0, minor) => (0, Some(minor), None),
+        (major, _) => (major, None, None),
+    }
+}
+
+fn render_dep(dep: &CrateDependency) -> String {
+    match &dep.alias {
+        Some(alias) => format!("{} (as {})", dep.id, alias),
+        None => dep.id.to_string(),
+    }
+}
+
+fn render_env((key, value): &(String, String)) -> String {
+    format!("{key}={value}")
+}
+
+/// Group the items of a [Select] by configuration after rendering them as strings.
+fn group_by_configuration<T: Selectable>(
+    select: &Select<T>,
+    render: fn(&T::ItemType) -> String,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut grouped: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (configuration, item) in select.items() {
+        grouped
+            .entry(configuration.unwrap_or_else(|| COMMON_CONFIGURATION.to_owned()))
+            .or_default()
+            .insert(render(&item));
+    }
+    grouped
+}
+
+/// Compute a per-configuration diff of two [Select]s and store it under `name` if non-empty.
+fn insert_select_diff<T: Selectable>(
+    attributes: &mut BTreeMap<String, SelectDiff>,
+    name: &str,
+    old: &Select<T>,
+    new: &Select<T>,
+    render: fn(&T::ItemType) -> String,
+) {
+    let old = group_by_configuration(old, render);
+    let new = group_by_configuration(new, render);
+    let empty = BTreeSet::new();
+
+    let diff: SelectDiff = old
+        .keys()
+        .chain(new.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter_map(|configuration| {
+            let old_values = old.get(configuration).unwrap_or(&empty);
+            let new_values = new.get(configuration).unwrap_or(&empty);
+            let values = ValuesDiff {
+                added: new_values.difference(old_values).cloned().collect(),
+                removed: old_values.difference(new_values).cloned().collect(),
+            };
+            if values == ValuesDiff::default() {
+                None
+            } else {
+                Some((configuration.clone(), values))
+            }
+        })
+        .collect();
+
+    if !diff.is_empty() {
+        attributes.insert(name.to_owned(), diff);
+    }
+}
+
+impl fmt::Display for LockfileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+            && self.changed.is_empty()
+        {
+            return writeln!(f, "No changes.");
+        }
+
+        if !self.added.is_empty() {
+            writeln!(f, "Added crates:")?;
+            for id in &self.added {
+                writeln!(f, "  + {id}")?;
+            }
+        }
+
+        if !self.removed.is_empty() {
+            writeln!(f, "Removed crates:")?;
+            for id in &self.removed {
+                writeln!(f, "  - {id}")?;
+            }
+        }
+
+        if !self.upgraded.is_empty() {
+            writeln!(f, "Upgraded crates:")?;
+            for change in &self.upgraded {
+                writeln!(f, "  {} {} -> {}", change.name, change.old, change.new)?;
+            }
+        }
+
+        if !self.downgraded.is_empty() {
+            writeln!(f, "Downgraded crates:")?;
+            for change in &self.downgraded {
+                writeln!(f, "  {} {} -> {}", change.name, change.old, change.new)?;
+            }
+        }
+
+        if !self.changed.is_empty() {
+            writeln!(f, "Changed crates:")?;
+            for changes in &self.changed {
+                let mut header = format!("  {} {}", changes.name, changes.old);
+                if changes.old != changes.new {
+                    write!(header, " -> {}", changes.new)?;
+                }
+                writeln!(f, "{header}")?;
+
+                match changes.build_script {
+                    Some(true) => writeln!(f, "    build script added")?,
+                    Some(false) => writeln!(f, "    build script removed")?,
+                    None => {}
+                }
+
+                for (attribute, diff) in &changes.attributes {
+                    writeln!(f, "    {attribute}:")?;
+                    for (configuration, values) in diff {
+                        writeln!(f, "      {configuration}:")?;
+                        for value in &values.added {
+                            writeln!(f, "        + {value}")?;
+                        }
+                        for value in &values.removed {
+                            writeln!(f, "        - {value}")?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use semver::Version;
+
+    fn mock_crate(name: &str, version: &str, common_attrs: serde_json::Value) -> CrateContext {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "license_ids": [],
+            "common_attrs": common_attrs,
+        }))
+        .unwrap()
+    }
+
+    fn mock_context(crates: Vec<CrateContext>) -> Context {
+        Context {
+            crates: crates
+                .into_iter()
+                .map(|ctx| (CrateId::new(ctx.name.clone(), ctx.version.clone()), ctx))
+                .collect(),
+            ..Context::default()
+        }
+    }
+
+    #[test]
+    fn diff_identical() {
+        let context = mock_context(vec![mock_crate("a", "1.0.0", serde_json::json!({}))]);
+
+        let report = LockfileDiff::new(&context, &context);
+
+        assert_eq!(report, LockfileDiff::default());
+        assert_eq!(report.to_string(), "No changes.\n");
+    }
+
+    #[test]
+    fn diff_added_removed_and_version_changes() {
+        let old = mock_context(vec![
+            mock_crate("a", "1.0.0", serde_json::json!({})),
+            mock_crate("b", "1.2.0", serde_json::json!({})),
+            mock_crate("c", "0.1.0", serde_json::json!({})),
+            mock_crate("rand", "0.7.3", serde_json::json!({})),
+        ]);
+        let new = mock_context(vec![
+            mock_crate("a", "1.1.0", serde_json::json!({})),
+            mock_crate("b", "1.1.0", serde_json::json!({})),
+            mock_crate("d", "0.1.0", serde_json::json!({})),
+            mock_crate("rand", "0.9.0", serde_json::json!({})),
+        ]);
+
+        let report = LockfileDiff::new(&old, &new);
+
+        assert_eq!(
+            report.added,
+            BTreeSet::from([CrateId::new("d".to_owned(), Version::new(0, 1, 0))])
+        );
+        assert_eq!(
+            report.removed,
+            BTreeSet::from([CrateId::new("c".to_owned(), Version::new(0, 1, 0))])
+        );
+        assert_eq!(
+            report.upgraded,
+            vec![
+                VersionChange {
+                    name: "a".to_owned(),
+                    old: Version::new(1, 0, 0),
+                    new: Version::new(1, 1, 0),
+                },
+                VersionChange {
+                    name: "rand".to_owned(),
+                    old: Version::new(0, 7, 3),
+                    new: Version::new(0, 9, 0),
+                },
+            ]
+        );
+        assert_eq!(
+            report.downgraded,
+            vec![VersionChange {
+                name: "b".to_owned(),
+                old: Version::new(1, 2, 0),
+                new: Version::new(1, 1, 0),
+            }]
+        );
+        assert!(report.changed.is_empty());
+    }
+
+    #[test]
+    fn diff_version_changes_across_compatibility_groups() {
+        let old = mock_context(vec![
+            mock_crate("rand", "0.8.5", serde_json::json!({})),
+            mock_crate("syn", "1.0.109", serde_json::json!({})),
+            mock_crate("syn", "2.0.0", serde_json::json!({})),
+            mock_crate("windows-sys", "0.52.0", serde_json::json!({})),
+            mock_crate("windows-sys", "0.48.0", serde_json::json!({})),
+        ]);
+        let new = mock_context(vec![
+            mock_crate("rand", "0.9.0", serde_json::json!({})),
+            mock_crate("syn", "2.0.0", serde_json::json!({})),
+            mock_crate("syn", "0.15.44", serde_json::json!({})),
+            mock_crate("windows-sys", "0.59.0", serde_json::json!({})),
+        ]);
+
+        let report = LockfileDiff::new(&old, &new);
+
+        // Single versions of a name on either side are paired across groups.
+        assert_eq!(
+            report.upgraded,
+            vec![VersionChange {
+                name: "rand".to_owned(),
+                old: Version::new(0, 8, 5),
+                new: Version::new(0, 9, 0),
+            }]
+        );
+        assert_eq!(
+            report.downgraded,
+            vec![VersionChange {
+                name: "syn".to_owned(),
+                old: Version::new(1, 0, 109),
+                new: Version::new(0, 15, 44),
+            }]
+        );
+
+        // Multiple leftover versions are ambiguous, so they're added and removed.
+        assert_eq!(
+            report.added,
+            BTreeSet::from([CrateId::new(
+                "windows-sys".to_owned(),
+                Version::new(0, 59, 0)
+            )])
+        );
+        assert_eq!(
+            report.removed,
+            BTreeSet::from([
+                CrateId::new("windows-sys".to_owned(), Version::new(0, 48, 0)),
+                CrateId::new("windows-sys".to_owned(), Version::new(0, 52, 0)),
+            ])
+        );
+    }
+
+    #[test]
+    fn diff_attributes_per_configuration() {
+        let old = mock_context(vec![mock_crate(
+            "a",
+            "1.0.0",
+            serde_json::json!({
+                "crate_features": {
+                    "common": ["default"],
+                    "selects": {
+                        "x86_64-unknown-linux-gnu": ["linux"],
+                    },
+                },
+                "rustc_flags": ["--cfg=old"],
+            }),
+        )]);
+        let new = mock_context(vec![mock_crate(
+            "a",
+            "1.0.0",
+            serde_json::json!({
+                "crate_features": {
+                    "common": ["default", "std"],
+                    "selects": {},
+                },
+                "rustc_flags": ["--cfg=old"],
+            }),
+        )]);
+
+        let report = LockfileDiff::new(&old, &new);
+
+        assert_eq!(report.changed.len(), 1);
+        let changes = &report.changed[0];
+        assert_eq!(
+            changes.attributes.keys().collect::<Vec<_>>(),
+            vec!["crate_features"]
+        );
+        assert_eq!(
+            changes.attributes["crate_features"],
+            BTreeMap::from([
+                (
+                    "common".to_owned(),
+                    ValuesDiff {
+                        added: BTreeSet::from(["std".to_owned()]),
+                        removed: BTreeSet::new(),
+                    }
+                ),
+                (
+                    "x86_64-unknown-linux-gnu".to_owned(),
+                    ValuesDiff {
+                        added: BTreeSet::new(),
+                        removed: BTreeSet::from(["linux".to_owned()]),
+                    }
+                ),
+            ])
+        );
+
+        assert_eq!(
+            report.to_string(),
+            indoc::indoc! {r#"
+                Changed crates:
+                  a 1.0.0
+                    crate_features:
+                      common:
+                        + std
+                      x86_64-unknown-linux-gnu:
+                        - linux
+            "#}
+        );
+    }
+}
@@ -10,9 +10,11 @@ use camino::Utf8PathBuf;
 use cargo_lock::Lockfile;
 use clap::Parser;
 
+use crate::config::license_policy::LicensePolicy;
+use crate::config::multiple_versions::{MultipleVersionsLevel, MultipleVersionsPolicy};
 use crate::config::Config;
 use crate::context::Context;
-use crate::lockfile::{lock_context, write_lockfile};
+use crate::lockfile::{lock_context, lockfile_shard, write_lockfile};
 use crate::metadata::{load_metadata, Annotations, Cargo, SourceAnnotation};
 use crate::rendering::{write_outputs, Renderer};
 use crate::splicing::SplicingManifest;
@@ -118,6 +120,11 @@ pub struct GenerateOptions {
     /// in other lockfiles where the cargo lockfile's sha is stored.
     #[clap(long)]
     pub strip_internal_dependencies_from_cargo_lockfile: bool,
+
+    /// Whether to write each crate in the lockfile to its own file, so changes to
+    /// unrelated crates do not conflict with one another.
+    #[clap(long)]
+    pub shard_lockfile: bool,
 }
 
 pub fn generate(opt: GenerateOptions) -> Result<()> {
@@ -125,7 +132,7 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
     // apparent -> canonical map reflecting the root module's current
     // `single_version_override` / `multiple_version_override` choices. It is
     // applied to the Context just before rendering and is sanitized out of
-    // the digest hash in `Digest::new`, so consumer-side overrides don't
+    // the digest hash in `DigestComponents::new`, so consumer-side overrides don't
     // force a producer-side repin (the producer's lockfile may live in a
     // read-only bzlmod cache).
     let config = Config::try_from_path(&opt.config)?;
@@ -156,12 +163,25 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
             write_paths_to_track(
                 &opt.paths_to_track,
                 &opt.warnings_output_path,
-                splicing_manifest.manifests.keys().cloned(),
+                splicing_manifest
+                    .manifests
+                    .keys()
+                    .cloned()
+                    .chain(lockfile_shards_to_track(
+                        opt.lockfile.as_deref(),
+                        opt.shard_lockfile,
+                        &context,
+                    )),
                 context
                     .crates
                     .values()
                     .filter_map(|crate_context| crate_context.repository.as_ref()),
                 context.unused_patches.iter(),
+                policy_warnings(
+                    config.license_policy.as_ref(),
+                    config.multiple_versions.as_ref(),
+                    &context,
+                )?,
                 &opt.nonhermetic_root_bazel_workspace_dir,
             )?;
 
@@ -202,31 +222,40 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
     }
     let (cargo_metadata, cargo_lockfile) = load_metadata(metadata_path, &lockfile_path)?;
 
-    // Annotate metadata
-    let annotations = Annotations::new(
+    let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
+
+    // Generate renderable contexts for each package. The Context here holds
+    // the user's APPARENT labels (e.g. `@openssl//:install`) because the
+    // label_injection mapping was detached at config load.
+    let (context, policy_warnings) = generate_context(
         cargo_metadata,
         &Some(lockfile_path),
         cargo_lockfile.clone(),
-        config.clone(),
+        &config,
         &opt.nonhermetic_root_bazel_workspace_dir,
     )?;
 
-    let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
-
     write_paths_to_track(
         &opt.paths_to_track,
         &opt.warnings_output_path,
-        splicing_manifest.manifests.keys().cloned(),
-        annotations.lockfile.crates.values(),
-        cargo_lockfile.patch.unused.iter(),
+        splicing_manifest
+            .manifests
+            .keys()
+            .cloned()
+            .chain(lockfile_shards_to_track(
+                opt.lockfile.as_deref(),
+                opt.shard_lockfile,
+                &context,
+            )),
+        context
+            .crates
+            .values()
+            .filter_map(|crate_context| crate_context.repository.as_ref()),
+        context.unused_patches.iter(),
+        policy_warnings,
         &opt.nonhermetic_root_bazel_workspace_dir,
     )?;
 
-    // Generate renderable contexts for each package. The Context here holds
-    // the user's APPARENT labels (e.g. `@openssl//:install`) because the
-    // label_injection mapping was detached at config load.
-    let context = Context::new(annotations, config.rendering.are_sources_present())?;
-
     // Render build files. Apply the apparent -> canonical mapping just for
     // rendering — the lockfile written below uses the original apparent
     // Context, so consumer-side overrides stay sound without producer-side
@@ -247,14 +276,6 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
         opt.dry_run,
     )?;
 
-    // Ensure Bazel lockfiles are written to disk so future generations can be short-circuited.
-    if let Some(lockfile) = opt.lockfile {
-        let lock_content =
-            lock_context(context, &config, &splicing_manifest, &cargo_bin, rustc_bin)?;
-
-        write_lockfile(lock_content, &lockfile, opt.dry_run)?;
-    }
-
     if !opt.skip_cargo_lockfile_overwrite {
         let cargo_lockfile_to_write = if opt.strip_internal_dependencies_from_cargo_lockfile {
             remove_internal_dependencies_from_cargo_lockfile(cargo_lockfile)
@@ -264,10 +285,27 @@ pub fn generate(opt: GenerateOptions) -> Result<()> {
         update_cargo_lockfile(&opt.cargo_lockfile, cargo_lockfile_to_write)?;
     }
 
+    // Ensure Bazel lockfiles are written to disk so future generations can be short-circuited.
+    // This happens after `Cargo.lock` is updated so the lockfile records its final contents.
+    if let Some(lockfile) = opt.lockfile {
+        let lock_content = lock_context(
+            context,
+            &config,
+            &splicing_manifest,
+            &cargo_bin,
+            rustc_bin,
+            Some(&opt.cargo_lockfile),
+        )?;
+
+        write_lockfile(lock_content, &lockfile, opt.shard_lockfile, opt.dry_run)?;
+    }
+
     Ok(())
 }
 
-fn remove_internal_dependencies_from_cargo_lockfile(cargo_lockfile: Lockfile) -> Lockfile {
+pub(crate) fn remove_internal_dependencies_from_cargo_lockfile(
+    cargo_lockfile: Lockfile,
+) -> Lockfile {
     let filtered_packages: Vec<_> = cargo_lockfile
         .packages
         .into_iter()
@@ -281,7 +319,7 @@ fn remove_internal_dependencies_from_cargo_lockfile(cargo_lockfile: Lockfile) ->
     }
 }
 
-fn update_cargo_lockfile(path: &Path, cargo_lockfile: Lockfile) -> Result<()> {
+pub(crate) fn update_cargo_lockfile(path: &Path, cargo_lockfile: Lockfile) -> Result<()> {
     let old_contents = fs::read_to_string(path).ok();
     let new_contents = cargo_lockfile.to_string();
 
@@ -305,7 +343,7 @@ fn render_and_write_outputs(
     dry_run: bool,
 ) -> Result<()> {
     let rendered = renderer.render_hub(context, generator)?;
-    let normalized_outputs = normalize_cargo_file_paths(rendered.files, repository_dir);
+    let normalized_outputs = normalize_cargo_file_paths(rendered.files, repository_dir, false);
     write_outputs(normalized_outputs, dry_run)?;
     write_hub_packages(hub_packages_output_path, &rendered.hub_packages)?;
     Ok(())
@@ -320,6 +358,78 @@ fn write_hub_packages(output_file: &Path, hub_packages: &[String]) -> Result<()>
     Ok(())
 }
 
+/// Annotate the metadata of a spliced workspace and generate the context of every package.
+/// Violations of `warn`-level policies are returned alongside it.
+pub(crate) fn generate_context(
+    cargo_metadata: cargo_metadata::Metadata,
+    cargo_lockfile_path: &Option<PathBuf>,
+    cargo_lockfile: Lockfile,
+    config: &Config,
+    nonhermetic_root_bazel_workspace_dir: &Utf8PathBuf,
+) -> Result<(Context, Vec<String>)> {
+    let annotations = Annotations::new(
+        cargo_metadata,
+        cargo_lockfile_path,
+        cargo_lockfile,
+        config.clone(),
+        nonhermetic_root_bazel_workspace_dir,
+    )?;
+
+    let context = Context::new(annotations, config.rendering.are_sources_present())?;
+    let warnings = policy_warnings(
+        config.license_policy.as_ref(),
+        config.multiple_versions.as_ref(),
+        &context,
+    )?;
+
+    Ok((context, warnings))
+}
+
+/// Violations of a `warn_only` license policy and duplicate crates under the
+/// `warn` multiple versions level. Enforced policies are errors raised by [Context::new].
+fn policy_warnings(
+    license_policy: Option<&LicensePolicy>,
+    multiple_versions: Option<&MultipleVersionsPolicy>,
+    context: &Context,
+) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    if let Some(policy) = license_policy.filter(|policy| policy.warn_only) {
+        warnings.extend(
+            policy
+                .violations(context)?
+                .into_iter()
+                .map(|violation| format!("License policy violation: {violation}")),
+        );
+    }
+    if let Some(policy) =
+        multiple_versions.filter(|policy| policy.level == MultipleVersionsLevel::Warn)
+    {
+        warnings.extend(
+            policy
+                .duplicates(context)
+                .into_iter()
+                .map(|duplicate| format!("Multiple versions: {duplicate}")),
+        );
+    }
+    Ok(warnings)
+}
+
+/// The files of a sharded lockfile, which must be tracked alongside the lockfile itself.
+fn lockfile_shards_to_track(
+    lockfile: Option<&Path>,
+    sharded: bool,
+    context: &Context,
+) -> Vec<Utf8PathBuf> {
+    match lockfile {
+        Some(lockfile) if sharded => context
+            .crates
+            .keys()
+            .filter_map(|id| Utf8PathBuf::from_path_buf(lockfile_shard(lockfile, id)).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 fn write_paths_to_track<
     'a,
     SourceAnnotations: Iterator<Item = &'a SourceAnnotation>,
@@ -331,6 +441,7 @@ fn write_paths_to_track<
     manifests: Paths,
     source_annotations: SourceAnnotations,
     unused_patches: UnusedPatches,
+    policy_warnings: Vec<String>,
     nonhermetic_root_bazel_workspace_dir: &Utf8PathBuf,
 ) -> Result<()> {
     let source_annotation_manifests: BTreeSet<_> = source_annotations
@@ -362,6 +473,7 @@ fn write_paths_to_track<
     for unused_patch in unused_patches {
         warnings.push(format!("You have a [patch] Cargo.toml entry that is being ignored by cargo. Unused patch: {} {}{}", unused_patch.name, unused_patch.version, if let Some(source) = unused_patch.source.as_ref() { format!(" ({})", source) } else { String::new() }));
     }
+    warnings.extend(policy_warnings);
 
     std::fs::write(
         warnings_output_path,
@@ -0,0 +1,385 @@
+//! The cli entrypoint for the `outdated` subcommand
+
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{Context as AnyhowContext, Result};
+use clap::Parser;
+use semver::{Comparator, Op, Version, VersionReq};
+use serde::Serialize;
+
+use crate::config::CrateId;
+use crate::context::Context;
+use crate::metadata::SourceAnnotation;
+use crate::splicing::CrateIndexLookup;
+use crate::utils;
+
+/// Command line options for the `outdated` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `outdated` subcommand", version)]
+pub struct OutdatedOptions {
+    /// The path to a cargo-bazel lockfile
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The path to a local mirror of the registry index. This is either a git checkout of
+    /// the index or a sparse index cache as found in `$CARGO_HOME/registry/index`.
+    #[clap(long)]
+    pub index: PathBuf,
+
+    /// The url of the registry index which is mirrored by `--index`. Only crates
+    /// downloaded from this registry are checked.
+    #[clap(long, default_value = utils::CRATES_IO_INDEX_URL)]
+    pub index_url: String,
+
+    /// If true, the report will be printed as JSON instead of a table.
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Report crates in a lockfile which have newer versions or are yanked in a local
+/// mirror of the registry index. The network is never accessed.
+pub fn outdated(opt: OutdatedOptions) -> Result<()> {
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let index = CrateIndexLookup::from_local_mirror(&opt.index, &opt.index_url)
+        .with_context(|| format!("Failed to load index `{}`", opt.index.display()))?;
+
+    let report = OutdatedReport::new(&context, &index)?;
+
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{report}");
+    }
+
+    Ok(())
+}
+
+/// The newer versions of a locked crate.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct OutdatedCrate {
+    name: String,
+
+    /// The locked version of the crate.
+    version: Version,
+
+    /// The newest version which is semver compatible with the locked version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compatible: Option<Version>,
+
+    /// The newest version which is not semver compatible with the locked version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    incompatible: Option<Version>,
+
+    /// Whether the locked version has been yanked.
+    yanked: bool,
+}
+
+impl OutdatedCrate {
+    /// Compare the locked crate `id` to the versions of the crate published in the index.
+    /// Returns `None` if the locked version is not in the index.
+    fn new(id: &CrateId, krate: &crates_index::Crate) -> Option<Self> {
+        let yanked = krate
+            .versions()
+            .iter()
+            .find(|version| Version::parse(version.version()).ok().as_ref() == Some(&id.version))?
+            .is_yanked();
+
+        let requirement = VersionReq {
+            comparators: vec![Comparator {
+                op: Op::Caret,
+                major: id.version.major,
+                minor: Some(id.version.minor),
+                patch: Some(id.version.patch),
+                pre: id.version.pre.clone(),
+            }],
+        };
+
+        // Pre-releases are only suggested to crates which are already locked to one.
+        let newer: Vec<Version> = krate
+            .versions()
+            .iter()
+            .filter(|version| !version.is_yanked())
+            .filter_map(|version| Version::parse(version.version()).ok())
+            .filter(|version| version > &id.version)
+            .filter(|version| version.pre.is_empty() || !id.version.pre.is_empty())
+            .collect();
+
+        Some(Self {
+            name: id.name.clone(),
+            version: id.version.clone(),
+            compatible: newer
+                .iter()
+                .filter(|version| requirement.matches(version))
+                .max()
+                .cloned(),
+            incompatible: newer
+                .iter()
+                .filter(|version| !requirement.matches(version))
+                .max()
+                .cloned(),
+            yanked,
+        })
+    }
+
+    fn is_outdated(&self) -> bool {
+        self.yanked || self.compatible.is_some() || self.incompatible.is_some()
+    }
+}
+
+/// The crates of a lockfile which are outdated or yanked.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+struct OutdatedReport {
+    crates: Vec<OutdatedCrate>,
+
+    /// Crates whose locked version could not be found in the index.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing: Vec<CrateId>,
+}
+
+impl OutdatedReport {
+    fn new(context: &Context, index: &CrateIndexLookup) -> Result<Self> {
+        let mut report = Self::default();
+
+        // Only crates downloaded from the registry are published in its index. The lockfile
+        // doesn't record the registry of a crate, so it's identified by the download url.
+        let registry_crates =
+            context
+                .crates
+                .iter()
+                .filter(|(id, crate_context)| match &crate_context.repository {
+                    Some(SourceAnnotation::Http { url, .. }) => {
+                        index
+                            .download_url(&id.name, &id.version.to_string())
+                            .as_ref()
+                            == Some(url)
+                    }
+                    _ => false,
+                });
+
+        for (id, _) in registry_crates {
+            let outdated = index
+                .get_crate(&id.name)
+                .with_context(|| format!("Failed to read `{}` from the index", id.name))?
+                .and_then(|krate| OutdatedCrate::new(id, &krate));
+            match outdated {
+                Some(outdated) if outdated.is_outdated() => report.crates.push(outdated),
+                Some(_) => {}
+                None => report.missing.push(id.clone()),
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl fmt::Display for OutdatedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.crates.is_empty() {
+            writeln!(f, "All crates are up to date.")?;
+        } else {
+            let optional = |version: &Option<Version>| match version {
+                Some(version) => version.to_string(),
+                None => "-".to_owned(),
+            };
+            let rows: Vec<[String; 5]> = std::iter::once(
+                ["Name", "Locked", "Compatible", "Latest", "Yanked"].map(str::to_owned),
+            )
+            .chain(self.crates.iter().map(|krate| {
+                [
+                    krate.name.clone(),
+                    krate.version.to_string(),
+                    optional(&krate.compatible),
+                    optional(&krate.incompatible),
+                    if krate.yanked { "yes" } else { "no" }.to_owned(),
+                ]
+            }))
+            .collect();
+
+            let mut widths = [0; 5];
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+
+            for row in &rows {
+                let line = row
+                    .iter()
+                    .zip(widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                writeln!(f, "{}", line.trim_end())?;
+            }
+        }
+
+        if !self.missing.is_empty() {
+            writeln!(f, "\nNot found in the index:")?;
+            for id in &self.missing {
+                writeln!(f, "  {id}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_crate() -> crates_index::Crate {
+        let version = |version: &str, yanked: bool| {
+            format!(
+                r#"{{"name":"mock","vers":"{version}","deps":[],"cksum":"{}","features":{{}},"yanked":{yanked}}}"#,
+                "0".repeat(64)
+            )
+        };
+        let lines = [
+            version("1.0.0", false),
+            version("1.1.0", true),
+            version("1.2.0", false),
+            version("1.3.0", true),
+            version("2.0.0", false),
+            version("3.0.0-alpha.1", false),
+        ];
+        crates_index::Crate::from_slice(lines.join("\n").as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn outdated_crate_versions() {
+        let id = CrateId::new("mock".to_owned(), Version::parse("1.1.0").unwrap());
+
+        assert_eq!(
+            OutdatedCrate::new(&id, &mock_crate()),
+            Some(OutdatedCrate {
+                name: "mock".to_owned(),
+                version: Version::parse("1.1.0").unwrap(),
+                compatible: Some(Version::parse("1.2.0").unwrap()),
+                incompatible: Some(Version::parse("2.0.0").unwrap()),
+                yanked: true,
+            })
+        );
+
+        let id = CrateId::new("mock".to_owned(), Version::parse("2.0.0").unwrap());
+        assert!(!OutdatedCrate::new(&id, &mock_crate())
+            .unwrap()
+            .is_outdated());
+
+        let id = CrateId::new("mock".to_owned(), Version::parse("1.4.0").unwrap());
+        assert_eq!(OutdatedCrate::new(&id, &mock_crate()), None);
+    }
+
+    #[test]
+    fn outdated_crate_from_local_mirror() {
+        let index = CrateIndexLookup::from_local_mirror(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(
+                "test_data/crate_indexes/lazy_static/cargo_home/registry/index/index.crates.io-6f17d22bba15001f",
+            ),
+            "sparse+https://index.crates.io/",
+        )
+        .unwrap();
+        let krate = index.get_crate("lazy_static").unwrap().unwrap();
+
+        let id = CrateId::new("lazy_static".to_owned(), Version::parse("0.2.1").unwrap());
+        let outdated = OutdatedCrate::new(&id, &krate).unwrap();
+
+        assert_eq!(outdated.compatible, Some(Version::parse("0.2.11").unwrap()));
+        assert_eq!(
+            outdated.incompatible,
+            Some(Version::parse("1.4.0").unwrap())
+        );
+        assert!(!outdated.yanked);
+    }
+
+    fn mock_context() -> Context {
+        let krate = |name: &str, version: &str, url: &str| {
+            serde_json::json!({
+                "name": name,
+                "version": version,
+                "license_ids": [],
+                "repository": {
+                    "Http": {
+                        "url": url,
+                    },
+                },
+            })
+        };
+        serde_json::from_value(serde_json::json!({
+            "checksum": null,
+            "crates": {
+                "lazy_static 0.2.1": krate(
+                    "lazy_static",
+                    "0.2.1",
+                    "https://crates.io/api/v1/crates/lazy_static/0.2.1/download",
+                ),
+                "missing 1.0.0": krate(
+                    "missing",
+                    "1.0.0",
+                    "https://crates.io/api/v1/crates/missing/1.0.0/download",
+                ),
+                "private 1.0.0": krate(
+                    "private",
+                    "1.0.0",
+                    "https://example.com/crates/private/1.0.0/download",
+                ),
+            },
+            "binary_crates": [],
+            "workspace_members": {},
+            "conditions": {},
+            "direct_deps": [],
+            "direct_dev_deps": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn outdated_report_only_checks_crates_from_the_index() {
+        let index = CrateIndexLookup::from_local_mirror(
+            &PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(
+                "test_data/crate_indexes/lazy_static/cargo_home/registry/index/index.crates.io-6f17d22bba15001f",
+            ),
+            "sparse+https://index.crates.io/",
+        )
+        .unwrap();
+
+        let report = OutdatedReport::new(&mock_context(), &index).unwrap();
+
+        assert_eq!(
+            report
+                .crates
+                .iter()
+                .map(|krate| krate.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["lazy_static"]
+        );
+        assert_eq!(
+            report.missing,
+            vec![CrateId::new(
+                "missing".to_owned(),
+                Version::parse("1.0.0").unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn outdated_report_propagates_index_errors() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("outdated_report_propagates_index_errors");
+        let mirror = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(
+            "test_data/crate_indexes/lazy_static/cargo_home/registry/index/index.crates.io-6f17d22bba15001f",
+        );
+        std::fs::copy(mirror.join("config.json"), tempdir.join("config.json")).unwrap();
+        std::fs::create_dir_all(tempdir.join(".cache/la/zy")).unwrap();
+        std::fs::write(tempdir.join(".cache/la/zy/lazy_static"), "corrupt").unwrap();
+
+        let index =
+            CrateIndexLookup::from_local_mirror(&tempdir, "sparse+https://index.crates.io/")
+                .unwrap();
+
+        assert!(OutdatedReport::new(&mock_context(), &index).is_err());
+    }
+}
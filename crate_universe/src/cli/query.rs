@@ -1,6 +1,5 @@
 //! The cli entrypoint for the `query` subcommand
 
-use std::fs;
 use std::path::PathBuf;
 
 use anyhow::{bail, Result};
@@ -8,7 +7,7 @@ use clap::Parser;
 
 use crate::config::Config;
 use crate::context::Context;
-use crate::lockfile::Digest;
+use crate::lockfile::{lockfile_has_merge_conflicts, DigestComponents};
 use crate::metadata::Cargo;
 use crate::splicing::SplicingManifest;
 
@@ -35,13 +34,24 @@ pub struct QueryOptions {
     /// The path to a rustc binary for use with Cargo
     #[clap(long, env = "RUSTC")]
     pub rustc: PathBuf,
+
+    /// The path to the workspace's `Cargo.lock` file
+    #[clap(long)]
+    pub cargo_lockfile: Option<PathBuf>,
 }
 
 /// Determine if the current lockfile needs to be re-pinned
 pub fn query(opt: QueryOptions) -> Result<()> {
-    // Read the lockfile
-    let content = match fs::read_to_string(&opt.lockfile) {
-        Ok(c) => c,
+    if lockfile_has_merge_conflicts(&opt.lockfile)? {
+        bail!(
+            "The lockfile `{}` contains merge conflicts. Run `cargo-bazel resolve-conflicts` or repin with `CARGO_BAZEL_REPIN=true` to resolve them.",
+            opt.lockfile.display()
+        );
+    }
+
+    // Read the lockfile, which may be sharded, so we can easily compare it with
+    let lockfile = match Context::try_from_path(&opt.lockfile) {
+        Ok(ctx) => ctx,
         Err(e) => bail!(
             "Unable to read lockfile `{}`\n{:?}",
             opt.lockfile.display(),
@@ -49,12 +59,6 @@ pub fn query(opt: QueryOptions) -> Result<()> {
         ),
     };
 
-    // Deserialize it so we can easily compare it with
-    let lockfile: Context = match serde_json::from_str(&content) {
-        Ok(ctx) => ctx,
-        Err(_) => bail!("Could not load lockfile"),
-    };
-
     // Check to see if a digest has been set
     let digest = match &lockfile.checksum {
         Some(d) => d.clone(),
@@ -62,7 +66,7 @@ pub fn query(opt: QueryOptions) -> Result<()> {
     };
 
     // Load the config file. `config.label_injection_mapping` is populated
-    // but unused for the digest check — `Digest::new` sanitizes it out
+    // but unused for the digest check — `DigestComponents::new` sanitizes it out
     // before hashing so this comparison stays stable across consumer-side
     // overrides (e.g., `single_version_override` on an injected dep);
     // otherwise every override would force a producer-side repin.
@@ -71,16 +75,37 @@ pub fn query(opt: QueryOptions) -> Result<()> {
     let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)?;
 
     // Generate a new digest so we can compare it with the one in the lockfile
-    let expected = Digest::new(
+    let components = DigestComponents::new(
         &lockfile,
         &config,
         &splicing_manifest,
         &Cargo::new(opt.cargo, opt.rustc.clone()),
         &opt.rustc,
+        opt.cargo_lockfile.as_deref(),
     )?;
+    let expected = components.digest();
 
     if digest != expected {
-        bail!("Digests do not match: Current {digest:?} != Expected {expected:?}");
+        // Lockfiles generated by older versions of `cargo-bazel` do not record
+        // which inputs contributed to the digest.
+        let changes = lockfile
+            .checksum_components
+            .as_ref()
+            .map(|recorded| recorded.explain_changes(&components))
+            .unwrap_or_default();
+
+        if changes.is_empty() {
+            bail!("Digests do not match: Current {digest:?} != Expected {expected:?}");
+        }
+
+        bail!(
+            "Digests do not match: Current {digest:?} != Expected {expected:?}\n{}",
+            changes
+                .iter()
+                .map(|change| format!("  - {change}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
     }
 
     // There is no need to repin
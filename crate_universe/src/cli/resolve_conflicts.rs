@@ -0,0 +1,177 @@
+//! The cli entrypoint for the `resolve-conflicts` subcommand
+
+use std::path::PathBuf;
+
+use anyhow::{Context as AnyhowContext, Result};
+use camino::Utf8PathBuf;
+use clap::Parser;
+
+use tracing::warn;
+
+use crate::cli::generate::{
+    generate_context, remove_internal_dependencies_from_cargo_lockfile, update_cargo_lockfile,
+};
+use crate::cli::splice::write_workspace_metadata;
+use crate::config::Config;
+use crate::lockfile::{lock_context, lockfile_has_merge_conflicts, write_lockfile};
+use crate::metadata::{Cargo, CargoUpdateRequest};
+use crate::splicing::{generate_lockfile, Splicer, SplicingManifest};
+use crate::utils::merge_conflicts::file_has_merge_conflicts;
+
+/// Command line options for the `resolve-conflicts` subcommand
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Command line options for the `resolve-conflicts` subcommand",
+    version
+)]
+pub struct ResolveConflictsOptions {
+    /// The path to a Cargo binary to use for gathering metadata
+    #[clap(long, env = "CARGO")]
+    pub cargo: PathBuf,
+
+    /// The path to a rustc binary for use with Cargo
+    #[clap(long, env = "RUSTC")]
+    pub rustc: PathBuf,
+
+    /// A directory in which to cache the results of `cargo tree` across runs. Entries are
+    /// keyed by the contents of every input to `cargo tree`, so stale results are never used.
+    #[clap(long, env = "CARGO_BAZEL_CARGO_TREE_CACHE_DIR")]
+    pub cargo_tree_cache_dir: Option<PathBuf>,
+
+    /// The config file with information about the Bazel and Cargo workspace
+    #[clap(long)]
+    pub config: PathBuf,
+
+    /// A generated manifest of splicing inputs
+    #[clap(long)]
+    pub splicing_manifest: PathBuf,
+
+    /// The path to the [Cargo.lock](https://doc.rust-lang.org/cargo/guide/cargo-toml-vs-cargo-lock.html)
+    /// file which may contain merge conflicts.
+    #[clap(long)]
+    pub cargo_lockfile: PathBuf,
+
+    /// The path to the Bazel lockfile which may contain merge conflicts.
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// Whether to write each crate in the Bazel lockfile to its own file.
+    #[clap(long)]
+    pub shard_lockfile: bool,
+
+    /// Whether to skip writing the merged cargo lockfile back to the workspace.
+    #[clap(long)]
+    pub skip_cargo_lockfile_overwrite: bool,
+
+    /// Whether to strip internal dependencies from the merged cargo lockfile.
+    #[clap(long)]
+    pub strip_internal_dependencies_from_cargo_lockfile: bool,
+
+    /// The path to the Bazel root workspace (i.e. the directory containing the WORKSPACE.bazel file or similar).
+    /// BE CAREFUL with this value. We never want to include it in a lockfile hash (to keep lockfiles portable),
+    /// which means you also should not use it anywhere that _should_ be guarded by a lockfile hash.
+    /// You basically never want to use this value.
+    #[clap(long)]
+    pub nonhermetic_root_bazel_workspace_dir: Utf8PathBuf,
+}
+
+/// Resolve git merge conflicts in `Cargo.lock` and the Bazel lockfile.
+///
+/// Like Cargo, the union of the packages locked on both sides of `Cargo.lock` is
+/// minimally updated with `cargo update --workspace`, keeping the upgrades made on
+/// either side. The Bazel lockfile is then regenerated from the result.
+pub fn resolve_conflicts(opt: ResolveConflictsOptions) -> Result<()> {
+    if !file_has_merge_conflicts(&opt.cargo_lockfile)?
+        && !lockfile_has_merge_conflicts(&opt.lockfile)?
+    {
+        println!("No merge conflicts found.");
+        return Ok(());
+    }
+
+    let splicing_manifest = SplicingManifest::try_from_path(&opt.splicing_manifest)
+        .context("Failed to parse splicing manifest")?;
+
+    let temp_dir = tempfile::tempdir().context("Failed to create temporary directory")?;
+    let temp_dir_path = Utf8PathBuf::from_path_buf(temp_dir.as_ref().to_path_buf())
+        .unwrap_or_else(|path| panic!("Temporary directory wasn't valid UTF-8: {:?}", path));
+
+    // Splice together the manifest
+    let manifest_path = Splicer::new(temp_dir_path, splicing_manifest.clone())
+        .context("Failed to create splicer")?
+        .splice_workspace(&opt.nonhermetic_root_bazel_workspace_dir)
+        .context("Failed to splice workspace")?;
+
+    let cargo = Cargo::new(opt.cargo, opt.rustc.clone());
+
+    // Conflicts in `Cargo.lock` are merged while generating the lockfile.
+    let cargo_lockfile = generate_lockfile(
+        &manifest_path,
+        &Some(opt.cargo_lockfile.clone()),
+        cargo.clone(),
+        &Some(CargoUpdateRequest::Workspace),
+        true,
+    )
+    .context("Failed to generate lockfile")?;
+
+    let config = Config::try_from_path(&opt.config).context("Failed to parse config")?;
+
+    write_workspace_metadata(
+        &cargo,
+        &manifest_path,
+        &cargo_lockfile,
+        &config,
+        &splicing_manifest.custom_targets,
+        splicing_manifest
+            .custom_target_cfgs(&opt.rustc)
+            .context("Failed to determine custom target cfgs")?,
+        opt.cargo_tree_cache_dir.clone(),
+    )?;
+
+    let cargo_metadata = cargo
+        .metadata_command_with_options(
+            manifest_path.as_path_buf().as_ref(),
+            vec!["--locked".to_owned()],
+        )?
+        .exec()
+        .context("Failed to generate cargo metadata")?;
+
+    let (context, policy_warnings) = generate_context(
+        cargo_metadata,
+        &Some(opt.cargo_lockfile.clone()),
+        cargo_lockfile.clone(),
+        &config,
+        &opt.nonhermetic_root_bazel_workspace_dir,
+    )?;
+    for warning in policy_warnings {
+        warn!("{warning}");
+    }
+
+    // As with `generate`, `Cargo.lock` is written before the Bazel lockfile so the
+    // latter records its final contents.
+    if !opt.skip_cargo_lockfile_overwrite {
+        let cargo_lockfile_to_write = if opt.strip_internal_dependencies_from_cargo_lockfile {
+            remove_internal_dependencies_from_cargo_lockfile(cargo_lockfile)
+        } else {
+            cargo_lockfile
+        };
+        update_cargo_lockfile(&opt.cargo_lockfile, cargo_lockfile_to_write)?;
+    }
+
+    let lock_content = lock_context(
+        context,
+        &config,
+        &splicing_manifest,
+        &cargo,
+        &opt.rustc,
+        Some(&opt.cargo_lockfile),
+    )?;
+    write_lockfile(lock_content, &opt.lockfile, opt.shard_lockfile, false)?;
+
+    println!(
+        "Resolved merge conflicts in {} and {}.",
+        opt.cargo_lockfile.display(),
+        opt.lockfile.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,738 @@
+//! The cli entrypoint for the `sbom` subcommand
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as AnyhowContext, Result};
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+
+use crate::config::{Commitish, CrateId};
+use crate::context::{Context, CrateContext};
+use crate::metadata::SourceAnnotation;
+
+/// Command line options for the `sbom` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `sbom` subcommand", version)]
+pub struct SbomOptions {
+    /// The path to a cargo-bazel lockfile
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The format of the generated document
+    #[clap(long, value_enum, default_value_t = SbomFormat::Cyclonedx)]
+    pub format: SbomFormat,
+
+    /// The name of the generated document
+    #[clap(long, default_value = "crate_universe")]
+    pub name: String,
+
+    /// The creation time of the document in seconds since the Unix epoch. Defaults to the current time.
+    #[clap(long, env = "SOURCE_DATE_EPOCH")]
+    pub timestamp: Option<u64>,
+
+    /// The path to write the document to. If unset, the document is printed to stdout.
+    #[clap(long)]
+    pub output: Option<PathBuf>,
+
+    /// Whether to include the dev dependencies of workspace members, which are never
+    /// part of a shipped artifact.
+    #[clap(long)]
+    pub include_dev: bool,
+}
+
+/// The supported Software Bill of Materials formats.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// [CycloneDX 1.5](https://cyclonedx.org/docs/1.5/json/) JSON
+    Cyclonedx,
+
+    /// [SPDX 2.3](https://spdx.github.io/spdx-spec/v2.3/) JSON
+    Spdx,
+}
+
+/// Generate a Software Bill of Materials for the crates in a lockfile.
+pub fn sbom(opt: SbomOptions) -> Result<()> {
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let timestamp = match opt.timestamp {
+        Some(timestamp) => timestamp,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    let created = format_timestamp(timestamp);
+
+    let content = match opt.format {
+        SbomFormat::Cyclonedx => serde_json::to_string_pretty(&CycloneDxBom::new(
+            &context,
+            &opt.name,
+            &created,
+            opt.include_dev,
+        ))?,
+        SbomFormat::Spdx => serde_json::to_string_pretty(&SpdxDocument::new(
+            &context,
+            &opt.name,
+            &created,
+            opt.include_dev,
+        ))?,
+    };
+
+    match &opt.output {
+        Some(path) => fs::write(path, content + "\n")
+            .with_context(|| format!("Failed to write file to disk: {}", path.display()))?,
+        None => println!("{content}"),
+    }
+
+    Ok(())
+}
+
+/// The crates a crate depends on to be built. Dev dependencies never end up in a
+/// shipped artifact and are only included if `include_dev` is set.
+fn dependencies(
+    context: &Context,
+    crate_context: &CrateContext,
+    include_dev: bool,
+) -> BTreeSet<CrateId> {
+    let attrs = &crate_context.common_attrs;
+    let mut deps: Vec<_> = attrs
+        .deps
+        .values()
+        .into_iter()
+        .chain(attrs.proc_macro_deps.values())
+        .collect();
+    if include_dev {
+        deps.extend(attrs.deps_dev.values());
+        deps.extend(attrs.proc_macro_deps_dev.values());
+    }
+    if let Some(attrs) = &crate_context.build_script_attrs {
+        deps.extend(attrs.deps.values());
+        deps.extend(attrs.link_deps.values());
+        deps.extend(attrs.proc_macro_deps.values());
+    }
+
+    deps.into_iter()
+        .map(|dep| dep.id)
+        // A crate's library depends on its own build script.
+        .filter(|id| id.name != crate_context.name || id.version != crate_context.version)
+        .filter(|id| context.crates.contains_key(id))
+        .collect()
+}
+
+/// The dependencies of every crate reachable from the workspace members.
+fn dependency_graph(context: &Context, include_dev: bool) -> BTreeMap<&CrateId, BTreeSet<CrateId>> {
+    let mut graph = BTreeMap::new();
+    let mut queue: Vec<&CrateId> = context.workspace_members.keys().collect();
+    while let Some(id) = queue.pop() {
+        if graph.contains_key(id) {
+            continue;
+        }
+        let Some((id, crate_context)) = context.crates.get_key_value(id) else {
+            continue;
+        };
+        let deps = dependencies(context, crate_context, include_dev);
+        queue.extend(
+            deps.iter()
+                .filter_map(|dep| context.crates.get_key_value(dep))
+                .map(|(dep, _)| dep),
+        );
+        graph.insert(id, deps);
+    }
+    graph
+}
+
+/// The [package URL](https://github.com/package-url/purl-spec) identifying a crate.
+fn purl(id: &CrateId) -> String {
+    format!("pkg:cargo/{}@{}", id.name, id.version)
+}
+
+/// Cargo historically allowed `/` as a separator in license expressions.
+fn license_expression(crate_context: &CrateContext) -> Option<String> {
+    crate_context.license.as_ref().map(|license| {
+        license
+            .split('/')
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" OR ")
+    })
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Convert days since the epoch to a civil date. See
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxBom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: CycloneDxMetadata,
+    components: Vec<CycloneDxComponent>,
+    dependencies: Vec<CycloneDxDependency>,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxMetadata {
+    timestamp: String,
+    tools: CycloneDxTools,
+    component: CycloneDxComponent,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxTools {
+    components: Vec<CycloneDxComponent>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxComponent {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref", skip_serializing_if = "Option::is_none")]
+    bom_ref: Option<String>,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<CycloneDxHash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    licenses: Vec<CycloneDxLicense>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<CycloneDxExternalReference>,
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxHash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CycloneDxLicense {
+    Expression(String),
+    License { id: String },
+}
+
+#[derive(Debug, Serialize)]
+struct CycloneDxExternalReference {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDxDependency {
+    #[serde(rename = "ref")]
+    reference: String,
+    depends_on: Vec<String>,
+}
+
+impl CycloneDxBom {
+    fn new(context: &Context, name: &str, created: &str, include_dev: bool) -> Self {
+        let graph = dependency_graph(context, include_dev);
+
+        let components = graph
+            .keys()
+            .map(|id| CycloneDxComponent::new(id, &context.crates[*id]))
+            .collect();
+
+        let dependencies = graph
+            .iter()
+            .map(|(id, deps)| CycloneDxDependency {
+                reference: purl(id),
+                depends_on: deps.iter().map(purl).collect(),
+            })
+            .collect();
+
+        Self {
+            bom_format: "CycloneDX",
+            spec_version: "1.5",
+            version: 1,
+            metadata: CycloneDxMetadata {
+                timestamp: created.to_owned(),
+                tools: CycloneDxTools {
+                    components: vec![CycloneDxComponent {
+                        kind: "application",
+                        bom_ref: None,
+                        name: "cargo-bazel".to_owned(),
+                        version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+                        purl: None,
+                        hashes: Vec::new(),
+                        licenses: Vec::new(),
+                        external_references: Vec::new(),
+                    }],
+                },
+                component: CycloneDxComponent {
+                    kind: "application",
+                    bom_ref: None,
+                    name: name.to_owned(),
+                    version: None,
+                    purl: None,
+                    hashes: Vec::new(),
+                    licenses: Vec::new(),
+                    external_references: Vec::new(),
+                },
+            },
+            components,
+            dependencies,
+        }
+    }
+}
+
+impl CycloneDxComponent {
+    fn new(id: &CrateId, crate_context: &CrateContext) -> Self {
+        let licenses = match license_expression(crate_context) {
+            Some(expression) => vec![CycloneDxLicense::Expression(expression)],
+            None => crate_context
+                .license_ids
+                .iter()
+                .map(|id| CycloneDxLicense::License { id: id.clone() })
+                .collect(),
+        };
+
+        let mut hashes = Vec::new();
+        let mut external_references = Vec::new();
+        match &crate_context.repository {
+            Some(SourceAnnotation::Http { url, sha256, .. }) => {
+                if let Some(sha256) = sha256 {
+                    hashes.push(CycloneDxHash {
+                        alg: "SHA-256",
+                        content: sha256.clone(),
+                    });
+                }
+                external_references.push(CycloneDxExternalReference {
+                    kind: "distribution",
+                    url: url.clone(),
+                });
+            }
+            Some(SourceAnnotation::Git { remote, .. }) => {
+                external_references.push(CycloneDxExternalReference {
+                    kind: "vcs",
+                    url: remote.clone(),
+                });
+            }
+            Some(SourceAnnotation::Path { .. }) | None => {}
+        }
+        if let Some(url) = &crate_context.package_url {
+            external_references.push(CycloneDxExternalReference {
+                kind: "website",
+                url: url.clone(),
+            });
+        }
+
+        Self {
+            kind: "library",
+            bom_ref: Some(purl(id)),
+            name: id.name.clone(),
+            version: Some(id.version.to_string()),
+            purl: Some(purl(id)),
+            hashes,
+            licenses,
+            external_references,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxDocument {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    name: String,
+    version_info: String,
+    download_location: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    homepage: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<SpdxChecksum>,
+    license_concluded: &'static str,
+    license_declared: String,
+    copyright_text: &'static str,
+    files_analyzed: bool,
+    external_refs: Vec<SpdxExternalRef>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxChecksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxRelationship {
+    spdx_element_id: String,
+    relationship_type: &'static str,
+    related_spdx_element: String,
+}
+
+/// The SPDX identifier of a crate. Identifiers may only contain letters, numbers, `.` and `-`.
+fn spdx_id(id: &CrateId) -> String {
+    format!("SPDXRef-Package-{}-{}", id.name, id.version)
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+impl SpdxDocument {
+    fn new(context: &Context, name: &str, created: &str, include_dev: bool) -> Self {
+        let graph = dependency_graph(context, include_dev);
+
+        let packages = graph
+            .keys()
+            .map(|id| SpdxPackage::new(id, &context.crates[*id]))
+            .collect();
+
+        let describes = context.workspace_members.keys().map(|id| SpdxRelationship {
+            spdx_element_id: "SPDXRef-DOCUMENT".to_owned(),
+            relationship_type: "DESCRIBES",
+            related_spdx_element: spdx_id(id),
+        });
+
+        let depends_on = graph.iter().flat_map(|(id, deps)| {
+            deps.iter().map(|dep| SpdxRelationship {
+                spdx_element_id: spdx_id(id),
+                relationship_type: "DEPENDS_ON",
+                related_spdx_element: spdx_id(dep),
+            })
+        });
+
+        // The lockfile checksum uniquely identifies the resolved graph, which keeps
+        // the namespace stable across regenerations of the same lockfile.
+        let namespace = match &context.checksum {
+            Some(checksum) => format!(
+                "https://spdx.org/spdxdocs/{name}-{}",
+                serde_json::to_value(checksum)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_owned))
+                    .unwrap_or_default()
+            ),
+            None => format!("https://spdx.org/spdxdocs/{name}"),
+        };
+
+        Self {
+            spdx_version: "SPDX-2.3",
+            data_license: "CC0-1.0",
+            spdx_id: "SPDXRef-DOCUMENT",
+            name: name.to_owned(),
+            document_namespace: namespace,
+            creation_info: SpdxCreationInfo {
+                created: created.to_owned(),
+                creators: vec![format!("Tool: cargo-bazel-{}", env!("CARGO_PKG_VERSION"))],
+            },
+            packages,
+            relationships: describes.chain(depends_on).collect(),
+        }
+    }
+}
+
+impl SpdxPackage {
+    fn new(id: &CrateId, crate_context: &CrateContext) -> Self {
+        let (download_location, checksums) = match &crate_context.repository {
+            Some(SourceAnnotation::Http { url, sha256, .. }) => (
+                url.clone(),
+                sha256
+                    .iter()
+                    .map(|sha256| SpdxChecksum {
+                        algorithm: "SHA256",
+                        checksum_value: sha256.clone(),
+                    })
+                    .collect(),
+            ),
+            Some(SourceAnnotation::Git {
+                remote, commitish, ..
+            }) => {
+                let revision = match commitish {
+                    Commitish::Tag(revision)
+                    | Commitish::Branch(revision)
+                    | Commitish::Rev(revision) => revision,
+                };
+                (format!("git+{remote}@{revision}"), Vec::new())
+            }
+            Some(SourceAnnotation::Path { .. }) | None => ("NOASSERTION".to_owned(), Vec::new()),
+        };
+
+        let license_declared = match license_expression(crate_context) {
+            Some(expression) => expression,
+            None if !crate_context.license_ids.is_empty() => crate_context
+                .license_ids
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" AND "),
+            None => "NOASSERTION".to_owned(),
+        };
+
+        Self {
+            spdx_id: spdx_id(id),
+            name: id.name.clone(),
+            version_info: id.version.to_string(),
+            download_location,
+            homepage: crate_context.package_url.clone(),
+            checksums,
+            license_concluded: "NOASSERTION",
+            license_declared,
+            copyright_text: "NOASSERTION",
+            files_analyzed: false,
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER",
+                reference_type: "purl",
+                reference_locator: purl(id),
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_context() -> Context {
+        serde_json::from_value(serde_json::json!({
+            "checksum": "abc123",
+            "crates": {
+                "app 0.1.0": {
+                    "name": "app",
+                    "version": "0.1.0",
+                    "license_ids": [],
+                    "common_attrs": {
+                        "deps": {
+                            "common": [{"id": "serde 1.0.0", "target": "serde"}],
+                            "selects": {
+                                "cfg(unix)": [{"id": "libc 0.2.0", "target": "libc"}],
+                            },
+                        },
+                        "deps_dev": [{"id": "tempfile 3.0.0", "target": "tempfile"}],
+                    },
+                },
+                "libc 0.2.0": {
+                    "name": "libc",
+                    "version": "0.2.0",
+                    "license": "MIT/Apache-2.0",
+                    "license_ids": ["Apache-2.0", "MIT"],
+                    "repository": {
+                        "Git": {
+                            "remote": "https://github.com/rust-lang/libc.git",
+                            "commitish": {"Rev": "deadbeef"},
+                        },
+                    },
+                },
+                "serde 1.0.0": {
+                    "name": "serde",
+                    "version": "1.0.0",
+                    "package_url": "https://github.com/serde-rs/serde",
+                    "license": "MIT OR Apache-2.0",
+                    "license_ids": ["Apache-2.0", "MIT"],
+                    "repository": {
+                        "Http": {
+                            "url": "https://static.crates.io/crates/serde/serde-1.0.0.crate",
+                            "sha256": "0123",
+                        },
+                    },
+                },
+                "tempfile 3.0.0": {
+                    "name": "tempfile",
+                    "version": "3.0.0",
+                    "license_ids": [],
+                },
+            },
+            "binary_crates": [],
+            "workspace_members": {
+                "app 0.1.0": "app",
+            },
+            "conditions": {},
+            "direct_deps": [],
+            "direct_dev_deps": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn cyclonedx() {
+        let context = mock_context();
+        let bom = serde_json::to_value(CycloneDxBom::new(
+            &context,
+            "example",
+            "2024-01-01T00:00:00Z",
+            false,
+        ))
+        .unwrap();
+
+        assert_eq!(bom["specVersion"], "1.5");
+        assert_eq!(bom["metadata"]["component"]["name"], "example");
+        assert_eq!(
+            bom["components"][2],
+            serde_json::json!({
+                "type": "library",
+                "bom-ref": "pkg:cargo/serde@1.0.0",
+                "name": "serde",
+                "version": "1.0.0",
+                "purl": "pkg:cargo/serde@1.0.0",
+                "hashes": [{"alg": "SHA-256", "content": "0123"}],
+                "licenses": [{"expression": "MIT OR Apache-2.0"}],
+                "externalReferences": [
+                    {
+                        "type": "distribution",
+                        "url": "https://static.crates.io/crates/serde/serde-1.0.0.crate",
+                    },
+                    {"type": "website", "url": "https://github.com/serde-rs/serde"},
+                ],
+            })
+        );
+        assert_eq!(
+            bom["dependencies"][0],
+            serde_json::json!({
+                "ref": "pkg:cargo/app@0.1.0",
+                "dependsOn": ["pkg:cargo/libc@0.2.0", "pkg:cargo/serde@1.0.0"],
+            })
+        );
+    }
+
+    #[test]
+    fn spdx() {
+        let context = mock_context();
+        let document = serde_json::to_value(SpdxDocument::new(
+            &context,
+            "example",
+            "2024-01-01T00:00:00Z",
+            false,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            document["documentNamespace"],
+            "https://spdx.org/spdxdocs/example-abc123"
+        );
+        assert_eq!(
+            document["packages"][1],
+            serde_json::json!({
+                "SPDXID": "SPDXRef-Package-libc-0.2.0",
+                "name": "libc",
+                "versionInfo": "0.2.0",
+                "downloadLocation": "git+https://github.com/rust-lang/libc.git@deadbeef",
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "MIT OR Apache-2.0",
+                "copyrightText": "NOASSERTION",
+                "filesAnalyzed": false,
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": "pkg:cargo/libc@0.2.0",
+                }],
+            })
+        );
+
+        let relationships: BTreeSet<(String, String, String)> = document["relationships"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                (
+                    r["spdxElementId"].as_str().unwrap().to_owned(),
+                    r["relationshipType"].as_str().unwrap().to_owned(),
+                    r["relatedSpdxElement"].as_str().unwrap().to_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            relationships,
+            BTreeSet::from([
+                (
+                    "SPDXRef-DOCUMENT".to_owned(),
+                    "DESCRIBES".to_owned(),
+                    "SPDXRef-Package-app-0.1.0".to_owned()
+                ),
+                (
+                    "SPDXRef-Package-app-0.1.0".to_owned(),
+                    "DEPENDS_ON".to_owned(),
+                    "SPDXRef-Package-libc-0.2.0".to_owned()
+                ),
+                (
+                    "SPDXRef-Package-app-0.1.0".to_owned(),
+                    "DEPENDS_ON".to_owned(),
+                    "SPDXRef-Package-serde-1.0.0".to_owned()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn dev_dependencies() {
+        let context = mock_context();
+        let names = |include_dev| {
+            dependency_graph(&context, include_dev)
+                .keys()
+                .map(|id| id.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(false), vec!["app", "libc", "serde"]);
+        assert_eq!(names(true), vec!["app", "libc", "serde", "tempfile"]);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1704067199), "2023-12-31T23:59:59Z");
+    }
+}
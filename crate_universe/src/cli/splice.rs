@@ -1,5 +1,6 @@
 //! The cli entrypoint for the `splice` subcommand
 
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::path::PathBuf;
 use std::process::Stdio;
@@ -11,10 +12,13 @@ use itertools::Itertools;
 
 use crate::cli::Result;
 use crate::config::Config;
-use crate::metadata::{Cargo, CargoUpdateRequest, TreeResolver};
+use crate::metadata::{
+    BuildScriptOutputs, BuildScriptPrerunner, Cargo, CargoUpdateRequest, TreeResolver,
+};
 use crate::splicing::{
-    generate_lockfile, Splicer, SplicerKind, SplicingManifest, WorkspaceMetadata,
+    generate_lockfile, SplicedManifest, Splicer, SplicerKind, SplicingManifest, WorkspaceMetadata,
 };
+use crate::utils::target_triple::{CustomTarget, TargetTriple};
 
 /// Command line options for the `splice` subcommand
 #[derive(Parser, Debug)]
@@ -61,6 +65,11 @@ pub struct SpliceOptions {
     #[clap(long, env = "RUSTC")]
     pub rustc: PathBuf,
 
+    /// A directory in which to cache the results of `cargo tree` across runs. Entries are
+    /// keyed by the contents of every input to `cargo tree`, so stale results are never used.
+    #[clap(long, env = "CARGO_BAZEL_CARGO_TREE_CACHE_DIR")]
+    pub cargo_tree_cache_dir: Option<PathBuf>,
+
     /// The name of the repository being generated.
     #[clap(long)]
     pub repository_name: String,
@@ -96,6 +105,12 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
         }
     };
 
+    // Custom targets are described by files only available while splicing
+    let custom_targets = splicing_manifest.custom_targets.clone();
+    let target_cfgs = splicing_manifest
+        .custom_target_cfgs(&opt.rustc)
+        .context("Failed to determine custom target cfgs")?;
+
     // Generate a splicer for creating a Cargo workspace manifest
     let splicer = Splicer::new(splicing_dir.clone(), splicing_manifest)?;
     let prepared_splicer = splicer.prepare()?;
@@ -123,6 +138,7 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
             &opt.cargo_lockfile,
             cargo.clone(),
             &opt.repin,
+            false,
         )
         .context("Failed to generate lockfile")?
     };
@@ -131,22 +147,15 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
     // populated but unused here. The substitution happens in `generate`.
     let config = Config::try_from_path(&opt.config).context("Failed to parse config")?;
 
-    let resolver_data = TreeResolver::new(cargo.clone())
-        .generate(
-            manifest_path.as_path_buf(),
-            &config.supported_platform_triples,
-        )
-        .context("Failed to generate features")?;
-
-    // Write the registry url info to the manifest now that a lockfile has been generated
-    WorkspaceMetadata::write_registry_urls_and_feature_map(
+    write_workspace_metadata(
         &cargo,
+        &manifest_path,
         &cargo_lockfile,
-        resolver_data,
-        manifest_path.as_path_buf(),
-        manifest_path.as_path_buf(),
-    )
-    .context("Failed to write registry URLs and feature map")?;
+        &config,
+        &custom_targets,
+        target_cfgs,
+        opt.cargo_tree_cache_dir.clone(),
+    )?;
 
     // Generate the consumable outputs of the splicing process
     std::fs::create_dir_all(&opt.output_dir).with_context(|| {
@@ -203,3 +212,55 @@ pub fn splice(opt: SpliceOptions) -> Result<()> {
     }
     Ok(())
 }
+
+/// Resolve the features of a spliced workspace and pre-run its build scripts, then write
+/// the results and the registry urls of its packages to the spliced manifest so they're
+/// reported by `cargo metadata`.
+pub(crate) fn write_workspace_metadata(
+    cargo: &Cargo,
+    manifest_path: &SplicedManifest,
+    cargo_lockfile: &cargo_lock::Lockfile,
+    config: &Config,
+    custom_targets: &BTreeMap<TargetTriple, CustomTarget>,
+    target_cfgs: BTreeMap<TargetTriple, Vec<String>>,
+    cargo_tree_cache_dir: Option<PathBuf>,
+) -> Result<()> {
+    let (resolver_data, host_resolver_data) = TreeResolver::new(cargo.clone())
+        .with_cache_dir(cargo_tree_cache_dir)
+        .with_feature_resolver(config.feature_resolver)
+        .generate(
+            manifest_path.as_path_buf(),
+            &config.supported_platform_triples,
+            custom_targets,
+        )
+        .context("Failed to generate features")?;
+
+    let build_script_outputs = match &config.prerun_build_scripts {
+        Some(settings) => {
+            let cargo_metadata = cargo
+                .metadata_command_with_options(
+                    manifest_path.as_path_buf().as_ref(),
+                    vec!["--locked".to_owned()],
+                )?
+                .exec()
+                .context("Failed to generate cargo metadata")?;
+            BuildScriptPrerunner::new(cargo, settings.clone())
+                .run(&cargo_metadata, config, &resolver_data)
+                .context("Failed to pre-run build scripts")?
+        }
+        None => BuildScriptOutputs::new(),
+    };
+
+    // Write the registry url info to the manifest now that a lockfile has been generated
+    WorkspaceMetadata::write_registry_urls_and_feature_map(
+        cargo,
+        cargo_lockfile,
+        resolver_data,
+        host_resolver_data,
+        build_script_outputs,
+        target_cfgs,
+        manifest_path.as_path_buf(),
+        manifest_path.as_path_buf(),
+    )
+    .context("Failed to write registry URLs and feature map")
+}
@@ -9,18 +9,21 @@ use std::process::{self, ExitStatus};
 use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context as AnyhowContext};
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::Parser;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
+use crate::cli::generate::generate_context;
+use crate::cli::splice::write_workspace_metadata;
 use crate::config::{Config, VendorMode};
-use crate::context::Context;
-use crate::lockfile::{lock_context, write_lockfile};
+use crate::lockfile::{lock_context, serialize_lockfile, stale_lockfile_shards, write_lockfile};
 use crate::metadata::CargoUpdateRequest;
-use crate::metadata::TreeResolver;
-use crate::metadata::{Annotations, Cargo, VendorGenerator};
-use crate::rendering::{render_module_label, write_outputs, Renderer};
-use crate::splicing::{generate_lockfile, Splicer, SplicingManifest, WorkspaceMetadata};
+use crate::metadata::{Cargo, VendorGenerator};
+use crate::rendering::{remove_stale_outputs, render_module_label, write_outputs, Renderer};
+use crate::splicing::{generate_lockfile, Splicer, SplicingManifest};
 use crate::utils::normalize_cargo_file_paths;
+use crate::utils::text_diff::unified_diff;
 
 /// Command line options for the `vendor` subcommand
 #[derive(Parser, Debug)]
@@ -34,6 +37,11 @@ pub struct VendorOptions {
     #[clap(long, env = "RUSTC")]
     pub rustc: PathBuf,
 
+    /// A directory in which to cache the results of `cargo tree` across runs. Entries are
+    /// keyed by the contents of every input to `cargo tree`, so stale results are never used.
+    #[clap(long, env = "CARGO_BAZEL_CARGO_TREE_CACHE_DIR")]
+    pub cargo_tree_cache_dir: Option<PathBuf>,
+
     /// The path to a buildifier binary for formatting generated BUILD files
     #[clap(long)]
     pub buildifier: Option<PathBuf>,
@@ -50,6 +58,11 @@ pub struct VendorOptions {
     #[clap(long)]
     pub lockfile: Option<PathBuf>,
 
+    /// Whether to write each crate in the Bazel lockfile to its own file, so changes to
+    /// unrelated crates do not conflict with one another.
+    #[clap(long)]
+    pub shard_lockfile: bool,
+
     /// The path to a [Cargo.lock](https://doc.rust-lang.org/cargo/guide/cargo-toml-vs-cargo-lock.html) file.
     #[clap(long)]
     pub cargo_lockfile: Option<PathBuf>,
@@ -82,6 +95,11 @@ pub struct VendorOptions {
     #[clap(long)]
     pub dry_run: bool,
 
+    /// If true, outputs are compared against the vendored files and lockfiles on disk
+    /// instead of being written, failing with a report of every difference.
+    #[clap(long, conflicts_with = "dry_run")]
+    pub check: bool,
+
     /// The path to the Bazel root workspace (i.e. the directory containing the WORKSPACE.bazel file or similar).
     /// BE CAREFUL with this value. We never want to include it in a lockfile hash (to keep lockfiles portable),
     /// which means you also should not use it anywhere that _should_ be guarded by a lockfile hash.
@@ -254,25 +272,24 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
         &opt.cargo_lockfile,
         cargo.clone(),
         &opt.repin,
+        false,
     )?;
 
     // Load the config from disk. `config.label_injection_mapping` is applied
     // to the Context just before render (see near `Renderer::new` below) and
-    // is sanitized out of the digest hash by `Digest::new`.
+    // is sanitized out of the digest hash by `DigestComponents::new`.
     let config = Config::try_from_path(&opt.config)?;
 
-    let resolver_data = TreeResolver::new(cargo.clone()).generate(
-        manifest_path.as_path_buf(),
-        &config.supported_platform_triples,
-    )?;
-
-    // Write the registry url info to the manifest now that a lockfile has been generated
-    WorkspaceMetadata::write_registry_urls_and_feature_map(
+    write_workspace_metadata(
         &cargo,
+        &manifest_path,
         &cargo_lockfile,
-        resolver_data,
-        manifest_path.as_path_buf(),
-        manifest_path.as_path_buf(),
+        &config,
+        &splicing_manifest.custom_targets,
+        splicing_manifest
+            .custom_target_cfgs(&opt.rustc)
+            .context("Failed to determine custom target cfgs")?,
+        opt.cargo_tree_cache_dir.clone(),
     )?;
 
     // Write metadata to the workspace for future reuse
@@ -283,17 +300,17 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
         )?
         .exec()?;
 
-    // Annotate metadata
-    let annotations = Annotations::new(
+    // Generate renderable contexts for search package
+    let (context, policy_warnings) = generate_context(
         cargo_metadata,
         &opt.cargo_lockfile,
         cargo_lockfile.clone(),
-        config.clone(),
+        &config,
         &opt.nonhermetic_root_bazel_workspace_dir,
     )?;
-
-    // Generate renderable contexts for search package
-    let context = Context::new(annotations, config.rendering.are_sources_present())?;
+    for warning in policy_warnings {
+        tracing::warn!("{warning}");
+    }
 
     // Apply label_injection just before render. The Context at this point
     // contains the user's apparent labels (e.g. `@openssl//:install`); the
@@ -304,33 +321,61 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
 
     // Render build files
     let outputs = Renderer::new(
-        Arc::new(config.rendering.clone()),
+        Arc::new(
+            config
+                .rendering
+                .clone()
+                .resolve(&opt.workspace_dir, &bazel_info.output_base),
+        ),
         Arc::new(config.supported_platform_triples.clone()),
     )
     .render(&context, None)?;
 
-    // First ensure vendoring and rendering happen in a clean directory
+    // Only files which are no longer rendered are removed so unchanged files keep their mtimes.
     let vendor_dir_label = render_module_label(&config.rendering.crates_module_template, "BUILD")?;
     let vendor_dir = opt.workspace_dir.join(vendor_dir_label.package().unwrap());
-    if vendor_dir.exists() {
-        fs::remove_dir_all(&vendor_dir)
-            .with_context(|| format!("Failed to delete {}", vendor_dir.display()))?;
-    }
+    let is_local = matches!(config.rendering.vendor_mode, Some(VendorMode::Local));
+
+    // Differences between the outputs and the files on disk in `--check` mode.
+    let mut drift = Vec::new();
+
+    // The Cargo.lock the vendored sources were last generated from.
+    let previous_cargo_lockfile = match &opt.cargo_lockfile {
+        Some(path) if is_local && path.exists() => Some(
+            cargo_lock::Lockfile::load(path)
+                .with_context(|| format!("Failed to load lockfile: {}", path.display()))?,
+        ),
+        _ => None,
+    };
 
     // Store the updated Cargo.lock
     if let Some(path) = &opt.cargo_lockfile {
-        fs::write(path, cargo_lockfile.to_string())
-            .context("Failed to write Cargo.lock file back to the workspace.")?;
+        if opt.check {
+            drift.extend(check_file(
+                path,
+                &cargo_lockfile.to_string(),
+                &opt.workspace_dir,
+            )?);
+        } else {
+            fs::write(path, cargo_lockfile.to_string())
+                .context("Failed to write Cargo.lock file back to the workspace.")?;
+        }
     }
 
-    if matches!(config.rendering.vendor_mode, Some(VendorMode::Local)) {
-        VendorGenerator::new(cargo.clone(), opt.rustc.clone())
-            .generate(manifest_path.as_path_buf(), &vendor_dir)
-            .context("Failed to vendor dependencies")?;
+    if is_local && !opt.check {
+        update_vendored_sources(
+            &VendorGenerator::new(cargo.clone(), opt.rustc.clone()),
+            manifest_path.as_path_buf(),
+            &vendor_dir,
+            previous_cargo_lockfile.as_ref(),
+            &cargo_lockfile,
+            opt.dry_run,
+        )
+        .context("Failed to vendor dependencies")?;
     }
 
     // make cargo versioned crates compatible with bazel labels
-    let normalized_outputs = normalize_cargo_file_paths(outputs, &opt.workspace_dir);
+    let normalized_outputs = normalize_cargo_file_paths(outputs, &opt.workspace_dir, is_local);
 
     // Optionally format outputs through buildifier before writing to disk.
     // Piping via stdin avoids a race where a freshly-written file may not yet
@@ -349,10 +394,35 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
     };
 
     // Write outputs
-    write_outputs(normalized_outputs, opt.dry_run).context("Failed writing output files")?;
+    if opt.check {
+        if is_local {
+            drift.extend(
+                check_vendored_sources(
+                    &vendor_dir,
+                    &cargo_lockfile,
+                    &normalized_outputs,
+                    &opt.workspace_dir,
+                )
+                .context("Failed to check vendored sources")?,
+            );
+        }
+        drift.extend(
+            check_outputs(
+                &vendor_dir,
+                &normalized_outputs,
+                is_local,
+                &opt.workspace_dir,
+            )
+            .context("Failed checking output files")?,
+        );
+    } else {
+        remove_stale_outputs(&vendor_dir, &normalized_outputs, opt.dry_run)
+            .context("Failed removing stale output files")?;
+        write_outputs(normalized_outputs, opt.dry_run).context("Failed writing output files")?;
+    }
 
     // Optionally perform bazel mod tidy to update the MODULE.bazel file
-    if bazel_info.release >= semver::Version::new(7, 0, 0) {
+    if bazel_info.release >= semver::Version::new(7, 0, 0) && !opt.check {
         let module_bazel = opt.workspace_dir.join("MODULE.bazel");
         if module_bazel.exists() {
             bzlmod_tidy(&opt.bazel, &opt.workspace_dir)?;
@@ -361,14 +431,335 @@ pub fn vendor(opt: VendorOptions) -> anyhow::Result<()> {
 
     // Write the rendering lockfile if requested.
     if let Some(lockfile) = opt.lockfile {
-        let lock_content = lock_context(context, &config, &splicing_manifest, &cargo, &opt.rustc)?;
+        let lock_content = lock_context(
+            context,
+            &config,
+            &splicing_manifest,
+            &cargo,
+            &opt.rustc,
+            opt.cargo_lockfile.as_deref(),
+        )?;
+
+        if opt.check {
+            let outputs = serialize_lockfile(&lock_content, &lockfile, opt.shard_lockfile)?;
+            for stale in stale_lockfile_shards(&lockfile, &outputs)? {
+                drift.push(format!(
+                    "Stale file: {}",
+                    display_path(&stale, &opt.workspace_dir)
+                ));
+            }
+            for (path, content) in &outputs {
+                drift.extend(check_file(path, content, &opt.workspace_dir)?);
+            }
+        } else {
+            write_lockfile(lock_content, &lockfile, opt.shard_lockfile, opt.dry_run)?;
+        }
+    }
 
-        write_lockfile(lock_content, &lockfile, opt.dry_run)?;
+    if !drift.is_empty() {
+        for difference in &drift {
+            println!("{difference}");
+        }
+        bail!(
+            "Found {} difference(s) between the vendored files and the current inputs. Rerun vendoring to update them.",
+            drift.len()
+        );
     }
 
     Ok(())
 }
 
+/// The contents of a `.cargo-checksum.json` file written by `cargo vendor`.
+#[derive(Debug, Deserialize)]
+struct CargoChecksums {
+    /// The sha256 checksum of each file in the crate, keyed by relative path.
+    files: BTreeMap<String, String>,
+
+    /// The sha256 checksum of the crate's `.crate` file, if it came from a registry.
+    package: Option<String>,
+}
+
+/// Render the path of a file relative to the workspace for reporting.
+fn display_path(path: &Path, workspace_dir: &Path) -> String {
+    path.strip_prefix(workspace_dir)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Compare the expected content of a file against the one on disk, describing any
+/// difference as a unified diff.
+fn check_file(path: &Path, expected: &str, workspace_dir: &Path) -> anyhow::Result<Option<String>> {
+    let name = display_path(path, workspace_dir);
+    if !path.exists() {
+        return Ok(Some(unified_diff(
+            "/dev/null",
+            &format!("b/{name}"),
+            "",
+            expected,
+        )));
+    }
+
+    let actual =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if actual == expected {
+        return Ok(None);
+    }
+
+    let diff = unified_diff(
+        &format!("a/{name}"),
+        &format!("b/{name}"),
+        &actual,
+        expected,
+    );
+    Ok(Some(if diff.is_empty() {
+        // Only line endings or the trailing newline differ.
+        format!("Whitespace differs: {name}")
+    } else {
+        diff
+    }))
+}
+
+/// Compare rendered outputs against the vendor directory, describing drifted,
+/// missing and stale files. Crate sources in `local` mode are checked by
+/// [check_vendored_sources] instead.
+fn check_outputs(
+    vendor_dir: &Path,
+    outputs: &BTreeMap<PathBuf, String>,
+    is_local: bool,
+    workspace_dir: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let mut drift = Vec::new();
+    for (path, content) in outputs {
+        drift.extend(check_file(path, content, workspace_dir)?);
+    }
+
+    if !vendor_dir.exists() {
+        return Ok(drift);
+    }
+
+    let is_vendored_crate = |path: &Path| is_local && path.join(".cargo-checksum.json").exists();
+    for entry in walkdir::WalkDir::new(vendor_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !is_vendored_crate(entry.path()))
+    {
+        let entry = entry.with_context(|| format!("Failed to read {}", vendor_dir.display()))?;
+        if entry.file_type().is_file() && !outputs.contains_key(entry.path()) {
+            drift.push(format!(
+                "Stale file: {}",
+                display_path(entry.path(), workspace_dir)
+            ));
+        }
+    }
+
+    Ok(drift)
+}
+
+/// The directory `cargo vendor --versioned-dirs` extracts each package with a source into.
+fn vendored_crate_dirs<'a>(
+    vendor_dir: &Path,
+    cargo_lockfile: &'a cargo_lock::Lockfile,
+) -> BTreeMap<PathBuf, &'a cargo_lock::Package> {
+    cargo_lockfile
+        .packages
+        .iter()
+        .filter(|package| package.source.is_some())
+        .map(|package| {
+            let dir = format!("{}-{}", package.name, package.version).replace('+', "-");
+            (vendor_dir.join(dir), package)
+        })
+        .collect()
+}
+
+/// The directories within `vendor_dir` which contain a crate vendored by `cargo vendor`.
+fn existing_vendored_crate_dirs(vendor_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !vendor_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(vendor_dir)
+        .with_context(|| format!("Failed to read {}", vendor_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.join(".cargo-checksum.json").exists() {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// The crates in `cargo_lockfile` which need to be vendored, either because they're
+/// missing from `vendor_dir` or because their version, source or checksum differs
+/// from `previous_cargo_lockfile`.
+fn outdated_vendored_crates<'a>(
+    vendor_dir: &Path,
+    previous_cargo_lockfile: Option<&cargo_lock::Lockfile>,
+    cargo_lockfile: &'a cargo_lock::Lockfile,
+) -> Vec<(PathBuf, &'a cargo_lock::Package)> {
+    let previous = previous_cargo_lockfile
+        .map(|lockfile| vendored_crate_dirs(vendor_dir, lockfile))
+        .unwrap_or_default();
+
+    vendored_crate_dirs(vendor_dir, cargo_lockfile)
+        .into_iter()
+        .filter(|(dir, package)| {
+            let unchanged = previous.get(dir).is_some_and(|previous| {
+                previous.source == package.source && previous.checksum == package.checksum
+            });
+            !unchanged || !dir.join(".cargo-checksum.json").exists()
+        })
+        .collect()
+}
+
+/// Bring the crates vendored in `local` mode up to date with `cargo_lockfile`.
+///
+/// Only the directories of [outdated_vendored_crates] are replaced. Crates no longer
+/// in the lockfile are removed and all other crate directories are left untouched.
+/// `cargo vendor` still extracts the full graph, so this reduces churn in
+/// `vendor_dir` rather than the work done to vendor.
+fn update_vendored_sources(
+    generator: &VendorGenerator,
+    manifest_path: &Utf8Path,
+    vendor_dir: &Path,
+    previous_cargo_lockfile: Option<&cargo_lock::Lockfile>,
+    cargo_lockfile: &cargo_lock::Lockfile,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let expected = vendored_crate_dirs(vendor_dir, cargo_lockfile);
+    for path in existing_vendored_crate_dirs(vendor_dir)? {
+        if expected.contains_key(&path) {
+            continue;
+        }
+        if dry_run {
+            println!("Would delete stale vendored crate {}", path.display());
+        } else {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+        }
+    }
+
+    let outdated = outdated_vendored_crates(vendor_dir, previous_cargo_lockfile, cargo_lockfile);
+    if outdated.is_empty() {
+        return Ok(());
+    }
+    if dry_run {
+        for (dir, _) in outdated {
+            println!("Would vendor {}", dir.display());
+        }
+        return Ok(());
+    }
+
+    // `cargo vendor` can only vendor the full graph, so it's staged next to the vendored
+    // crates and only the outdated ones are moved into place.
+    fs::create_dir_all(vendor_dir)
+        .with_context(|| format!("Failed to create {}", vendor_dir.display()))?;
+    let staging_dir = tempfile::Builder::new()
+        .prefix(".vendor-")
+        .tempdir_in(vendor_dir)
+        .context("Failed to create a staging directory")?;
+    generator.generate(manifest_path, staging_dir.path())?;
+
+    for (dir, package) in outdated {
+        let staged = staging_dir
+            .path()
+            .join(format!("{}-{}", package.name, package.version));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("Failed to delete {}", dir.display()))?;
+        }
+        fs::rename(&staged, &dir)
+            .with_context(|| format!("Failed to move {} to {}", staged.display(), dir.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Verify the crates vendored in `local` mode against the lockfile and their
+/// `.cargo-checksum.json` files, describing any crate which was added, removed
+/// or modified. Rendered `outputs` within crate directories are checked elsewhere.
+fn check_vendored_sources(
+    vendor_dir: &Path,
+    cargo_lockfile: &cargo_lock::Lockfile,
+    outputs: &BTreeMap<PathBuf, String>,
+    workspace_dir: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let expected = vendored_crate_dirs(vendor_dir, cargo_lockfile);
+
+    let mut drift = Vec::new();
+    for path in existing_vendored_crate_dirs(vendor_dir)? {
+        if !expected.contains_key(&path) {
+            drift.push(format!(
+                "Stale vendored crate: {}",
+                display_path(&path, workspace_dir)
+            ));
+        }
+    }
+
+    for (dir, package) in expected {
+        let checksum_path = dir.join(".cargo-checksum.json");
+        if !checksum_path.exists() {
+            drift.push(format!(
+                "Missing vendored crate: {}",
+                display_path(&dir, workspace_dir)
+            ));
+            continue;
+        }
+
+        let checksums: CargoChecksums = serde_json::from_str(
+            &fs::read_to_string(&checksum_path)
+                .with_context(|| format!("Failed to read {}", checksum_path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse {}", checksum_path.display()))?;
+
+        if let (Some(vendored), Some(locked)) = (&checksums.package, &package.checksum) {
+            if *vendored != locked.to_string() {
+                drift.push(format!(
+                    "Vendored crate does not match the checksum in Cargo.lock: {}",
+                    display_path(&dir, workspace_dir)
+                ));
+            }
+        }
+
+        for (file, checksum) in &checksums.files {
+            let path = dir.join(file);
+            match fs::read(&path) {
+                Ok(content) if hex::encode(Sha256::digest(&content)) == *checksum => {}
+                Ok(_) => drift.push(format!(
+                    "Modified vendored file: {}",
+                    display_path(&path, workspace_dir)
+                )),
+                Err(_) => drift.push(format!(
+                    "Missing vendored file: {}",
+                    display_path(&path, workspace_dir)
+                )),
+            }
+        }
+
+        for entry in walkdir::WalkDir::new(&dir).sort_by_file_name() {
+            let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+            let path = entry.path();
+            if !entry.file_type().is_file() || path == checksum_path || outputs.contains_key(path) {
+                continue;
+            }
+            let file = path
+                .strip_prefix(&dir)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if !checksums.files.contains_key(&file) {
+                drift.push(format!(
+                    "Unexpected vendored file: {}",
+                    display_path(path, workspace_dir)
+                ));
+            }
+        }
+    }
+
+    Ok(drift)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -414,4 +805,261 @@ mod tests {
         );
         assert_eq!(PathBuf::from("/tmp/output_base"), info.output_base);
     }
+
+    #[test]
+    fn check_outputs_reports_drift() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("check_outputs_reports_drift");
+        let vendor_dir = tempdir.join("crates");
+        fs::create_dir_all(vendor_dir.join("stale")).unwrap();
+        fs::write(vendor_dir.join("BUILD.bazel"), "unchanged\n").unwrap();
+        fs::write(vendor_dir.join("defs.bzl"), "old\n").unwrap();
+        fs::write(vendor_dir.join("stale/BUILD.bazel"), "stale\n").unwrap();
+
+        let outputs = BTreeMap::from([
+            (vendor_dir.join("BUILD.bazel"), "unchanged\n".to_owned()),
+            (vendor_dir.join("defs.bzl"), "new\n".to_owned()),
+            (vendor_dir.join("alias_rules.bzl"), "added\n".to_owned()),
+        ]);
+
+        let drift = check_outputs(&vendor_dir, &outputs, false, &tempdir).unwrap();
+
+        assert_eq!(
+            drift,
+            vec![
+                "--- /dev/null\n+++ b/crates/alias_rules.bzl\n@@ -0,0 +1,1 @@\n+added\n".to_owned(),
+                "--- a/crates/defs.bzl\n+++ b/crates/defs.bzl\n@@ -1,1 +1,1 @@\n-old\n+new\n"
+                    .to_owned(),
+                "Stale file: crates/stale/BUILD.bazel".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_vendored_sources_reports_drift() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("check_vendored_sources_reports_drift");
+        let vendor_dir = tempdir.join("crates");
+        let cargo_lockfile: cargo_lock::Lockfile = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "direct-cargo-bazel-deps"
+            version = "0.0.1"
+
+            [[package]]
+            name = "either"
+            version = "1.9.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "a26ae43d7bcc3b814de94796a5e736d4029efb0ee900c12e2d54c993ad1a1e07"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.9"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38"
+        "#}
+        .parse()
+        .unwrap();
+
+        // `either` is vendored with one modified and one unlisted file, alongside
+        // its rendered BUILD file.
+        let either = vendor_dir.join("either-1.9.0");
+        fs::create_dir_all(either.join("src")).unwrap();
+        fs::write(either.join("BUILD.bazel"), "rendered").unwrap();
+        fs::write(either.join("src/lib.rs"), "modified").unwrap();
+        fs::write(either.join("src/mod.rs"), "unchanged").unwrap();
+        fs::write(either.join("src/extra.rs"), "unlisted").unwrap();
+        fs::write(
+            either.join(".cargo-checksum.json"),
+            serde_json::json!({
+                "files": {
+                    "src/lib.rs": hex::encode(Sha256::digest(b"original")),
+                    "src/mod.rs": hex::encode(Sha256::digest(b"unchanged")),
+                },
+                "package": "a26ae43d7bcc3b814de94796a5e736d4029efb0ee900c12e2d54c993ad1a1e07",
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        // `serde` is no longer in the lockfile and `itoa` was never vendored.
+        let serde = vendor_dir.join("serde-1.0.0");
+        fs::create_dir_all(&serde).unwrap();
+        fs::write(
+            serde.join(".cargo-checksum.json"),
+            r#"{"files":{},"package":null}"#,
+        )
+        .unwrap();
+
+        let outputs = BTreeMap::from([(either.join("BUILD.bazel"), "rendered".to_owned())]);
+
+        let drift =
+            check_vendored_sources(&vendor_dir, &cargo_lockfile, &outputs, &tempdir).unwrap();
+
+        assert_eq!(
+            drift,
+            vec![
+                "Stale vendored crate: crates/serde-1.0.0".to_owned(),
+                "Modified vendored file: crates/either-1.9.0/src/lib.rs".to_owned(),
+                "Unexpected vendored file: crates/either-1.9.0/src/extra.rs".to_owned(),
+                "Missing vendored crate: crates/itoa-1.0.9".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_semver_metadata_crates() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("check_semver_metadata_crates");
+        let vendor_dir = tempdir.join("crates");
+        let cargo_lockfile: cargo_lock::Lockfile = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "libbpf-sys"
+            version = "1.4.0+v1.4.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+        "#}
+        .parse()
+        .unwrap();
+
+        // A clean tree, where the crate was vendored into its sanitized directory.
+        let crate_dir = vendor_dir.join("libbpf-sys-1.4.0-v1.4.0");
+        fs::create_dir_all(&crate_dir).unwrap();
+        fs::write(crate_dir.join("BUILD.bazel"), "rendered").unwrap();
+        fs::write(crate_dir.join("lib.rs"), "source").unwrap();
+        fs::write(
+            crate_dir.join(".cargo-checksum.json"),
+            serde_json::json!({
+                "files": {
+                    "lib.rs": hex::encode(Sha256::digest(b"source")),
+                },
+                "package": "0000000000000000000000000000000000000000000000000000000000000000",
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let outputs = normalize_cargo_file_paths(
+            BTreeMap::from([(
+                PathBuf::from("crates/libbpf-sys-1.4.0+v1.4.0/BUILD.bazel"),
+                "rendered".to_owned(),
+            )]),
+            &tempdir,
+            true,
+        );
+
+        let mut drift =
+            check_vendored_sources(&vendor_dir, &cargo_lockfile, &outputs, &tempdir).unwrap();
+        drift.extend(check_outputs(&vendor_dir, &outputs, true, &tempdir).unwrap());
+
+        assert_eq!(drift, Vec::<String>::new());
+        assert!(!vendor_dir.join("libbpf-sys-1.4.0+v1.4.0").exists());
+    }
+
+    #[test]
+    fn outdated_vendored_crates_only_includes_changes() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("outdated_vendored_crates_only_includes_changes");
+        let vendor_dir = tempdir.join("crates");
+        for dir in ["either-1.9.0", "itoa-1.0.9"] {
+            fs::create_dir_all(vendor_dir.join(dir)).unwrap();
+            fs::write(vendor_dir.join(dir).join(".cargo-checksum.json"), "{}").unwrap();
+        }
+
+        let previous: cargo_lock::Lockfile = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "either"
+            version = "1.9.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "a26ae43d7bcc3b814de94796a5e736d4029efb0ee900c12e2d54c993ad1a1e07"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.9"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38"
+        "#}
+        .parse()
+        .unwrap();
+
+        // `either` was republished to a different registry, `itoa` is unchanged and
+        // `serde` is new.
+        let current: cargo_lock::Lockfile = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "either"
+            version = "1.9.0"
+            source = "registry+https://example.com/index"
+            checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+
+            [[package]]
+            name = "itoa"
+            version = "1.0.9"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "af150ab688ff2122fcef229be89cb50dd66af9e01a4ff320cc137eecc9bacc38"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.188"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "cf9e0fcba69a370eed61bcf2b728575f726b50b55cba78064753d708ddc7549e"
+        "#}
+        .parse()
+        .unwrap();
+
+        let outdated: Vec<PathBuf> =
+            outdated_vendored_crates(&vendor_dir, Some(&previous), &current)
+                .into_iter()
+                .map(|(dir, _)| dir)
+                .collect();
+        assert_eq!(
+            outdated,
+            vec![
+                vendor_dir.join("either-1.9.0"),
+                vendor_dir.join("serde-1.0.188"),
+            ]
+        );
+
+        // Without a previous lockfile, every crate is vendored again.
+        assert_eq!(
+            outdated_vendored_crates(&vendor_dir, None, &current).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn semver_metadata_outputs_match_vendored_crate_dirs() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("semver_metadata_outputs_match_vendored_crate_dirs");
+        let vendor_dir = tempdir.join("crates");
+        let cargo_lockfile: cargo_lock::Lockfile = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "libbpf-sys"
+            version = "1.4.0+v1.4.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "0000000000000000000000000000000000000000000000000000000000000000"
+        "#}
+        .parse()
+        .unwrap();
+
+        let outputs = normalize_cargo_file_paths(
+            BTreeMap::from([(
+                PathBuf::from("crates/libbpf-sys-1.4.0+v1.4.0/BUILD.bazel"),
+                String::new(),
+            )]),
+            &tempdir,
+            true,
+        );
+
+        let dirs: Vec<PathBuf> = vendored_crate_dirs(&vendor_dir, &cargo_lockfile)
+            .into_keys()
+            .map(|dir| dir.join("BUILD.bazel"))
+            .collect();
+        assert_eq!(outputs.into_keys().collect::<Vec<_>>(), dirs);
+    }
 }
@@ -0,0 +1,401 @@
+//! The cli entrypoint for the `why` subcommand
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context as AnyhowContext, Result};
+use clap::Parser;
+use semver::VersionReq;
+
+use crate::config::CrateId;
+use crate::context::{Context, CrateDependency};
+use crate::select::Select;
+use crate::utils::target_triple::TargetTriple;
+
+/// Command line options for the `why` subcommand
+#[derive(Parser, Debug)]
+#[clap(about = "Command line options for the `why` subcommand", version)]
+pub struct WhyOptions {
+    /// The path to a cargo-bazel lockfile
+    #[clap(long)]
+    pub lockfile: PathBuf,
+
+    /// The name of the crate to explain
+    pub name: String,
+
+    /// An optional semver requirement used to select specific versions of the crate
+    #[clap(long)]
+    pub version_req: Option<VersionReq>,
+
+    /// The maximum number of paths to print. The number of paths can grow exponentially
+    /// with the size of the dependency graph.
+    #[clap(long, default_value_t = 20)]
+    pub max_paths: usize,
+}
+
+/// Print the dependency paths from the workspace members to a given crate.
+pub fn why(opt: WhyOptions) -> Result<()> {
+    let context = Context::try_from_path(&opt.lockfile)
+        .with_context(|| format!("Failed to load lockfile `{}`", opt.lockfile.display()))?;
+
+    let targets: BTreeSet<&CrateId> = context
+        .crates
+        .keys()
+        .filter(|id| id.name == opt.name)
+        .filter(|id| match &opt.version_req {
+            Some(req) => req.matches(&id.version),
+            None => true,
+        })
+        .collect();
+
+    if targets.is_empty() {
+        match &opt.version_req {
+            Some(req) => bail!("No crate `{}` matching `{}` was found", opt.name, req),
+            None => bail!("No crate `{}` was found", opt.name),
+        }
+    }
+
+    let graph = DependencyGraph::new(&context);
+    // Search for one more path than is printed to know whether any were left out.
+    let mut paths = graph.paths_to(&targets, opt.max_paths.saturating_add(1));
+    let truncated = paths.len() > opt.max_paths;
+    paths.truncate(opt.max_paths);
+
+    if paths.is_empty() {
+        println!(
+            "No workspace member depends on {}",
+            targets
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        return Ok(());
+    }
+
+    for path in &paths {
+        println!("{}\n", path.render(&context.conditions));
+    }
+
+    if truncated {
+        println!(
+            "Only the first {} paths were printed. Use `--max-paths` to print more.",
+            opt.max_paths
+        );
+    }
+
+    Ok(())
+}
+
+/// The kind of a dependency edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DependencyKind {
+    Normal,
+    Dev,
+    Build,
+    ProcMacro,
+}
+
+impl fmt::Display for DependencyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::Dev => "dev",
+            Self::Build => "build",
+            Self::ProcMacro => "proc-macro",
+        })
+    }
+}
+
+/// A reason one crate depends on another.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Edge {
+    kind: DependencyKind,
+
+    /// The `select` configuration the dependency is gated on, if any.
+    configuration: Option<String>,
+}
+
+/// All dependency edges between the crates of a [Context].
+struct DependencyGraph<'a> {
+    /// The workspace members every path starts from.
+    roots: BTreeSet<&'a CrateId>,
+
+    edges: BTreeMap<&'a CrateId, BTreeMap<&'a CrateId, BTreeSet<Edge>>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    fn new(context: &'a Context) -> Self {
+        let mut edges: BTreeMap<&CrateId, BTreeMap<&CrateId, BTreeSet<Edge>>> = BTreeMap::new();
+
+        let mut add = |from: &'a CrateId,
+                       deps: &'a Select<BTreeSet<CrateDependency>>,
+                       kind: DependencyKind| {
+            for (configuration, dep) in deps.items() {
+                // Only track edges to crates which are actually part of the graph.
+                let Some((to, _)) = context.crates.get_key_value(&dep.id) else {
+                    continue;
+                };
+                edges
+                    .entry(from)
+                    .or_default()
+                    .entry(to)
+                    .or_default()
+                    .insert(Edge {
+                        kind,
+                        configuration,
+                    });
+            }
+        };
+
+        for (id, crate_context) in &context.crates {
+            let attrs = &crate_context.common_attrs;
+            add(id, &attrs.deps, DependencyKind::Normal);
+            add(id, &attrs.proc_macro_deps, DependencyKind::ProcMacro);
+
+            // Dev dependencies are only ever built for workspace members.
+            if context.workspace_members.contains_key(id) {
+                add(id, &attrs.deps_dev, DependencyKind::Dev);
+                add(id, &attrs.proc_macro_deps_dev, DependencyKind::Dev);
+            }
+
+            if let Some(attrs) = &crate_context.build_script_attrs {
+                add(id, &attrs.deps, DependencyKind::Build);
+                add(id, &attrs.link_deps, DependencyKind::Build);
+                add(id, &attrs.proc_macro_deps, DependencyKind::Build);
+            }
+        }
+
+        Self {
+            roots: context.workspace_members.keys().collect(),
+            edges,
+        }
+    }
+
+    /// Collect all crates which can reach any of the `targets`, including the targets themselves.
+    fn reverse_reachable(&self, targets: &BTreeSet<&'a CrateId>) -> BTreeSet<&'a CrateId> {
+        let mut reverse: BTreeMap<&CrateId, BTreeSet<&CrateId>> = BTreeMap::new();
+        for (from, deps) in &self.edges {
+            for to in deps.keys() {
+                reverse.entry(*to).or_default().insert(*from);
+            }
+        }
+
+        let mut reachable: BTreeSet<&CrateId> = targets.clone();
+        let mut queue: VecDeque<&CrateId> = targets.iter().copied().collect();
+        while let Some(id) = queue.pop_front() {
+            for parent in reverse.get(id).into_iter().flatten() {
+                if reachable.insert(*parent) {
+                    queue.push_back(*parent);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Enumerate up to `limit` acyclic paths from a workspace member to one of the `targets`.
+    fn paths_to(
+        &'a self,
+        targets: &BTreeSet<&'a CrateId>,
+        limit: usize,
+    ) -> Vec<DependencyPath<'a>> {
+        let reachable = self.reverse_reachable(targets);
+
+        let mut paths = Vec::new();
+        for root in self.roots.intersection(&reachable).copied() {
+            let mut current = DependencyPath {
+                root,
+                steps: Vec::new(),
+            };
+            self.walk(root, targets, &reachable, limit, &mut current, &mut paths);
+        }
+
+        paths
+    }
+
+    fn walk(
+        &'a self,
+        id: &'a CrateId,
+        targets: &BTreeSet<&'a CrateId>,
+        reachable: &BTreeSet<&'a CrateId>,
+        limit: usize,
+        current: &mut DependencyPath<'a>,
+        paths: &mut Vec<DependencyPath<'a>>,
+    ) {
+        if paths.len() >= limit {
+            return;
+        }
+        if targets.contains(id) && !current.steps.is_empty() {
+            paths.push(current.clone());
+            return;
+        }
+
+        for (dep, edges) in self.edges.get(id).into_iter().flatten() {
+            // Skip anything which cannot lead to a target or would introduce a cycle.
+            if !reachable.contains(dep) || current.contains(dep) {
+                continue;
+            }
+            current.steps.push((dep, edges));
+            self.walk(dep, targets, reachable, limit, current, paths);
+            current.steps.pop();
+        }
+    }
+}
+
+/// A chain of dependencies starting at a workspace member.
+#[derive(Debug, Clone)]
+struct DependencyPath<'a> {
+    root: &'a CrateId,
+    steps: Vec<(&'a CrateId, &'a BTreeSet<Edge>)>,
+}
+
+impl DependencyPath<'_> {
+    fn contains(&self, id: &CrateId) -> bool {
+        self.root == id || self.steps.iter().any(|(step, _)| *step == id)
+    }
+
+    /// Render the path as an indented tree, labeling each edge with its kind and the
+    /// platforms it applies to.
+    fn render(&self, conditions: &BTreeMap<String, BTreeSet<TargetTriple>>) -> String {
+        let mut lines = vec![self.root.to_string()];
+        for (depth, (id, edges)) in self.steps.iter().enumerate() {
+            let labels = edges
+                .iter()
+                .map(|edge| match &edge.configuration {
+                    None => edge.kind.to_string(),
+                    Some(configuration) => match conditions.get(configuration) {
+                        Some(triples) => format!(
+                            "{} on {} [{}]",
+                            edge.kind,
+                            configuration,
+                            triples
+                                .iter()
+                                .map(TargetTriple::to_bazel)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        None => format!("{} on {}", edge.kind, configuration),
+                    },
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            lines.push(format!("{}└─ {} ({})", "   ".repeat(depth), id, labels));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use semver::Version;
+
+    fn mock_context() -> Context {
+        serde_json::from_value(serde_json::json!({
+            "checksum": null,
+            "crates": {
+                "app 0.1.0": {
+                    "name": "app",
+                    "version": "0.1.0",
+                    "license_ids": [],
+                    "common_attrs": {
+                        "deps": {
+                            "common": [{"id": "a 1.0.0", "target": "a"}],
+                            "selects": {
+                                "cfg(unix)": [{"id": "b 1.0.0", "target": "b"}],
+                            },
+                        },
+                        "deps_dev": [{"id": "c 1.0.0", "target": "c"}],
+                    },
+                },
+                "a 1.0.0": {
+                    "name": "a",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                    "common_attrs": {
+                        "deps": [{"id": "c 1.0.0", "target": "c"}],
+                    },
+                },
+                "b 1.0.0": {
+                    "name": "b",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                    "build_script_attrs": {
+                        "deps": [{"id": "c 1.0.0", "target": "c"}],
+                    },
+                    "common_attrs": {
+                        "deps_dev": [{"id": "d 1.0.0", "target": "d"}],
+                    },
+                },
+                "c 1.0.0": {
+                    "name": "c",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                },
+                "d 1.0.0": {
+                    "name": "d",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                },
+            },
+            "binary_crates": [],
+            "workspace_members": {
+                "app 0.1.0": "app",
+            },
+            "conditions": {
+                "cfg(unix)": ["x86_64-unknown-linux-gnu"],
+            },
+            "direct_deps": [],
+            "direct_dev_deps": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn paths_to_transitive_dependency() {
+        let context = mock_context();
+        let graph = DependencyGraph::new(&context);
+
+        let target = CrateId::new("c".to_owned(), Version::new(1, 0, 0));
+        let paths = graph.paths_to(&BTreeSet::from([&target]), usize::MAX);
+
+        let rendered: Vec<String> = paths
+            .iter()
+            .map(|path| path.render(&context.conditions))
+            .collect();
+
+        assert_eq!(
+            rendered,
+            vec![
+                "app 0.1.0\n└─ a 1.0.0 (normal)\n   └─ c 1.0.0 (normal)",
+                "app 0.1.0\n└─ b 1.0.0 (normal on cfg(unix) [x86_64-unknown-linux-gnu])\n   └─ c 1.0.0 (build)",
+                "app 0.1.0\n└─ c 1.0.0 (dev)",
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_to_stops_at_limit() {
+        let context = mock_context();
+        let graph = DependencyGraph::new(&context);
+
+        let target = CrateId::new("c".to_owned(), Version::new(1, 0, 0));
+        let paths = graph.paths_to(&BTreeSet::from([&target]), 2);
+
+        assert_eq!(paths.len(), 2);
+    }
+
+    #[test]
+    fn dev_deps_of_dependencies_are_ignored() {
+        let context = mock_context();
+        let graph = DependencyGraph::new(&context);
+
+        let target = CrateId::new("d".to_owned(), Version::new(1, 0, 0));
+        let paths = graph.paths_to(&BTreeSet::from([&target]), usize::MAX);
+
+        assert!(paths.is_empty());
+    }
+}
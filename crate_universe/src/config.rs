@@ -1,6 +1,8 @@
 //! A module for configuration information
 
 pub(crate) mod label_injection;
+pub(crate) mod license_policy;
+pub(crate) mod multiple_versions;
 
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
@@ -12,13 +14,16 @@ use std::str::FromStr;
 use std::{fmt, fs};
 
 use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
 use cargo_lock::package::GitReference;
 use cargo_metadata::Package;
-use semver::VersionReq;
+use semver::{Version, VersionReq};
 use serde::de::value::SeqAccessDeserializer;
 use serde::de::{Deserializer, SeqAccess, Unexpected, Visitor};
 use serde::{Deserialize, Serialize, Serializer};
 
+use crate::config::license_policy::LicensePolicy;
+use crate::config::multiple_versions::MultipleVersionsPolicy;
 use crate::select::{Select, Selectable};
 use crate::utils::starlark::Label;
 use crate::utils::target_triple::TargetTriple;
@@ -112,6 +117,14 @@ pub(crate) struct RenderConfig {
     /// This is expected to always be true except for bootstrapping.
     pub(crate) generate_cargo_toml_env_vars: bool,
 
+    /// Whether to compress the `select` statements of generated BUILD files.
+    /// Values selected by every supported platform are moved out of the
+    /// `select`, and platforms with identical branches share one branch matched
+    /// by a `config_setting_group` in the hub repository. Hoisted values then
+    /// also apply on unsupported platforms.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) compress_selects: bool,
+
     /// Incompatibility flag. Suppresses the top-level `alias()` rules in the
     /// hub repository's root `BUILD.bazel` (e.g. `@crate_index//:clap`).
     /// Per-alias subpackages (e.g. `@crate_index//clap`) are always emitted,
@@ -132,6 +145,11 @@ pub(crate) struct RenderConfig {
     /// continue to write subpackage `BUILD.bazel`s into the hub repo directly.
     #[serde(default)]
     pub(crate) crates_vendor_synthesizes_subpackages: bool,
+
+    /// A directory of Jinja templates which replace the built-in templates of
+    /// the same name, or add new partials under `partials/` for them to include.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) template_overrides: Option<Utf8PathBuf>,
 }
 
 // Default is manually implemented so that the default values match the default
@@ -154,8 +172,10 @@ impl Default for RenderConfig {
             regen_command: String::default(),
             vendor_mode: Option::default(),
             generate_rules_license_metadata: default_generate_rules_license_metadata(),
+            compress_selects: false,
             incompatible_no_root_alias_targets: false,
             crates_vendor_synthesizes_subpackages: false,
+            template_overrides: None,
         }
     }
 }
@@ -164,6 +184,22 @@ impl RenderConfig {
     pub(crate) fn are_sources_present(&self) -> bool {
         self.vendor_mode == Some(VendorMode::Local)
     }
+
+    /// Expand the `${build_workspace_directory}` and `${output_base}` placeholders
+    /// `crates_vendor` writes into [RenderConfig::template_overrides].
+    pub(crate) fn resolve(mut self, workspace_dir: &Path, output_base: &Path) -> Self {
+        self.template_overrides = self.template_overrides.map(|dir| {
+            Utf8PathBuf::from(
+                dir.as_str()
+                    .replace(
+                        "${build_workspace_directory}",
+                        &workspace_dir.to_string_lossy(),
+                    )
+                    .replace("${output_base}", &output_base.to_string_lossy()),
+            )
+        });
+        self
+    }
 }
 
 fn default_build_file_template() -> String {
@@ -275,6 +311,16 @@ pub(crate) struct CrateAnnotations {
     /// Determines whether or not Cargo build scripts should be generated for the current package
     pub(crate) gen_build_script: Option<bool>,
 
+    /// Whether `cdylib` and `staticlib` crate types should be generated as
+    /// `rust_shared_library` and `rust_static_library` targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) gen_native_libraries: Option<bool>,
+
+    /// Whether `rust_test` targets should be generated for the crate's unit and
+    /// integration tests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) gen_tests: Option<bool>,
+
     /// Additional data to pass to
     /// [deps](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-deps) attribute.
     pub(crate) deps: Option<Select<BTreeSet<Label>>>,
@@ -291,6 +337,11 @@ pub(crate) struct CrateAnnotations {
     /// [crate_features](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-crate_features) attribute.
     pub(crate) crate_features: Option<Select<BTreeSet<String>>>,
 
+    /// Resolved features which can be switched off at build time through a
+    /// `bool_flag` generated in the hub repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) toggleable_features: Option<BTreeSet<String>>,
+
     /// Additional data to pass to  the target's
     /// [data](https://bazelbuild.github.io/rules_rust/defs.html#rust_library-data) attribute.
     pub(crate) data: Option<Select<BTreeSet<Label>>>,
@@ -433,6 +484,32 @@ macro_rules! joined_extra_member {
     };
 }
 
+impl CrateAnnotations {
+    /// Whether or not any annotation customizes how the crate's build script is built or run.
+    pub(crate) fn customizes_build_script(&self) -> bool {
+        self.gen_build_script == Some(false)
+            || self.build_script_deps.is_some()
+            || self.build_script_link_deps.is_some()
+            || self.build_script_proc_macro_deps.is_some()
+            || self.build_script_compile_data.is_some()
+            || self.build_script_data.is_some()
+            || self.build_script_tools.is_some()
+            || self.build_script_data_glob.is_some()
+            || self.build_script_env.is_some()
+            || self.build_script_env_files.is_some()
+            || self.build_script_rustc_env.is_some()
+            || self.build_script_exec_properties.is_some()
+            || self.build_script_toolchains.is_some()
+            || self.build_script_use_default_shell_env.is_some()
+            || self.build_script_use_cc_toolchain.is_some()
+            || self.build_script_rundir.is_some()
+            || self
+                .override_targets
+                .as_ref()
+                .is_some_and(|targets| targets.contains_key("custom-build"))
+    }
+}
+
 impl Add for CrateAnnotations {
     type Output = CrateAnnotations;
 
@@ -457,10 +534,13 @@ impl Add for CrateAnnotations {
         let output = CrateAnnotations {
             gen_binaries: self.gen_binaries.or(rhs.gen_binaries),
             gen_build_script: self.gen_build_script.or(rhs.gen_build_script),
+            gen_native_libraries: self.gen_native_libraries.or(rhs.gen_native_libraries),
+            gen_tests: self.gen_tests.or(rhs.gen_tests),
             deps: select_merge(self.deps, rhs.deps),
             proc_macro_deps: select_merge(self.proc_macro_deps, rhs.proc_macro_deps),
             link_deps: select_merge(self.link_deps, rhs.link_deps),
             crate_features: select_merge(self.crate_features, rhs.crate_features),
+            toggleable_features: joined_extra_member!(self.toggleable_features, rhs.toggleable_features, BTreeSet::new, BTreeSet::extend),
             data: select_merge(self.data, rhs.data),
             data_glob: joined_extra_member!(self.data_glob, rhs.data_glob, BTreeSet::new, BTreeSet::extend),
             disable_pipelining: self.disable_pipelining || rhs.disable_pipelining,
@@ -743,6 +823,40 @@ impl<'de> Visitor<'de> for GenBinariesVisitor {
     }
 }
 
+/// The methods available for determining which features and optional dependencies
+/// are enabled on each platform.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FeatureResolver {
+    /// Run `cargo tree` for each combination of host and target platform
+    #[default]
+    CargoTree,
+
+    /// Resolve features in-process from `cargo metadata` output
+    Metadata,
+
+    /// Use both `cargo tree` and `metadata` and fail if their results differ
+    Differential,
+}
+
+impl FeatureResolver {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Settings for running "pure" build scripts (those which only emit `cfg`s and
+/// environment variables) while repinning so their outputs can be recorded in the
+/// lockfile instead of generating `cargo_build_script` targets.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PrerunBuildScripts {
+    /// Crates whose build scripts are never run ahead of time. This is in addition to
+    /// scripts which are known to compile or link native code.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) denylist: BTreeSet<CrateNameAndVersionReq>,
+}
+
 /// Workspace specific settings to control how targets are generated
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
@@ -753,6 +867,10 @@ pub(crate) struct Config {
     /// Whether or not to generate Cargo build scripts by default
     pub(crate) generate_build_scripts: bool,
 
+    /// Whether to generate `rust_test` targets for unit and integration tests by default
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) generate_tests: bool,
+
     /// Additional settings to apply to generated crates
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub(crate) annotations: BTreeMap<CrateNameAndVersionReq, CrateAnnotations>,
@@ -768,12 +886,33 @@ pub(crate) struct Config {
     #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
     pub(crate) supported_platform_triples: BTreeSet<TargetTriple>,
 
+    /// A mapping of `cfg` atoms (e.g. `target_feature = "avx2"`) to the labels of
+    /// `config_setting`s which enable them in generated select statements
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) cfg_settings: BTreeMap<String, Label>,
+
+    /// An optional policy the licenses of third party crates must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) license_policy: Option<LicensePolicy>,
+
+    /// An optional policy for crates resolved to more than one version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) multiple_versions: Option<MultipleVersionsPolicy>,
+
+    /// How the features enabled on each platform are determined
+    #[serde(default, skip_serializing_if = "FeatureResolver::is_default")]
+    pub(crate) feature_resolver: FeatureResolver,
+
+    /// Optional settings for running pure build scripts while repinning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) prerun_build_scripts: Option<PrerunBuildScripts>,
+
     /// Apparent -> canonical label_injection map extracted from each
     /// annotation's `label_injections` field at load time. Populated by
     /// `Config::try_from_path`; not present in config.json itself
     /// (`deny_unknown_fields` is fine because `extract_global_mapping`
     /// strips `label_injections` before deserialization). Sanitized to
-    /// `Default::default()` in `Digest::new` before hashing — same trick
+    /// `Default::default()` in `DigestComponents::new` before hashing — same trick
     /// as `Context.checksum = None` in `lockfile.rs` — so consumer-side
     /// `single_version_override` shifts (which change the canonical names
     /// here) don't perturb the digest and force a producer-side repin.
@@ -814,7 +953,7 @@ impl Config {
     /// any root-level `single_version_override` / `multiple_version_override`).
     ///
     /// The mapping is excluded from the digest hash via
-    /// [`crate::lockfile::Digest::new`], mirroring the
+    /// [`crate::lockfile::DigestComponents::new`], mirroring the
     /// `Context.checksum = None` clear in the same file. That's what keeps
     /// the digest stable across consumer-side overrides.
     pub(crate) fn try_from_path<T: AsRef<Path>>(path: T) -> Result<Self> {
@@ -845,10 +984,13 @@ impl Serialize for CrateNameAndVersionReq {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&format!(
-            "{} {}",
-            self.name, self.version_req_string.original
-        ))
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl fmt::Display for CrateNameAndVersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version_req_string.original)
     }
 }
 
@@ -981,14 +1123,23 @@ impl CrateNameAndVersionReq {
 
     /// Compares a [CrateNameAndVersionReq] against a [cargo_metadata::Package].
     pub fn matches(&self, package: &Package) -> bool {
+        self.matches_name_and_version(&package.name, &package.version)
+    }
+
+    /// Compares a [CrateNameAndVersionReq] against a [CrateId].
+    pub fn matches_crate_id(&self, id: &CrateId) -> bool {
+        self.matches_name_and_version(&id.name, &id.version)
+    }
+
+    fn matches_name_and_version(&self, name: &str, version: &Version) -> bool {
         // If the package name does not match, it's obviously
         // not the right package
-        if self.name != "*" && self.name != package.name {
+        if self.name != "*" && self.name != name {
             return false;
         }
 
         // First see if the package version matches exactly
-        if package.version.to_string() == self.version_req_string.original {
+        if version.to_string() == self.version_req_string.original {
             return true;
         }
 
@@ -1004,7 +1155,7 @@ impl CrateNameAndVersionReq {
 
         // Next, check to see if the version provided is a semver req and
         // check if the package matches the condition
-        self.version_req_string.parsed.matches(&package.version)
+        self.version_req_string.parsed.matches(version)
     }
 }
 
@@ -1114,4 +1265,20 @@ mod test {
             "//custom/platform:{triple}"
         );
     }
+
+    #[test]
+    fn resolve_template_overrides() {
+        let config = RenderConfig {
+            template_overrides: Some(Utf8PathBuf::from(
+                "${build_workspace_directory}/tools/templates",
+            )),
+            ..RenderConfig::default()
+        }
+        .resolve(Path::new("/workspace"), Path::new("/output_base"));
+
+        assert_eq!(
+            config.template_overrides,
+            Some(Utf8PathBuf::from("/workspace/tools/templates"))
+        );
+    }
 }
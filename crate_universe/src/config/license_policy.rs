@@ -0,0 +1,287 @@
+//! Validation of crate licenses against a user provided policy.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Context as AnyhowContext, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CrateId, CrateNameAndVersionReq};
+use crate::context::Context;
+
+/// A policy describing which licenses third party crates may be used under.
+///
+/// Each entry is an SPDX expression and applies to every license it names, so
+/// `MIT OR Apache-2.0` allows (or denies) both. Licenses may be followed by a
+/// `WITH` exception (e.g. `Apache-2.0 WITH LLVM-exception`). A crate's license
+/// expression is evaluated as a whole, so `MIT OR GPL-3.0` is acceptable when
+/// `MIT` is allowed while `MIT AND GPL-3.0` is not when `GPL-3.0` is denied.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LicensePolicy {
+    /// Licenses crates may be used under. If empty, any license which is not
+    /// denied is accepted.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) allow: BTreeSet<String>,
+
+    /// Licenses crates may never be used under.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) deny: BTreeSet<String>,
+
+    /// Additional licenses accepted for specific crates, regardless of `allow`
+    /// and `deny`. Crates without a license expression are only accepted if
+    /// they have an exception.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) exceptions: BTreeMap<CrateNameAndVersionReq, BTreeSet<String>>,
+
+    /// Report violations as warnings instead of failing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) warn_only: bool,
+}
+
+impl LicensePolicy {
+    /// Describe every crate in `context` (other than workspace members) whose
+    /// license does not satisfy the policy.
+    pub(crate) fn violations(&self, context: &Context) -> Result<Vec<String>> {
+        let allow = parse_licensees(&self.allow, "allow")?;
+        let deny = parse_licensees(&self.deny, "deny")?;
+        let exceptions = self
+            .exceptions
+            .iter()
+            .map(|(req, licenses)| {
+                Ok((
+                    req,
+                    parse_licensees(licenses, &format!("exceptions[{req}]"))?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut violations = Vec::new();
+        for (id, crate_context) in &context.crates {
+            if context.workspace_members.contains_key(id) {
+                continue;
+            }
+
+            let matching_exceptions: Vec<_> = exceptions
+                .iter()
+                .filter(|(req, _)| req.matches_crate_id(id))
+                .collect();
+            let has_exception = !matching_exceptions.is_empty();
+            let crate_exceptions: Vec<&spdx::Licensee> = matching_exceptions
+                .into_iter()
+                .flat_map(|(_, licensees)| licensees)
+                .collect();
+
+            if let Some(violation) = Self::check(
+                id,
+                crate_context.license.as_deref(),
+                &allow,
+                &deny,
+                &crate_exceptions,
+                has_exception,
+            ) {
+                violations.push(violation);
+            }
+        }
+
+        Ok(violations)
+    }
+
+    fn check(
+        id: &CrateId,
+        license: Option<&str>,
+        allow: &[spdx::Licensee],
+        deny: &[spdx::Licensee],
+        exceptions: &[&spdx::Licensee],
+        has_exception: bool,
+    ) -> Option<String> {
+        let Some(license) = license else {
+            return match has_exception {
+                true => None,
+                false => Some(format!("`{id}` does not declare a license")),
+            };
+        };
+
+        let expression = match spdx::Expression::parse_mode(license, spdx::ParseMode::LAX) {
+            Ok(expression) => expression,
+            Err(_) => {
+                return Some(format!(
+                    "`{id}` has a license expression which is not valid SPDX: `{license}`"
+                ))
+            }
+        };
+
+        let is_accepted = |req: &spdx::LicenseReq| {
+            if exceptions.iter().any(|licensee| licensee.satisfies(req)) {
+                return true;
+            }
+            if deny.iter().any(|licensee| licensee.satisfies(req)) {
+                return false;
+            }
+            allow.is_empty() || allow.iter().any(|licensee| licensee.satisfies(req))
+        };
+
+        match expression.evaluate_with_failures(is_accepted) {
+            Ok(()) => None,
+            Err(failures) => Some(format!(
+                "`{id}` is licensed under `{license}` which is not accepted by the license policy (rejected: {})",
+                failures
+                    .iter()
+                    .map(|failure| failure.req.to_string())
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Parse the SPDX expressions of a policy field into the licenses they name.
+fn parse_licensees(licenses: &BTreeSet<String>, field: &str) -> Result<Vec<spdx::Licensee>> {
+    let mut licensees = Vec::new();
+    for license in licenses {
+        let expression =
+            spdx::Expression::parse_mode(license, spdx::ParseMode::LAX).with_context(|| {
+                format!("Invalid SPDX expression `{license}` in `license_policy.{field}`")
+            })?;
+        for requirement in expression.requirements() {
+            // `+` and `-or-later` describe which licenses a crate may be used under,
+            // not a single license which can be accepted.
+            if let spdx::LicenseItem::Spdx { or_later: true, .. } = requirement.req.license {
+                bail!(
+                    "Invalid SPDX expression `{license}` in `license_policy.{field}`: `{}` must name a single license version",
+                    requirement.req
+                );
+            }
+            licensees.push(spdx::Licensee::new(
+                requirement.req.license.clone(),
+                requirement.req.exception,
+            ));
+        }
+    }
+    Ok(licensees)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_context() -> Context {
+        serde_json::from_value(serde_json::json!({
+            "checksum": null,
+            "crates": {
+                "app 0.1.0": {
+                    "name": "app",
+                    "version": "0.1.0",
+                    "license": "GPL-3.0-only",
+                    "license_ids": [],
+                },
+                "dual 1.0.0": {
+                    "name": "dual",
+                    "version": "1.0.0",
+                    "license": "MIT/Apache-2.0",
+                    "license_ids": [],
+                },
+                "either 1.0.0": {
+                    "name": "either",
+                    "version": "1.0.0",
+                    "license": "MIT OR GPL-3.0-or-later",
+                    "license_ids": [],
+                },
+                "both 1.0.0": {
+                    "name": "both",
+                    "version": "1.0.0",
+                    "license": "MIT AND GPL-2.0-only",
+                    "license_ids": [],
+                },
+                "llvm 1.0.0": {
+                    "name": "llvm",
+                    "version": "1.0.0",
+                    "license": "Apache-2.0 WITH LLVM-exception",
+                    "license_ids": [],
+                },
+                "unlicensed 1.0.0": {
+                    "name": "unlicensed",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                },
+            },
+            "binary_crates": [],
+            "workspace_members": {
+                "app 0.1.0": "app",
+            },
+            "conditions": {},
+            "direct_deps": [],
+            "direct_dev_deps": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let policy = LicensePolicy {
+            allow: BTreeSet::from(["MIT".to_owned(), "Apache-2.0".to_owned()]),
+            deny: BTreeSet::from(["GPL-2.0".to_owned()]),
+            ..LicensePolicy::default()
+        };
+
+        assert_eq!(
+            policy.violations(&mock_context()).unwrap(),
+            vec![
+                "`both 1.0.0` is licensed under `MIT AND GPL-2.0-only` which is not accepted by the license policy (rejected: GPL-2.0)".to_owned(),
+                "`llvm 1.0.0` is licensed under `Apache-2.0 WITH LLVM-exception` which is not accepted by the license policy (rejected: Apache-2.0 WITH LLVM-exception)".to_owned(),
+                "`unlicensed 1.0.0` does not declare a license".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn exceptions_override_policy() {
+        let policy = LicensePolicy {
+            deny: BTreeSet::from(["GPL-2.0".to_owned(), "GPL-3.0".to_owned()]),
+            exceptions: BTreeMap::from([
+                (
+                    CrateNameAndVersionReq::new("both".to_owned(), "1".parse().unwrap()),
+                    BTreeSet::from(["GPL-2.0".to_owned()]),
+                ),
+                (
+                    CrateNameAndVersionReq::new("unlicensed".to_owned(), "*".parse().unwrap()),
+                    BTreeSet::new(),
+                ),
+            ]),
+            ..LicensePolicy::default()
+        };
+
+        assert!(policy.violations(&mock_context()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn policy_entries_are_expressions() {
+        let policy = LicensePolicy {
+            allow: BTreeSet::from([
+                "MIT OR Apache-2.0".to_owned(),
+                "Apache-2.0 WITH LLVM-exception".to_owned(),
+            ]),
+            deny: BTreeSet::from(["GPL-2.0 OR GPL-3.0".to_owned()]),
+            ..LicensePolicy::default()
+        };
+
+        assert_eq!(
+            policy.violations(&mock_context()).unwrap(),
+            vec![
+                "`both 1.0.0` is licensed under `MIT AND GPL-2.0-only` which is not accepted by the license policy (rejected: GPL-2.0)".to_owned(),
+                "`unlicensed 1.0.0` does not declare a license".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_policy() {
+        let policy = LicensePolicy {
+            allow: BTreeSet::from(["GPL-3.0-or-later".to_owned()]),
+            ..LicensePolicy::default()
+        };
+
+        assert!(policy.violations(&mock_context()).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Detection of crates resolved to more than one version.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CrateId, CrateNameAndVersionReq};
+use crate::context::Context;
+
+/// How to treat crates which are resolved to more than one version.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MultipleVersionsLevel {
+    /// Duplicate versions are accepted silently.
+    #[default]
+    Allow,
+
+    /// Duplicate versions are reported as warnings.
+    Warn,
+
+    /// Duplicate versions are errors.
+    Deny,
+}
+
+/// A policy for crates which are resolved to more than one version.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MultipleVersionsPolicy {
+    /// How duplicate versions are reported.
+    pub(crate) level: MultipleVersionsLevel,
+
+    /// Crate versions to ignore when looking for duplicates.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) skip: BTreeSet<CrateNameAndVersionReq>,
+}
+
+/// A crate which is resolved to more than one version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DuplicateCrate {
+    /// The name of the crate.
+    pub(crate) name: String,
+
+    /// Each version of the crate mapped to the workspace members which depend on it.
+    pub(crate) versions: BTreeMap<Version, BTreeSet<CrateId>>,
+}
+
+impl fmt::Display for DuplicateCrate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let versions = self
+            .versions
+            .iter()
+            .map(|(version, members)| match members.is_empty() {
+                true => format!("{version} (not used by any workspace member)"),
+                false => format!(
+                    "{version} (via {})",
+                    members
+                        .iter()
+                        .map(|member| format!("`{member}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "`{}` has multiple versions: {versions}", self.name)
+    }
+}
+
+impl MultipleVersionsPolicy {
+    /// Find every crate in `context` (other than workspace members) which is
+    /// resolved to more than one version.
+    pub(crate) fn duplicates(&self, context: &Context) -> Vec<DuplicateCrate> {
+        let mut by_name: BTreeMap<&str, BTreeSet<&CrateId>> = BTreeMap::new();
+        for id in context.crates.keys() {
+            if context.workspace_members.contains_key(id)
+                || self.skip.iter().any(|req| req.matches_crate_id(id))
+            {
+                continue;
+            }
+            by_name.entry(&id.name).or_default().insert(id);
+        }
+        by_name.retain(|_, ids| ids.len() > 1);
+
+        if by_name.is_empty() {
+            return Vec::new();
+        }
+
+        let used_by = Self::workspace_member_dependencies(context);
+
+        by_name
+            .into_iter()
+            .map(|(name, ids)| DuplicateCrate {
+                name: name.to_owned(),
+                versions: ids
+                    .into_iter()
+                    .map(|id| {
+                        let members = used_by
+                            .iter()
+                            .filter(|(_, deps)| deps.contains(id))
+                            .map(|(member, _)| (*member).clone())
+                            .collect();
+                        (id.version.clone(), members)
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Collect the transitive dependencies of each workspace member, including
+    /// build dependencies and the member's own dev dependencies.
+    fn workspace_member_dependencies(context: &Context) -> BTreeMap<&CrateId, BTreeSet<&CrateId>> {
+        context
+            .workspace_members
+            .keys()
+            .map(|member| {
+                let mut reachable: BTreeSet<&CrateId> = BTreeSet::new();
+                let mut queue: Vec<(&CrateId, bool)> = vec![(member, true)];
+                while let Some((id, is_member)) = queue.pop() {
+                    let Some(crate_context) = context.crates.get(id) else {
+                        continue;
+                    };
+
+                    let attrs = &crate_context.common_attrs;
+                    let mut deps = attrs.deps.values();
+                    deps.extend(attrs.proc_macro_deps.values());
+                    if is_member {
+                        deps.extend(attrs.deps_dev.values());
+                        deps.extend(attrs.proc_macro_deps_dev.values());
+                    }
+                    if let Some(attrs) = &crate_context.build_script_attrs {
+                        deps.extend(attrs.deps.values());
+                        deps.extend(attrs.link_deps.values());
+                        deps.extend(attrs.proc_macro_deps.values());
+                    }
+
+                    for dep in deps {
+                        if let Some((dep_id, _)) = context.crates.get_key_value(&dep.id) {
+                            if reachable.insert(dep_id) {
+                                queue.push((dep_id, false));
+                            }
+                        }
+                    }
+                }
+                (member, reachable)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mock_context() -> Context {
+        serde_json::from_value(serde_json::json!({
+            "checksum": null,
+            "crates": {
+                "app 0.1.0": {
+                    "name": "app",
+                    "version": "0.1.0",
+                    "license_ids": [],
+                    "common_attrs": {
+                        "deps": [
+                            {"id": "syn 1.0.0", "target": "syn"},
+                            {"id": "serde 1.0.0", "target": "serde"},
+                        ],
+                        "deps_dev": [{"id": "windows-sys 0.48.0", "target": "windows_sys"}],
+                    },
+                },
+                "lib 0.1.0": {
+                    "name": "lib",
+                    "version": "0.1.0",
+                    "license_ids": [],
+                    "common_attrs": {
+                        "deps": [{"id": "windows-sys 0.52.0", "target": "windows_sys"}],
+                    },
+                },
+                "serde 1.0.0": {
+                    "name": "serde",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                    "build_script_attrs": {
+                        "deps": [{"id": "syn 2.0.0", "target": "syn"}],
+                    },
+                },
+                "syn 1.0.0": {
+                    "name": "syn",
+                    "version": "1.0.0",
+                    "license_ids": [],
+                },
+                "syn 2.0.0": {
+                    "name": "syn",
+                    "version": "2.0.0",
+                    "license_ids": [],
+                },
+                "windows-sys 0.48.0": {
+                    "name": "windows-sys",
+                    "version": "0.48.0",
+                    "license_ids": [],
+                },
+                "windows-sys 0.52.0": {
+                    "name": "windows-sys",
+                    "version": "0.52.0",
+                    "license_ids": [],
+                },
+            },
+            "binary_crates": [],
+            "workspace_members": {
+                "app 0.1.0": "app",
+                "lib 0.1.0": "lib",
+            },
+            "conditions": {},
+            "direct_deps": [],
+            "direct_dev_deps": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn report_duplicates() {
+        let policy = MultipleVersionsPolicy {
+            level: MultipleVersionsLevel::Warn,
+            ..MultipleVersionsPolicy::default()
+        };
+
+        assert_eq!(
+            policy
+                .duplicates(&mock_context())
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "`syn` has multiple versions: 1.0.0 (via `app 0.1.0`), 2.0.0 (via `app 0.1.0`)",
+                "`windows-sys` has multiple versions: 0.48.0 (via `app 0.1.0`), 0.52.0 (via `lib 0.1.0`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_duplicates() {
+        let policy = MultipleVersionsPolicy {
+            level: MultipleVersionsLevel::Deny,
+            skip: BTreeSet::from([
+                CrateNameAndVersionReq::new("syn".to_owned(), "1".parse().unwrap()),
+                CrateNameAndVersionReq::new("windows-sys".to_owned(), "*".parse().unwrap()),
+            ]),
+        };
+
+        assert!(policy.duplicates(&mock_context()).is_empty());
+    }
+}
@@ -4,19 +4,19 @@ pub(crate) mod crate_context;
 mod platforms;
 
 use std::collections::{BTreeMap, BTreeSet};
-use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::config::label_injection;
+use crate::config::multiple_versions::MultipleVersionsLevel;
 use crate::config::{CrateId, LabelInjectionMapping, RenderConfig};
 use crate::context::platforms::resolve_cfg_platforms;
-use crate::lockfile::Digest;
+use crate::lockfile::{read_lockfile, Digest, DigestComponents};
 use crate::metadata::{Annotations, Dependency};
-use crate::select::Select;
+use crate::select::{CompoundCondition, Select};
 use crate::utils::target_triple::TargetTriple;
 
 pub(crate) use self::crate_context::*;
@@ -28,6 +28,10 @@ pub(crate) struct Context {
     /// The collective checksum of all inputs to the context
     pub(crate) checksum: Option<Digest>,
 
+    /// The digests of each input which contributed to `checksum`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) checksum_components: Option<DigestComponents>,
+
     /// The collection of all crates that make up the dependency graph
     pub(crate) crates: BTreeMap<CrateId, CrateContext>,
 
@@ -41,6 +45,11 @@ pub(crate) struct Context {
     /// A mapping of `cfg` flags to platform triples supporting the configuration
     pub(crate) conditions: BTreeMap<String, BTreeSet<TargetTriple>>,
 
+    /// A mapping of `cfg` flags to platforms which only support the configuration
+    /// when certain `config_setting`s are enabled. See [crate::config::Config::cfg_settings].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) compound_conditions: BTreeMap<String, BTreeSet<CompoundCondition>>,
+
     /// A list of crates visible to any bazel module.
     pub(crate) direct_deps: BTreeSet<CrateId>,
 
@@ -57,9 +66,9 @@ pub(crate) struct Context {
 }
 
 impl Context {
+    /// Load a lockfile in either of the layouts written by [crate::lockfile::write_lockfile].
     pub(crate) fn try_from_path<T: AsRef<Path>>(path: T) -> Result<Self> {
-        let data = fs::read_to_string(path.as_ref())?;
-        Ok(serde_json::from_str(&data)?)
+        read_lockfile(path.as_ref())
     }
 
     pub(crate) fn new(annotations: Annotations, sources_are_present: bool) -> anyhow::Result<Self> {
@@ -75,10 +84,15 @@ impl Context {
                     &annotations.lockfile.crates,
                     &annotations.pairred_extras,
                     &annotations.metadata.workspace_metadata.tree_metadata,
+                    &annotations.metadata.workspace_metadata.host_tree_metadata,
                     annotations.config.generate_binaries,
                     annotations.config.generate_build_scripts,
+                    annotations.config.generate_tests,
                     sources_are_present,
-                )?;
+                )?
+                .with_build_script_outputs(
+                    &annotations.metadata.workspace_metadata.build_script_outputs,
+                );
                 let id = CrateId::new(context.name.clone(), context.version.clone());
                 Ok::<_, anyhow::Error>((id, context))
             })
@@ -95,9 +109,11 @@ impl Context {
 
         // Given a list of all conditional dependencies, build a set of platform
         // triples which satisfy the conditions.
-        let conditions = resolve_cfg_platforms(
+        let (conditions, compound_conditions) = resolve_cfg_platforms(
             crates.values().collect(),
             &annotations.config.supported_platform_triples,
+            &annotations.metadata.workspace_metadata.target_cfgs,
+            &annotations.config.cfg_settings,
         )?;
 
         // Generate a list of all workspace members
@@ -151,16 +167,48 @@ impl Context {
 
         let unused_patches = annotations.lockfile.unused_patches;
 
-        Ok(Self {
+        let context = Self {
             checksum: None,
+            checksum_components: None,
             crates,
             binary_crates,
             workspace_members,
             conditions,
+            compound_conditions,
             direct_dev_deps: direct_dev_deps.difference(&direct_deps).cloned().collect(),
             direct_deps,
             unused_patches,
-        })
+        };
+
+        // Violations of a `warn_only` policy are reported by the caller.
+        if let Some(policy) = &annotations.config.license_policy {
+            let violations = policy.violations(&context)?;
+            if !policy.warn_only && !violations.is_empty() {
+                bail!(
+                    "The following crates violate the license policy:\n{}",
+                    violations.join("\n")
+                );
+            }
+        }
+
+        // Duplicates under the `warn` level are reported by the caller.
+        if let Some(policy) = &annotations.config.multiple_versions {
+            if policy.level == MultipleVersionsLevel::Deny {
+                let duplicates = policy.duplicates(&context);
+                if !duplicates.is_empty() {
+                    bail!(
+                        "The following crates are resolved to multiple versions:\n{}",
+                        duplicates
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    );
+                }
+            }
+        }
+
+        Ok(context)
     }
 
     /// Rewrite apparent-label prefixes in every string under this Context to
@@ -309,6 +357,10 @@ pub struct SingleBuildFileRenderContext {
     /// See Context::conditions.
     pub(crate) platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
 
+    /// See Context::compound_conditions.
+    #[serde(default)]
+    pub(crate) compound_conditions: Arc<BTreeMap<String, BTreeSet<CompoundCondition>>>,
+
     /// The CrateContext for the crate being rendered.
     pub(crate) crate_context: Arc<CrateContext>,
 }
@@ -319,6 +371,8 @@ mod test {
     use camino::Utf8Path;
     use semver::Version;
 
+    use crate::config::license_policy::LicensePolicy;
+    use crate::config::multiple_versions::MultipleVersionsPolicy;
     use crate::config::Config;
 
     fn mock_context_common() -> Context {
@@ -363,6 +417,70 @@ mod test {
         Context::new(annotations, false).unwrap()
     }
 
+    #[test]
+    fn license_policy_violations_are_errors() {
+        let config = |warn_only| Config {
+            license_policy: Some(LicensePolicy {
+                allow: BTreeSet::from(["Apache-2.0".to_owned()]),
+                deny: BTreeSet::from(["Apache-2.0".to_owned()]),
+                warn_only,
+                ..LicensePolicy::default()
+            }),
+            ..Config::default()
+        };
+        let annotations = |config| {
+            Annotations::new(
+                crate::test::metadata::common(),
+                &None,
+                crate::test::lockfile::common(),
+                config,
+                Utf8Path::new("/tmp/bazelworkspace"),
+            )
+            .unwrap()
+        };
+
+        let err = Context::new(annotations(config(false)), false).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`bitflags 1.3.2` is licensed under `MIT/Apache-2.0`"));
+
+        assert!(Context::new(annotations(config(true)), false).is_ok());
+    }
+
+    #[test]
+    fn multiple_versions_denied() {
+        let config = Config {
+            multiple_versions: Some(MultipleVersionsPolicy {
+                level: MultipleVersionsLevel::Deny,
+                ..MultipleVersionsPolicy::default()
+            }),
+            ..Config::default()
+        };
+
+        let annotations = Annotations::new(
+            crate::test::metadata::alias(),
+            &None,
+            crate::test::lockfile::alias(),
+            config.clone(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+        let err = Context::new(annotations, false).unwrap_err().to_string();
+        assert!(err.contains(
+            "`log` has multiple versions: 0.3.9 (via `aliases 0.1.0`), 0.4.21 (via `aliases 0.1.0`)"
+        ));
+
+        let annotations = Annotations::new(
+            crate::test::metadata::common(),
+            &None,
+            crate::test::lockfile::common(),
+            config,
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+        assert!(Context::new(annotations, false).is_ok());
+    }
+
     #[test]
     fn workspace_member_deps_collection() {
         let context = mock_context_common();
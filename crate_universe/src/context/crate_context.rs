@@ -6,10 +6,12 @@ use std::str::FromStr;
 use camino::Utf8PathBuf;
 use cargo_metadata::{Node, Package, PackageId};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
 
 use crate::config::{AliasRule, CrateId, GenBinaries};
 use crate::metadata::{
-    CrateAnnotation, Dependency, PairedExtras, SourceAnnotation, TreeResolverMetadata,
+    BuildScriptOutputs, CrateAnnotation, Dependency, HostTreeMetadata, PairedExtras,
+    SourceAnnotation, TreeResolverMetadata,
 };
 use crate::select::Select;
 use crate::utils::sanitize_module_name;
@@ -51,6 +53,11 @@ pub(crate) struct TargetAttributes {
 
     /// A glob pattern of all source files required by the target
     pub(crate) srcs: Glob,
+
+    /// The configurations the target is limited to, if it is not compatible with all of them.
+    /// This is used for binaries whose `required-features` are only enabled on some platforms.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) compatible_configurations: Option<BTreeSet<String>>,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone)]
@@ -66,17 +73,45 @@ pub(crate) enum Rule {
 
     /// `cargo_build_script`
     BuildScript(TargetAttributes),
+
+    /// `rust_shared_library`
+    SharedLibrary(TargetAttributes),
+
+    /// `rust_static_library`
+    StaticLibrary(TargetAttributes),
+
+    /// `rust_test` for an integration test
+    Test(TargetAttributes),
+
+    /// `rust_test` for the unit tests of a library
+    UnitTest(TargetAttributes),
 }
 
 impl Rule {
     /// The keys that can be used in override_targets to override these Rule sources.
     /// These intentionally match the accepted `Target.kind`s returned by cargo-metadata.
-    pub(crate) fn override_target_key(&self) -> &'static str {
+    /// Unit tests have no such kind and cannot be overridden.
+    pub(crate) fn override_target_key(&self) -> Option<&'static str> {
         match self {
-            Self::Library(..) => "lib",
-            Self::ProcMacro(..) => "proc-macro",
-            Self::Binary(..) => "bin",
-            Self::BuildScript(..) => "custom-build",
+            Self::Library(..) => Some("lib"),
+            Self::ProcMacro(..) => Some("proc-macro"),
+            Self::Binary(..) => Some("bin"),
+            Self::BuildScript(..) => Some("custom-build"),
+            Self::SharedLibrary(..) => Some("cdylib"),
+            Self::StaticLibrary(..) => Some("staticlib"),
+            Self::Test(..) => Some("test"),
+            Self::UnitTest(..) => None,
+        }
+    }
+
+    /// The name of the alias rendered in place of this rule when it is overridden,
+    /// matching the name of the target it replaces.
+    pub(crate) fn override_alias_name(&self) -> String {
+        match self {
+            Self::SharedLibrary(attrs) => format!("{}__cdylib", attrs.crate_name),
+            Self::StaticLibrary(attrs) => format!("{}__staticlib", attrs.crate_name),
+            Self::Test(attrs) => format!("{}__test", attrs.crate_name),
+            _ => self.crate_name().to_owned(),
         }
     }
 
@@ -85,7 +120,11 @@ impl Rule {
             Self::Library(attrs)
             | Self::ProcMacro(attrs)
             | Self::Binary(attrs)
-            | Self::BuildScript(attrs) => &attrs.crate_name,
+            | Self::BuildScript(attrs)
+            | Self::SharedLibrary(attrs)
+            | Self::StaticLibrary(attrs)
+            | Self::Test(attrs)
+            | Self::UnitTest(attrs) => &attrs.crate_name,
         }
     }
 }
@@ -307,6 +346,37 @@ impl Default for BuildScriptAttributes {
     }
 }
 
+/// A crate feature which can be switched off at build time, along with
+/// everything which is only enabled through it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct FeatureToggle {
+    /// The feature itself and any features only it enables.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) crate_features: BTreeSet<String>,
+
+    /// Optional dependencies only enabled through the feature.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) deps: BTreeSet<CrateDependency>,
+
+    /// Optional proc-macro dependencies only enabled through the feature.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) proc_macro_deps: BTreeSet<CrateDependency>,
+}
+
+/// Attributes of a crate's distinct host library. These are only populated for crates
+/// which are built for the host with different features or dependencies than for the
+/// target of the same platform. See [HostTreeMetadata].
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct HostAttributes {
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) crate_features: Select<BTreeSet<String>>,
+
+    #[serde(skip_serializing_if = "Select::is_empty")]
+    pub(crate) deps: Select<BTreeSet<CrateDependency>>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct CrateContext {
     /// The package name of the current crate
@@ -344,6 +414,11 @@ pub(crate) struct CrateContext {
     #[serde(default)]
     pub(crate) build_script_attrs: Option<BuildScriptAttributes>,
 
+    /// Optional attributes for a distinct library built for the host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub(crate) host_attrs: Option<HostAttributes>,
+
     /// The license used by the crate
     #[serde(default)]
     pub(crate) license: Option<String>,
@@ -380,6 +455,16 @@ pub(crate) struct CrateContext {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     pub(crate) override_targets: BTreeMap<String, Label>,
+
+    /// Features which can be switched off at build time, keyed by feature name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[serde(default)]
+    pub(crate) feature_toggles: BTreeMap<String, FeatureToggle>,
+}
+
+/// The name of the distinct host library of a library target. See [HostAttributes].
+pub(crate) fn host_library_name(library_target_name: &str) -> String {
+    format!("{}__host", library_target_name)
 }
 
 impl CrateContext {
@@ -390,8 +475,10 @@ impl CrateContext {
         source_annotations: &BTreeMap<PackageId, SourceAnnotation>,
         extras: &BTreeMap<CrateId, PairedExtras>,
         resolver_data: &TreeResolverMetadata,
+        host_resolver_data: &HostTreeMetadata,
         include_binaries: bool,
         include_build_scripts: bool,
+        include_tests: bool,
         sources_are_present: bool,
     ) -> anyhow::Result<Self> {
         let package: &Package = &packages[&annotation.node.id];
@@ -403,7 +490,10 @@ impl CrateContext {
             // Unfortunately, The package graph and resolve graph of cargo metadata have different representations
             // for the crate names (resolve graph sanitizes names to match module names) so to get the rest of this
             // content to align when rendering, the dependency target needs to be explicitly sanitized.
-            let target = sanitize_module_name(&dep.target_name);
+            let mut target = sanitize_module_name(&dep.target_name);
+            if dep.host {
+                target = host_library_name(&target);
+            }
 
             CrateDependency {
                 id: CrateId::new(pkg.name.clone(), pkg.version.clone()),
@@ -426,18 +516,21 @@ impl CrateContext {
             .clone()
             .map(new_crate_dep);
 
-        let crate_features = resolver_data
-            .get(&current_crate_id)
-            .map(|tree_data| {
-                let mut select = Select::<BTreeSet<String>>::new();
-                for (config, data) in tree_data.items() {
-                    for feature in data.features {
-                        select.insert(feature, config.clone());
+        let collect_features = |resolver_data: &TreeResolverMetadata| {
+            resolver_data
+                .get(&current_crate_id)
+                .map(|tree_data| {
+                    let mut select = Select::<BTreeSet<String>>::new();
+                    for (config, data) in tree_data.items() {
+                        for feature in data.features {
+                            select.insert(feature, config.clone());
+                        }
                     }
-                }
-                select
-            })
-            .unwrap_or_default();
+                    select
+                })
+                .unwrap_or_default()
+        };
+        let crate_features = collect_features(resolver_data);
 
         // Gather all "common" attributes
         let mut common_attrs = CommonAttributes {
@@ -468,15 +561,61 @@ impl CrateContext {
                 &gen_none
             });
 
+        // Binaries are only generated where their `required-features` are enabled,
+        // including any features added through annotations.
+        let enabled_features = match package_extra
+            .and_then(|(_, settings)| settings.crate_extra.crate_features.as_ref())
+        {
+            Some(extra) => Select::merge(common_attrs.crate_features.clone(), extra.clone()),
+            None => common_attrs.crate_features.clone(),
+        };
+
+        let include_native_libraries = package_extra
+            .and_then(|(_, settings)| settings.crate_extra.gen_native_libraries)
+            .unwrap_or(false);
+
+        // Cargo only resolves dev dependencies for workspace members, so tests of other
+        // packages which declare any would fail to build.
+        let explicit_tests = package_extra.and_then(|(_, settings)| settings.crate_extra.gen_tests);
+        let mut include_tests = explicit_tests.unwrap_or(include_tests);
+        if include_tests && !Self::dev_dependencies_are_resolved(package, &annotation.node) {
+            if explicit_tests == Some(true) {
+                warn!(
+                    "`gen_tests` is set for {} {} but its tests are skipped as its dev dependencies were not resolved. Only workspace members have their dev dependencies resolved by Cargo.",
+                    package.name, package.version
+                );
+            } else {
+                debug!(
+                    "Skipping tests of {} {} whose dev dependencies were not resolved",
+                    package.name, package.version
+                );
+            }
+            include_tests = false;
+        }
+
         // Iterate over each target and produce a Bazel target for all supported "kinds"
         let targets = Self::collect_targets(
             &annotation.node,
             packages,
             gen_binaries,
+            &enabled_features,
             include_build_scripts,
+            include_native_libraries,
+            include_tests,
             sources_are_present,
         )?;
 
+        // Toggleable features are split out of the unconditional attributes after
+        // targets are collected so `required-features` still see them as enabled.
+        let feature_toggles = match package_extra
+            .and_then(|(_, settings)| settings.crate_extra.toggleable_features.as_ref())
+        {
+            Some(toggleable) => {
+                Self::collect_feature_toggles(package, toggleable, &mut common_attrs)
+            }
+            None => BTreeMap::new(),
+        };
+
         // Parse the library crate name from the set of included targets
         let library_target_name = {
             let lib_targets: Vec<&TargetAttributes> = targets
@@ -529,6 +668,18 @@ impl CrateContext {
             None
         };
 
+        // Crates built for the host with different features than for the target get a
+        // distinct host library. Proc-macros are only ever built for the host.
+        let host_attrs = match library_target_name {
+            Some(_) if host_resolver_data.variants.contains_key(&current_crate_id) => {
+                Some(HostAttributes {
+                    crate_features: collect_features(&host_resolver_data.variants),
+                    deps: annotation.deps.host_normal_deps.clone().map(new_crate_dep),
+                })
+            }
+            _ => None,
+        };
+
         // Save the repository information for the current crate
         let repository = source_annotations.get(&package.id).cloned();
 
@@ -564,11 +715,13 @@ impl CrateContext {
             library_target_name,
             common_attrs,
             build_script_attrs,
+            host_attrs,
             additive_build_file_content: None,
             disable_pipelining: false,
             extra_aliased_targets: BTreeMap::new(),
             alias_rule: None,
             override_targets: BTreeMap::new(),
+            feature_toggles,
         }
         .with_overrides(extras))
     }
@@ -580,6 +733,16 @@ impl CrateContext {
         if let Some(paired_override) = extras.get(&id) {
             let crate_extra = &paired_override.crate_extra;
 
+            // Host library features
+            if let (Some(extra), Some(host_attrs)) =
+                (&crate_extra.crate_features, &mut self.host_attrs)
+            {
+                host_attrs.crate_features = Select::merge(
+                    std::mem::take(&mut host_attrs.crate_features),
+                    extra.clone(),
+                );
+            }
+
             // Deps
             if let Some(extra) = &crate_extra.deps {
                 self.common_attrs.extra_deps =
@@ -798,6 +961,83 @@ impl CrateContext {
         self
     }
 
+    /// Replace the crate's build script with outputs pinned by running it ahead of time.
+    pub(crate) fn with_build_script_outputs(mut self, outputs: &BuildScriptOutputs) -> Self {
+        let id = CrateId::new(self.name.clone(), self.version.clone());
+        let Some(outputs) = outputs.get(&id) else {
+            return self;
+        };
+        if self.build_script_attrs.take().is_none() {
+            return self;
+        }
+
+        let mut build_script_label = None;
+        self.targets.retain(|rule| match rule {
+            Rule::BuildScript(attrs) => {
+                build_script_label =
+                    Some(Label::from_str(&format!(":{}", attrs.crate_name)).unwrap());
+                false
+            }
+            _ => true,
+        });
+        if let Some(label) = build_script_label {
+            self.common_attrs
+                .extra_deps
+                .take_common(|dep| dep == &label);
+        }
+
+        // Flags and environment variables emitted on every platform are left unconfigured.
+        let mut common_flags: Option<BTreeSet<String>> = None;
+        let mut common_env: Option<BTreeMap<String, String>> = None;
+        for output in outputs.values() {
+            let flags: BTreeSet<String> = output.rustc_flags().into_iter().collect();
+            common_flags = Some(match common_flags {
+                Some(common) => common.intersection(&flags).cloned().collect(),
+                None => flags,
+            });
+            common_env = Some(match common_env {
+                Some(mut common) => {
+                    common.retain(|key, value| output.env.get(key) == Some(value));
+                    common
+                }
+                None => output.env.clone(),
+            });
+        }
+        let common_flags = common_flags.unwrap_or_default();
+        let common_env = common_env.unwrap_or_default();
+
+        // Values in `rustc_env` are subject to make variable expansion.
+        let escape = |value: &str| value.replace('$', "$$");
+
+        for flag in &common_flags {
+            self.common_attrs.rustc_flags.insert(flag.clone(), None);
+        }
+        for (key, value) in &common_env {
+            self.common_attrs
+                .rustc_env
+                .insert((key.clone(), escape(value)), None);
+        }
+        for (triple, output) in outputs {
+            let configuration = Some(triple.to_bazel());
+            for flag in output.rustc_flags() {
+                if !common_flags.contains(&flag) {
+                    self.common_attrs
+                        .rustc_flags
+                        .insert(flag, configuration.clone());
+                }
+            }
+            for (key, value) in &output.env {
+                if !common_env.contains_key(key) {
+                    self.common_attrs
+                        .rustc_env
+                        .insert((key.clone(), escape(value)), configuration.clone());
+                }
+            }
+        }
+
+        self
+    }
+
     fn locate_license_file(package: &Package) -> Option<String> {
         if let Some(license_file_path) = &package.license_file {
             return Some(license_file_path.to_string());
@@ -839,11 +1079,15 @@ impl CrateContext {
     }
 
     /// Collect all Bazel targets that should be generated for a particular Package
+    #[allow(clippy::too_many_arguments)]
     fn collect_targets(
         node: &Node,
         packages: &BTreeMap<PackageId, Package>,
         gen_binaries: &GenBinaries,
+        enabled_features: &Select<BTreeSet<String>>,
         include_build_scripts: bool,
+        include_native_libraries: bool,
+        include_tests: bool,
         sources_are_present: bool,
     ) -> anyhow::Result<BTreeSet<Rule>> {
         let package = &packages[&node.id];
@@ -854,7 +1098,7 @@ impl CrateContext {
             .parent()
             .expect("Every manifest should have a parent directory");
 
-        package
+        let mut targets = package
             .targets
             .iter()
             .flat_map(|target| {
@@ -880,6 +1124,7 @@ impl CrateContext {
                             crate_name,
                             crate_root,
                             srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations: None,
                         })));
                     }
 
@@ -889,6 +1134,7 @@ impl CrateContext {
                             crate_name,
                             crate_root,
                             srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations: None,
                         })));
                     }
 
@@ -898,6 +1144,49 @@ impl CrateContext {
                             crate_name,
                             crate_root,
                             srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations: None,
+                        })));
+                    }
+
+                    // Check to see if the dependencies is an integration test target
+                    if include_tests && target.test && matches!(kind, cargo_metadata::TargetKind::Test) {
+                        let compatible_configurations = Self::required_features_configurations(
+                            package,
+                            &target.required_features,
+                            enabled_features,
+                        );
+
+                        // Skip tests whose required features are never enabled
+                        if compatible_configurations
+                            .as_ref()
+                            .is_some_and(BTreeSet::is_empty)
+                        {
+                            return None;
+                        }
+
+                        return Some(Ok(Rule::Test(TargetAttributes {
+                            crate_name,
+                            crate_root,
+                            srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations,
+                        })));
+                    }
+
+                    // Check to see if the dependencies is a shared or static library target
+                    if include_native_libraries && matches!(kind, cargo_metadata::TargetKind::CDyLib) {
+                        return Some(Ok(Rule::SharedLibrary(TargetAttributes {
+                            crate_name,
+                            crate_root,
+                            srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations: None,
+                        })));
+                    }
+                    if include_native_libraries && matches!(kind, cargo_metadata::TargetKind::StaticLib) {
+                        return Some(Ok(Rule::StaticLibrary(TargetAttributes {
+                            crate_name,
+                            crate_root,
+                            srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations: None,
                         })));
                     }
 
@@ -908,18 +1197,259 @@ impl CrateContext {
                             GenBinaries::Some(set) => set.contains(&target.name),
                         }
                     {
+                        let compatible_configurations = Self::required_features_configurations(
+                            package,
+                            &target.required_features,
+                            enabled_features,
+                        );
+
+                        // Skip binaries whose required features are never enabled
+                        if compatible_configurations
+                            .as_ref()
+                            .is_some_and(BTreeSet::is_empty)
+                        {
+                            return None;
+                        }
+
                         return Some(Ok(Rule::Binary(TargetAttributes {
                             crate_name: target.name.clone(),
                             crate_root,
                             srcs: Glob::new_rust_srcs(!sources_are_present),
+                            compatible_configurations,
                         })));
                     }
 
                     None
                 })
             })
+            .collect::<anyhow::Result<BTreeSet<Rule>>>()?;
+
+        // Unit tests are compiled from the library's sources. Proc-macro
+        // libraries are not supported.
+        if include_tests {
+            let unit_tests: Vec<Rule> = package
+                .targets
+                .iter()
+                .filter(|target| target.test)
+                .filter_map(|target| {
+                    targets.iter().find_map(|rule| match rule {
+                        Rule::Library(attrs)
+                            if attrs.crate_name == sanitize_module_name(&target.name) =>
+                        {
+                            Some(Rule::UnitTest(attrs.clone()))
+                        }
+                        _ => None,
+                    })
+                })
+                .collect();
+            targets.extend(unit_tests);
+        }
+
+        Ok(targets)
+    }
+
+    /// Whether every dev dependency declared by a package was resolved by Cargo.
+    fn dev_dependencies_are_resolved(package: &Package, node: &Node) -> bool {
+        let declares_dev_deps = package
+            .dependencies
+            .iter()
+            .any(|dep| dep.kind == cargo_metadata::DependencyKind::Development);
+        let resolved_dev_deps = node.deps.iter().any(|dep| {
+            dep.dep_kinds
+                .iter()
+                .any(|kind| kind.kind == cargo_metadata::DependencyKind::Development)
+        });
+
+        !declares_dev_deps || resolved_dev_deps
+    }
+
+    /// Determine the configurations in which all of a target's `required-features`
+    /// are enabled. `None` is returned if they're enabled unconditionally.
+    fn required_features_configurations(
+        package: &Package,
+        required_features: &[String],
+        enabled_features: &Select<BTreeSet<String>>,
+    ) -> Option<BTreeSet<String>> {
+        // Features of dependencies (`dep/feature`) only require the dependency itself,
+        // which is only tracked as a feature if it's optional.
+        let required: BTreeSet<&str> = required_features
+            .iter()
+            .filter_map(|feature| match feature.split_once('/') {
+                Some((dep, _)) => {
+                    let dep = dep.trim_end_matches('?');
+                    package.features.contains_key(dep).then_some(dep)
+                }
+                None => Some(feature.as_str()),
+            })
+            .collect();
+
+        let (common, selects) = enabled_features.clone().into_parts();
+        let missing: BTreeSet<&str> = required
+            .into_iter()
+            .filter(|feature| !common.contains(*feature))
+            .collect();
+        if missing.is_empty() {
+            return None;
+        }
+
+        Some(
+            selects
+                .into_iter()
+                .filter(|(_, features)| missing.iter().all(|feature| features.contains(*feature)))
+                .map(|(configuration, _)| configuration)
+                .collect(),
+        )
+    }
+
+    /// Move the unconditionally enabled features listed in `toggleable`, and the
+    /// features and optional dependencies which only they enable, out of
+    /// `common_attrs`. Anything which is also reachable from a feature that isn't
+    /// toggled, or from more than one toggled feature, stays unconditional, as
+    /// do toggled features which are implied by some other feature.
+    fn collect_feature_toggles(
+        package: &Package,
+        toggleable: &BTreeSet<String>,
+        common_attrs: &mut CommonAttributes,
+    ) -> BTreeMap<String, FeatureToggle> {
+        let enabled: BTreeSet<String> = common_attrs
+            .crate_features
+            .items()
+            .into_iter()
+            .filter_map(|(configuration, feature)| configuration.is_none().then_some(feature))
+            .collect();
+        let toggled: BTreeSet<&String> = toggleable.intersection(&enabled).collect();
+        if toggled.is_empty() {
+            return BTreeMap::new();
+        }
+
+        // Everything reachable from a feature that isn't toggled is always enabled.
+        let (reachable_from_toggled, _) =
+            Self::feature_closure(package, toggled.iter().map(|feature| feature.as_str()));
+        let all_features = common_attrs.crate_features.values();
+        let (always_features, always_deps) = Self::feature_closure(
+            package,
+            all_features
+                .iter()
+                .filter(|feature| !reachable_from_toggled.contains(*feature))
+                .map(String::as_str),
+        );
+
+        let mut owned: BTreeMap<&str, (BTreeSet<String>, BTreeSet<String>)> = toggled
+            .iter()
+            .map(|toggle| {
+                let (features, deps) = Self::feature_closure(package, [toggle.as_str()]);
+                (
+                    toggle.as_str(),
+                    (
+                        features
+                            .into_iter()
+                            .filter(|feature| {
+                                enabled.contains(feature) && !always_features.contains(feature)
+                            })
+                            .collect(),
+                        deps.difference(&always_deps).cloned().collect(),
+                    ),
+                )
+            })
+            .collect();
+
+        // Items shared between toggles can't be gated by any single one of them.
+        let count = |items: Vec<&String>| {
+            items.into_iter().fold(BTreeMap::new(), |mut acc, item| {
+                *acc.entry(item.clone()).or_insert(0_usize) += 1;
+                acc
+            })
+        };
+        let feature_counts = count(owned.values().flat_map(|(features, _)| features).collect());
+        let dep_counts = count(owned.values().flat_map(|(_, deps)| deps).collect());
+        for (features, deps) in owned.values_mut() {
+            features.retain(|feature| feature_counts[feature] == 1);
+            deps.retain(|dep| dep_counts[dep] == 1);
+        }
+        owned.retain(|toggle, (features, _)| features.contains(*toggle));
+
+        // Optional dependencies are matched to their entry in the manifest to
+        // recover the name features refer to them by.
+        let dependency_name = |dep: &CrateDependency| -> Option<String> {
+            package
+                .dependencies
+                .iter()
+                .filter(|d| d.optional && d.kind == cargo_metadata::DependencyKind::Normal)
+                .find(|d| d.name == dep.id.name && d.req.matches(&dep.id.version))
+                .map(|d| d.rename.clone().unwrap_or_else(|| d.name.clone()))
+        };
+
+        owned
+            .into_iter()
+            .map(|(toggle, (crate_features, deps))| {
+                let take_deps = |select: &mut Select<BTreeSet<CrateDependency>>| {
+                    select.take_common(|dep| {
+                        dependency_name(dep).is_some_and(|name| deps.contains(&name))
+                    })
+                };
+                let toggle_deps = take_deps(&mut common_attrs.deps);
+                let toggle_proc_macro_deps = take_deps(&mut common_attrs.proc_macro_deps);
+                common_attrs
+                    .crate_features
+                    .take_common(|feature| crate_features.contains(feature));
+
+                (
+                    toggle.to_owned(),
+                    FeatureToggle {
+                        crate_features,
+                        deps: toggle_deps,
+                        proc_macro_deps: toggle_proc_macro_deps,
+                    },
+                )
+            })
             .collect()
     }
+
+    /// Collect the features and optional dependency names transitively enabled
+    /// by `roots` according to the package's `[features]` table.
+    fn feature_closure<'a>(
+        package: &Package,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> (BTreeSet<String>, BTreeSet<String>) {
+        let is_optional_dependency = |name: &str| {
+            package
+                .dependencies
+                .iter()
+                .any(|d| d.optional && d.rename.as_deref().unwrap_or(&d.name) == name)
+        };
+
+        let mut features = BTreeSet::new();
+        let mut deps = BTreeSet::new();
+        let mut queue: Vec<&str> = roots.into_iter().collect();
+        while let Some(feature) = queue.pop() {
+            if !features.insert(feature.to_owned()) {
+                continue;
+            }
+
+            // Optional dependencies without an explicit feature have an implicit one.
+            let Some(entries) = package.features.get(feature) else {
+                if is_optional_dependency(feature) {
+                    deps.insert(feature.to_owned());
+                }
+                continue;
+            };
+
+            for entry in entries {
+                if let Some(dep) = entry.strip_prefix("dep:") {
+                    deps.insert(dep.to_owned());
+                } else if let Some((dep, _)) = entry.split_once('/') {
+                    // `dep?/feature` does not enable `dep`.
+                    if !dep.ends_with('?') {
+                        queue.push(dep);
+                    }
+                } else {
+                    queue.push(entry);
+                }
+            }
+        }
+
+        (features, deps)
+    }
 }
 
 #[cfg(test)]
@@ -930,7 +1460,8 @@ mod test {
     use semver::Version;
 
     use crate::config::CrateAnnotations;
-    use crate::metadata::{Annotations, CargoTreeEntry};
+    use crate::metadata::{Annotations, BuildScriptOutput, CargoTreeEntry};
+    use crate::utils::target_triple::TargetTriple;
 
     fn common_annotations() -> Annotations {
         Annotations::new(
@@ -953,6 +1484,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
         let context = CrateContext::new(
             crate_annotation,
@@ -960,8 +1492,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -973,10 +1507,186 @@ mod test {
                 crate_name: "common".to_owned(),
                 crate_root: Some("lib.rs".to_owned()),
                 srcs: Glob::new_rust_srcs(!are_sources_present),
+                compatible_configurations: None,
+            })]),
+        );
+    }
+
+    #[test]
+    fn context_skips_binaries_with_disabled_required_features() {
+        let mut metadata = crate::test::metadata::common();
+        let package = metadata
+            .packages
+            .iter_mut()
+            .find(|package| package.name == "common")
+            .unwrap();
+        for target in &mut package.targets {
+            if target.name == "common-bin" {
+                target.required_features = vec!["missing".to_owned()];
+            }
+        }
+        let annotations = Annotations::new(
+            metadata,
+            &None,
+            crate::test::lockfile::common(),
+            crate::config::Config::default(),
+            Utf8Path::new("/tmp/bazelworkspace"),
+        )
+        .unwrap();
+
+        let crate_annotation = &annotations.metadata.crates[&PackageId {
+            repr: "path+file://{TEMP_DIR}/common#0.1.0".to_owned(),
+        }];
+
+        let include_binaries = true;
+        let include_build_scripts = false;
+        let include_tests = false;
+        let are_sources_present = false;
+        let context = CrateContext::new(
+            crate_annotation,
+            &annotations.metadata.packages,
+            &annotations.lockfile.crates,
+            &annotations.pairred_extras,
+            &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
+            include_binaries,
+            include_build_scripts,
+            include_tests,
+            are_sources_present,
+        )
+        .unwrap();
+
+        assert_eq!(
+            context.targets,
+            BTreeSet::from([Rule::Library(TargetAttributes {
+                crate_name: "common".to_owned(),
+                crate_root: Some("lib.rs".to_owned()),
+                srcs: Glob::new_rust_srcs(!are_sources_present),
+                compatible_configurations: None,
             })]),
         );
     }
 
+    #[test]
+    fn required_features_configurations() {
+        let mut package = crate::test::metadata::common()
+            .packages
+            .into_iter()
+            .find(|package| package.name == "common")
+            .unwrap();
+        package.features.insert("opt".to_owned(), Vec::new());
+
+        let mut enabled_features = Select::<BTreeSet<String>>::new();
+        enabled_features.insert("std".to_owned(), None);
+        enabled_features.insert("ssl".to_owned(), Some("cfg(unix)".to_owned()));
+        enabled_features.insert("opt".to_owned(), Some("cfg(windows)".to_owned()));
+
+        let configurations = |required: &[&str]| {
+            CrateContext::required_features_configurations(
+                &package,
+                &required.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+                &enabled_features,
+            )
+        };
+
+        assert_eq!(configurations(&[]), None);
+        assert_eq!(configurations(&["std"]), None);
+        assert_eq!(configurations(&["std", "dep/feature"]), None);
+        assert_eq!(
+            configurations(&["std", "ssl"]),
+            Some(BTreeSet::from(["cfg(unix)".to_owned()]))
+        );
+        assert_eq!(
+            configurations(&["opt?/feature"]),
+            Some(BTreeSet::from(["cfg(windows)".to_owned()]))
+        );
+        assert_eq!(configurations(&["ssl", "opt"]), Some(BTreeSet::new()));
+        assert_eq!(configurations(&["gui"]), Some(BTreeSet::new()));
+    }
+
+    #[test]
+    fn collect_feature_toggles() {
+        let package = crate::test::metadata::optional_deps_enabled()
+            .packages
+            .into_iter()
+            .find(|package| package.name == "clap")
+            .unwrap();
+
+        let dep = |name: &str, version: &str| CrateDependency {
+            id: CrateId::new(name.to_owned(), Version::parse(version).unwrap()),
+            target: name.replace('-', "_"),
+            alias: None,
+            local_path: None,
+        };
+
+        let mut common_attrs = CommonAttributes::default();
+        for feature in [
+            "color",
+            "error-context",
+            "help",
+            "std",
+            "suggestions",
+            "wrap_help",
+        ] {
+            common_attrs.crate_features.insert(feature.to_owned(), None);
+        }
+        for (name, version) in [
+            ("bitflags", "1.3.2"),
+            ("is-terminal", "0.4.2"),
+            ("strsim", "0.10.0"),
+            ("termcolor", "1.2.0"),
+            ("terminal_size", "0.2.3"),
+        ] {
+            common_attrs.deps.insert(dep(name, version), None);
+        }
+
+        // `help` is implied by `wrap_help`, which isn't toggleable.
+        let toggleable = BTreeSet::from([
+            "color".to_owned(),
+            "help".to_owned(),
+            "suggestions".to_owned(),
+            "unicode".to_owned(),
+        ]);
+        let toggles =
+            CrateContext::collect_feature_toggles(&package, &toggleable, &mut common_attrs);
+
+        assert_eq!(
+            toggles,
+            BTreeMap::from([
+                (
+                    "color".to_owned(),
+                    FeatureToggle {
+                        crate_features: BTreeSet::from(["color".to_owned()]),
+                        deps: BTreeSet::from([
+                            dep("is-terminal", "0.4.2"),
+                            dep("termcolor", "1.2.0"),
+                        ]),
+                        proc_macro_deps: BTreeSet::new(),
+                    }
+                ),
+                (
+                    "suggestions".to_owned(),
+                    FeatureToggle {
+                        crate_features: BTreeSet::from([
+                            "error-context".to_owned(),
+                            "suggestions".to_owned(),
+                        ]),
+                        deps: BTreeSet::from([dep("strsim", "0.10.0")]),
+                        proc_macro_deps: BTreeSet::new(),
+                    }
+                ),
+            ])
+        );
+        assert_eq!(
+            common_attrs.crate_features.values(),
+            vec!["help".to_owned(), "std".to_owned(), "wrap_help".to_owned()]
+        );
+        assert_eq!(
+            common_attrs.deps.values(),
+            vec![dep("bitflags", "1.3.2"), dep("terminal_size", "0.2.3")]
+        );
+    }
+
     #[test]
     fn context_with_overrides() {
         let annotations = common_annotations();
@@ -1002,6 +1712,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
         let context = CrateContext::new(
             crate_annotation,
@@ -1009,8 +1720,10 @@ mod test {
             &annotations.lockfile.crates,
             &pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1023,11 +1736,13 @@ mod test {
                     crate_name: "common".to_owned(),
                     crate_root: Some("lib.rs".to_owned()),
                     srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
                 }),
                 Rule::Binary(TargetAttributes {
                     crate_name: "common-bin".to_owned(),
                     crate_root: Some("main.rs".to_owned()),
                     srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
                 }),
             ]),
         );
@@ -1072,6 +1787,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = true;
+        let include_tests = false;
         let are_sources_present = false;
         let context = CrateContext::new(
             crate_annotation,
@@ -1079,8 +1795,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1094,11 +1812,13 @@ mod test {
                     crate_name: "openssl_sys".to_owned(),
                     crate_root: Some("src/lib.rs".to_owned()),
                     srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
                 }),
                 Rule::BuildScript(TargetAttributes {
                     crate_name: "build_script_main".to_owned(),
                     crate_root: Some("build/main.rs".to_owned()),
                     srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
                 })
             ]),
         );
@@ -1148,9 +1868,11 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             false,
             true,
             false,
+            false,
         )
         .unwrap();
 
@@ -1173,6 +1895,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
         let context = CrateContext::new(
             crate_annotation,
@@ -1180,8 +1903,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1194,10 +1919,81 @@ mod test {
                 crate_name: "openssl_sys".to_owned(),
                 crate_root: Some("src/lib.rs".to_owned()),
                 srcs: Glob::new_rust_srcs(!are_sources_present),
+                compatible_configurations: None,
             })]),
         );
     }
 
+    #[test]
+    fn context_prerun_build_script() {
+        let annotations = build_script_annotations();
+
+        let package_id = PackageId {
+            repr: "registry+https://github.com/rust-lang/crates.io-index#openssl-sys@0.9.87"
+                .to_owned(),
+        };
+
+        let crate_annotation = &annotations.metadata.crates[&package_id];
+
+        let outputs = BuildScriptOutputs::from([(
+            CrateId::new("openssl-sys".to_owned(), Version::new(0, 9, 87)),
+            BTreeMap::from([
+                (
+                    TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned()),
+                    BuildScriptOutput {
+                        cfgs: BTreeSet::from(["ossl300".to_owned(), "unix_like".to_owned()]),
+                        check_cfgs: BTreeSet::new(),
+                        env: BTreeMap::from([("PREFIX".to_owned(), "$ORIGIN".to_owned())]),
+                    },
+                ),
+                (
+                    TargetTriple::from_bazel("x86_64-pc-windows-msvc".to_owned()),
+                    BuildScriptOutput {
+                        cfgs: BTreeSet::from(["ossl300".to_owned()]),
+                        check_cfgs: BTreeSet::new(),
+                        env: BTreeMap::from([("PREFIX".to_owned(), "$ORIGIN".to_owned())]),
+                    },
+                ),
+            ]),
+        )]);
+
+        let context = CrateContext::new(
+            crate_annotation,
+            &annotations.metadata.packages,
+            &annotations.lockfile.crates,
+            &annotations.pairred_extras,
+            &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
+            false,
+            true,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_build_script_outputs(&outputs);
+
+        assert!(context.build_script_attrs.is_none());
+        assert!(!context
+            .targets
+            .iter()
+            .any(|rule| matches!(rule, Rule::BuildScript(..))));
+        assert!(context.common_attrs.extra_deps.is_empty());
+
+        let (common_flags, selected_flags) = context.common_attrs.rustc_flags.into_parts();
+        assert_eq!(common_flags, vec!["--cfg=ossl300".to_owned()]);
+        assert_eq!(
+            selected_flags,
+            BTreeMap::from([(
+                "x86_64-unknown-linux-gnu".to_owned(),
+                vec!["--cfg=unix_like".to_owned()]
+            )])
+        );
+        assert_eq!(
+            context.common_attrs.rustc_env.into_parts().0,
+            BTreeMap::from([("PREFIX".to_owned(), "$$ORIGIN".to_owned())])
+        );
+    }
+
     #[test]
     fn context_rlib_crate_type() {
         let annotations = crate_type_annotations();
@@ -1210,6 +2006,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
         let context = CrateContext::new(
             crate_annotation,
@@ -1217,8 +2014,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1231,10 +2030,146 @@ mod test {
                 crate_name: "sysinfo".to_owned(),
                 crate_root: Some("src/lib.rs".to_owned()),
                 srcs: Glob::new_rust_srcs(!are_sources_present),
+                compatible_configurations: None,
             })]),
         );
     }
 
+    #[test]
+    fn context_cdylib_crate_type() {
+        let annotations = crate_type_annotations();
+
+        let package_id = PackageId {
+            repr: "registry+https://github.com/rust-lang/crates.io-index#sysinfo@0.22.5".to_owned(),
+        };
+
+        let crate_annotation = &annotations.metadata.crates[&package_id];
+
+        let mut pairred_extras = BTreeMap::new();
+        pairred_extras.insert(
+            CrateId::new("sysinfo".to_owned(), semver::Version::new(0, 22, 5)),
+            PairedExtras {
+                package_id,
+                crate_extra: CrateAnnotations {
+                    gen_native_libraries: Some(true),
+                    ..CrateAnnotations::default()
+                },
+            },
+        );
+
+        let include_binaries = false;
+        let include_build_scripts = false;
+        let include_tests = false;
+        let are_sources_present = false;
+        let context = CrateContext::new(
+            crate_annotation,
+            &annotations.metadata.packages,
+            &annotations.lockfile.crates,
+            &pairred_extras,
+            &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
+            include_binaries,
+            include_build_scripts,
+            include_tests,
+            are_sources_present,
+        )
+        .unwrap();
+
+        assert_eq!(
+            context.targets,
+            BTreeSet::from([
+                Rule::Library(TargetAttributes {
+                    crate_name: "sysinfo".to_owned(),
+                    crate_root: Some("src/lib.rs".to_owned()),
+                    srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
+                }),
+                Rule::SharedLibrary(TargetAttributes {
+                    crate_name: "sysinfo".to_owned(),
+                    crate_root: Some("src/lib.rs".to_owned()),
+                    srcs: Glob::new_rust_srcs(!are_sources_present),
+                    compatible_configurations: None,
+                }),
+            ]),
+        );
+        assert_eq!(context.library_target_name, Some("sysinfo".to_owned()));
+    }
+
+    #[test]
+    fn context_with_tests() {
+        let mut annotations = crate_type_annotations();
+
+        let package_id = PackageId {
+            repr: "registry+https://github.com/rust-lang/crates.io-index#sysinfo@0.22.5".to_owned(),
+        };
+
+        let include_binaries = false;
+        let include_build_scripts = false;
+        let include_tests = true;
+        let are_sources_present = false;
+        let new_context = |annotations: &Annotations| {
+            CrateContext::new(
+                &annotations.metadata.crates[&package_id],
+                &annotations.metadata.packages,
+                &annotations.lockfile.crates,
+                &annotations.pairred_extras,
+                &annotations.metadata.workspace_metadata.tree_metadata,
+                &annotations.metadata.workspace_metadata.host_tree_metadata,
+                include_binaries,
+                include_build_scripts,
+                include_tests,
+                are_sources_present,
+            )
+            .unwrap()
+        };
+
+        let library = TargetAttributes {
+            crate_name: "sysinfo".to_owned(),
+            crate_root: Some("src/lib.rs".to_owned()),
+            srcs: Glob::new_rust_srcs(!are_sources_present),
+            compatible_configurations: None,
+        };
+
+        // The dev dependencies of `sysinfo` are not resolved as it's not a workspace member.
+        assert_eq!(
+            new_context(&annotations).targets,
+            BTreeSet::from([Rule::Library(library.clone())]),
+        );
+
+        annotations
+            .metadata
+            .packages
+            .get_mut(&package_id)
+            .unwrap()
+            .dependencies
+            .retain(|dep| dep.kind != cargo_metadata::DependencyKind::Development);
+        let context = new_context(&annotations);
+
+        let test = |name: &str| {
+            Rule::Test(TargetAttributes {
+                crate_name: name.to_owned(),
+                crate_root: Some(format!("tests/{name}.rs")),
+                srcs: Glob::new_rust_srcs(!are_sources_present),
+                compatible_configurations: None,
+            })
+        };
+
+        assert_eq!(
+            context.targets,
+            BTreeSet::from([
+                Rule::Library(library.clone()),
+                Rule::UnitTest(library),
+                test("disk_list"),
+                test("extras"),
+                test("network"),
+                test("process"),
+                test("processor"),
+                test("send_sync"),
+                test("uptime"),
+            ]),
+        );
+    }
+
     fn package_context_test(
         set_package: fn(package: &mut Package),
         check_context: fn(context: CrateContext),
@@ -1245,6 +2180,7 @@ mod test {
         }];
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
 
         let package = annotations
@@ -1260,8 +2196,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1385,6 +2323,7 @@ mod test {
         }];
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
 
         let context = CrateContext::new(
@@ -1393,8 +2332,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap();
@@ -1422,6 +2363,7 @@ mod test {
 
         let include_binaries = false;
         let include_build_scripts = false;
+        let include_tests = false;
         let are_sources_present = false;
         let err = CrateContext::new(
             crate_annotation,
@@ -1429,8 +2371,10 @@ mod test {
             &annotations.lockfile.crates,
             &annotations.pairred_extras,
             &annotations.metadata.workspace_metadata.tree_metadata,
+            &annotations.metadata.workspace_metadata.host_tree_metadata,
             include_binaries,
             include_build_scripts,
+            include_tests,
             are_sources_present,
         )
         .unwrap_err()
@@ -1,19 +1,38 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use anyhow::{anyhow, Context, Result};
-use cfg_expr::targets::{get_builtin_target_by_triple, TargetInfo};
+use anyhow::{bail, Context, Result};
 use cfg_expr::{Expression, Predicate};
 
 use crate::context::CrateContext;
-use crate::utils::target_triple::TargetTriple;
+use crate::select::CompoundCondition;
+use crate::utils::starlark::Label;
+use crate::utils::target_triple::{CfgTarget, TargetTriple};
+
+/// The most `cfg_settings` a single `cfg` expression may refer to. Every
+/// combination of them is evaluated for each platform.
+const MAX_SETTINGS_PER_CFG: usize = 8;
+
+/// Configurations mapped to the platforms which unconditionally satisfy them.
+pub(crate) type CfgPlatforms = BTreeMap<String, BTreeSet<TargetTriple>>;
+
+/// Configurations mapped to the [CompoundCondition]s which satisfy them.
+pub(crate) type CfgCompoundConditions = BTreeMap<String, BTreeSet<CompoundCondition>>;
 
 /// Walk through all dependencies in a [CrateContext] list for all configuration specific
 /// dependencies to produce a mapping of configurations/Cargo target_triples to compatible
 /// Bazel target_triples.  Also adds mappings for all known target_triples.
+///
+/// Triples unknown to `rustc` are evaluated using the `cfg` values in `target_cfgs`.
+///
+/// Atoms in `cfg_settings` are controlled by `config_setting`s rather than the platform.
+/// Configurations only satisfied on a platform when some of those settings are enabled
+/// are returned separately as [CompoundCondition]s.
 pub(crate) fn resolve_cfg_platforms(
     crates: Vec<&CrateContext>,
     supported_platform_triples: &BTreeSet<TargetTriple>,
-) -> Result<BTreeMap<String, BTreeSet<TargetTriple>>> {
+    target_cfgs: &BTreeMap<TargetTriple, Vec<String>>,
+    cfg_settings: &BTreeMap<String, Label>,
+) -> Result<(CfgPlatforms, CfgCompoundConditions)> {
     // Collect all unique configurations from all dependencies into a single set
     let configurations: BTreeSet<String> = crates
         .iter()
@@ -39,16 +58,32 @@ pub(crate) fn resolve_cfg_platforms(
     // Generate target information for each triple string
     let target_infos = supported_platform_triples
         .iter()
-        .map(
-            |target_triple| match get_builtin_target_by_triple(&target_triple.to_cargo()) {
-                Some(info) => Ok((target_triple, info)),
-                None => Err(anyhow!(
-                    "Invalid platform triple in supported platforms: {}",
-                    target_triple
-                )),
-            },
-        )
-        .collect::<Result<BTreeMap<&TargetTriple, &'static TargetInfo>>>()?;
+        .map(|target_triple| {
+            CfgTarget::new(target_triple, target_cfgs).map(|target| (target_triple, target))
+        })
+        .collect::<Result<BTreeMap<&TargetTriple, CfgTarget>>>()?;
+
+    // Parse the `cfg` atoms controlled by `config_setting`s
+    let setting_expressions = cfg_settings
+        .iter()
+        .map(|(atom, label)| {
+            let expression = Expression::parse(&format!("cfg({atom})"))
+                .with_context(|| format!("Failed to parse cfg setting: '{atom}'"))?;
+            let is_atom = expression.predicates().count() == 1
+                && expression.eval(|_| true)
+                && !expression.eval(|_| false);
+            if !is_atom {
+                bail!("cfg settings must be a single predicate, found: '{}'", atom);
+            }
+            Ok((expression, label))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let settings: Vec<(Predicate, &Label)> = setting_expressions
+        .iter()
+        .flat_map(|(expression, label)| expression.predicates().map(|p| (p, *label)))
+        .collect();
+
+    let mut compound_conditions: BTreeMap<String, BTreeSet<CompoundCondition>> = BTreeMap::new();
 
     // `cfg-expr` does not understand configurations that are simply platform triples
     // (`x86_64-unknown-linux-gnu` vs `cfg(target = "x86_64-unknown-linux-gnu")`). So
@@ -74,20 +109,59 @@ pub(crate) fn resolve_cfg_platforms(
             let expression =
                 Expression::parse(&cfg).context(format!("Failed to parse expression: '{cfg}'"))?;
 
-            let triples = target_infos
+            // Settings referenced by this configuration
+            let referenced: Vec<&(Predicate, &Label)> = settings
                 .iter()
-                .filter(|(_, target_info)| {
-                    expression.eval(|p| match p {
-                        Predicate::Target(tp) => tp.matches(**target_info),
-                        Predicate::KeyValue { key, val } => {
-                            *key == "target" && val == &target_info.triple.as_str()
-                        }
-                        // For now there is no other kind of matching
-                        _ => false,
-                    })
-                })
-                .map(|(triple, _)| (*triple).clone())
+                .filter(|(atom, _)| expression.predicates().any(|p| &p == atom))
                 .collect();
+            if referenced.len() > MAX_SETTINGS_PER_CFG {
+                bail!(
+                    "'{}' refers to more than {} cfg settings",
+                    cfg,
+                    MAX_SETTINGS_PER_CFG
+                );
+            }
+
+            // Evaluate every combination of enabled settings, smallest first, keeping
+            // only the minimal combinations which satisfy the configuration.
+            let mut combinations: Vec<u32> = (0..(1u32 << referenced.len())).collect();
+            combinations.sort_by_key(|enabled| enabled.count_ones());
+
+            let mut triples = BTreeSet::new();
+            let mut compounds = BTreeSet::new();
+            for (triple, target) in target_infos.iter() {
+                let mut satisfying: Vec<u32> = Vec::new();
+                for enabled in combinations.iter().copied() {
+                    if satisfying.iter().any(|found| found & enabled == *found) {
+                        continue;
+                    }
+                    let matched = expression.eval(|p| {
+                        match referenced.iter().position(|(atom, _)| atom == p) {
+                            Some(index) => enabled & (1 << index) != 0,
+                            None => target.matches(p),
+                        }
+                    });
+                    if matched {
+                        satisfying.push(enabled);
+                    }
+                }
+
+                if satisfying.contains(&0) {
+                    triples.insert((*triple).clone());
+                    continue;
+                }
+                compounds.extend(satisfying.into_iter().map(|enabled| {
+                    CompoundCondition {
+                        triple: (*triple).clone(),
+                        settings: referenced
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| enabled & (1 << index) != 0)
+                            .map(|(_, (_, label))| (*label).clone())
+                            .collect(),
+                    }
+                }));
+            }
 
             // Map any renamed configurations back to their original IDs
             let cfg = match original_cfgs.get(&cfg) {
@@ -95,6 +169,10 @@ pub(crate) fn resolve_cfg_platforms(
                 None => cfg,
             };
 
+            if !compounds.is_empty() {
+                compound_conditions.insert(cfg.clone(), compounds);
+            }
+
             Ok((cfg, triples))
         })
         .collect::<Result<BTreeMap<String, BTreeSet<TargetTriple>>>>()?;
@@ -105,11 +183,13 @@ pub(crate) fn resolve_cfg_platforms(
             .or_default()
             .insert(target_triple.clone());
     }
-    Ok(conditions)
+    Ok((conditions, compound_conditions))
 }
 
 #[cfg(test)]
 mod test {
+    use std::str::FromStr;
+
     use crate::config::CrateId;
     use crate::context::crate_context::CrateDependency;
     use crate::context::CommonAttributes;
@@ -152,6 +232,7 @@ mod test {
                 ..CommonAttributes::default()
             },
             build_script_attrs: None,
+            host_attrs: None,
             license: None,
             license_ids: BTreeSet::default(),
             license_file: None,
@@ -160,10 +241,16 @@ mod test {
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            feature_toggles: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -211,6 +298,7 @@ mod test {
                 ..CommonAttributes::default()
             },
             build_script_attrs: None,
+            host_attrs: None,
             license: None,
             license_ids: BTreeSet::default(),
             license_file: None,
@@ -219,6 +307,7 @@ mod test {
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            feature_toggles: BTreeMap::default(),
         }
     }
 
@@ -243,8 +332,13 @@ mod test {
         data.into_iter().for_each(|(configuration, expectation)| {
             let context = mock_resolve_context(configuration.clone());
 
-            let configurations =
-                resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+            let (configurations, _) = resolve_cfg_platforms(
+                vec![&context],
+                &supported_platform_triples(),
+                &BTreeMap::new(),
+                &BTreeMap::new(),
+            )
+            .unwrap();
 
             assert_eq!(
                 configurations,
@@ -298,6 +392,7 @@ mod test {
                 ..CommonAttributes::default()
             },
             build_script_attrs: None,
+            host_attrs: None,
             license: None,
             license_ids: BTreeSet::default(),
             license_file: None,
@@ -306,10 +401,16 @@ mod test {
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            feature_toggles: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -365,6 +466,7 @@ mod test {
                 ..CommonAttributes::default()
             },
             build_script_attrs: None,
+            host_attrs: None,
             license: None,
             license_ids: BTreeSet::default(),
             license_file: None,
@@ -373,10 +475,16 @@ mod test {
             extra_aliased_targets: BTreeMap::default(),
             alias_rule: None,
             override_targets: BTreeMap::default(),
+            feature_toggles: BTreeMap::default(),
         };
 
-        let configurations =
-            resolve_cfg_platforms(vec![&context], &supported_platform_triples()).unwrap();
+        let (configurations, _) = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        )
+        .unwrap();
 
         assert_eq!(
             configurations,
@@ -400,4 +508,190 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn resolve_custom_target() {
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let acme = TargetTriple::from_bazel("thumbv7em-acme-none-eabihf".to_owned());
+        let supported_platform_triples = BTreeSet::from([linux.clone(), acme.clone()]);
+        let target_cfgs = BTreeMap::from([(
+            acme.clone(),
+            vec![
+                "acme_secure".to_owned(),
+                "panic=\"abort\"".to_owned(),
+                "target_arch=\"arm\"".to_owned(),
+                "target_endian=\"little\"".to_owned(),
+                "target_feature=\"vfp4\"".to_owned(),
+                "target_has_atomic=\"32\"".to_owned(),
+                "target_os=\"acme\"".to_owned(),
+                "target_pointer_width=\"32\"".to_owned(),
+                "target_vendor=\"unknown\"".to_owned(),
+            ],
+        )]);
+
+        let data = BTreeMap::from([
+            (
+                r#"cfg(target_os = "acme")"#.to_owned(),
+                BTreeSet::from([acme.clone()]),
+            ),
+            (
+                r#"cfg(target_feature = "vfp4")"#.to_owned(),
+                BTreeSet::from([acme.clone()]),
+            ),
+            (
+                r#"cfg(all(target_arch = "arm", acme_secure))"#.to_owned(),
+                BTreeSet::from([acme.clone()]),
+            ),
+            (
+                r#"cfg(target_has_atomic = "32")"#.to_owned(),
+                BTreeSet::from([acme.clone(), linux.clone()]),
+            ),
+            (
+                r#"cfg(target_pointer_width = "64")"#.to_owned(),
+                BTreeSet::from([linux.clone()]),
+            ),
+            (
+                "thumbv7em-acme-none-eabihf".to_owned(),
+                BTreeSet::from([acme.clone()]),
+            ),
+        ]);
+
+        data.into_iter().for_each(|(configuration, expectation)| {
+            let context = mock_resolve_context(configuration.clone());
+
+            let (configurations, _) = resolve_cfg_platforms(
+                vec![&context],
+                &supported_platform_triples,
+                &target_cfgs,
+                &BTreeMap::new(),
+            )
+            .unwrap();
+
+            assert_eq!(
+                configurations.get(&configuration),
+                Some(&expectation),
+                "Unexpected platforms for {}",
+                configuration
+            );
+        })
+    }
+
+    #[test]
+    fn resolve_unknown_triple_without_cfg() {
+        let context = mock_resolve_context(r#"cfg(unix)"#.to_owned());
+        let supported_platform_triples = BTreeSet::from([TargetTriple::from_bazel(
+            "thumbv7em-acme-none-eabihf".to_owned(),
+        )]);
+
+        let err = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+        )
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("Invalid platform triple in supported platforms"));
+    }
+
+    #[test]
+    fn resolve_cfg_settings() {
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let darwin = TargetTriple::from_bazel("aarch64-apple-darwin".to_owned());
+        let supported_platform_triples = BTreeSet::from([linux.clone(), darwin.clone()]);
+
+        let avx2 = Label::from_str("//:avx2").unwrap();
+        let abort = Label::from_str("//:panic_abort").unwrap();
+        let cfg_settings = BTreeMap::from([
+            (r#"target_feature = "avx2""#.to_owned(), avx2.clone()),
+            (r#"panic = "abort""#.to_owned(), abort.clone()),
+        ]);
+
+        let compound = |triple: &TargetTriple, settings: &[&Label]| CompoundCondition {
+            triple: triple.clone(),
+            settings: settings.iter().map(|label| (*label).clone()).collect(),
+        };
+
+        let data = vec![
+            (
+                r#"cfg(target_feature = "avx2")"#,
+                BTreeSet::new(),
+                Some(BTreeSet::from([
+                    compound(&darwin, &[&avx2]),
+                    compound(&linux, &[&avx2]),
+                ])),
+            ),
+            (
+                r#"cfg(all(target_arch = "x86_64", target_feature = "avx2"))"#,
+                BTreeSet::new(),
+                Some(BTreeSet::from([compound(&linux, &[&avx2])])),
+            ),
+            (
+                r#"cfg(all(target_feature = "avx2", panic = "abort"))"#,
+                BTreeSet::new(),
+                Some(BTreeSet::from([
+                    compound(&darwin, &[&avx2, &abort]),
+                    compound(&linux, &[&avx2, &abort]),
+                ])),
+            ),
+            (
+                r#"cfg(any(target_os = "macos", panic = "abort"))"#,
+                BTreeSet::from([darwin.clone()]),
+                Some(BTreeSet::from([compound(&linux, &[&abort])])),
+            ),
+            (
+                r#"cfg(any(unix, target_feature = "avx2"))"#,
+                BTreeSet::from([darwin.clone(), linux.clone()]),
+                None,
+            ),
+        ];
+
+        for (configuration, triples, compounds) in data {
+            let context = mock_resolve_context(configuration.to_owned());
+
+            let (configurations, compound_conditions) = resolve_cfg_platforms(
+                vec![&context],
+                &supported_platform_triples,
+                &BTreeMap::new(),
+                &cfg_settings,
+            )
+            .unwrap();
+
+            assert_eq!(
+                configurations.get(configuration),
+                Some(&triples),
+                "Unexpected platforms for {}",
+                configuration
+            );
+            assert_eq!(
+                compound_conditions.get(configuration),
+                compounds.as_ref(),
+                "Unexpected compound conditions for {}",
+                configuration
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_cfg_settings_requires_atoms() {
+        let context = mock_resolve_context(r#"cfg(unix)"#.to_owned());
+        let cfg_settings = BTreeMap::from([(
+            r#"any(target_feature = "avx2", target_feature = "avx")"#.to_owned(),
+            Label::from_str("//:avx").unwrap(),
+        )]);
+
+        let err = resolve_cfg_platforms(
+            vec![&context],
+            &supported_platform_triples(),
+            &BTreeMap::new(),
+            &cfg_settings,
+        )
+        .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .contains("cfg settings must be a single predicate"));
+    }
 }
@@ -3,7 +3,7 @@
 use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::{bail, Context as AnyhowContext, Result};
@@ -13,10 +13,11 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest as Sha2Digest, Sha256};
 
-use crate::config::Config;
+use crate::config::{Config, CrateId, RenderConfig};
 use crate::context::Context;
 use crate::metadata::Cargo;
 use crate::splicing::{SplicingManifest, SplicingMetadata};
+use crate::utils::merge_conflicts::file_has_merge_conflicts;
 
 pub(crate) fn lock_context(
     mut context: Context,
@@ -24,90 +25,262 @@ pub(crate) fn lock_context(
     splicing_manifest: &SplicingManifest,
     cargo_bin: &Cargo,
     rustc_bin: &Path,
+    cargo_lockfile: Option<&Path>,
 ) -> Result<Context> {
     // Ensure there is no existing checksum which could impact the lockfile results
     context.checksum = None;
-
-    let checksum = Digest::new(&context, config, splicing_manifest, cargo_bin, rustc_bin)
-        .context("Failed to generate context digest")?;
+    context.checksum_components = None;
+
+    let components = DigestComponents::new(
+        &context,
+        config,
+        splicing_manifest,
+        cargo_bin,
+        rustc_bin,
+        cargo_lockfile,
+    )
+    .context("Failed to generate context digest")?;
 
     Ok(Context {
-        checksum: Some(checksum),
+        checksum: Some(components.digest()),
+        checksum_components: Some(components),
         ..context
     })
 }
 
-/// Write a [crate::context::Context] to disk
-pub(crate) fn write_lockfile(lockfile: Context, path: &Path, dry_run: bool) -> Result<()> {
-    let content = serde_json::to_string_pretty(&lockfile)?;
+/// Write a [crate::context::Context] to disk, optionally sharding the crates of the
+/// lockfile into separate files. See [serialize_lockfile].
+pub(crate) fn write_lockfile(
+    lockfile: Context,
+    path: &Path,
+    sharded: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let outputs = serialize_lockfile(&lockfile, path, sharded)?;
 
     if dry_run {
-        println!("{content:#?}");
-    } else {
-        // Ensure the parent directory exists
+        for content in outputs.values() {
+            println!("{:#?}", content.trim_end());
+        }
+        return Ok(());
+    }
+
+    // Ensure the parent directory exists
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Shards of crates which are no longer dependencies, or every shard when the
+    // lockfile is no longer sharded, are removed.
+    for stale in stale_lockfile_shards(path, &outputs)? {
+        fs::remove_file(&stale).with_context(|| format!("Failed to delete {}", stale.display()))?;
+    }
+    let shards_dir = lockfile_shards_dir(path);
+    if shards_dir.exists() && fs::read_dir(&shards_dir)?.next().is_none() {
+        fs::remove_dir(&shards_dir)
+            .with_context(|| format!("Failed to delete {}", shards_dir.display()))?;
+    }
+
+    for (path, content) in outputs {
+        // Unchanged shards are left alone to keep merges and file watchers quiet.
+        if fs::read_to_string(&path).is_ok_and(|existing| existing == content) {
+            continue;
+        }
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        fs::write(path, content + "\n")
+        fs::write(&path, content)
             .context(format!("Failed to write file to disk: {}", path.display()))?;
     }
 
     Ok(())
 }
 
+/// Serialize a lockfile exactly as [write_lockfile] writes it to disk, keyed by
+/// the path of each file.
+///
+/// A sharded lockfile writes each crate to its own file in [lockfile_shards_dir],
+/// leaving only the path of that file, relative to the lockfile, in the `crates`
+/// of the lockfile itself. This way changes to unrelated crates never touch the
+/// same lines. Both layouts are read by [read_lockfile] and produce the same
+/// [Digest].
+pub(crate) fn serialize_lockfile(
+    lockfile: &Context,
+    path: &Path,
+    sharded: bool,
+) -> Result<BTreeMap<PathBuf, String>> {
+    if !sharded {
+        return Ok(BTreeMap::from([(
+            path.to_path_buf(),
+            serde_json::to_string_pretty(lockfile)? + "\n",
+        )]));
+    }
+
+    let mut outputs = BTreeMap::new();
+    let mut index = serde_json::to_value(lockfile)?;
+    let crates = index
+        .get_mut("crates")
+        .and_then(serde_json::Value::as_object_mut)
+        .expect("Lockfiles always contain crates");
+    for (id, crate_context) in &lockfile.crates {
+        let shard = lockfile_shard(path, id);
+        let relative_shard = format!(
+            "{}/{}",
+            shard
+                .parent()
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_string_lossy(),
+            shard.file_name().unwrap().to_string_lossy()
+        );
+        outputs.insert(shard, serde_json::to_string_pretty(crate_context)? + "\n");
+        crates.insert(id.to_string(), serde_json::Value::String(relative_shard));
+    }
+    outputs.insert(
+        path.to_path_buf(),
+        serde_json::to_string_pretty(&index)? + "\n",
+    );
+
+    Ok(outputs)
+}
+
+/// The directory which holds the crates of a sharded lockfile, named after the
+/// lockfile (e.g. `cargo-bazel-lock.crates` for `cargo-bazel-lock.json`).
+pub(crate) fn lockfile_shards_dir(path: &Path) -> PathBuf {
+    resolve_symlink(path).with_extension("crates")
+}
+
+/// Lockfiles may be accessed through a chain of symlinks (e.g. from the runfiles of
+/// `crates_vendor`) while their shards are only next to the real file in the workspace.
+/// Lockfiles which don't exist yet are returned as is.
+fn resolve_symlink(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The file holding the crate `id` in the sharded lockfile at `path`.
+pub(crate) fn lockfile_shard(path: &Path, id: &CrateId) -> PathBuf {
+    lockfile_shards_dir(path).join(format!("{}-{}.json", id.name, id.version))
+}
+
+/// Files in the shards directory of the lockfile at `path` which are not part of `outputs`.
+pub(crate) fn stale_lockfile_shards(
+    path: &Path,
+    outputs: &BTreeMap<PathBuf, String>,
+) -> Result<Vec<PathBuf>> {
+    let shards_dir = lockfile_shards_dir(path);
+    if !shards_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut stale = Vec::new();
+    for entry in fs::read_dir(&shards_dir)
+        .with_context(|| format!("Failed to read {}", shards_dir.display()))?
+    {
+        let shard = entry?.path();
+        if shard.extension() == Some(OsStr::new("json")) && !outputs.contains_key(&shard) {
+            stale.push(shard);
+        }
+    }
+    stale.sort();
+    Ok(stale)
+}
+
+/// Returns true if the lockfile at `path`, or any of its shards, contains git merge conflicts.
+pub(crate) fn lockfile_has_merge_conflicts(path: &Path) -> Result<bool> {
+    if file_has_merge_conflicts(path)? {
+        return Ok(true);
+    }
+
+    let shards_dir = lockfile_shards_dir(path);
+    if !shards_dir.is_dir() {
+        return Ok(false);
+    }
+    for entry in fs::read_dir(&shards_dir)
+        .with_context(|| format!("Failed to read {}", shards_dir.display()))?
+    {
+        if file_has_merge_conflicts(&entry?.path())? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Read a lockfile written by [write_lockfile] in either layout.
+pub(crate) fn read_lockfile(path: &Path) -> Result<Context> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut lockfile: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    // The crates of a sharded lockfile are paths to the files holding them.
+    if let Some(crates) = lockfile
+        .get_mut("crates")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        let lockfile_dir = lockfile_shards_dir(path);
+        let lockfile_dir = lockfile_dir.parent().unwrap_or(Path::new(""));
+        for crate_context in crates.values_mut() {
+            if let serde_json::Value::String(shard) = crate_context {
+                let shard = lockfile_dir.join(&*shard);
+                let content = fs::read_to_string(&shard)
+                    .with_context(|| format!("Failed to read {}", shard.display()))?;
+                *crate_context = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse {}", shard.display()))?;
+            }
+        }
+    }
+
+    serde_json::from_value(lockfile).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub(crate) struct Digest(String);
 
-impl Digest {
-    pub(crate) fn new(
-        context: &Context,
-        config: &Config,
-        splicing_manifest: &SplicingManifest,
-        cargo_bin: &Cargo,
-        rustc_bin: &Path,
-    ) -> Result<Self> {
-        let splicing_metadata = SplicingMetadata::try_from((*splicing_manifest).clone())?;
-        let cargo_version = cargo_bin.full_version()?;
-        let rustc_version = Self::bin_version(rustc_bin)?;
-        let cargo_bazel_version = env!("CARGO_PKG_VERSION");
+/// The digests of each individual input to a lockfile's [Digest].
+///
+/// These are persisted next to the checksum so that when a lockfile falls out
+/// of date, the input responsible can be reported instead of two opaque hashes.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub(crate) struct DigestComponents {
+    /// The version of `cargo-bazel` which produced the lockfile.
+    pub(crate) cargo_bazel_version: String,
 
-        // Mirror the Context.checksum sanitization below for Config's
-        // `label_injection_mapping`: that field is a per-session derived
-        // artifact (apparent -> canonical labels resolved through the consumer
-        // module's repo_mapping). If it entered the hash, a consumer-side
-        // `single_version_override` would shift the canonical names, change
-        // the digest, and force a producer-side repin to recover — which is
-        // impossible for registry-distributed producers whose lockfile lives
-        // in a read-only bzlmod cache.
-        let config_for_hash = Config {
-            label_injection_mapping: Default::default(),
-            ..config.clone()
-        };
+    /// The lockfile context (typically `cargo-bazel-lock.json`).
+    pub(crate) lockfile_context: String,
 
-        // Ensure the checksum of a digest is not present before computing one
-        Ok(match context.checksum {
-            Some(_) => Self::compute(
-                &Context {
-                    checksum: None,
-                    ..context.clone()
-                },
-                &config_for_hash,
-                &splicing_metadata,
-                cargo_bazel_version,
-                &cargo_version,
-                &rustc_version,
-            ),
-            None => Self::compute(
-                context,
-                &config_for_hash,
-                &splicing_metadata,
-                cargo_bazel_version,
-                &cargo_version,
-                &rustc_version,
-            ),
-        })
-    }
+    /// The workspace config generated by Bazel rules.
+    pub(crate) workspace_config: String,
 
+    /// The splicing manifest generated by Bazel rules.
+    pub(crate) splicing_manifest: String,
+
+    /// The version of Cargo used to generate the lockfile.
+    pub(crate) cargo_version: String,
+
+    /// The version of Rustc used to generate the lockfile.
+    pub(crate) rustc_version: String,
+
+    /// Digests of each crate annotation in the workspace config, keyed by
+    /// the annotated crate name and version requirement.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) annotations: BTreeMap<String, String>,
+
+    /// Digests of each Cargo manifest in the splicing manifest, keyed by label.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) manifests: BTreeMap<String, String>,
+
+    /// The digest of the Cargo config file, if one was provided.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) cargo_config: Option<String>,
+
+    /// The digest of the `Cargo.lock` file, if one was provided. This is purely
+    /// informational and does not contribute to the lockfile [Digest].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) cargo_lockfile: Option<String>,
+}
+
+impl Digest {
     /// A helper for generating a hash and logging it's contents.
     fn compute_single_hash(data: &str, id: &str) -> String {
         let mut hasher = Sha256::new();
@@ -145,6 +318,7 @@ impl Digest {
         re.replace_all(json, "@@//")
     }
 
+    #[cfg(test)]
     fn compute(
         context: &Context,
         config: &Config,
@@ -153,53 +327,15 @@ impl Digest {
         cargo_version: &str,
         rustc_version: &str,
     ) -> Self {
-        // Since this method is private, it should be expected that context is
-        // always None. This then allows us to have this method not return a
-        // Result.
-        debug_assert!(context.checksum.is_none());
-
-        let mut hasher = Sha256::new();
-
-        hasher.update(Digest::compute_single_hash(
+        DigestComponents::compute(
+            context,
+            config,
+            splicing_metadata,
             cargo_bazel_version,
-            "cargo-bazel version",
-        ));
-        hasher.update(b"\0");
-
-        // The lockfile context (typically `cargo-bazel-lock.json`).
-        hasher.update(Digest::compute_single_hash(
-            &serde_json::to_string(context).unwrap(),
-            "lockfile context",
-        ));
-        hasher.update(b"\0");
-
-        // This content is generated by various attributes in Bazel rules and written to a file behind the scenes.
-        // Labels are normalized to strip bzlmod canonical repository names so the digest is stable
-        // regardless of whether the module is used as a root module or a non-root dependency.
-        hasher.update(Digest::compute_single_hash(
-            &Self::normalize_labels_for_digest(&serde_json::to_string(config).unwrap()),
-            "workspace config",
-        ));
-        hasher.update(b"\0");
-
-        // Data collected about Cargo manifests and configs that feed into dependency generation. This file
-        // is also generated by Bazel behind the scenes based on user inputs.
-        hasher.update(Digest::compute_single_hash(
-            &serde_json::to_string(splicing_metadata).unwrap(),
-            "splicing manifest",
-        ));
-        hasher.update(b"\0");
-
-        hasher.update(Digest::compute_single_hash(cargo_version, "Cargo version"));
-        hasher.update(b"\0");
-
-        hasher.update(Digest::compute_single_hash(rustc_version, "Rustc version"));
-        hasher.update(b"\0");
-
-        let hash = hasher.finalize().encode_hex::<String>();
-        tracing::debug!("Digest hash: {}", hash);
-
-        Self(hash)
+            cargo_version,
+            rustc_version,
+        )
+        .digest()
     }
 
     pub(crate) fn bin_version(binary: &Path) -> Result<String> {
@@ -253,6 +389,268 @@ impl Digest {
     }
 }
 
+impl DigestComponents {
+    pub(crate) fn new(
+        context: &Context,
+        config: &Config,
+        splicing_manifest: &SplicingManifest,
+        cargo_bin: &Cargo,
+        rustc_bin: &Path,
+        cargo_lockfile: Option<&Path>,
+    ) -> Result<Self> {
+        let splicing_metadata = SplicingMetadata::try_from((*splicing_manifest).clone())?;
+        let cargo_version = cargo_bin.full_version()?;
+        let rustc_version = Digest::bin_version(rustc_bin)?;
+        let cargo_bazel_version = env!("CARGO_PKG_VERSION");
+
+        // Ensure the checksum of a digest is not present before computing one
+        let mut components =
+            match context.checksum.is_some() || context.checksum_components.is_some() {
+                true => Self::compute(
+                    &Context {
+                        checksum: None,
+                        checksum_components: None,
+                        ..context.clone()
+                    },
+                    config,
+                    &splicing_metadata,
+                    cargo_bazel_version,
+                    &cargo_version,
+                    &rustc_version,
+                ),
+                false => Self::compute(
+                    context,
+                    config,
+                    &splicing_metadata,
+                    cargo_bazel_version,
+                    &cargo_version,
+                    &rustc_version,
+                ),
+            };
+
+        if let Some(path) = cargo_lockfile {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            // Normalize line endings so the digest is stable across checkouts.
+            components.cargo_lockfile = Some(Digest::compute_single_hash(
+                &content.replace("\r\n", "\n"),
+                "Cargo.lock",
+            ));
+        }
+
+        Ok(components)
+    }
+
+    fn compute(
+        context: &Context,
+        config: &Config,
+        splicing_metadata: &SplicingMetadata,
+        cargo_bazel_version: &str,
+        cargo_version: &str,
+        rustc_version: &str,
+    ) -> Self {
+        // Since this method is private, it should be expected that context is
+        // always None. This then allows us to have this method not return a
+        // Result.
+        debug_assert!(context.checksum.is_none());
+        debug_assert!(context.checksum_components.is_none());
+
+        // Mirror the Context.checksum sanitization for Config's
+        // `label_injection_mapping`: that field is a per-session derived
+        // artifact (apparent -> canonical labels resolved through the consumer
+        // module's repo_mapping). If it entered the hash, a consumer-side
+        // `single_version_override` would shift the canonical names, change
+        // the digest, and force a producer-side repin to recover — which is
+        // impossible for registry-distributed producers whose lockfile lives
+        // in a read-only bzlmod cache.
+        //
+        // `template_overrides` is an absolute path to the checkout and only
+        // affects rendering, which is redone on every fetch, so it's left out too.
+        let config = &Config {
+            label_injection_mapping: Default::default(),
+            rendering: RenderConfig {
+                template_overrides: None,
+                ..config.rendering.clone()
+            },
+            ..config.clone()
+        };
+
+        let annotations = config
+            .annotations
+            .iter()
+            .map(|(id, annotation)| {
+                let id = id.to_string();
+                let hash = Digest::compute_single_hash(
+                    &Digest::normalize_labels_for_digest(
+                        &serde_json::to_string(annotation).unwrap(),
+                    ),
+                    &format!("annotation `{id}`"),
+                );
+                (id, hash)
+            })
+            .collect();
+
+        let manifests = splicing_metadata
+            .manifests
+            .iter()
+            .map(|(label, manifest)| {
+                (
+                    label.to_string(),
+                    Digest::compute_single_hash(
+                        &serde_json::to_string(manifest).unwrap(),
+                        &format!("manifest `{label}`"),
+                    ),
+                )
+            })
+            .collect();
+
+        let cargo_config = splicing_metadata.cargo_config.as_ref().map(|cargo_config| {
+            Digest::compute_single_hash(
+                &serde_json::to_string(cargo_config).unwrap(),
+                "cargo config",
+            )
+        });
+
+        Self {
+            cargo_bazel_version: Digest::compute_single_hash(
+                cargo_bazel_version,
+                "cargo-bazel version",
+            ),
+
+            // The lockfile context (typically `cargo-bazel-lock.json`).
+            lockfile_context: Digest::compute_single_hash(
+                &serde_json::to_string(context).unwrap(),
+                "lockfile context",
+            ),
+
+            // This content is generated by various attributes in Bazel rules and written to a file behind the scenes.
+            // Labels are normalized to strip bzlmod canonical repository names so the digest is stable
+            // regardless of whether the module is used as a root module or a non-root dependency.
+            workspace_config: Digest::compute_single_hash(
+                &Digest::normalize_labels_for_digest(&serde_json::to_string(config).unwrap()),
+                "workspace config",
+            ),
+
+            // Data collected about Cargo manifests and configs that feed into dependency generation. This file
+            // is also generated by Bazel behind the scenes based on user inputs.
+            splicing_manifest: Digest::compute_single_hash(
+                &serde_json::to_string(splicing_metadata).unwrap(),
+                "splicing manifest",
+            ),
+
+            cargo_version: Digest::compute_single_hash(cargo_version, "Cargo version"),
+
+            rustc_version: Digest::compute_single_hash(rustc_version, "Rustc version"),
+
+            annotations,
+            manifests,
+            cargo_config,
+            cargo_lockfile: None,
+        }
+    }
+
+    /// Combine the components which guard the lockfile into a single [Digest].
+    pub(crate) fn digest(&self) -> Digest {
+        let mut hasher = Sha256::new();
+        for component in [
+            &self.cargo_bazel_version,
+            &self.lockfile_context,
+            &self.workspace_config,
+            &self.splicing_manifest,
+            &self.cargo_version,
+            &self.rustc_version,
+        ] {
+            hasher.update(component);
+            hasher.update(b"\0");
+        }
+
+        let hash = hasher.finalize().encode_hex::<String>();
+        tracing::debug!("Digest hash: {}", hash);
+
+        Digest(hash)
+    }
+
+    /// Describe which inputs differ between a lockfile's recorded components and
+    /// the components computed from the current inputs.
+    pub(crate) fn explain_changes(&self, current: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        if self.cargo_bazel_version != current.cargo_bazel_version {
+            changes.push("cargo-bazel version changed".to_owned());
+        }
+
+        if self.lockfile_context != current.lockfile_context {
+            changes.push("lockfile contents were modified after being generated".to_owned());
+        }
+
+        if self.workspace_config != current.workspace_config {
+            let annotation_changes = Self::explain_map_changes(
+                "annotation for",
+                &self.annotations,
+                &current.annotations,
+            );
+            if annotation_changes.is_empty() {
+                changes.push("workspace config changed".to_owned());
+            } else {
+                changes.extend(annotation_changes);
+            }
+        }
+
+        if self.splicing_manifest != current.splicing_manifest {
+            let mut splicing_changes =
+                Self::explain_map_changes("manifest", &self.manifests, &current.manifests);
+            if self.cargo_config != current.cargo_config {
+                splicing_changes.push("cargo config changed".to_owned());
+            }
+            if splicing_changes.is_empty() {
+                changes.push("splicing manifest changed".to_owned());
+            } else {
+                changes.extend(splicing_changes);
+            }
+        }
+
+        if self.cargo_version != current.cargo_version {
+            changes.push("cargo version changed".to_owned());
+        }
+
+        if self.rustc_version != current.rustc_version {
+            changes.push("rustc version changed".to_owned());
+        }
+
+        // `Cargo.lock` is only compared when both sides recorded it.
+        if let (Some(recorded), Some(current)) = (&self.cargo_lockfile, &current.cargo_lockfile) {
+            if recorded != current {
+                changes.push("Cargo.lock changed".to_owned());
+            }
+        }
+
+        changes
+    }
+
+    fn explain_map_changes(
+        kind: &str,
+        recorded: &BTreeMap<String, String>,
+        current: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (key, hash) in recorded {
+            match current.get(key) {
+                None => changes.push(format!("{kind} `{key}` removed")),
+                Some(current_hash) if current_hash != hash => {
+                    changes.push(format!("{kind} `{key}` changed"))
+                }
+                _ => {}
+            }
+        }
+        for key in current.keys() {
+            if !recorded.contains_key(key) {
+                changes.push(format!("{kind} `{key}` added"));
+            }
+        }
+        changes
+    }
+}
+
 impl PartialEq<str> for Digest {
     fn eq(&self, other: &str) -> bool {
         self.0 == other
@@ -275,6 +673,61 @@ mod test {
 
     use std::collections::BTreeSet;
 
+    #[test]
+    fn sharded_lockfile_round_trip() {
+        let context: Context = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/cargo_bazel_lockfile/multi_package-cargo-bazel-lock.json"
+        )))
+        .unwrap();
+        let (_tempdir, tempdir) = crate::test::test_tempdir("sharded_lockfile_round_trip");
+        let path = tempdir.join("cargo-bazel-lock.json");
+        let shards_dir = tempdir.join("cargo-bazel-lock.crates");
+
+        write_lockfile(context.clone(), &path, true, false).unwrap();
+
+        assert_eq!(
+            fs::read_dir(&shards_dir).unwrap().count(),
+            context.crates.len()
+        );
+        let index: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            index["crates"]["pkg_a 0.1.0"],
+            "cargo-bazel-lock.crates/pkg_a-0.1.0.json"
+        );
+        assert_eq!(read_lockfile(&path).unwrap(), context);
+
+        // Switching back to a single file removes the shards.
+        write_lockfile(context.clone(), &path, false, false).unwrap();
+
+        assert!(!shards_dir.exists());
+        assert_eq!(read_lockfile(&path).unwrap(), context);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lockfile_shards_dir_follows_symlink_chains() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("lockfile_shards_dir_follows_symlink_chains");
+        let workspace = tempdir.join("workspace");
+        let runfiles = tempdir.join("runfiles");
+        fs::create_dir_all(&workspace).unwrap();
+        fs::create_dir_all(&runfiles).unwrap();
+
+        let lockfile = workspace.join("cargo-bazel-lock.json");
+        fs::write(&lockfile, "{}").unwrap();
+        std::os::unix::fs::symlink(&lockfile, runfiles.join("link.json")).unwrap();
+        std::os::unix::fs::symlink("link.json", runfiles.join("cargo-bazel-lock.json")).unwrap();
+
+        assert_eq!(
+            lockfile_shards_dir(&runfiles.join("cargo-bazel-lock.json")),
+            fs::canonicalize(&workspace)
+                .unwrap()
+                .join("cargo-bazel-lock.crates")
+        );
+    }
+
     #[test]
     fn simple_digest() {
         let context = Context::default();
@@ -355,6 +808,7 @@ mod test {
             )]),
             manifests: BTreeMap::new(),
             cargo_config: None,
+            custom_targets: BTreeMap::new(),
         };
 
         let digest = Digest::compute(
@@ -523,6 +977,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn digest_stable_across_checkouts_with_template_overrides() {
+        let context = Context::default();
+        let splicing_metadata = SplicingMetadata::default();
+
+        let make_config = |template_overrides: &str| -> Config {
+            Config {
+                rendering: RenderConfig {
+                    template_overrides: Some(template_overrides.into()),
+                    ..RenderConfig::default()
+                },
+                ..Config::default()
+            }
+        };
+
+        let digests: Vec<Digest> = ["/home/alice/repo/templates", "/ci/workspace/templates"]
+            .into_iter()
+            .map(|template_overrides| {
+                Digest::compute(
+                    &context,
+                    &make_config(template_overrides),
+                    &splicing_metadata,
+                    "0.1.0",
+                    "cargo 1.57.0 (b2e52d7ca 2021-10-21)",
+                    "rustc 1.57.0 (f1edd0429 2021-11-29)",
+                )
+            })
+            .collect();
+
+        assert_eq!(digests[0], digests[1]);
+    }
+
     #[test]
     fn digest_stable_for_workspace_root_vs_dep() {
         // Verifies that the digest is stable in legacy WORKSPACE mode, where labels also
@@ -572,4 +1058,79 @@ mod test {
             "Digests should be identical for WORKSPACE root (@@//...) and dep (@@repo_name//...) contexts"
         );
     }
+
+    #[test]
+    fn digest_components_ignore_informational_digests() {
+        let context = Context::default();
+        let config = Config::default();
+        let splicing_metadata = SplicingMetadata::default();
+
+        let mut components = DigestComponents::compute(
+            &context,
+            &config,
+            &splicing_metadata,
+            "0.1.0",
+            "cargo 1.57.0 (b2e52d7ca 2021-10-21)",
+            "rustc 1.57.0 (f1edd0429 2021-11-29)",
+        );
+        let digest = components.digest();
+        components.cargo_lockfile = Some("0123".to_owned());
+
+        assert_eq!(
+            Digest("8c404322a9233ae973beca7ab04300fc61d60380153a36d0e23e70ba2d42f39a".to_owned()),
+            digest,
+        );
+        assert_eq!(digest, components.digest());
+    }
+
+    #[test]
+    fn explain_digest_component_changes() {
+        let context = Context::default();
+        let splicing_metadata = SplicingMetadata::default();
+        let make_config = |glob: &str| Config {
+            annotations: BTreeMap::from([
+                (
+                    CrateNameAndVersionReq::new("openssl".to_owned(), "*".parse().unwrap()),
+                    CrateAnnotations {
+                        compile_data_glob: Some(BTreeSet::from([glob.to_owned()])),
+                        ..CrateAnnotations::default()
+                    },
+                ),
+                (
+                    CrateNameAndVersionReq::new(
+                        "rustonomicon".to_owned(),
+                        "1.0.0".parse().unwrap(),
+                    ),
+                    CrateAnnotations::default(),
+                ),
+            ]),
+            ..Config::default()
+        };
+
+        let recorded = DigestComponents::compute(
+            &context,
+            &make_config("arts/**"),
+            &splicing_metadata,
+            "0.1.0",
+            "cargo 1.57.0 (b2e52d7ca 2021-10-21)",
+            "rustc 1.57.0 (f1edd0429 2021-11-29)",
+        );
+        let current = DigestComponents::compute(
+            &context,
+            &make_config("crafts/**"),
+            &splicing_metadata,
+            "0.1.0",
+            "cargo 1.57.0 (b2e52d7ca 2021-10-21)",
+            "rustc 1.82.0 (f6e511eec 2024-10-15)",
+        );
+
+        assert_eq!(
+            recorded.explain_changes(&current),
+            vec![
+                "annotation for `openssl *` changed".to_owned(),
+                "rustc version changed".to_owned(),
+            ]
+        );
+        assert!(recorded.explain_changes(&recorded).is_empty());
+    }
 }
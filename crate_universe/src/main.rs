@@ -38,5 +38,25 @@ fn main() -> cli::Result<()> {
             cli::init_logging("Render", level);
             cli::render(opt)
         }
+        cli::Options::Diff(opt) => {
+            cli::init_logging("Diff", level);
+            cli::diff(opt)
+        }
+        cli::Options::Why(opt) => {
+            cli::init_logging("Why", level);
+            cli::why(opt)
+        }
+        cli::Options::Sbom(opt) => {
+            cli::init_logging("Sbom", level);
+            cli::sbom(opt)
+        }
+        cli::Options::ResolveConflicts(opt) => {
+            cli::init_logging("ResolveConflicts", level);
+            cli::resolve_conflicts(opt)
+        }
+        cli::Options::Outdated(opt) => {
+            cli::init_logging("Outdated", level);
+            cli::outdated(opt)
+        }
     }
 }
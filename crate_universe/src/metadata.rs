@@ -1,9 +1,12 @@
 //! Tools for gathering various kinds of metadata (Cargo.lock, Cargo metadata, Crate Index info).
 
+mod build_script_prerunner;
 mod cargo_bin;
+mod cargo_tree_cache;
 mod cargo_tree_resolver;
 mod dependency;
 mod metadata_annotation;
+mod metadata_resolver;
 
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -13,6 +16,9 @@ use anyhow::{bail, Context, Result};
 use camino::Utf8Path;
 use tracing::debug;
 
+use crate::utils::merge_conflicts::split_merge_conflicts;
+
+pub(crate) use self::build_script_prerunner::*;
 pub(crate) use self::cargo_bin::*;
 pub(crate) use self::cargo_tree_resolver::*;
 pub(crate) use self::dependency::*;
@@ -114,11 +120,25 @@ impl CargoUpdateRequest {
 pub(crate) struct LockGenerator {
     /// Interface to cargo.
     cargo_bin: Cargo,
+
+    /// Whether git merge conflicts in an existing lockfile are resolved rather than
+    /// reported as an error.
+    resolve_conflicts: bool,
 }
 
 impl LockGenerator {
     pub(crate) fn new(cargo_bin: Cargo) -> Self {
-        Self { cargo_bin }
+        Self {
+            cargo_bin,
+            resolve_conflicts: false,
+        }
+    }
+
+    pub(crate) fn with_resolve_conflicts(self, resolve_conflicts: bool) -> Self {
+        Self {
+            resolve_conflicts,
+            ..self
+        }
     }
 
     #[tracing::instrument(name = "LockGenerator::generate", skip_all)]
@@ -146,9 +166,33 @@ impl LockGenerator {
             if generated_lockfile_path.exists() {
                 fs::remove_file(&generated_lockfile_path)?;
             }
-            fs::copy(lock, &generated_lockfile_path)?;
+            let content = fs::read_to_string(lock)
+                .with_context(|| format!("Failed to read {}", lock.display()))?;
+            let update_request = match resolve_lockfile_conflicts(&content).with_context(|| {
+                format!("Failed to resolve merge conflicts in {}", lock.display())
+            })? {
+                Some(_) if !self.resolve_conflicts => {
+                    bail!(
+                        "{} contains git merge conflicts. Resolve them with `CARGO_BAZEL_RESOLVE_CONFLICTS=true` or the `resolve-conflicts` subcommand.",
+                        lock.display()
+                    )
+                }
+                Some(merged) => {
+                    debug!("Resolving merge conflicts in {}", lock.display());
+                    fs::write(&generated_lockfile_path, merged.to_string())?;
+
+                    // Like Cargo, the union of both sides is minimally resolved again.
+                    update_request
+                        .clone()
+                        .or(Some(CargoUpdateRequest::Workspace))
+                }
+                None => {
+                    fs::copy(lock, &generated_lockfile_path)?;
+                    update_request.clone()
+                }
+            };
 
-            if let Some(request) = update_request {
+            if let Some(request) = &update_request {
                 request.update(manifest_path.as_std_path(), &self.cargo_bin)?;
             }
 
@@ -221,6 +265,47 @@ impl LockGenerator {
     }
 }
 
+/// Resolve git merge conflicts in the contents of a `Cargo.lock` file by taking the
+/// union of the packages locked by either side. Returns `None` if there are no conflicts.
+pub(crate) fn resolve_lockfile_conflicts(content: &str) -> Result<Option<cargo_lock::Lockfile>> {
+    let Some(sides) = split_merge_conflicts(content) else {
+        return Ok(None);
+    };
+
+    let ours = cargo_lock::Lockfile::from_str(&sides.ours)
+        .context("Failed to parse the current side of the conflict")?;
+    let theirs = cargo_lock::Lockfile::from_str(&sides.theirs)
+        .context("Failed to parse the incoming side of the conflict")?;
+
+    let mut packages = ours.packages;
+    for package in theirs.packages {
+        let locked = packages.iter().any(|locked| {
+            locked.name == package.name
+                && locked.version == package.version
+                && locked.source == package.source
+        });
+        if !locked {
+            packages.push(package);
+        }
+    }
+    packages.sort_by(|a, b| {
+        (a.name.as_str(), &a.version, &a.source).cmp(&(b.name.as_str(), &b.version, &b.source))
+    });
+
+    let mut patch = ours.patch;
+    for unused in theirs.patch.unused {
+        if !patch.unused.contains(&unused) {
+            patch.unused.push(unused);
+        }
+    }
+
+    Ok(Some(cargo_lock::Lockfile {
+        packages,
+        patch,
+        ..ours
+    }))
+}
+
 /// A generator which runs `cargo vendor` on a given manifest
 pub(crate) struct VendorGenerator {
     /// The path to a `cargo` binary
@@ -353,4 +438,67 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn resolve_lockfile_conflicts_without_conflicts() {
+        let content = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "a"
+            version = "0.1.0"
+        "#};
+
+        assert!(resolve_lockfile_conflicts(content).unwrap().is_none());
+    }
+
+    #[test]
+    fn resolve_lockfile_conflicts_union() {
+        let content = indoc::indoc! {r#"
+            version = 3
+
+            [[package]]
+            name = "a"
+            version = "0.1.0"
+            dependencies = [
+            <<<<<<< HEAD
+             "b",
+            =======
+             "c",
+            >>>>>>> feature
+            ]
+
+            [[package]]
+            name = "b"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            <<<<<<< HEAD
+            checksum = "1111111111111111111111111111111111111111111111111111111111111111"
+            =======
+            checksum = "1111111111111111111111111111111111111111111111111111111111111111"
+
+            [[package]]
+            name = "c"
+            version = "2.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "2222222222222222222222222222222222222222222222222222222222222222"
+            >>>>>>> feature
+        "#};
+
+        let lockfile = resolve_lockfile_conflicts(content).unwrap().unwrap();
+        let packages: Vec<(String, String)> = lockfile
+            .packages
+            .iter()
+            .map(|package| (package.name.to_string(), package.version.to_string()))
+            .collect();
+
+        assert_eq!(
+            packages,
+            vec![
+                ("a".to_owned(), "0.1.0".to_owned()),
+                ("b".to_owned(), "1.0.0".to_owned()),
+                ("c".to_owned(), "2.0.0".to_owned()),
+            ]
+        );
+    }
 }
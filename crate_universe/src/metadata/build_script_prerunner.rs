@@ -0,0 +1,731 @@
+//! Running "pure" build scripts ahead of time so their outputs can be pinned.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use cargo_metadata::{DependencyKind, Metadata as CargoMetadata, Package, PackageId, TargetKind};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::config::{Config, CrateAnnotations, CrateId, PrerunBuildScripts};
+use crate::metadata::cargo_bin::Cargo;
+use crate::metadata::TreeResolverMetadata;
+use crate::utils::sanitize_module_name;
+use crate::utils::target_triple::TargetTriple;
+
+/// Environment variables passed through to build scripts from the current process.
+const PRESERVED_ENV_VARS: [&str; 6] = ["HOME", "PATH", "SYSTEMROOT", "TEMP", "TMP", "TMPDIR"];
+
+/// The outputs of a build script which only affect how its crate is compiled.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BuildScriptOutput {
+    /// `cargo::rustc-cfg` values.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) cfgs: BTreeSet<String>,
+
+    /// `cargo::rustc-check-cfg` values.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) check_cfgs: BTreeSet<String>,
+
+    /// `cargo::rustc-env` values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) env: BTreeMap<String, String>,
+}
+
+impl BuildScriptOutput {
+    /// Parse the stdout of a build script. Directives which can't be expressed as attributes
+    /// of the crate's library (e.g. anything related to linking) are an error.
+    fn parse(stdout: &str) -> Result<Self> {
+        let mut output = Self::default();
+        for line in stdout.lines() {
+            let Some(directive) = line
+                .strip_prefix("cargo::")
+                .or_else(|| line.strip_prefix("cargo:"))
+            else {
+                continue;
+            };
+            let (key, value) = directive
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Malformed build script directive `{}`", line))?;
+            let value = value.trim();
+            match key {
+                "rustc-cfg" => {
+                    output.cfgs.insert(value.to_owned());
+                }
+                "rustc-check-cfg" => {
+                    output.check_cfgs.insert(value.to_owned());
+                }
+                "rustc-env" => {
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("Malformed build script directive `{}`", line))?;
+                    output.env.insert(name.to_owned(), value.to_owned());
+                }
+                "rerun-if-changed" | "rerun-if-env-changed" | "warning" => {}
+                _ => bail!("Unsupported build script directive `{}`", line),
+            }
+        }
+        Ok(output)
+    }
+
+    /// The flags to pass to `rustc` in place of running the build script.
+    pub(crate) fn rustc_flags(&self) -> Vec<String> {
+        self.cfgs
+            .iter()
+            .map(|cfg| format!("--cfg={}", cfg))
+            .chain(
+                self.check_cfgs
+                    .iter()
+                    .map(|check_cfg| format!("--check-cfg={}", check_cfg)),
+            )
+            .collect()
+    }
+}
+
+/// The pinned outputs of build scripts keyed by crate and the platform they were run for.
+pub(crate) type BuildScriptOutputs = BTreeMap<CrateId, BTreeMap<TargetTriple, BuildScriptOutput>>;
+
+/// Details about a platform build scripts are run for.
+struct PlatformInfo {
+    /// The `--target` value for the platform.
+    target: String,
+
+    /// The `CARGO_CFG_*` environment variables for the platform.
+    cfg_env: BTreeMap<String, String>,
+}
+
+/// Compiles and runs build scripts which don't compile or link native code for every
+/// supported platform, recording the `cfg`s and environment variables they emit.
+///
+/// Only scripts which can be compiled directly with `rustc` are considered: their
+/// build-dependencies may not have dependencies, features or build scripts of their own
+/// (e.g. `autocfg` and `version_check`). Crates with a `links` key, crates which read
+/// `OUT_DIR` and crates whose build scripts are customized by annotations are skipped, as
+/// are scripts which emit any directives other than `rustc-cfg`, `rustc-check-cfg` and
+/// `rustc-env` or whose outputs differ between debug and release profiles.
+pub(crate) struct BuildScriptPrerunner {
+    /// The path to a `rustc` binary.
+    rustc: PathBuf,
+
+    /// User settings.
+    settings: PrerunBuildScripts,
+}
+
+impl BuildScriptPrerunner {
+    pub(crate) fn new(cargo: &Cargo, settings: PrerunBuildScripts) -> Self {
+        Self {
+            rustc: cargo.rustc_path().to_owned(),
+            settings,
+        }
+    }
+
+    /// Run all eligible build scripts in the workspace for each supported platform.
+    #[tracing::instrument(name = "BuildScriptPrerunner::run", skip_all)]
+    pub(crate) fn run(
+        &self,
+        metadata: &CargoMetadata,
+        config: &Config,
+        resolver_data: &TreeResolverMetadata,
+    ) -> Result<BuildScriptOutputs> {
+        let mut platforms = BTreeMap::new();
+        for triple in &config.supported_platform_triples {
+            match self.platform_info(triple)? {
+                Some(info) => {
+                    platforms.insert(triple.clone(), info);
+                }
+                None => {
+                    warn!(
+                        "Not running build scripts ahead of time: the standard library is not available for `{}`",
+                        triple
+                    );
+                    return Ok(BuildScriptOutputs::new());
+                }
+            }
+        }
+        let host = self.host_triple()?;
+
+        let tempdir = tempfile::tempdir().context("Failed to make tempdir")?;
+        let mut compiled_deps: BTreeMap<PackageId, Option<PathBuf>> = BTreeMap::new();
+        let mut outputs = BuildScriptOutputs::new();
+
+        for package in metadata.packages.iter().filter(|p| p.source.is_some()) {
+            let crate_id = CrateId::from(package);
+            let Some(build_script) = package
+                .targets
+                .iter()
+                .find(|t| t.kind.contains(&TargetKind::CustomBuild))
+            else {
+                continue;
+            };
+            if let Some(reason) = self.ineligible_reason(package, config) {
+                debug!("Not pre-running build script of {}: {}", crate_id, reason);
+                continue;
+            }
+
+            let mut externs = Vec::new();
+            let mut compilable = true;
+            for (name, dep) in build_dependencies(package, metadata) {
+                let rlib = match compiled_deps.get(&dep.id) {
+                    Some(rlib) => rlib.clone(),
+                    None => {
+                        let rlib = self
+                            .compile_build_dependency(dep, tempdir.path())
+                            .unwrap_or_else(|err| {
+                                debug!("Failed to compile build-dependency {}: {:?}", dep.id, err);
+                                None
+                            });
+                        compiled_deps.insert(dep.id.clone(), rlib.clone());
+                        rlib
+                    }
+                };
+                match rlib {
+                    Some(rlib) => externs.push((name, rlib)),
+                    None => compilable = false,
+                }
+            }
+            if !compilable {
+                debug!(
+                    "Not pre-running build script of {}: unsupported build-dependencies",
+                    crate_id
+                );
+                continue;
+            }
+
+            let script_dir = tempdir
+                .path()
+                .join(format!("{}-{}", package.name, package.version));
+            let mut scripts: BTreeMap<BTreeSet<String>, PathBuf> = BTreeMap::new();
+            let mut crate_outputs = BTreeMap::new();
+            for (triple, info) in &platforms {
+                let features = platform_features(resolver_data, &crate_id, triple);
+                let script = match scripts.get(&features) {
+                    Some(script) => script.clone(),
+                    None => match self.compile_build_script(
+                        package,
+                        build_script.src_path.as_std_path(),
+                        &features,
+                        &externs,
+                        &script_dir.join(scripts.len().to_string()),
+                    ) {
+                        Ok(script) => {
+                            scripts.insert(features.clone(), script.clone());
+                            script
+                        }
+                        // Build scripts which can't be compiled standalone are left to Bazel.
+                        Err(err) => {
+                            debug!("Not pre-running build script of {}: {:?}", crate_id, err);
+                            crate_outputs.clear();
+                            break;
+                        }
+                    },
+                };
+
+                match self.run_build_script(package, &script, &features, &host, info, &script_dir) {
+                    Ok(output) => {
+                        crate_outputs.insert(triple.clone(), output);
+                    }
+                    Err(err) => {
+                        debug!("Not pre-running build script of {}: {:?}", crate_id, err);
+                        crate_outputs.clear();
+                        break;
+                    }
+                }
+            }
+
+            if !crate_outputs.is_empty() {
+                outputs.insert(crate_id, crate_outputs);
+            }
+        }
+
+        info!("Pre-ran {} build scripts", outputs.len());
+        Ok(outputs)
+    }
+
+    /// Determine why a package's build script can never be run ahead of time, if it can't.
+    fn ineligible_reason(&self, package: &Package, config: &Config) -> Option<&'static str> {
+        if package.links.is_some() {
+            return Some("the package links a native library");
+        }
+        if self.settings.denylist.iter().any(|id| id.matches(package)) {
+            return Some("the crate is denylisted");
+        }
+
+        let mut annotations: CrateAnnotations = config
+            .annotations
+            .iter()
+            .filter(|(id, _)| id.matches(package))
+            .map(|(_, annotation)| annotation.clone())
+            .sum();
+        annotations.apply_defaults_from_package_metadata(&package.metadata);
+        if annotations.customizes_build_script() {
+            return Some("the build script is customized by annotations");
+        }
+
+        if reads_out_dir(package) {
+            return Some("the crate reads `OUT_DIR`");
+        }
+        None
+    }
+
+    /// Gather information about a platform. `None` is returned if the standard library
+    /// is not available for the platform, as build scripts commonly probe `rustc` by
+    /// compiling code for the target.
+    fn platform_info(&self, triple: &TargetTriple) -> Result<Option<PlatformInfo>> {
+        let target = triple.to_cargo();
+
+        let libdir = Command::new(&self.rustc)
+            .arg("--print=target-libdir")
+            .arg("--target")
+            .arg(&target)
+            .output()
+            .with_context(|| format!("Failed to spawn rustc for target `{}`", target))?;
+        if !libdir.status.success() {
+            return Ok(None);
+        }
+        let libdir = PathBuf::from(String::from_utf8(libdir.stdout)?.trim());
+        let has_std = fs::read_dir(&libdir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .any(|entry| entry.file_name().to_string_lossy().starts_with("libstd-"))
+            })
+            .unwrap_or(false);
+        if !has_std {
+            return Ok(None);
+        }
+
+        let cfg = Command::new(&self.rustc)
+            .arg("--print=cfg")
+            .arg("--target")
+            .arg(&target)
+            .output()
+            .with_context(|| format!("Failed to spawn rustc for target `{}`", target))?;
+        if !cfg.status.success() {
+            bail!(
+                "Failed to query `cfg` for target `{}`:\n{}",
+                target,
+                String::from_utf8_lossy(&cfg.stderr)
+            );
+        }
+
+        Ok(Some(PlatformInfo {
+            target,
+            cfg_env: cfg_env_vars(&String::from_utf8(cfg.stdout)?),
+        }))
+    }
+
+    fn host_triple(&self) -> Result<String> {
+        let output = Command::new(&self.rustc)
+            .arg("-vV")
+            .output()
+            .context("Failed to spawn rustc")?;
+        String::from_utf8(output.stdout)?
+            .lines()
+            .find_map(|line| line.strip_prefix("host: "))
+            .map(|host| host.trim().to_owned())
+            .ok_or_else(|| anyhow!("Failed to determine the host triple of rustc"))
+    }
+
+    /// Compile a build-dependency into an rlib. `None` is returned for dependencies which
+    /// can't be compiled on their own.
+    fn compile_build_dependency(&self, package: &Package, dir: &Path) -> Result<Option<PathBuf>> {
+        let is_standalone = package
+            .dependencies
+            .iter()
+            .all(|dep| dep.kind == DependencyKind::Development)
+            && package.features.is_empty()
+            && !package
+                .targets
+                .iter()
+                .any(|t| t.kind.contains(&TargetKind::CustomBuild));
+        let lib = package.targets.iter().find(|t| {
+            t.kind
+                .iter()
+                .any(|kind| matches!(kind, TargetKind::Lib | TargetKind::RLib))
+        });
+        let Some(lib) = lib.filter(|_| is_standalone) else {
+            return Ok(None);
+        };
+
+        let crate_name = sanitize_module_name(&lib.name);
+        let out_dir = dir.join(format!("deps/{}-{}", package.name, package.version));
+        let output = Command::new(&self.rustc)
+            .arg(lib.src_path.as_std_path())
+            .arg(format!("--crate-name={}", crate_name))
+            .arg("--crate-type=rlib")
+            .arg(format!("--edition={}", package.edition.as_str()))
+            .arg("--cap-lints=allow")
+            .arg("--out-dir")
+            .arg(&out_dir)
+            .output()
+            .with_context(|| format!("Failed to spawn rustc for {}", package.name))?;
+        if !output.status.success() {
+            debug!(
+                "Failed to compile build-dependency {} {}:\n{}",
+                package.name,
+                package.version,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(out_dir.join(format!("lib{}.rlib", crate_name))))
+    }
+
+    fn compile_build_script(
+        &self,
+        package: &Package,
+        src_path: &Path,
+        features: &BTreeSet<String>,
+        externs: &[(String, PathBuf)],
+        out_dir: &Path,
+    ) -> Result<PathBuf> {
+        fs::create_dir_all(out_dir)
+            .with_context(|| format!("Failed to create directory {}", out_dir.display()))?;
+        let script = out_dir.join(format!(
+            "build_script_build{}",
+            std::env::consts::EXE_SUFFIX
+        ));
+
+        let mut command = Command::new(&self.rustc);
+        command
+            .arg(src_path)
+            .arg("--crate-name=build_script_build")
+            .arg("--crate-type=bin")
+            .arg(format!("--edition={}", package.edition.as_str()))
+            .arg("--cap-lints=allow")
+            .arg("-o")
+            .arg(&script);
+        for feature in features {
+            command.arg("--cfg").arg(format!("feature=\"{}\"", feature));
+        }
+        for (name, rlib) in externs {
+            command
+                .arg("--extern")
+                .arg(format!("{}={}", name, rlib.display()));
+        }
+
+        let output = command
+            .output()
+            .with_context(|| format!("Failed to spawn rustc for {}", package.name))?;
+        if !output.status.success() {
+            bail!(
+                "Failed to compile the build script of {} {}:\n{}",
+                package.name,
+                package.version,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(script)
+    }
+
+    /// Run a build script for a platform in both debug and release profiles, requiring
+    /// the outputs to match as only one set of outputs is pinned.
+    fn run_build_script(
+        &self,
+        package: &Package,
+        script: &Path,
+        features: &BTreeSet<String>,
+        host: &str,
+        platform: &PlatformInfo,
+        dir: &Path,
+    ) -> Result<BuildScriptOutput> {
+        let manifest_dir = package
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow!("All manifests should have a valid parent."))?;
+
+        let mut outputs = Vec::new();
+        for (profile, debug, opt_level) in [("debug", "true", "0"), ("release", "false", "3")] {
+            let out_dir = dir.join(format!("out-{}-{}", platform.target, profile));
+            fs::create_dir_all(&out_dir)
+                .with_context(|| format!("Failed to create directory {}", out_dir.display()))?;
+
+            let mut command = Command::new(script);
+            command.current_dir(manifest_dir).env_clear();
+            for name in PRESERVED_ENV_VARS {
+                if let Some(value) = std::env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+            command
+                .envs(package_env_vars(package))
+                .envs(&platform.cfg_env)
+                .env("CARGO_CRATE_NAME", sanitize_module_name(&package.name))
+                .env("CARGO_ENCODED_RUSTFLAGS", "")
+                .env("CARGO_MANIFEST_DIR", manifest_dir)
+                .env("CARGO_MANIFEST_PATH", &package.manifest_path)
+                .env("DEBUG", debug)
+                .env("HOST", host)
+                .env("NUM_JOBS", "1")
+                .env("OPT_LEVEL", opt_level)
+                .env("OUT_DIR", &out_dir)
+                .env("PROFILE", profile)
+                .env("RUSTC", &self.rustc)
+                .env("TARGET", &platform.target);
+            for feature in features {
+                command.env(
+                    format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_")),
+                    "1",
+                );
+            }
+
+            let output = command
+                .output()
+                .with_context(|| format!("Failed to spawn {}", script.display()))?;
+            if !output.status.success() {
+                bail!(
+                    "The build script failed for `{}`:\n{}",
+                    platform.target,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+
+            let stdout = String::from_utf8(output.stdout)?;
+            let parsed = BuildScriptOutput::parse(&stdout)?;
+            if parsed
+                .env
+                .values()
+                .any(|value| value.contains(&*dir.to_string_lossy()))
+            {
+                bail!("The build script emitted environment variables referencing its outputs");
+            }
+            outputs.push(parsed);
+        }
+
+        if outputs[0] != outputs[1] {
+            bail!(
+                "The build script emits different outputs for debug and release profiles for `{}`",
+                platform.target
+            );
+        }
+        Ok(outputs.remove(0))
+    }
+}
+
+/// Collect the build-dependencies of a package along with the names they're imported as.
+fn build_dependencies<'a>(
+    package: &Package,
+    metadata: &'a CargoMetadata,
+) -> Vec<(String, &'a Package)> {
+    let Some(node) = metadata
+        .resolve
+        .as_ref()
+        .and_then(|resolve| resolve.nodes.iter().find(|node| node.id == package.id))
+    else {
+        return Vec::new();
+    };
+
+    node.deps
+        .iter()
+        .filter(|dep| {
+            dep.dep_kinds
+                .iter()
+                .any(|kind| kind.kind == DependencyKind::Build)
+        })
+        .map(|dep| (dep.name.clone(), &metadata[&dep.pkg]))
+        .collect()
+}
+
+/// The features enabled for a crate on a given platform.
+fn platform_features(
+    resolver_data: &TreeResolverMetadata,
+    crate_id: &CrateId,
+    triple: &TargetTriple,
+) -> BTreeSet<String> {
+    let Some(tree_data) = resolver_data.get(crate_id) else {
+        return BTreeSet::new();
+    };
+    let configuration = triple.to_bazel();
+    tree_data
+        .items()
+        .into_iter()
+        .filter(|(config, _)| config.is_none() || config.as_ref() == Some(&configuration))
+        .flat_map(|(_, entry)| entry.features)
+        .collect()
+}
+
+/// Determine whether or not any of a crate's sources (other than its build script) read
+/// `OUT_DIR`, in which case the crate relies on files generated by the build script.
+fn reads_out_dir(package: &Package) -> bool {
+    let Some(root) = package.manifest_path.parent() else {
+        return true;
+    };
+    let build_scripts: BTreeSet<&Path> = package
+        .targets
+        .iter()
+        .filter(|t| t.kind.contains(&TargetKind::CustomBuild))
+        .map(|t| t.src_path.as_std_path())
+        .collect();
+
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "rs"))
+        .filter(|entry| !build_scripts.contains(entry.path()))
+        .any(|entry| {
+            fs::read_to_string(entry.path())
+                .map(|content| content.contains("OUT_DIR"))
+                .unwrap_or(true)
+        })
+}
+
+/// The `CARGO_PKG_*` environment variables Cargo provides to build scripts.
+fn package_env_vars(package: &Package) -> BTreeMap<&'static str, String> {
+    let version = &package.version;
+    BTreeMap::from([
+        ("CARGO_PKG_AUTHORS", package.authors.join(":")),
+        (
+            "CARGO_PKG_DESCRIPTION",
+            package.description.clone().unwrap_or_default(),
+        ),
+        (
+            "CARGO_PKG_HOMEPAGE",
+            package.homepage.clone().unwrap_or_default(),
+        ),
+        (
+            "CARGO_PKG_LICENSE",
+            package.license.clone().unwrap_or_default(),
+        ),
+        ("CARGO_PKG_NAME", package.name.to_string()),
+        (
+            "CARGO_PKG_REPOSITORY",
+            package.repository.clone().unwrap_or_default(),
+        ),
+        (
+            "CARGO_PKG_RUST_VERSION",
+            package
+                .rust_version
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        ),
+        ("CARGO_PKG_VERSION", version.to_string()),
+        ("CARGO_PKG_VERSION_MAJOR", version.major.to_string()),
+        ("CARGO_PKG_VERSION_MINOR", version.minor.to_string()),
+        ("CARGO_PKG_VERSION_PATCH", version.patch.to_string()),
+        ("CARGO_PKG_VERSION_PRE", version.pre.to_string()),
+    ])
+}
+
+/// Convert the output of `rustc --print=cfg` into `CARGO_CFG_*` environment variables.
+fn cfg_env_vars(cfg: &str) -> BTreeMap<String, String> {
+    let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for line in cfg.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match line.split_once('=') {
+            Some((key, value)) => values
+                .entry(key.to_owned())
+                .or_default()
+                .push(value.trim_matches('"').to_owned()),
+            None => {
+                values.entry(line.to_owned()).or_default();
+            }
+        }
+    }
+
+    values
+        .into_iter()
+        .map(|(key, value)| (format!("CARGO_CFG_{}", key.to_uppercase()), value.join(",")))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_build_script_output() {
+        let output = BuildScriptOutput::parse(indoc::indoc! {r#"
+            cargo:rerun-if-changed=build.rs
+            cargo:rustc-cfg=has_const_fn
+            cargo::rustc-check-cfg=cfg(has_const_fn)
+            cargo:rustc-env=RUSTC_MINOR=80
+            cargo:warning=ignored
+            not a directive
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            output,
+            BuildScriptOutput {
+                cfgs: BTreeSet::from(["has_const_fn".to_owned()]),
+                check_cfgs: BTreeSet::from(["cfg(has_const_fn)".to_owned()]),
+                env: BTreeMap::from([("RUSTC_MINOR".to_owned(), "80".to_owned())]),
+            }
+        );
+        assert_eq!(
+            output.rustc_flags(),
+            vec![
+                "--cfg=has_const_fn".to_owned(),
+                "--check-cfg=cfg(has_const_fn)".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_impure_build_script_output() {
+        assert!(BuildScriptOutput::parse("cargo:rustc-link-lib=z\n").is_err());
+        assert!(BuildScriptOutput::parse("cargo::rustc-link-search=/usr/lib\n").is_err());
+        assert!(BuildScriptOutput::parse("cargo:include=/tmp/include\n").is_err());
+    }
+
+    #[test]
+    fn cfg_env() {
+        let env = cfg_env_vars(indoc::indoc! {r#"
+            debug_assertions
+            target_arch="x86_64"
+            target_feature="fxsr"
+            target_feature="sse"
+            target_os="linux"
+            unix
+        "#});
+
+        assert_eq!(
+            env,
+            BTreeMap::from([
+                ("CARGO_CFG_DEBUG_ASSERTIONS".to_owned(), "".to_owned()),
+                ("CARGO_CFG_TARGET_ARCH".to_owned(), "x86_64".to_owned()),
+                ("CARGO_CFG_TARGET_FEATURE".to_owned(), "fxsr,sse".to_owned()),
+                ("CARGO_CFG_TARGET_OS".to_owned(), "linux".to_owned()),
+                ("CARGO_CFG_UNIX".to_owned(), "".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn build_scripts_which_fail_to_compile_are_skipped() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("build_scripts_which_fail_to_compile");
+        let build_rs = tempdir.join("build.rs");
+        fs::write(&build_rs, "fn main() { include!(\"missing.rs\"); }\n").unwrap();
+
+        let mut metadata = crate::test::metadata::build_scripts();
+        for package in &mut metadata.packages {
+            for target in &mut package.targets {
+                if target.kind.contains(&TargetKind::CustomBuild) {
+                    target.src_path = camino::Utf8PathBuf::from_path_buf(build_rs.clone()).unwrap();
+                }
+            }
+        }
+
+        let prerunner = BuildScriptPrerunner {
+            rustc: PathBuf::from("rustc"),
+            settings: PrerunBuildScripts::default(),
+        };
+        let config = Config {
+            supported_platform_triples: BTreeSet::from([TargetTriple::from_bazel(
+                prerunner.host_triple().unwrap(),
+            )]),
+            ..Config::default()
+        };
+
+        let outputs = prerunner
+            .run(&metadata, &config, &TreeResolverMetadata::new())
+            .unwrap();
+
+        assert!(outputs.is_empty());
+    }
+}
@@ -84,6 +84,28 @@ impl Cargo {
         Ok(full_version.clone().unwrap())
     }
 
+    /// Returns the path to the `rustc` binary used by this cargo.
+    pub(crate) fn rustc_path(&self) -> &Path {
+        &self.rustc_path
+    }
+
+    /// Returns the output of running `rustc --version`, trimming any leading or trailing whitespace.
+    pub(crate) fn rustc_version(&self) -> Result<String> {
+        Digest::bin_version(&self.rustc_path)
+    }
+
+    /// Returns the `CARGO_HOME` directory Cargo will use, if one can be determined.
+    pub(crate) fn cargo_home(&self) -> Option<PathBuf> {
+        self.cargo_home
+            .clone()
+            .or_else(|| std::env::var_os("CARGO_HOME").map(PathBuf::from))
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .map(|home| PathBuf::from(home).join(".cargo"))
+            })
+    }
+
     pub(crate) fn is_nightly(&self) -> Result<bool> {
         let full_version = self.full_version()?;
         let version_str = full_version.split(' ').nth(1);
@@ -0,0 +1,338 @@
+//! A persistent, content-addressed cache of parsed `cargo tree` results.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as Sha2Digest, Sha256};
+use tracing::debug;
+
+use crate::metadata::cargo_bin::Cargo;
+use crate::metadata::cargo_tree_resolver::CargoTreeOutput;
+
+/// The version of the cache format. Changing the contents of [CacheEntry] or the way
+/// keys are computed requires this to be bumped.
+const CACHE_VERSION: &str = "1";
+
+/// Environment variables which do not influence the output of `cargo tree`
+/// despite matching the prefixes in [is_relevant_env_var].
+const IGNORED_ENV_VARS: [&str; 2] = ["CARGO_HOME", "CARGO_TARGET_DIR"];
+
+/// The contents of a single file in the cache.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// The key the entry was stored under. Entries whose key does not match the
+    /// one they were looked up with are never served.
+    key: String,
+
+    /// The parsed output of `cargo tree`.
+    output: CargoTreeOutput,
+}
+
+/// A cache of [CargoTreeOutput]s for a single Cargo workspace.
+///
+/// Each entry is keyed by a digest of everything `cargo tree` reads when resolving the
+/// workspace (manifests, `Cargo.lock`, Cargo configuration, relevant environment variables
+/// and the versions of `cargo` and `rustc`) along with the host and target platform it was
+/// invoked for. Any change to those inputs produces a new key, so stale entries are never
+/// served and unaffected platforms continue to be served from the cache.
+#[derive(Debug)]
+pub(crate) struct CargoTreeCache {
+    /// The directory entries are stored in.
+    dir: PathBuf,
+
+    /// A digest of all inputs shared by every `cargo tree` invocation in the workspace.
+    workspace_digest: String,
+}
+
+impl CargoTreeCache {
+    pub(crate) fn new(dir: &Path, inputs: &BTreeMap<String, Vec<u8>>) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_VERSION.as_bytes());
+        for (name, contents) in inputs {
+            update_hasher(&mut hasher, name.as_bytes());
+            update_hasher(&mut hasher, contents);
+        }
+
+        Self {
+            dir: dir.to_owned(),
+            workspace_digest: hasher.finalize().encode_hex::<String>(),
+        }
+    }
+
+    /// Collect the inputs of `cargo tree` which are shared across all platforms.
+    pub(crate) fn workspace_inputs(
+        cargo: &Cargo,
+        manifest_path: &Path,
+        rustc_wrapper: &Path,
+        metadata: &cargo_metadata::Metadata,
+    ) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut inputs = BTreeMap::new();
+
+        inputs.insert(
+            "cargo_bazel_version".to_owned(),
+            env!("CARGO_PKG_VERSION").as_bytes().to_vec(),
+        );
+        inputs.insert(
+            "cargo_version".to_owned(),
+            cargo.full_version()?.into_bytes(),
+        );
+        inputs.insert(
+            "rustc_version".to_owned(),
+            cargo.rustc_version()?.into_bytes(),
+        );
+        inputs.insert("rustc_wrapper".to_owned(), read(rustc_wrapper)?);
+
+        let manifest_dir = manifest_path
+            .parent()
+            .context("All manifests should have a valid parent.")?;
+        inputs.insert("Cargo.toml".to_owned(), read(manifest_path)?);
+        inputs.insert(
+            "Cargo.lock".to_owned(),
+            read(&manifest_dir.join("Cargo.lock"))?,
+        );
+
+        // Any package without a source is read from disk, so its manifest is an input.
+        for package in metadata.packages.iter().filter(|p| p.source.is_none()) {
+            let path = package
+                .manifest_path
+                .strip_prefix(&metadata.workspace_root)
+                .unwrap_or(&package.manifest_path);
+            inputs.insert(
+                format!("manifest/{}", path),
+                read(package.manifest_path.as_std_path())?,
+            );
+        }
+
+        // Cargo reads configuration from every parent of the directory it's run in. These are
+        // keyed by depth so the (temporary) location of the workspace doesn't matter.
+        for (depth, dir) in manifest_dir.ancestors().enumerate() {
+            for name in ["config", "config.toml"] {
+                let path = dir.join(".cargo").join(name);
+                if path.is_file() {
+                    inputs.insert(format!("cargo_config/{}/{}", depth, name), read(&path)?);
+                }
+            }
+        }
+        if let Some(cargo_home) = cargo.cargo_home() {
+            for name in ["config", "config.toml"] {
+                let path = cargo_home.join(name);
+                if path.is_file() {
+                    inputs.insert(format!("cargo_home/{}", name), read(&path)?);
+                }
+            }
+        }
+
+        for (name, value) in std::env::vars_os() {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if is_relevant_env_var(name) {
+                inputs.insert(
+                    format!("env/{}", name),
+                    value.to_string_lossy().as_bytes().to_vec(),
+                );
+            }
+        }
+
+        Ok(inputs)
+    }
+
+    /// Compute the key of a `cargo tree` invocation for the given host and target. `target_file`
+    /// is the file describing the target if it's a custom target.
+    pub(crate) fn key(
+        &self,
+        cargo_host: &str,
+        cargo_target: &str,
+        target_file: Option<&Path>,
+    ) -> Result<String> {
+        let mut hasher = Sha256::new();
+        update_hasher(&mut hasher, self.workspace_digest.as_bytes());
+        update_hasher(&mut hasher, cargo_host.as_bytes());
+        update_hasher(&mut hasher, cargo_target.as_bytes());
+        if let Some(target_file) = target_file {
+            update_hasher(&mut hasher, &read(target_file)?);
+        }
+        Ok(hasher.finalize().encode_hex::<String>())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Look up a cached result. Missing or unreadable entries are treated as cache misses.
+    pub(crate) fn get(&self, key: &str) -> Option<CargoTreeOutput> {
+        let path = self.entry_path(key);
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str::<CacheEntry>(&content) {
+            Ok(entry) if entry.key == key => Some(entry.output),
+            Ok(_) => {
+                debug!(
+                    "Ignoring mismatched `cargo tree` cache entry {}",
+                    path.display()
+                );
+                None
+            }
+            Err(err) => {
+                debug!(
+                    "Ignoring invalid `cargo tree` cache entry {}: {}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Store a result in the cache. Entries are written atomically so concurrent
+    /// readers never observe partially written files.
+    pub(crate) fn put(&self, key: &str, output: &CargoTreeOutput) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!(
+                "Failed to create `cargo tree` cache directory {}",
+                self.dir.display()
+            )
+        })?;
+
+        let entry = CacheEntry {
+            key: key.to_owned(),
+            output: output.clone(),
+        };
+        let temp = tempfile::NamedTempFile::new_in(&self.dir)
+            .context("Failed to create `cargo tree` cache entry")?;
+        serde_json::to_writer(temp.as_file(), &entry)
+            .context("Failed to serialize `cargo tree` cache entry")?;
+        let path = self.entry_path(key);
+        temp.persist(&path).with_context(|| {
+            format!(
+                "Failed to write `cargo tree` cache entry {}",
+                path.display()
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Determine whether or not an environment variable may influence the output of `cargo tree`.
+fn is_relevant_env_var(name: &str) -> bool {
+    if IGNORED_ENV_VARS.contains(&name) || name.starts_with("CARGO_BAZEL_") {
+        return false;
+    }
+    name.starts_with("CARGO_") || name == "RUSTFLAGS" || name == "RUSTC_BOOTSTRAP"
+}
+
+/// Hash a length-prefixed value so adjacent values can't be confused with one another.
+fn update_hasher(hasher: &mut Sha256, value: &[u8]) {
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeSet;
+
+    use crate::config::CrateId;
+    use crate::metadata::CargoTreeEntry;
+
+    fn output() -> CargoTreeOutput {
+        CargoTreeOutput {
+            target: BTreeMap::from([(
+                CrateId::new("serde".to_owned(), semver::Version::new(1, 0, 0)),
+                CargoTreeEntry {
+                    features: BTreeSet::from(["std".to_owned()]),
+                    deps: BTreeSet::new(),
+                },
+            )]),
+            host: BTreeMap::new(),
+        }
+    }
+
+    fn inputs(lockfile: &str) -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([("Cargo.lock".to_owned(), lockfile.as_bytes().to_vec())])
+    }
+
+    #[test]
+    fn round_trip() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("cargo_tree_cache_round_trip");
+        let cache = CargoTreeCache::new(&tempdir, &inputs("lock"));
+
+        let key = cache
+            .key("x86_64-unknown-linux-gnu", "wasm32-unknown-unknown", None)
+            .unwrap();
+        assert_eq!(cache.get(&key), None);
+
+        cache.put(&key, &output()).unwrap();
+        assert_eq!(cache.get(&key), Some(output()));
+    }
+
+    #[test]
+    fn key_changes_with_inputs() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("cargo_tree_cache_key_changes_with_inputs");
+        let target_file = tempdir.join("target.json");
+        fs::write(&target_file, "{}").unwrap();
+
+        let cache = CargoTreeCache::new(&tempdir, &inputs("lock"));
+        let key = |cache: &CargoTreeCache, host: &str, target_file: Option<&Path>| {
+            cache
+                .key(host, "wasm32-unknown-unknown", target_file)
+                .unwrap()
+        };
+        let base = key(&cache, "x86_64-unknown-linux-gnu", None);
+
+        assert_eq!(base, key(&cache, "x86_64-unknown-linux-gnu", None));
+        assert_ne!(base, key(&cache, "aarch64-apple-darwin", None));
+
+        let custom = key(&cache, "x86_64-unknown-linux-gnu", Some(&target_file));
+        assert_ne!(base, custom);
+        fs::write(&target_file, r#"{"arch": "wasm32"}"#).unwrap();
+        assert_ne!(
+            custom,
+            key(&cache, "x86_64-unknown-linux-gnu", Some(&target_file))
+        );
+
+        let updated = CargoTreeCache::new(&tempdir, &inputs("updated lock"));
+        assert_ne!(base, key(&updated, "x86_64-unknown-linux-gnu", None));
+    }
+
+    #[test]
+    fn invalid_entries_are_ignored() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("cargo_tree_cache_invalid_entries_are_ignored");
+        let cache = CargoTreeCache::new(&tempdir, &inputs("lock"));
+
+        let key = cache
+            .key("x86_64-unknown-linux-gnu", "wasm32-unknown-unknown", None)
+            .unwrap();
+        fs::write(cache.entry_path(&key), "{ not json").unwrap();
+        assert_eq!(cache.get(&key), None);
+
+        // An entry stored under another key is never served.
+        let other = cache
+            .key("aarch64-apple-darwin", "wasm32-unknown-unknown", None)
+            .unwrap();
+        cache.put(&other, &output()).unwrap();
+        fs::copy(cache.entry_path(&other), cache.entry_path(&key)).unwrap();
+        assert_eq!(cache.get(&key), None);
+    }
+
+    #[test]
+    fn relevant_env_vars() {
+        assert!(is_relevant_env_var("RUSTFLAGS"));
+        assert!(is_relevant_env_var("CARGO_ENCODED_RUSTFLAGS"));
+        assert!(is_relevant_env_var("CARGO_BUILD_TARGET"));
+        assert!(!is_relevant_env_var("CARGO_HOME"));
+        assert!(!is_relevant_env_var("CARGO_BAZEL_REPIN"));
+        assert!(!is_relevant_env_var("PATH"));
+    }
+}
@@ -13,11 +13,13 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, trace};
 use url::Url;
 
-use crate::config::CrateId;
+use crate::config::{CrateId, FeatureResolver};
 use crate::metadata::cargo_bin::Cargo;
+use crate::metadata::cargo_tree_cache::CargoTreeCache;
+use crate::metadata::metadata_resolver::{diff_outputs, MetadataResolver};
 use crate::select::{Select, SelectableScalar};
 use crate::utils::symlink::symlink;
-use crate::utils::target_triple::TargetTriple;
+use crate::utils::target_triple::{CfgTarget, CustomTarget, TargetTriple};
 
 /// A list platform triples that support host tools
 ///
@@ -86,9 +88,49 @@ impl CargoTreeEntry {
 
 impl SelectableScalar for CargoTreeEntry {}
 
+/// The parsed output of a single `cargo tree` invocation.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CargoTreeOutput {
+    /// Feature resolver info for crates built for the `--target` platform.
+    pub target: BTreeMap<CrateId, CargoTreeEntry>,
+
+    /// Feature resolver info for crates built for the host platform.
+    pub host: BTreeMap<CrateId, CargoTreeEntry>,
+}
+
 /// Feature and dependency metadata generated from [TreeResolver].
 pub(crate) type TreeResolverMetadata = BTreeMap<CrateId, Select<CargoTreeEntry>>;
 
+/// Feature and dependency metadata for crates built for the host (build scripts,
+/// proc-macros and their dependencies) generated from [TreeResolver].
+///
+/// Bazel builds these crates in the exec configuration but generated `select`s can
+/// only distinguish platforms. A crate built for both the host and the target of the
+/// same platform with differing features (e.g. `std` enabled only for a build script)
+/// is therefore given a distinct host library described by [HostTreeMetadata::variants].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct HostTreeMetadata {
+    /// Crates which are only ever built for the host.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) host_only: BTreeSet<CrateId>,
+
+    /// The host features and dependencies of crates which require a distinct host library.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) variants: TreeResolverMetadata,
+}
+
+impl HostTreeMetadata {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.host_only.is_empty() && self.variants.is_empty()
+    }
+}
+
+/// Resolver outputs keyed by host and then target triple.
+type PlatformOutputs = BTreeMap<TargetTriple, BTreeMap<TargetTriple, CargoTreeOutput>>;
+
+/// Resolver info for each crate keyed by the platform it's built for.
+type PlatformEntries = BTreeMap<CrateId, BTreeMap<TargetTriple, CargoTreeEntry>>;
+
 /// Generates metadata about a Cargo workspace tree which supplements the inaccuracies in
 /// standard [Cargo metadata](https://doc.rust-lang.org/cargo/commands/cargo-metadata.html)
 /// due lack of [Feature resolver 2](https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2)
@@ -97,24 +139,49 @@ pub(crate) type TreeResolverMetadata = BTreeMap<CrateId, Select<CargoTreeEntry>>
 pub(crate) struct TreeResolver {
     /// The path to a `cargo` binary
     cargo_bin: Cargo,
+
+    /// An optional directory in which to cache `cargo tree` results across runs.
+    cache_dir: Option<PathBuf>,
+
+    /// How features are resolved.
+    feature_resolver: FeatureResolver,
 }
 
 impl TreeResolver {
     pub(crate) fn new(cargo_bin: Cargo) -> Self {
-        Self { cargo_bin }
+        Self {
+            cargo_bin,
+            cache_dir: None,
+            feature_resolver: FeatureResolver::default(),
+        }
     }
 
-    /// Execute `cargo tree` for each target triple and return the stdout
-    /// streams containing structured output.
+    /// Select how features are resolved. See [FeatureResolver].
+    pub(crate) fn with_feature_resolver(mut self, feature_resolver: FeatureResolver) -> Self {
+        self.feature_resolver = feature_resolver;
+        self
+    }
+
+    /// Cache the results of `cargo tree` in `cache_dir`. See [CargoTreeCache].
+    pub(crate) fn with_cache_dir(mut self, cache_dir: Option<PathBuf>) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    /// Execute `cargo tree` for each target triple and return the parsed
+    /// structured output. Results found in `cache` are served without
+    /// spawning `cargo` and any newly computed results are added to it.
     fn execute_cargo_tree(
         &self,
         manifest_path: &Path,
         host_triples: &BTreeSet<TargetTriple>,
         target_triples: &BTreeSet<TargetTriple>,
+        custom_targets: &BTreeMap<TargetTriple, CustomTarget>,
         rustc_wrapper: &Path,
-    ) -> Result<BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>>> {
-        // A collection of all stdout logs from each process
-        let mut stdouts: BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>> = BTreeMap::new();
+        cache: Option<&CargoTreeCache>,
+    ) -> Result<PlatformOutputs> {
+        // A collection of all parsed outputs from each process
+        let mut outputs = PlatformOutputs::new();
 
         // We only want to spawn processes for unique cargo platforms
         let mut cargo_host_triples = BTreeMap::<String, BTreeSet<&TargetTriple>>::new();
@@ -125,9 +192,32 @@ impl TreeResolver {
                 .insert(triple);
         }
         let mut cargo_target_triples = BTreeMap::<String, BTreeSet<&TargetTriple>>::new();
+        // Captured `rustc --print cfg` files keyed by the `--target` value they describe.
+        let mut target_cfg_files = BTreeMap::<String, PathBuf>::new();
+        // The files describing custom targets, keyed by their `--target` value.
+        let mut custom_target_files = BTreeMap::<String, PathBuf>::new();
         for triple in target_triples {
+            let cargo_target = match custom_targets.get(triple) {
+                Some(custom_target) => {
+                    let cargo_target = custom_target.cargo_target(triple);
+                    custom_target_files
+                        .insert(cargo_target.clone(), custom_target.path().clone().into());
+                    if let CustomTarget::RustcCfg(path) = custom_target {
+                        if cfg!(windows) {
+                            bail!(
+                                "Custom target '{}' is described by a captured `rustc --print cfg` \
+                                 file which is not supported on Windows. Use a target JSON instead.",
+                                triple
+                            );
+                        }
+                        target_cfg_files.insert(cargo_target.clone(), path.clone().into());
+                    }
+                    cargo_target
+                }
+                None => triple.to_cargo(),
+            };
             cargo_target_triples
-                .entry(triple.to_cargo())
+                .entry(cargo_target)
                 .or_default()
                 .insert(triple);
         }
@@ -168,6 +258,34 @@ impl TreeResolver {
             bail!("No `cargo tree` invocations to run: no target triples were provided.");
         }
 
+        // Serve any jobs whose results are already cached.
+        let mut cached = Vec::<(String, String, CargoTreeOutput)>::new();
+        let mut cache_keys = BTreeMap::<(String, String), String>::new();
+        if let Some(cache) = cache {
+            let mut uncached = Vec::new();
+            for (cargo_host, cargo_target) in jobs {
+                let key = cache.key(
+                    &cargo_host,
+                    &cargo_target,
+                    custom_target_files.get(&cargo_target).map(PathBuf::as_path),
+                )?;
+                match cache.get(&key) {
+                    Some(output) => {
+                        debug!(
+                            "Using cached `cargo tree` output for host `{}`: {}",
+                            cargo_host, cargo_target,
+                        );
+                        cached.push((cargo_host, cargo_target, output));
+                    }
+                    None => {
+                        cache_keys.insert((cargo_host.clone(), cargo_target.clone()), key);
+                        uncached.push((cargo_host, cargo_target));
+                    }
+                }
+            }
+            jobs = uncached;
+        }
+
         // Spawn workers up to the cap; join one whenever the cap is reached.
         let mut in_flight =
             Vec::<thread::JoinHandle<anyhow::Result<(String, String, Output)>>>::new();
@@ -190,6 +308,7 @@ impl TreeResolver {
 
             let manifest_path = manifest_path.to_owned();
             let rustc_wrapper = rustc_wrapper.to_owned();
+            let target_cfg_file = target_cfg_files.get(&cargo_target).cloned();
             let cargo_bin = self.cargo_bin.clone();
 
             in_flight.push(thread::spawn(
@@ -199,8 +318,12 @@ impl TreeResolver {
                     // This is unfortunately a bit of a hack. See:
                     // - https://github.com/rust-lang/cargo/issues/9863
                     // - https://github.com/bazelbuild/rules_rust/issues/1662
-                    let child = cargo_bin
-                        .command()?
+                    let mut command = cargo_bin.command()?;
+                    // Targets unknown to rustc have their `cfg` values served by the wrapper.
+                    if let Some(target_cfg_file) = &target_cfg_file {
+                        command.env("TARGET_CFG_FILE", target_cfg_file);
+                    }
+                    let child = command
                         // These next two environment variables are used to hack cargo into using a custom
                         // host triple instead of the host triple detected by rustc.
                         .env("RUSTC_WRAPPER", &rustc_wrapper)
@@ -254,7 +377,7 @@ impl TreeResolver {
             results.push(res);
         }
 
-        // Process results and replicate outputs for de-duplicated platforms.
+        // Parse results, caching any newly computed ones.
         for (cargo_host, cargo_target, output) in results {
             if !output.status.success() {
                 tracing::error!("{}", String::from_utf8_lossy(&output.stdout));
@@ -268,18 +391,39 @@ impl TreeResolver {
                 cargo_host
             );
 
-            // Replicate outputs for any de-duplicated platforms
+            trace!(
+                "Parsing (host={}) `cargo tree --target {}` output:\n```\n{}\n```",
+                cargo_host,
+                cargo_target,
+                String::from_utf8_lossy(&output.stdout),
+            );
+
+            let (target, host) = parse_cargo_tree_output(output.stdout.lines())?;
+            let parsed = CargoTreeOutput { target, host };
+
+            if let Some(cache) = cache {
+                let key = &cache_keys[&(cargo_host.clone(), cargo_target.clone())];
+                if let Err(err) = cache.put(key, &parsed) {
+                    tracing::warn!("Failed to cache `cargo tree` output: {:?}", err);
+                }
+            }
+
+            cached.push((cargo_host, cargo_target, parsed));
+        }
+
+        // Replicate outputs for any de-duplicated platforms
+        for (cargo_host, cargo_target, output) in cached {
             for host_plat in cargo_host_triples[&cargo_host].iter() {
                 for target_plat in cargo_target_triples[&cargo_target].iter() {
-                    stdouts
+                    outputs
                         .entry((*host_plat).clone())
                         .or_default()
-                        .insert((*target_plat).clone(), output.stdout.clone());
+                        .insert((*target_plat).clone(), output.clone());
                 }
             }
         }
 
-        Ok(stdouts)
+        Ok(outputs)
     }
 
     // The use of this wrapper should __never__ escape this class.
@@ -359,6 +503,11 @@ impl TreeResolver {
     /// indicates platform info is being requested. When intercepted a `--target ${HOST_TRIPLE}` argument will be
     /// appended where `HOST_TRIPLE` is an environment variable set by this class.
     ///
+    /// For custom targets described by a captured `rustc --print cfg` file, `TARGET_CFG_FILE` is also set.
+    /// `rustc` cannot answer the command above for such a target, so the wrapper asks about `HOST_TRIPLE`
+    /// instead and replaces everything following the last `___` line, printed by `--print=crate-name`
+    /// (the `cfg` values), with the contents of that file.
+    ///
     /// The design/use of this script feels blasphemous but it is the only way I could figure out how to get the
     /// necessary information from Cargo without reimplementing the dependency+feature resolver logic. This is
     /// valuable in allowing `cargo-bazel` to scale with different versions of Rust.
@@ -369,13 +518,144 @@ impl TreeResolver {
         Self::create_rustc_wrapper_impl(output_dir)
     }
 
+    /// Resolve features by running `cargo tree` for each combination of host and target triple.
+    fn resolve_with_cargo_tree(
+        &self,
+        manifest_path: &Path,
+        cargo_metadata: &cargo_metadata::Metadata,
+        host_triples: &BTreeSet<TargetTriple>,
+        target_triples: &BTreeSet<TargetTriple>,
+        custom_targets: &BTreeMap<TargetTriple, CustomTarget>,
+        output_dir: &Path,
+    ) -> Result<PlatformOutputs> {
+        let rustc_wrapper = Self::create_rustc_wrapper(output_dir)?;
+
+        let cache = match &self.cache_dir {
+            Some(cache_dir) => Some(CargoTreeCache::new(
+                cache_dir,
+                &CargoTreeCache::workspace_inputs(
+                    &self.cargo_bin,
+                    manifest_path,
+                    &rustc_wrapper,
+                    cargo_metadata,
+                )?,
+            )),
+            None => None,
+        };
+
+        // This is a very expensive process. Here we iterate over all target triples
+        // and generate tree data as though they were also the host triple
+        self.execute_cargo_tree(
+            manifest_path,
+            host_triples,
+            target_triples,
+            custom_targets,
+            &rustc_wrapper,
+            cache.as_ref(),
+        )
+    }
+
+    /// Resolve features in-process from `cargo metadata` output using a [MetadataResolver].
+    fn resolve_with_metadata(
+        &self,
+        cargo_metadata: &cargo_metadata::Metadata,
+        host_triples: &BTreeSet<TargetTriple>,
+        target_triples: &BTreeSet<TargetTriple>,
+        custom_targets: &BTreeMap<TargetTriple, CustomTarget>,
+    ) -> Result<PlatformOutputs> {
+        if host_triples.is_empty() {
+            bail!(
+                "`supported_platform_triples` contains no platforms with host tools, so \
+                 feature resolution cannot be performed."
+            );
+        }
+
+        let resolver = MetadataResolver::new(cargo_metadata)?.with_proc_macro_roots();
+
+        let target_cfgs = custom_targets
+            .iter()
+            .map(|(triple, target)| {
+                let cfg = target
+                    .cfg(self.cargo_bin.rustc_path())
+                    .with_context(|| format!("Failed to load custom target '{}'", triple))?;
+                Ok((triple.clone(), cfg))
+            })
+            .collect::<Result<BTreeMap<TargetTriple, Vec<String>>>>()?;
+        let targets = target_triples
+            .iter()
+            .map(|triple| CfgTarget::new(triple, &target_cfgs).map(|target| (triple, target)))
+            .collect::<Result<Vec<(&TargetTriple, CfgTarget)>>>()?;
+
+        let mut outputs = PlatformOutputs::new();
+        for host_triple in host_triples {
+            let host = CfgTarget::new(host_triple, &BTreeMap::new())?;
+            for (target_triple, target) in targets.iter() {
+                let output = resolver.resolve(&host, target).with_context(|| {
+                    format!(
+                        "Failed to resolve features for host `{}`: {}",
+                        host_triple, target_triple
+                    )
+                })?;
+                outputs
+                    .entry(host_triple.clone())
+                    .or_default()
+                    .insert((*target_triple).clone(), output);
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    /// Fail if the features resolved by `cargo tree` and the [MetadataResolver] differ.
+    fn check_differential(cargo_tree: &PlatformOutputs, metadata: &PlatformOutputs) -> Result<()> {
+        let empty = CargoTreeOutput::default();
+        let mut diffs = Vec::new();
+        for (host_triple, target_outputs) in cargo_tree {
+            for (target_triple, expected) in target_outputs {
+                let actual = metadata
+                    .get(host_triple)
+                    .and_then(|target_outputs| target_outputs.get(target_triple))
+                    .unwrap_or(&empty);
+                for (kind, expected, actual) in [
+                    ("target", &expected.target, &actual.target),
+                    ("host", &expected.host, &actual.host),
+                ] {
+                    diffs.extend(diff_outputs(expected, actual).into_iter().map(|diff| {
+                        format!(
+                            "(host={}, target={}, {}) {}",
+                            host_triple, target_triple, kind, diff
+                        )
+                    }));
+                }
+            }
+        }
+
+        if diffs.is_empty() {
+            return Ok(());
+        }
+
+        const MAX_REPORTED_DIFFS: usize = 50;
+        let count = diffs.len();
+        diffs.truncate(MAX_REPORTED_DIFFS);
+        if count > MAX_REPORTED_DIFFS {
+            diffs.push(format!("... and {} more", count - MAX_REPORTED_DIFFS));
+        }
+        bail!(
+            "The `metadata` feature resolver disagrees with `cargo tree` in {} places \
+             (`-` is only found by `cargo tree`, `+` only by `metadata`):\n{}",
+            count,
+            diffs.join("\n")
+        );
+    }
+
     /// Computes the set of enabled features for each target triplet for each crate.
     #[tracing::instrument(name = "TreeResolver::generate", skip_all)]
     pub(crate) fn generate(
         &self,
         pristine_manifest_path: &Utf8Path,
         target_triples: &BTreeSet<TargetTriple>,
-    ) -> Result<TreeResolverMetadata> {
+        custom_targets: &BTreeMap<TargetTriple, CustomTarget>,
+    ) -> Result<(TreeResolverMetadata, HostTreeMetadata)> {
         debug!(
             "Generating features for manifest {}",
             pristine_manifest_path
@@ -383,58 +663,67 @@ impl TreeResolver {
 
         let tempdir = tempfile::tempdir().context("Failed to make tempdir")?;
 
-        let manifest_path_with_transitive_proc_macros = self
+        let (manifest_path_with_transitive_proc_macros, cargo_metadata) = self
             .copy_project_with_explicit_deps_on_all_transitive_proc_macros(
                 pristine_manifest_path,
                 &tempdir.path().join("explicit_proc_macro_deps"),
             )
             .context("Failed to copy project with proc macro deps made direct")?;
 
-        let rustc_wrapper = Self::create_rustc_wrapper(tempdir.path())?;
-
         let host_triples: BTreeSet<TargetTriple> = target_triples
             .iter()
             // Only query triples for platforms that have host tools.
             .filter(|host_triple| {
-                RUSTC_TRIPLES_WITH_HOST_TOOLS.contains(&host_triple.to_cargo().as_str())
+                !custom_targets.contains_key(*host_triple)
+                    && RUSTC_TRIPLES_WITH_HOST_TOOLS.contains(&host_triple.to_cargo().as_str())
             })
             .cloned()
             .collect();
 
-        // This is a very expensive process. Here we iterate over all target triples
-        // and generate tree data as though they were also the host triple
-        let deps_tree_streams: BTreeMap<TargetTriple, BTreeMap<TargetTriple, Vec<u8>>> = self
-            .execute_cargo_tree(
+        let resolve_with_cargo_tree = || {
+            self.resolve_with_cargo_tree(
                 &manifest_path_with_transitive_proc_macros,
+                &cargo_metadata,
                 &host_triples,
                 target_triples,
-                &rustc_wrapper,
-            )?;
-
-        let mut metadata: BTreeMap<CrateId, BTreeMap<TargetTriple, CargoTreeEntry>> =
-            BTreeMap::new();
-
-        for (host_triple, target_streams) in deps_tree_streams.into_iter() {
-            for (target_triple, stdout) in target_streams.into_iter() {
-                trace!(
-                    "Parsing (host={}) `cargo tree --target {}` output:\n```\n{}\n```",
-                    host_triple,
-                    target_triple,
-                    String::from_utf8_lossy(&stdout),
-                );
+                custom_targets,
+                tempdir.path(),
+            )
+        };
+        let resolve_with_metadata = || {
+            self.resolve_with_metadata(
+                &cargo_metadata,
+                &host_triples,
+                target_triples,
+                custom_targets,
+            )
+        };
+
+        let deps_tree_outputs = match self.feature_resolver {
+            FeatureResolver::CargoTree => resolve_with_cargo_tree()?,
+            FeatureResolver::Metadata => resolve_with_metadata()?,
+            FeatureResolver::Differential => {
+                let cargo_tree_outputs = resolve_with_cargo_tree()?;
+                Self::check_differential(&cargo_tree_outputs, &resolve_with_metadata()?)?;
+                cargo_tree_outputs
+            }
+        };
 
-                let (target_tree_data, host_tree_data) = parse_cargo_tree_output(stdout.lines())?;
+        let mut target_metadata = PlatformEntries::new();
+        let mut host_metadata = PlatformEntries::new();
 
-                for (entry, tree_data) in target_tree_data {
-                    metadata
+        for (host_triple, target_outputs) in deps_tree_outputs.into_iter() {
+            for (target_triple, output) in target_outputs.into_iter() {
+                for (entry, tree_data) in output.target {
+                    target_metadata
                         .entry(entry.clone())
                         .or_default()
                         .entry(target_triple.clone())
                         .or_default()
                         .consume(tree_data);
                 }
-                for (entry, tree_data) in host_tree_data {
-                    metadata
+                for (entry, tree_data) in output.host {
+                    host_metadata
                         .entry(entry.clone())
                         .or_default()
                         .entry(host_triple.clone())
@@ -444,7 +733,96 @@ impl TreeResolver {
             }
         }
 
-        // Collect all metadata into a mapping of crate to it's metadata per target.
+        let (metadata, host) = Self::split_host_metadata(target_metadata, host_metadata);
+
+        Ok((Self::into_selects(metadata), host))
+    }
+
+    /// Merge the features and dependencies a crate has when built for the host into the ones
+    /// it has when built for the target of the same platform. Where the two differ, the target
+    /// retains its own and the host configuration is recorded as a distinct variant in the
+    /// returned [HostTreeMetadata].
+    fn split_host_metadata(
+        target_metadata: PlatformEntries,
+        mut host_metadata: PlatformEntries,
+    ) -> (PlatformEntries, HostTreeMetadata) {
+        let host_only: BTreeSet<CrateId> = host_metadata
+            .keys()
+            .filter(|crate_id| !target_metadata.contains_key(*crate_id))
+            .cloned()
+            .collect();
+
+        let mut metadata = PlatformEntries::new();
+        let mut split: BTreeSet<CrateId> = BTreeSet::new();
+        for (crate_id, target_data) in target_metadata {
+            let mut platforms = target_data;
+            if let Some(host_data) = host_metadata.get(&crate_id) {
+                for (triple, host_entry) in host_data {
+                    match platforms.get_mut(triple) {
+                        Some(target_entry) if target_entry != host_entry => {
+                            split.insert(crate_id.clone());
+                        }
+                        Some(_) => {}
+                        None => {
+                            platforms.insert(triple.clone(), host_entry.clone());
+                        }
+                    }
+                }
+            }
+            metadata.insert(crate_id, platforms);
+        }
+
+        // Any crate with its own host library must depend on the host libraries of its
+        // dependencies, so its host configuration has to be split from the target too.
+        loop {
+            let dependents: BTreeSet<CrateId> = host_metadata
+                .iter()
+                .filter(|(crate_id, _)| {
+                    !split.contains(*crate_id) && !host_only.contains(*crate_id)
+                })
+                .filter(|(_, host_data)| {
+                    host_data
+                        .values()
+                        .any(|entry| entry.deps.iter().any(|dep| split.contains(dep)))
+                })
+                .map(|(crate_id, _)| crate_id.clone())
+                .collect();
+            if dependents.is_empty() {
+                break;
+            }
+            split.extend(dependents);
+        }
+
+        for crate_id in &host_only {
+            if let Some(host_data) = host_metadata.remove(crate_id) {
+                metadata.insert(crate_id.clone(), host_data);
+            }
+        }
+
+        // Host libraries are built on the platforms a crate is used on for the host, and
+        // otherwise fall back to the features the crate has on a platform.
+        let variants = split
+            .into_iter()
+            .map(|crate_id| {
+                let mut platforms = metadata[&crate_id].clone();
+                if let Some(host_data) = host_metadata.remove(&crate_id) {
+                    platforms.extend(host_data);
+                }
+                (crate_id, platforms)
+            })
+            .collect();
+
+        (
+            metadata,
+            HostTreeMetadata {
+                host_only,
+                variants: Self::into_selects(variants),
+            },
+        )
+    }
+
+    /// Collect all metadata into a mapping of crate to it's metadata per target.
+    fn into_selects(metadata: PlatformEntries) -> TreeResolverMetadata {
         let mut result = TreeResolverMetadata::new();
         for (crate_id, tree_data) in metadata.into_iter() {
             let common = CargoTreeEntry {
@@ -493,7 +871,7 @@ impl TreeResolver {
             }
             result.insert(crate_id, select);
         }
-        Ok(result)
+        result
     }
 
     // Artificially inject all proc macros as dependency roots.
@@ -518,7 +896,7 @@ impl TreeResolver {
         &self,
         pristine_manifest_path: &Utf8Path,
         output_dir: &Path,
-    ) -> Result<PathBuf> {
+    ) -> Result<(PathBuf, cargo_metadata::Metadata)> {
         if !output_dir.exists() {
             std::fs::create_dir_all(output_dir)?;
         }
@@ -632,7 +1010,7 @@ impl TreeResolver {
         }
         let manifest_path_with_transitive_proc_macros = output_dir.join("Cargo.toml");
         crate::splicing::write_manifest(&manifest_path_with_transitive_proc_macros, &manifest)?;
-        Ok(manifest_path_with_transitive_proc_macros)
+        Ok((manifest_path_with_transitive_proc_macros, cargo_metadata))
     }
 }
 
@@ -925,6 +1303,78 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn split_host_metadata() {
+        let linux = TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned());
+        let wasm = TargetTriple::from_bazel("wasm32-unknown-unknown".to_owned());
+        let id = |name: &str| CrateId::new(name.to_owned(), Version::new(1, 0, 0));
+        let entry = |features: &[&str], deps: &[&str]| CargoTreeEntry {
+            features: features.iter().map(|f| f.to_string()).collect(),
+            deps: deps.iter().map(|d| id(d)).collect(),
+        };
+
+        let target_metadata = PlatformEntries::from([
+            (
+                id("serde"),
+                BTreeMap::from([
+                    (linux.clone(), entry(&[], &[])),
+                    (wasm.clone(), entry(&[], &[])),
+                ]),
+            ),
+            (
+                id("serde_json"),
+                BTreeMap::from([(linux.clone(), entry(&[], &["serde"]))]),
+            ),
+            (
+                id("log"),
+                BTreeMap::from([(linux.clone(), entry(&[], &[]))]),
+            ),
+        ]);
+        let host_metadata = PlatformEntries::from([
+            (
+                id("serde"),
+                BTreeMap::from([(linux.clone(), entry(&["std"], &[]))]),
+            ),
+            (
+                id("serde_json"),
+                BTreeMap::from([(linux.clone(), entry(&[], &["serde"]))]),
+            ),
+            (
+                id("log"),
+                BTreeMap::from([(linux.clone(), entry(&[], &[]))]),
+            ),
+            (
+                id("serde_derive"),
+                BTreeMap::from([(linux.clone(), entry(&[], &["serde"]))]),
+            ),
+        ]);
+
+        let (metadata, host) = TreeResolver::split_host_metadata(target_metadata, host_metadata);
+
+        // Target libraries keep their own features.
+        assert_eq!(metadata[&id("serde")][&linux], entry(&[], &[]));
+        assert_eq!(
+            metadata[&id("serde_derive")][&linux],
+            entry(&[], &["serde"])
+        );
+        assert_eq!(host.host_only, BTreeSet::from([id("serde_derive")]));
+
+        // `serde_json` has the same features on the host but depends on the host library of
+        // `serde` there. `log` can share a library between the host and target.
+        assert_eq!(
+            host.variants.keys().cloned().collect::<BTreeSet<_>>(),
+            BTreeSet::from([id("serde"), id("serde_json")])
+        );
+        let serde = &host.variants[&id("serde")];
+        assert_eq!(
+            serde.items(),
+            vec![(
+                Some("x86_64-unknown-linux-gnu".to_owned()),
+                entry(&["std"], &[])
+            )]
+        );
+    }
+
     #[test]
     fn parse_sparse_source() {
         let source = Source::parse(
@@ -1196,6 +1646,68 @@ mod test {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn cargo_tree_rustc_wrapper_target_cfg_file() {
+        let (_, tempdir) = crate::test::test_tempdir("cargo_tree_rustc_wrapper_target_cfg_file");
+
+        let script_text = dedent(
+            r#"
+            #!/bin/sh
+            set -eu
+            echo "___"
+            echo "$@"
+            echo "___"
+            echo 'target_os="linux"'
+            "#,
+        );
+
+        let (rustc, rustc_wrapper) = create_mock_rustc(&tempdir, &script_text);
+
+        let target_cfg_file = tempdir.join("acme.cfg");
+        std::fs::write(
+            &target_cfg_file,
+            "target_arch=\"arm\"\ntarget_os=\"acme\"\n",
+        )
+        .unwrap();
+
+        let output = new_mock_rustc_command(&rustc_wrapper, &rustc, &tempdir)
+            .env("TARGET_CFG_FILE", &target_cfg_file)
+            .arg("-")
+            .arg("--crate-name")
+            .arg("___")
+            .arg("--print=file-names")
+            .arg("--target")
+            .arg("thumbv7em-acme-none-eabihf")
+            .arg("--print=crate-name")
+            .arg("--print=cfg")
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            output.status.code().unwrap(),
+            0,
+            "Exit with {}\n{}\n{}",
+            output.status.code().unwrap(),
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(
+            stdout.contains("--target mock-platform-triple")
+                && !stdout.contains("thumbv7em-acme-none-eabihf"),
+            "The rustc wrapper did not replace `--target`. Got output:\n{}",
+            stdout
+        );
+        assert!(
+            stdout.ends_with("___\ntarget_arch=\"arm\"\ntarget_os=\"acme\"\n"),
+            "The rustc wrapper did not substitute the captured cfg. Got output:\n{}",
+            stdout
+        );
+    }
+
     #[test]
     fn serde_cargo_tree_entry() {
         {
@@ -1672,7 +2184,9 @@ mod test {
                 &tempdir.join("Cargo.toml"),
                 &host_triples,
                 &target_triples,
+                &BTreeMap::new(),
                 &tempdir.join("rustc_wrapper"),
+                None,
             )
             .expect_err("expected an error when no host triples are supplied");
 
@@ -5,6 +5,62 @@
 
 set -eu
 
+# When cargo is detecting the configuration of a custom target described by a
+# captured `rustc --print cfg` file, query the host instead and replace the
+# `cfg` values (printed after the last `___` line, which is the output of
+# `--print=crate-name`) with the captured ones.
+if [ -n "${TARGET_CFG_FILE:-}" ]; then
+    case "$*" in
+        *"rustc - --crate-name ___ "*" --target "*)
+            _TARGET_ARG=0
+            for arg do
+                shift
+                if [ "${_TARGET_ARG}" = 1 ]; then
+                    arg="${HOST_TRIPLE}"
+                    _TARGET_ARG=0
+                elif [ "${arg}" = "--target" ]; then
+                    _TARGET_ARG=1
+                fi
+                set -- "$@" "${arg}"
+            done
+
+            set +e
+            _RUSTC_OUTPUT="$("$@")"
+            _EXIT_CODE=$?
+            set -e
+
+            if [ ${_EXIT_CODE} -ne 0 ]; then
+                echo "${_RUSTC_OUTPUT}"
+                exit ${_EXIT_CODE}
+            fi
+
+            _DELIMITERS=0
+            while IFS= read -r line; do
+                if [ "${line}" = "___" ]; then
+                    _DELIMITERS=$((_DELIMITERS + 1))
+                fi
+            done <<EOF
+${_RUSTC_OUTPUT}
+EOF
+
+            while IFS= read -r line; do
+                echo "${line}"
+                if [ "${line}" = "___" ]; then
+                    _DELIMITERS=$((_DELIMITERS - 1))
+                    if [ ${_DELIMITERS} -eq 0 ]; then
+                        break
+                    fi
+                fi
+            done <<EOF
+${_RUSTC_OUTPUT}
+EOF
+
+            cat "${TARGET_CFG_FILE}"
+            exit 0
+            ;;
+    esac
+fi
+
 # When cargo is detecting the host configuration, the host target needs to be
 # injected into the command.
 case "$*" in
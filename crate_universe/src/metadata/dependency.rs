@@ -10,7 +10,7 @@ use cargo_platform::Platform;
 use serde::{Deserialize, Serialize};
 
 use crate::config::CrateId;
-use crate::metadata::TreeResolverMetadata;
+use crate::metadata::{HostTreeMetadata, TreeResolverMetadata};
 use crate::select::Select;
 use crate::utils::sanitize_module_name;
 
@@ -25,6 +25,10 @@ pub(crate) struct Dependency {
 
     /// The alias for the dependency from the perspective of the current package
     pub(crate) alias: Option<String>,
+
+    /// Whether or not the dependency refers to the distinct host library of the target.
+    /// See [HostTreeMetadata].
+    pub(crate) host: bool,
 }
 
 /// A collection of [Dependency]s sorted by dependency kind.
@@ -37,6 +41,41 @@ pub(crate) struct DependencySet {
     pub(crate) build_deps: Select<BTreeSet<Dependency>>,
     pub(crate) build_link_deps: Select<BTreeSet<Dependency>>,
     pub(crate) build_proc_macro_deps: Select<BTreeSet<Dependency>>,
+    /// The normal dependencies of the crate's distinct host library, if it has one.
+    pub(crate) host_normal_deps: Select<BTreeSet<Dependency>>,
+}
+
+/// A dependency tree of crates built for the host. See [HostTreeMetadata].
+#[derive(Debug, Default)]
+pub(crate) struct HostDepTree {
+    /// Crates which are only ever built for the host.
+    pub(crate) host_only: BTreeSet<CrateId>,
+
+    /// The dependencies of crates with distinct host libraries.
+    pub(crate) variants: BTreeMap<CrateId, Select<BTreeSet<CrateId>>>,
+}
+
+impl HostDepTree {
+    pub(crate) fn new(host_data: &HostTreeMetadata) -> Self {
+        Self {
+            host_only: host_data.host_only.clone(),
+            variants: build_dep_tree(&host_data.variants),
+        }
+    }
+
+    /// Point dependencies with a distinct host library at it.
+    fn use_host_libraries(
+        &self,
+        deps: Select<BTreeSet<Dependency>>,
+        metadata: &CargoMetadata,
+    ) -> Select<BTreeSet<Dependency>> {
+        deps.map(|mut dep| {
+            dep.host = self
+                .variants
+                .contains_key(&CrateId::from(&metadata[&dep.package_id]));
+            dep
+        })
+    }
 }
 
 impl DependencySet {
@@ -45,9 +84,16 @@ impl DependencySet {
         node: &Node,
         metadata: &CargoMetadata,
         tree_data: Option<&Select<BTreeSet<CrateId>>>,
+        host_tree: &HostDepTree,
     ) -> Self {
-        let (normal_dev_deps, normal_deps) = {
-            let (dev, normal) = node
+        let crate_id = CrateId::from(&metadata[&node.id]);
+
+        // Crates only built for the host must depend on the host libraries of their dependencies.
+        let is_host_only =
+            host_tree.host_only.contains(&crate_id) || is_proc_macro_package(&metadata[&node.id]);
+
+        let (normal_dev_deps, normal_deps, host_normal_deps) = {
+            let (dev, normal): (Vec<&NodeDep>, Vec<&NodeDep>) = node
                 .deps
                 .iter()
                 // Do not track workspace members as dependencies. Users are expected to maintain those connections
@@ -56,6 +102,27 @@ impl DependencySet {
                 .filter(|dep| is_normal_dependency(dep) || is_dev_dependency(dep))
                 .partition(|dep| is_dev_dependency(dep));
 
+            let normal_deps = collect_deps_selectable(
+                node,
+                normal.clone(),
+                metadata,
+                DependencyKind::Normal,
+                tree_data,
+            );
+            let host_normal_deps = match host_tree.variants.get(&crate_id) {
+                Some(host_data) => host_tree.use_host_libraries(
+                    collect_deps_selectable(
+                        node,
+                        normal,
+                        metadata,
+                        DependencyKind::Normal,
+                        Some(host_data),
+                    ),
+                    metadata,
+                ),
+                None => Select::default(),
+            };
+
             (
                 collect_deps_selectable(
                     node,
@@ -64,7 +131,12 @@ impl DependencySet {
                     DependencyKind::Development,
                     tree_data,
                 ),
-                collect_deps_selectable(node, normal, metadata, DependencyKind::Normal, tree_data),
+                if is_host_only {
+                    host_tree.use_host_libraries(normal_deps, metadata)
+                } else {
+                    normal_deps
+                },
+                host_normal_deps,
             )
         };
 
@@ -102,15 +174,28 @@ impl DependencySet {
                 .filter(|dep| !is_dev_dependency(dep))
                 .partition(|dep| is_proc_macro_package(&metadata[&dep.pkg]));
 
+            // Build scripts are built for the host.
             (
-                collect_deps_selectable(
-                    node,
-                    proc_macro,
+                host_tree.use_host_libraries(
+                    collect_deps_selectable(
+                        node,
+                        proc_macro,
+                        metadata,
+                        DependencyKind::Build,
+                        tree_data,
+                    ),
+                    metadata,
+                ),
+                host_tree.use_host_libraries(
+                    collect_deps_selectable(
+                        node,
+                        normal,
+                        metadata,
+                        DependencyKind::Build,
+                        tree_data,
+                    ),
                     metadata,
-                    DependencyKind::Build,
-                    tree_data,
                 ),
-                collect_deps_selectable(node, normal, metadata, DependencyKind::Build, tree_data),
             )
         };
 
@@ -140,6 +225,7 @@ impl DependencySet {
             build_deps,
             build_link_deps,
             build_proc_macro_deps,
+            host_normal_deps,
         }
     }
 }
@@ -219,6 +305,7 @@ fn collect_deps_selectable(
                                 package_id: dep.pkg.clone(),
                                 target_name: target_name.clone(),
                                 alias: alias.clone(),
+                                host: false,
                             };
                             select.insert(dependency, config);
                         }
@@ -229,6 +316,7 @@ fn collect_deps_selectable(
                     package_id: dep.pkg.clone(),
                     target_name: target_name.clone(),
                     alias: alias.clone(),
+                    host: false,
                 };
                 select.insert(
                     dependency,
@@ -520,7 +608,8 @@ mod test {
         let metadata = metadata::example_proc_macro_dep();
 
         let node = find_metadata_node("example-proc-macro-dep", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         let normal_deps: Vec<_> = dependencies
             .normal_deps
@@ -544,7 +633,8 @@ mod test {
         let metadata = metadata::alias();
 
         let node = find_metadata_node("surrealdb-core", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         let bindings = dependencies.normal_deps.items();
 
@@ -567,7 +657,8 @@ mod test {
 
         let openssl_node = find_metadata_node("openssl", &metadata);
 
-        let dependencies = DependencySet::new_for_node(openssl_node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(openssl_node, &metadata, None, &HostDepTree::default());
 
         let normal_sys_crate =
             dependencies
@@ -600,7 +691,8 @@ mod test {
         let metadata = metadata::build_scripts();
 
         let libssh2 = find_metadata_node("libssh2-sys", &metadata);
-        let libssh2_depset = DependencySet::new_for_node(libssh2, &metadata, None);
+        let libssh2_depset =
+            DependencySet::new_for_node(libssh2, &metadata, None, &HostDepTree::default());
 
         // Collect build dependencies into a set
         let build_deps: BTreeSet<String> = libssh2_depset
@@ -644,12 +736,71 @@ mod test {
         assert!(libssh2_depset.build_proc_macro_deps.is_empty());
     }
 
+    #[test]
+    fn host_libraries() {
+        let metadata = metadata::build_scripts();
+        let libssh2 = find_metadata_node("libssh2-sys", &metadata);
+
+        let cc = CrateId::new("cc".to_owned(), semver::Version::new(1, 0, 72));
+        let libc = CrateId::new("libc".to_owned(), semver::Version::new(0, 2, 112));
+        let libssh2_id = CrateId::new("libssh2-sys".to_owned(), semver::Version::new(0, 3, 0));
+
+        let host_deps = |deps: &Select<BTreeSet<Dependency>>| -> BTreeSet<String> {
+            deps.values()
+                .into_iter()
+                .filter(|dep| dep.host)
+                .map(|dep| metadata[&dep.package_id].name.clone())
+                .collect()
+        };
+
+        // Build scripts use the host libraries of their dependencies.
+        let mut host_tree = HostDepTree {
+            host_only: BTreeSet::new(),
+            variants: BTreeMap::from([
+                (cc.clone(), Select::default()),
+                (libc.clone(), Select::default()),
+            ]),
+        };
+        let depset = DependencySet::new_for_node(libssh2, &metadata, None, &host_tree);
+        assert_eq!(
+            host_deps(&depset.build_deps),
+            BTreeSet::from(["cc".to_owned()])
+        );
+        assert!(host_deps(&depset.normal_deps).is_empty());
+        assert!(depset.host_normal_deps.is_empty());
+
+        // A crate with its own host library uses the host libraries of its dependencies there.
+        host_tree
+            .variants
+            .insert(libssh2_id.clone(), Select::default());
+        let depset = DependencySet::new_for_node(libssh2, &metadata, None, &host_tree);
+        assert!(host_deps(&depset.normal_deps).is_empty());
+        assert_eq!(
+            host_deps(&depset.host_normal_deps),
+            BTreeSet::from(["libc".to_owned()])
+        );
+        assert_eq!(
+            depset.host_normal_deps.values().len(),
+            depset.normal_deps.values().len()
+        );
+
+        // Crates only built for the host always use host libraries.
+        host_tree.variants.remove(&libssh2_id);
+        host_tree.host_only.insert(libssh2_id);
+        let depset = DependencySet::new_for_node(libssh2, &metadata, None, &host_tree);
+        assert_eq!(
+            host_deps(&depset.normal_deps),
+            BTreeSet::from(["libc".to_owned()])
+        );
+    }
+
     #[test]
     fn tracked_aliases() {
         let metadata = metadata::alias();
 
         let aliases_node = find_metadata_node("aliases", &metadata);
-        let dependencies = DependencySet::new_for_node(aliases_node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(aliases_node, &metadata, None, &HostDepTree::default());
 
         let aliases: Vec<Dependency> = dependencies
             .normal_deps
@@ -675,7 +826,8 @@ mod test {
         let metadata = metadata::crate_types();
 
         let node = find_metadata_node("crate-types", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         let rlib_deps: Vec<Dependency> = dependencies
             .normal_deps
@@ -705,7 +857,8 @@ mod test {
         let metadata = metadata::multi_cfg_dep();
 
         let node = find_metadata_node("cpufeatures", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         let libc_cfgs: BTreeSet<Option<String>> = dependencies
             .normal_deps
@@ -730,7 +883,8 @@ mod test {
         let metadata = metadata::multi_kind_proc_macro_dep();
 
         let node = find_metadata_node("multi-kind-proc-macro-dep", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         let lib_deps: Vec<_> = dependencies
             .proc_macro_deps
@@ -754,7 +908,8 @@ mod test {
         let metadata = metadata::optional_deps_disabled();
 
         let node = find_metadata_node("clap", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         assert!(!dependencies
             .normal_deps
@@ -769,7 +924,8 @@ mod test {
         let metadata = metadata::renamed_optional_deps_disabled();
 
         let serde_with = find_metadata_node("serde_with", &metadata);
-        let serde_with_depset = DependencySet::new_for_node(serde_with, &metadata, None);
+        let serde_with_depset =
+            DependencySet::new_for_node(serde_with, &metadata, None, &HostDepTree::default());
         assert!(!serde_with_depset
             .normal_deps
             .items()
@@ -797,7 +953,12 @@ mod test {
 
         let clap = find_metadata_node("clap", &metadata);
         let dep_tree = build_dep_tree(&resolver_data);
-        let clap_depset = DependencySet::new_for_node(clap, &metadata, dep_tree.get(&crate_id));
+        let clap_depset = DependencySet::new_for_node(
+            clap,
+            &metadata,
+            dep_tree.get(&crate_id),
+            &HostDepTree::default(),
+        );
         assert_eq!(
             clap_depset
                 .normal_deps
@@ -810,7 +971,8 @@ mod test {
         );
 
         let notify = find_metadata_node("notify", &metadata);
-        let notify_depset = DependencySet::new_for_node(notify, &metadata, None);
+        let notify_depset =
+            DependencySet::new_for_node(notify, &metadata, None, &HostDepTree::default());
 
         // mio is not present in the common list of dependencies
         assert!(!notify_depset
@@ -843,7 +1005,8 @@ mod test {
         let metadata = metadata::optional_deps_disabled_build_dep_enabled();
 
         let node = find_metadata_node("gherkin", &metadata);
-        let dependencies = DependencySet::new_for_node(node, &metadata, None);
+        let dependencies =
+            DependencySet::new_for_node(node, &metadata, None, &HostDepTree::default());
 
         assert!(!dependencies
             .normal_deps
@@ -876,7 +1039,12 @@ mod test {
 
         let p256 = find_metadata_node("p256", &metadata);
         let dep_tree = build_dep_tree(&resolver_data);
-        let p256_depset = DependencySet::new_for_node(p256, &metadata, dep_tree.get(&crate_id));
+        let p256_depset = DependencySet::new_for_node(
+            p256,
+            &metadata,
+            dep_tree.get(&crate_id),
+            &HostDepTree::default(),
+        );
         assert_eq!(
             p256_depset
                 .normal_deps
@@ -933,8 +1101,12 @@ mod test {
 
         let tokio_node = find_metadata_node("tokio", &metadata);
         let dep_tree = build_dep_tree(&resolver_data);
-        let tokio_depset =
-            DependencySet::new_for_node(tokio_node, &metadata, dep_tree.get(&crate_id));
+        let tokio_depset = DependencySet::new_for_node(
+            tokio_node,
+            &metadata,
+            dep_tree.get(&crate_id),
+            &HostDepTree::default(),
+        );
         assert_eq!(
             tokio_depset
                 .normal_deps
@@ -10,7 +10,7 @@ use hex::ToHex;
 use serde::{Deserialize, Serialize};
 
 use crate::config::{Commitish, Config, CrateAnnotations, CrateId};
-use crate::metadata::dependency::{build_dep_tree, DependencySet};
+use crate::metadata::dependency::{build_dep_tree, DependencySet, HostDepTree};
 use crate::select::Select;
 use crate::splicing::{SourceInfo, WorkspaceMetadata};
 
@@ -69,6 +69,7 @@ impl MetadataAnnotation {
             .collect();
 
         let dep_tree = build_dep_tree(&workspace_metadata.tree_metadata);
+        let host_tree = HostDepTree::new(&workspace_metadata.host_tree_metadata);
 
         let crates = resolve
             .nodes
@@ -76,7 +77,7 @@ impl MetadataAnnotation {
             .map(|node| {
                 (
                     node.id.clone(),
-                    Self::annotate_crate(node, &metadata, &dep_tree),
+                    Self::annotate_crate(node, &metadata, &dep_tree, &host_tree),
                 )
             })
             .collect();
@@ -100,11 +101,12 @@ impl MetadataAnnotation {
         node: Node,
         metadata: &CargoMetadata,
         dep_tree: &BTreeMap<CrateId, Select<BTreeSet<CrateId>>>,
+        host_tree: &HostDepTree,
     ) -> CrateAnnotation {
         // Gather all dependencies
         let tree_data = dep_tree.get(&CrateId::from(&metadata[&node.id]));
 
-        let deps = DependencySet::new_for_node(&node, metadata, tree_data);
+        let deps = DependencySet::new_for_node(&node, metadata, tree_data, host_tree);
 
         CrateAnnotation { node, deps }
     }
@@ -0,0 +1,481 @@
+//! An in-process alternative to [TreeResolver](crate::metadata::TreeResolver) which
+//! resolves features from `cargo metadata` output.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use anyhow::Result;
+use cargo_metadata::{Dependency, DependencyKind, Metadata, Node, Package, PackageId};
+
+use crate::config::CrateId;
+use crate::metadata::cargo_tree_resolver::{CargoTreeEntry, CargoTreeOutput};
+use crate::utils::target_triple::CfgTarget;
+
+/// The features of a package activated for either the target or the host platform.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Activation {
+    /// Features enabled on the package.
+    features: BTreeSet<String>,
+
+    /// The names (or renames) of optional dependencies enabled by features.
+    deps: BTreeSet<String>,
+
+    /// Features enabled on dependencies through `dep/feature` values.
+    dep_features: BTreeMap<String, BTreeSet<String>>,
+
+    /// Features enabled on dependencies through `dep?/feature` values. These only
+    /// apply if the dependency is enabled by something else.
+    weak_dep_features: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Determines the features and dependencies enabled for a pair of host and target platforms
+/// from `cargo metadata` output following the rules of
+/// [feature resolver 2](https://doc.rust-lang.org/cargo/reference/resolver.html#feature-resolver-version-2).
+///
+/// This produces the same [CargoTreeOutput] as `cargo tree` would without spawning any processes
+/// or relying on a `rustc` wrapper. Note that platform specific dependencies are evaluated using
+/// `cfg-expr` rather than `rustc`, so `cfg` values only `rustc` knows of (e.g. `--cfg` flags from
+/// `RUSTFLAGS` or `target_feature`s of builtin targets) are never considered enabled.
+pub(crate) struct MetadataResolver<'a> {
+    /// All packages in the workspace's dependency graph.
+    packages: BTreeMap<&'a PackageId, &'a Package>,
+
+    /// The resolved dependencies of each package.
+    nodes: BTreeMap<&'a PackageId, &'a Node>,
+
+    /// The members of the workspace. These are the roots of resolution.
+    members: BTreeSet<&'a PackageId>,
+
+    /// The package at the root of the workspace, if it isn't virtual.
+    root: Option<&'a PackageId>,
+
+    /// Whether or not to treat all transitive proc-macros as dependencies of the root package.
+    proc_macro_roots: bool,
+}
+
+impl<'a> MetadataResolver<'a> {
+    pub(crate) fn new(metadata: &'a Metadata) -> Result<Self> {
+        let resolve = metadata
+            .resolve
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Cargo metadata is missing a dependency graph"))?;
+
+        Ok(Self {
+            packages: metadata.packages.iter().map(|p| (&p.id, p)).collect(),
+            nodes: resolve.nodes.iter().map(|n| (&n.id, n)).collect(),
+            members: metadata.workspace_members.iter().collect(),
+            root: metadata.root_package().map(|p| &p.id),
+            proc_macro_roots: false,
+        })
+    }
+
+    /// Resolve as though every transitive proc-macro from outside the workspace was a direct
+    /// dependency of the root package, matching the manifest `TreeResolver` runs `cargo tree`
+    /// against. Virtual workspaces get a `rules_rust_fake_proc_macro_root` package instead.
+    pub(crate) fn with_proc_macro_roots(mut self) -> Self {
+        self.proc_macro_roots = true;
+        self
+    }
+
+    /// Resolve the features of every package reachable from the workspace when building
+    /// for `target` on `host`.
+    pub(crate) fn resolve(&self, host: &CfgTarget, target: &CfgTarget) -> Result<CargoTreeOutput> {
+        let mut activations = BTreeMap::<(&PackageId, bool), Activation>::new();
+        let mut edges = BTreeMap::<(&PackageId, bool), BTreeSet<CrateId>>::new();
+        let mut platforms = BTreeMap::<(bool, String), bool>::new();
+
+        // Members are built with their default features, proc-macros always for the host.
+        let mut queue = VecDeque::new();
+        for member in self.members.iter() {
+            let package = self.package(member)?;
+            queue.push_back((
+                *member,
+                is_proc_macro(package),
+                BTreeSet::from(["default".to_owned()]),
+            ));
+        }
+        let proc_macros: BTreeSet<&PackageId> = match self.proc_macro_roots {
+            true => self
+                .packages
+                .values()
+                .filter(|package| package.source.is_some() && is_proc_macro(package))
+                .map(|package| &package.id)
+                .collect(),
+            false => BTreeSet::new(),
+        };
+        for proc_macro in proc_macros.iter() {
+            // These dependencies are added without default features.
+            queue.push_back((*proc_macro, true, BTreeSet::new()));
+        }
+
+        while let Some((id, for_host, features)) = queue.pop_front() {
+            let package = self.package(id)?;
+            let activation = match activations.get_mut(&(id, for_host)) {
+                Some(activation) => {
+                    let previous = activation.clone();
+                    for feature in features.iter() {
+                        activate_feature(package, activation, feature);
+                    }
+                    if *activation == previous {
+                        continue;
+                    }
+                    activation.clone()
+                }
+                None => {
+                    let mut activation = Activation::default();
+                    for feature in features.iter() {
+                        activate_feature(package, &mut activation, feature);
+                    }
+                    activations.insert((id, for_host), activation.clone());
+                    activation
+                }
+            };
+
+            // Anything newly activated may enable more dependencies or features on them.
+            for dep in package.dependencies.iter() {
+                let name = dep_name(dep);
+                if dep.kind == DependencyKind::Development && !self.members.contains(id) {
+                    continue;
+                }
+                if dep.optional && !activation.deps.contains(name) {
+                    continue;
+                }
+                if let Some(platform) = &dep.target {
+                    // Build dependencies and anything built for the host use the host platform.
+                    let is_host = for_host || dep.kind == DependencyKind::Build;
+                    let platform = platform.to_string();
+                    let enabled = match platforms.get(&(is_host, platform.clone())) {
+                        Some(enabled) => *enabled,
+                        None => {
+                            let enabled = if is_host { host } else { target }.eval(&platform)?;
+                            platforms.insert((is_host, platform), enabled);
+                            enabled
+                        }
+                    };
+                    if !enabled {
+                        continue;
+                    }
+                }
+                let Some(dep_id) = self.resolve_dependency(id, dep) else {
+                    continue;
+                };
+                let dep_package = self.package(dep_id)?;
+
+                let mut dep_features: BTreeSet<String> = dep.features.iter().cloned().collect();
+                if dep.uses_default_features {
+                    dep_features.insert("default".to_owned());
+                }
+                for enabled in [&activation.dep_features, &activation.weak_dep_features] {
+                    dep_features.extend(enabled.get(name).into_iter().flatten().cloned());
+                }
+
+                edges
+                    .entry((id, for_host))
+                    .or_default()
+                    .insert(crate_id(dep_package));
+                queue.push_back((
+                    dep_id,
+                    for_host || dep.kind == DependencyKind::Build || is_proc_macro(dep_package),
+                    dep_features,
+                ));
+            }
+        }
+
+        let mut output = CargoTreeOutput::default();
+        for ((id, for_host), activation) in activations {
+            let package = self.package(id)?;
+            let entry: &mut CargoTreeEntry = match for_host {
+                true => &mut output.host,
+                false => &mut output.target,
+            }
+            .entry(crate_id(package))
+            .or_default();
+            entry.features.extend(activation.features);
+            entry
+                .deps
+                .extend(edges.remove(&(id, for_host)).unwrap_or_default());
+        }
+
+        if !proc_macros.is_empty() {
+            let root = match self.root {
+                Some(root) => crate_id(self.package(root)?),
+                None => CrateId::new(
+                    "rules_rust_fake_proc_macro_root".to_owned(),
+                    semver::Version::new(0, 0, 0),
+                ),
+            };
+            for proc_macro in proc_macros {
+                let proc_macro = crate_id(self.package(proc_macro)?);
+                output
+                    .target
+                    .entry(root.clone())
+                    .or_default()
+                    .deps
+                    .insert(proc_macro);
+            }
+        }
+
+        Ok(output)
+    }
+
+    fn package(&self, id: &PackageId) -> Result<&'a Package> {
+        self.packages
+            .get(id)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Package {} is missing from cargo metadata", id))
+    }
+
+    /// Locate the package a dependency was resolved to.
+    fn resolve_dependency(&self, id: &PackageId, dep: &Dependency) -> Option<&'a PackageId> {
+        let node = self.nodes.get(id)?;
+        let candidates: Vec<&'a PackageId> = node
+            .deps
+            .iter()
+            .filter(|node_dep| {
+                node_dep
+                    .dep_kinds
+                    .iter()
+                    .any(|kind| kind.kind == dep.kind && kind.target == dep.target)
+            })
+            .map(|node_dep| &node_dep.pkg)
+            .filter(|pkg| {
+                self.packages
+                    .get(pkg)
+                    .is_some_and(|package| package.name == dep.name)
+            })
+            .collect();
+
+        // The same package may be depended on at multiple versions under different names.
+        candidates
+            .iter()
+            .find(|pkg| dep.req.matches(&self.packages[*pkg].version))
+            .or(candidates.first())
+            .copied()
+    }
+}
+
+/// Compare the outputs of two resolvers, describing each crate which differs.
+pub(crate) fn diff_outputs(
+    expected: &BTreeMap<CrateId, CargoTreeEntry>,
+    actual: &BTreeMap<CrateId, CargoTreeEntry>,
+) -> Vec<String> {
+    let empty = CargoTreeEntry::default();
+    let crates: BTreeSet<&CrateId> = expected.keys().chain(actual.keys()).collect();
+
+    let mut diffs = Vec::new();
+    for crate_id in crates {
+        let expected = expected.get(crate_id).unwrap_or(&empty);
+        let actual = actual.get(crate_id).unwrap_or(&empty);
+        if expected == actual {
+            continue;
+        }
+
+        let mut changes = Vec::new();
+        for feature in expected.features.difference(&actual.features) {
+            changes.push(format!("-feature {}", feature));
+        }
+        for feature in actual.features.difference(&expected.features) {
+            changes.push(format!("+feature {}", feature));
+        }
+        for dep in expected.deps.difference(&actual.deps) {
+            changes.push(format!("-dep {}", dep));
+        }
+        for dep in actual.deps.difference(&expected.deps) {
+            changes.push(format!("+dep {}", dep));
+        }
+        diffs.push(format!("{}: {}", crate_id, changes.join(", ")));
+    }
+    diffs
+}
+
+/// Enable a feature of a package along with everything it enables.
+fn activate_feature(package: &Package, activation: &mut Activation, feature: &str) {
+    let Some(values) = package.features.get(feature) else {
+        // Older versions of Cargo omit the implicit features of optional dependencies.
+        if has_implicit_feature(package, feature) && activation.features.insert(feature.to_owned())
+        {
+            activation.deps.insert(feature.to_owned());
+        }
+        return;
+    };
+
+    if !activation.features.insert(feature.to_owned()) {
+        return;
+    }
+
+    for value in values {
+        if let Some(dep) = value.strip_prefix("dep:") {
+            activation.deps.insert(dep.to_owned());
+        } else if let Some((dep, dep_feature)) = value.split_once('/') {
+            match dep.strip_suffix('?') {
+                Some(dep) => {
+                    activation
+                        .weak_dep_features
+                        .entry(dep.to_owned())
+                        .or_default()
+                        .insert(dep_feature.to_owned());
+                }
+                None => {
+                    if is_optional_dep(package, dep) {
+                        activation.deps.insert(dep.to_owned());
+                        // `dep/feature` also enables the implicit feature of the dependency.
+                        if has_implicit_feature(package, dep) {
+                            activate_feature(package, activation, dep);
+                        }
+                    }
+                    activation
+                        .dep_features
+                        .entry(dep.to_owned())
+                        .or_default()
+                        .insert(dep_feature.to_owned());
+                }
+            }
+        } else {
+            activate_feature(package, activation, value);
+        }
+    }
+}
+
+/// The name features use to refer to a dependency.
+fn dep_name(dep: &Dependency) -> &str {
+    dep.rename.as_deref().unwrap_or(&dep.name)
+}
+
+fn is_optional_dep(package: &Package, name: &str) -> bool {
+    package
+        .dependencies
+        .iter()
+        .any(|dep| dep.optional && dep_name(dep) == name)
+}
+
+/// Optional dependencies have an implicit feature of the same name unless
+/// some feature refers to them with the `dep:` syntax.
+fn has_implicit_feature(package: &Package, name: &str) -> bool {
+    let dep_value = format!("dep:{}", name);
+    is_optional_dep(package, name)
+        && !package.features.iter().any(|(feature, values)| {
+            // Newer versions of Cargo list implicit features as `name = ["dep:name"]`
+            let is_implicit = feature == name && values.len() == 1;
+            !is_implicit && values.contains(&dep_value)
+        })
+}
+
+fn is_proc_macro(package: &Package) -> bool {
+    package.targets.iter().any(|target| {
+        target
+            .kind
+            .iter()
+            .any(|kind| matches!(kind, cargo_metadata::TargetKind::ProcMacro))
+    })
+}
+
+fn crate_id(package: &Package) -> CrateId {
+    CrateId::new(package.name.clone(), package.version.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::utils::target_triple::TargetTriple;
+
+    fn cfg_target(triple: &str) -> CfgTarget {
+        CfgTarget::new(
+            &TargetTriple::from_bazel(triple.to_owned()),
+            &BTreeMap::new(),
+        )
+        .unwrap()
+    }
+
+    fn features<'a>(
+        tree_data: &'a BTreeMap<CrateId, CargoTreeEntry>,
+        name: &str,
+    ) -> &'a BTreeSet<String> {
+        &tree_data
+            .iter()
+            .find(|(crate_id, _)| crate_id.name == name)
+            .unwrap_or_else(|| panic!("{} was not resolved", name))
+            .1
+            .features
+    }
+
+    #[test]
+    fn resolver_2_deps() {
+        let metadata = crate::test::metadata::resolver_2_deps();
+        let resolver = MetadataResolver::new(&metadata).unwrap();
+        let host = cfg_target("x86_64-unknown-linux-gnu");
+
+        // `reqwest` only enables `tokio/net` (through `hyper-util`) outside of wasm.
+        let linux = resolver.resolve(&host, &host).unwrap();
+        assert!(features(&linux.target, "tokio").contains("net"));
+        assert!(linux.target.keys().any(|crate_id| crate_id.name == "mio"));
+
+        let wasm = resolver
+            .resolve(&host, &cfg_target("wasm32-unknown-unknown"))
+            .unwrap();
+        assert!(features(&wasm.target, "tokio").contains("io-util"));
+        assert!(!features(&wasm.target, "tokio").contains("net"));
+        assert!(!wasm.target.keys().any(|crate_id| crate_id.name == "mio"));
+    }
+
+    #[test]
+    fn target_cfg_features() {
+        let metadata = crate::test::metadata::target_cfg_features();
+        let resolver = MetadataResolver::new(&metadata).unwrap();
+        let host = cfg_target("x86_64-unknown-linux-gnu");
+
+        let linux = resolver.resolve(&host, &host).unwrap();
+        assert!(features(&linux.target, "tokio").contains("fs"));
+
+        let windows = resolver
+            .resolve(&host, &cfg_target("x86_64-pc-windows-msvc"))
+            .unwrap();
+        assert!(!features(&windows.target, "tokio").contains("fs"));
+    }
+
+    #[test]
+    fn host_specific_build_deps() {
+        let metadata = crate::test::metadata::host_specific_build_deps();
+        let resolver = MetadataResolver::new(&metadata).unwrap();
+
+        // Build dependencies are resolved for the host rather than the target.
+        let output = resolver
+            .resolve(
+                &cfg_target("x86_64-unknown-linux-gnu"),
+                &cfg_target("wasm32-unknown-unknown"),
+            )
+            .unwrap();
+        assert!(output
+            .host
+            .keys()
+            .any(|crate_id| crate_id.name == "prost-build"));
+        assert!(!output
+            .target
+            .keys()
+            .any(|crate_id| crate_id.name == "prost-build"));
+    }
+
+    #[test]
+    fn diff_outputs_describes_changes() {
+        let crate_id = CrateId::new("tokio".to_owned(), semver::Version::new(1, 0, 0));
+        let expected = BTreeMap::from([(
+            crate_id.clone(),
+            CargoTreeEntry {
+                features: BTreeSet::from(["fs".to_owned()]),
+                deps: BTreeSet::new(),
+            },
+        )]);
+        let actual = BTreeMap::from([(
+            crate_id,
+            CargoTreeEntry {
+                features: BTreeSet::from(["net".to_owned()]),
+                deps: BTreeSet::new(),
+            },
+        )]);
+
+        assert!(diff_outputs(&expected, &expected).is_empty());
+        assert_eq!(
+            diff_outputs(&expected, &actual),
+            vec!["tokio 1.0.0: -feature fs, +feature net".to_owned()]
+        );
+    }
+}
@@ -4,31 +4,122 @@ mod template_engine;
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::PathBuf;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::{bail, Context as AnyhowContext, Result};
 use itertools::Itertools;
+use serde::{Serialize, Serializer};
 
-use crate::config::{AliasRule, RenderConfig, VendorMode};
-use crate::context::crate_context::{CrateContext, CrateDependency, Rule};
+use crate::config::{AliasRule, CrateId, RenderConfig, VendorMode};
+use crate::context::crate_context::{
+    host_library_name, CrateContext, CrateDependency, FeatureToggle, HostAttributes, Rule,
+};
 use crate::context::{Context, TargetAttributes};
 use crate::metadata::SourceAnnotation;
 use crate::rendering::template_engine::TemplateEngine;
-use crate::select::Select;
+use crate::select::{
+    CompoundCondition, Select, SelectableOrderedValue, SelectableScalar, SelectableValue,
+};
 use crate::splicing::default_splicing_package_crate_id;
 use crate::utils::starlark::{
-    self, Alias, CargoBuildScript, CargoTomlEnvVars, CommonAttrs, Data, ExportsFiles, Filegroup,
-    Glob, Label, Load, Package, RustBinary, RustLibrary, RustProcMacro, SelectDict, SelectList,
-    SelectScalar, SelectSet, Starlark, TargetCompatibleWith,
+    self, Alias, BoolFlag, CargoBuildScript, CargoTomlEnvVars, CommonAttrs, ConfigSetting,
+    ConfigSettingGroup, Data, ExportsFiles, Filegroup, Glob, Label, Load, Package, Repository,
+    RustBinary, RustLibrary, RustNativeLibrary, RustProcMacro, RustTest, SelectCompression,
+    SelectDict, SelectList, SelectScalar, SelectSet, Starlark, TargetCompatibleWith,
 };
 use crate::utils::target_triple::TargetTriple;
 use crate::utils::{self, sanitize_repository_name};
 
 // Configuration remapper used to convert from cfg expressions like "cfg(unix)"
 // to platform labels like "@rules_rust//rust/platform:x86_64-unknown-linux-gnu".
-pub(crate) type Platforms = BTreeMap<String, BTreeSet<String>>;
+#[derive(Debug, Default)]
+pub(crate) struct Platforms {
+    conditions: BTreeMap<String, BTreeSet<String>>,
+    // The labels of compound conditions, mapped to the label of the platform
+    // each one refines.
+    compound: BTreeMap<String, String>,
+    // Set when `select`s are compressed. See [RenderConfig::compress_selects].
+    compression: Option<SelectCompression>,
+}
+
+impl Platforms {
+    /// Re-keys a [Select] of sets by platform, rendering elements of compound
+    /// conditions in `select`s of their own.
+    fn select_set<T>(&self, select: Select<BTreeSet<T>>) -> SelectSet<T>
+    where
+        T: SelectableOrderedValue,
+    {
+        let select_set =
+            SelectSet::new(select, &self.conditions).with_compound_conditions(&self.compound);
+        match &self.compression {
+            Some(compression) => select_set.compress(compression),
+            None => select_set,
+        }
+    }
+
+    fn select_list<T>(&self, select: Select<Vec<T>>) -> SelectList<T>
+    where
+        T: SelectableValue,
+    {
+        let select_list = SelectList::new(select, &self.conditions);
+        match &self.compression {
+            Some(compression) => select_list.compress(compression),
+            None => select_list,
+        }
+    }
+
+    fn select_dict<U, T>(&self, select: Select<BTreeMap<U, T>>) -> SelectDict<U, T>
+    where
+        U: SelectableOrderedValue,
+        T: SelectableValue,
+    {
+        let select_dict = SelectDict::new(select, &self.conditions);
+        match &self.compression {
+            Some(compression) => select_dict.compress(compression),
+            None => select_dict,
+        }
+    }
+
+    fn select_scalar<T>(&self, select: Select<T>) -> SelectScalar<T>
+    where
+        T: SelectableScalar,
+    {
+        let select_scalar = SelectScalar::new(select, &self.conditions);
+        match &self.compression {
+            Some(compression) => select_scalar.compress(compression),
+            None => select_scalar,
+        }
+    }
+
+    /// The `config_setting_group`s referenced by compressed `select`s, keyed
+    /// by target name and mapped to the platforms they match.
+    fn platform_groups(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.compression
+            .as_ref()
+            .map(SelectCompression::groups)
+            .unwrap_or_default()
+    }
+}
+
+impl Deref for Platforms {
+    type Target = BTreeMap<String, BTreeSet<String>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conditions
+    }
+}
+
+impl Serialize for Platforms {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.conditions.serialize(serializer)
+    }
+}
 
 /// A single hub alias — the `alias()` target and the `alias_rule` it uses.
 struct HubAlias {
@@ -86,20 +177,32 @@ impl Renderer {
         generator: Option<Label>,
     ) -> Result<RenderedHub> {
         let conditions = Arc::new(context.conditions.clone());
-        let engine = self.create_engine(Arc::clone(&conditions));
+        let compound_conditions = Arc::new(context.compound_conditions.clone());
+        let engine =
+            self.create_engine(Arc::clone(&conditions), Arc::clone(&compound_conditions))?;
 
         let aliases = self.collect_hub_aliases(context)?;
+        let feature_toggles = collect_feature_toggles(context);
 
         let mut files = BTreeMap::new();
-        let platforms = self.render_platform_labels(conditions);
+        let platforms = self.render_platform_labels(conditions, &compound_conditions)?;
         files.extend(self.render_build_files(&engine, context, &platforms)?);
-        files.extend(self.render_crates_module(&engine, context, &platforms, generator, &aliases)?);
+        files.extend(self.render_crates_module(
+            &engine,
+            context,
+            &platforms,
+            generator,
+            &aliases,
+            &feature_toggles,
+        )?);
 
         let hub_packages = aliases
             .workspace_member
             .iter()
             .chain(aliases.binaries.iter())
             .map(|entry| entry.alias.name.clone())
+            .chain(feature_toggles.keys().cloned())
+            .unique()
             .collect();
 
         Ok(RenderedHub {
@@ -111,19 +214,22 @@ impl Renderer {
     pub(crate) fn create_engine(
         &self,
         conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
-    ) -> TemplateEngine {
+        compound_conditions: Arc<BTreeMap<String, BTreeSet<CompoundCondition>>>,
+    ) -> Result<TemplateEngine> {
         TemplateEngine::new(
             Arc::clone(&self.config),
             Arc::clone(&self.supported_platform_triples),
             Arc::clone(&conditions),
+            compound_conditions,
         )
     }
 
     pub(crate) fn render_platform_labels(
         &self,
         conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
-    ) -> BTreeMap<String, BTreeSet<String>> {
-        conditions
+        compound_conditions: &BTreeMap<String, BTreeSet<CompoundCondition>>,
+    ) -> Result<Platforms> {
+        let mut platforms: BTreeMap<String, BTreeSet<String>> = conditions
             .iter()
             .map(|(cfg, target_triples)| {
                 (
@@ -139,7 +245,46 @@ impl Renderer {
                         .collect(),
                 )
             })
-            .collect()
+            .collect();
+
+        // Compound conditions are matched by a `config_setting_group` in the hub.
+        let mut compound = BTreeMap::new();
+        for (cfg, compounds) in compound_conditions {
+            for condition in compounds {
+                let label = self.render_hub_label(None, condition.name())?.to_string();
+                platforms
+                    .entry(cfg.clone())
+                    .or_default()
+                    .insert(label.clone());
+                compound.insert(
+                    label,
+                    render_platform_constraint_label(
+                        &self.config.platforms_template,
+                        &condition.triple,
+                    ),
+                );
+            }
+        }
+
+        let compression = if self.config.compress_selects {
+            let platforms = self
+                .supported_platform_triples
+                .iter()
+                .map(|triple| {
+                    render_platform_constraint_label(&self.config.platforms_template, triple)
+                })
+                .collect();
+            let package = self.render_hub_label(None, String::new())?;
+            Some(SelectCompression::new(platforms, package))
+        } else {
+            None
+        };
+
+        Ok(Platforms {
+            conditions: platforms,
+            compound,
+            compression,
+        })
     }
 
     fn render_crates_module(
@@ -149,6 +294,7 @@ impl Renderer {
         platforms: &Platforms,
         generator: Option<Label>,
         aliases: &HubAliases,
+        feature_toggles: &BTreeMap<String, BTreeSet<String>>,
     ) -> Result<BTreeMap<PathBuf, String>> {
         let path = |name: &str| -> Result<PathBuf> {
             let label = render_module_label(&self.config.crates_module_template, name)
@@ -164,7 +310,12 @@ impl Renderer {
             (path("defs.bzl")?, engine.render_defs_bzl_shim()?),
             (
                 path("BUILD.bazel")?,
-                self.render_module_build_file(engine, aliases)?,
+                self.render_module_build_file(
+                    engine,
+                    aliases,
+                    &context.compound_conditions,
+                    platforms,
+                )?,
             ),
             (
                 path("alias_rules.bzl")?,
@@ -181,9 +332,19 @@ impl Renderer {
         // `incompatible_no_root_alias_targets`. Skip in local-vendor mode,
         // where the per-crate paths `<output_pkg>/<name>-<version>/BUILD.bazel`
         // are already occupied by the vendored `rust_library` `BUILD.bazel`s.
-        if self.config.vendor_mode != Some(VendorMode::Local) {
-            map.extend(self.render_alias_subpackages(engine, aliases)?);
-        }
+        // Feature toggles are rendered regardless as their packages are named
+        // after the crate alone.
+        let subpackage_aliases: Vec<&HubAlias> =
+            if self.config.vendor_mode != Some(VendorMode::Local) {
+                aliases
+                    .workspace_member
+                    .iter()
+                    .chain(aliases.binaries.iter())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+        map.extend(self.render_hub_subpackages(engine, &subpackage_aliases, feature_toggles)?);
 
         Ok(map)
     }
@@ -192,36 +353,74 @@ impl Renderer {
     /// a single `alias()` (plus any `load()` its `alias_rule` requires) so
     /// that `@<repo>//<alias>` resolves to that alias as the package's
     /// default target.
-    fn render_alias_subpackages(
+    ///
+    /// Crates with feature toggles get a `bool_flag` and matching
+    /// `config_setting` per toggle in the `<crate>/BUILD.bazel` subpackage,
+    /// which is shared with the crate's alias when it has one.
+    fn render_hub_subpackages(
         &self,
         engine: &TemplateEngine,
-        aliases: &HubAliases,
+        aliases: &[&HubAlias],
+        feature_toggles: &BTreeMap<String, BTreeSet<String>>,
     ) -> Result<BTreeMap<PathBuf, String>> {
         let header = engine.render_header()?;
 
-        let mut map = BTreeMap::new();
-        for entry in aliases
-            .workspace_member
+        let aliases: BTreeMap<&str, &HubAlias> = aliases
             .iter()
-            .chain(aliases.binaries.iter())
-        {
-            let mut starlark = vec![Starlark::Verbatim(header.clone())];
+            .map(|entry| (entry.alias.name.as_str(), *entry))
+            .collect();
+        let names: BTreeSet<&str> = aliases
+            .keys()
+            .copied()
+            .chain(feature_toggles.keys().map(String::as_str))
+            .collect();
 
-            if let Some(bzl) = entry.alias_rule.bzl() {
+        let mut map = BTreeMap::new();
+        for name in names {
+            let mut starlark = vec![Starlark::Verbatim(header.clone())];
+            let alias = aliases.get(name);
+            let features = feature_toggles.get(name);
+
+            if let Some(entry) = alias {
+                if let Some(bzl) = entry.alias_rule.bzl() {
+                    starlark.push(Starlark::Load(Load {
+                        bzl,
+                        items: BTreeSet::from([entry.alias_rule.rule()]),
+                    }));
+                }
+            }
+            if features.is_some() {
                 starlark.push(Starlark::Load(Load {
-                    bzl,
-                    items: BTreeSet::from([entry.alias_rule.rule()]),
+                    bzl: "@bazel_skylib//rules:common_settings.bzl".to_owned(),
+                    items: BTreeSet::from(["bool_flag".to_owned()]),
                 }));
             }
 
             starlark.push(Starlark::Package(Package::default_visibility_public(
                 BTreeSet::new(),
             )));
-            starlark.push(Starlark::Alias(entry.alias.clone()));
+            if let Some(entry) = alias {
+                starlark.push(Starlark::Alias(entry.alias.clone()));
+            }
+            for feature in features.into_iter().flatten() {
+                starlark.push(Starlark::BoolFlag(BoolFlag {
+                    name: feature.clone(),
+                    build_setting_default: true,
+                }));
+                starlark.push(Starlark::ConfigSetting(ConfigSetting {
+                    name: format!("{feature}__enabled"),
+                    flag_values: BTreeMap::from([(
+                        Label::Relative {
+                            target: feature.clone(),
+                        },
+                        "true".to_owned(),
+                    )]),
+                }));
+            }
 
             let subpackage_label = render_module_label(
                 &self.config.crates_module_template,
-                &format!("{}/BUILD.bazel", entry.alias.name),
+                &format!("{name}/BUILD.bazel"),
             )
             .context("Failed to resolve subpackage BUILD file label")?;
             map.insert(
@@ -236,6 +435,8 @@ impl Renderer {
         &self,
         engine: &TemplateEngine,
         aliases: &HubAliases,
+        compound_conditions: &BTreeMap<String, BTreeSet<CompoundCondition>>,
+        platforms: &Platforms,
     ) -> Result<String> {
         let mut starlark = Vec::new();
 
@@ -264,6 +465,16 @@ impl Renderer {
             }
         }
 
+        let compounds: BTreeSet<&CompoundCondition> =
+            compound_conditions.values().flatten().collect();
+        let platform_groups = platforms.platform_groups();
+        if !compounds.is_empty() || !platform_groups.is_empty() {
+            starlark.push(Starlark::Load(Load {
+                bzl: "@bazel_skylib//lib:selects.bzl".to_owned(),
+                items: BTreeSet::from(["selects".to_owned()]),
+            }));
+        }
+
         // Package visibility, exported bzl files.
         let package = Package::default_visibility_public(BTreeSet::new());
         starlark.push(Starlark::Package(package));
@@ -292,6 +503,38 @@ impl Renderer {
         };
         starlark.push(Starlark::Filegroup(filegroup));
 
+        // Platforms paired with the `config_setting`s of `cfg` atoms the
+        // platform alone doesn't decide.
+        if !compounds.is_empty() {
+            let comment = "# Compound Conditions".to_owned();
+            starlark.push(Starlark::Verbatim(comment));
+            starlark.extend(compounds.into_iter().map(|compound| {
+                Starlark::ConfigSettingGroup(ConfigSettingGroup {
+                    name: compound.name(),
+                    match_all: std::iter::once(render_platform_constraint_label(
+                        &self.config.platforms_template,
+                        &compound.triple,
+                    ))
+                    .chain(compound.settings.iter().map(ToString::to_string))
+                    .collect(),
+                    match_any: BTreeSet::new(),
+                })
+            }));
+        }
+
+        // Platforms sharing a branch in compressed `select`s.
+        if !platform_groups.is_empty() {
+            let comment = "# Platform Groups".to_owned();
+            starlark.push(Starlark::Verbatim(comment));
+            starlark.extend(platform_groups.into_iter().map(|(name, platforms)| {
+                Starlark::ConfigSettingGroup(ConfigSettingGroup {
+                    name,
+                    match_any: platforms,
+                    match_all: BTreeSet::new(),
+                })
+            }));
+        }
+
         // `incompatible_no_root_alias_targets` drops the duplicate root-level
         // `alias()` rules. Subpackage `BUILD.bazel`s always exist, so consumers
         // can still use `@<repo>//<name>` — the flag just stops emitting the
@@ -476,31 +719,57 @@ impl Renderer {
         platforms: &Platforms,
     ) -> Result<BTreeMap<PathBuf, String>> {
         let default_splicing_package_id = default_splicing_package_crate_id();
-        context
+        let ids: Vec<&CrateId> = context
             .crates
             .keys()
             // Do not render the default splicing package
             .filter(|id| *id != &default_splicing_package_id)
             // Do not render local packages
             .filter(|id| !context.workspace_members.contains_key(id))
-            .map(|id| {
-                let label = match render_build_file_template(
-                    &self.config.build_file_template,
-                    &id.name,
-                    &id.version.to_string(),
-                ) {
-                    Ok(label) => label,
-                    Err(e) => bail!(e),
-                };
+            .collect();
 
-                let filename = match &context.crates[id].repository {
-                    Some(SourceAnnotation::Path { path }) => path.join("BUILD.bazel").into(),
-                    _ => Renderer::label_to_path(&label),
-                };
-                let content = self.render_one_build_file(engine, platforms, &context.crates[id])?;
-                Ok((filename, content))
-            })
-            .collect()
+        let render = |id: &CrateId| -> Result<(PathBuf, String)> {
+            let label = match render_build_file_template(
+                &self.config.build_file_template,
+                &id.name,
+                &id.version.to_string(),
+            ) {
+                Ok(label) => label,
+                Err(e) => bail!(e),
+            };
+
+            let filename = match &context.crates[id].repository {
+                Some(SourceAnnotation::Path { path }) => path.join("BUILD.bazel").into(),
+                _ => Renderer::label_to_path(&label),
+            };
+            let content = self.render_one_build_file(engine, platforms, &context.crates[id])?;
+            Ok((filename, content))
+        };
+
+        // Each crate renders independently, so split them evenly across threads.
+        let max_parallel = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        let chunk_size = ids.len().div_ceil(max_parallel).max(1);
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = ids
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(|| {
+                        chunk
+                            .iter()
+                            .map(|id| render(id))
+                            .collect::<Result<Vec<_>>>()
+                    })
+                })
+                .collect();
+
+            let mut files = BTreeMap::new();
+            for worker in workers {
+                files.extend(worker.join().expect("render thread panicked")?);
+            }
+            Ok(files)
+        })
     }
 
     pub(crate) fn render_one_build_file(
@@ -608,10 +877,13 @@ impl Renderer {
         }
 
         for rule in &krate.targets {
-            if let Some(override_target) = krate.override_targets.get(rule.override_target_key()) {
+            if let Some(override_target) = rule
+                .override_target_key()
+                .and_then(|key| krate.override_targets.get(key))
+            {
                 starlark.push(Starlark::Alias(Alias {
                     rule: AliasRule::default().rule(),
-                    name: rule.crate_name().to_owned(),
+                    name: rule.override_alias_name(),
                     actual: override_target.clone(),
                     tags: BTreeSet::from(["manual".to_owned()]),
                 }));
@@ -639,12 +911,41 @@ impl Renderer {
                         load("@rules_rust//rust:defs.bzl", "rust_library");
                         let rust_library = self.make_rust_library(platforms, &krate, target)?;
                         starlark.push(Starlark::RustLibrary(rust_library));
+                        if let Some(host_attrs) = &krate.host_attrs {
+                            let rust_library =
+                                self.make_rust_host_library(platforms, &krate, target, host_attrs)?;
+                            starlark.push(Starlark::RustLibrary(rust_library));
+                        }
                     }
                     Rule::Binary(target) => {
                         load("@rules_rust//rust:defs.bzl", "rust_binary");
                         let rust_binary = self.make_rust_binary(platforms, &krate, target)?;
                         starlark.push(Starlark::RustBinary(rust_binary));
                     }
+                    Rule::SharedLibrary(target) => {
+                        load("@rules_rust//rust:defs.bzl", "rust_shared_library");
+                        let rust_shared_library =
+                            self.make_rust_native_library(platforms, &krate, target, "cdylib")?;
+                        starlark.push(Starlark::RustSharedLibrary(rust_shared_library));
+                    }
+                    Rule::StaticLibrary(target) => {
+                        load("@rules_rust//rust:defs.bzl", "rust_static_library");
+                        let rust_static_library =
+                            self.make_rust_native_library(platforms, &krate, target, "staticlib")?;
+                        starlark.push(Starlark::RustStaticLibrary(rust_static_library));
+                    }
+                    Rule::Test(target) => {
+                        load("@rules_rust//rust:defs.bzl", "rust_test");
+                        let rust_test =
+                            self.make_rust_test(platforms, &krate, target, "test", true)?;
+                        starlark.push(Starlark::RustTest(rust_test));
+                    }
+                    Rule::UnitTest(target) => {
+                        load("@rules_rust//rust:defs.bzl", "rust_test");
+                        let rust_test =
+                            self.make_rust_test(platforms, &krate, target, "unit_test", false)?;
+                        starlark.push(Starlark::RustTest(rust_test));
+                    }
                 }
             }
         }
@@ -662,7 +963,7 @@ impl Renderer {
         starlark.splice(1..1, loads);
 
         let starlark = starlark::serialize(&starlark)?;
-        Ok(starlark)
+        engine.render_crate_build_file(&krate.name, &krate.version.to_string(), &starlark)
     }
 
     fn make_cargo_build_script(
@@ -684,18 +985,16 @@ impl Renderer {
             //
             // This is set to a short name to avoid long path name issues on windows.
             name: "_bs".to_string(),
-            aliases: SelectDict::new(self.make_aliases(krate, true, false), platforms),
-            build_script_env: SelectDict::new(
+            aliases: platforms.select_dict(self.make_aliases(krate, true, false)),
+            build_script_env: platforms.select_dict(
                 attrs
                     .map(|attrs| attrs.build_script_env.clone())
                     .unwrap_or_default(),
-                platforms,
             ),
-            build_script_env_files: SelectSet::new(
+            build_script_env_files: platforms.select_set(
                 attrs
                     .map(|attrs| attrs.build_script_env_files.clone())
                     .unwrap_or_default(),
-                platforms,
             ),
             use_default_shell_env: attrs.and_then(|a| a.use_default_shell_env),
             use_cc_toolchain: attrs.and_then(|a| a.use_cc_toolchain),
@@ -711,13 +1010,14 @@ impl Renderer {
                     .map(|attrs| attrs.compile_data.clone())
                     .unwrap_or_default(),
             ),
-            exec_properties: SelectDict::new(
+            exec_properties: platforms.select_dict(
                 attrs
                     .map(|attrs| attrs.exec_properties.clone())
                     .unwrap_or_default(),
-                platforms,
             ),
-            crate_features: SelectSet::new(krate.common_attrs.crate_features.clone(), platforms),
+            crate_features: platforms
+                .select_set(krate.common_attrs.crate_features.clone())
+                .with_toggles(self.make_toggles(krate, |toggle| toggle.crate_features.clone())?),
             crate_name: utils::sanitize_module_name(&target.crate_name),
             crate_root: target.crate_root.clone(),
             data: make_data(
@@ -728,16 +1028,15 @@ impl Renderer {
                 Default::default(),
                 attrs.map(|attrs| attrs.data.clone()).unwrap_or_default(),
             ),
-            deps: SelectSet::new(
+            deps: platforms.select_set(
                 self.make_deps(
                     attrs.map(|attrs| attrs.deps.clone()).unwrap_or_default(),
                     attrs
                         .map(|attrs| attrs.extra_deps.clone())
                         .unwrap_or_default(),
                 ),
-                platforms,
             ),
-            link_deps: SelectSet::new(
+            link_deps: platforms.select_set(
                 self.make_deps(
                     attrs
                         .map(|attrs| attrs.link_deps.clone())
@@ -746,7 +1045,6 @@ impl Renderer {
                         .map(|attrs| attrs.extra_link_deps.clone())
                         .unwrap_or_default(),
                 ),
-                platforms,
             ),
             // Match Cargo's default: registry/git crates are quiet unless the
             // build fails or the user passes `-vv`.
@@ -755,7 +1053,7 @@ impl Renderer {
             linker_script: krate.common_attrs.linker_script.clone(),
             links: attrs.and_then(|attrs| attrs.links.clone()),
             pkg_name: Some(krate.name.clone()),
-            proc_macro_deps: SelectSet::new(
+            proc_macro_deps: platforms.select_set(
                 self.make_deps(
                     attrs
                         .map(|attrs| attrs.proc_macro_deps.clone())
@@ -764,25 +1062,20 @@ impl Renderer {
                         .map(|attrs| attrs.extra_proc_macro_deps.clone())
                         .unwrap_or_default(),
                 ),
-                platforms,
-            ),
-            rundir: SelectScalar::new(
-                attrs.map(|attrs| attrs.rundir.clone()).unwrap_or_default(),
-                platforms,
             ),
-            rustc_env: SelectDict::new(
+            rundir: platforms
+                .select_scalar(attrs.map(|attrs| attrs.rundir.clone()).unwrap_or_default()),
+            rustc_env: platforms.select_dict(
                 attrs
                     .map(|attrs| attrs.rustc_env.clone())
                     .unwrap_or_default(),
-                platforms,
             ),
-            rustc_env_files: SelectSet::new(
+            rustc_env_files: platforms.select_set(
                 attrs
                     .map(|attrs| attrs.rustc_env_files.clone())
                     .unwrap_or_default(),
-                platforms,
             ),
-            rustc_flags: SelectList::new(
+            rustc_flags: platforms.select_list(
                 // In most cases, warnings in 3rd party crates are not
                 // interesting as they're out of the control of consumers. The
                 // flag here silences warnings. For more details see:
@@ -793,7 +1086,6 @@ impl Renderer {
                         .map(|attrs| attrs.rustc_flags.clone())
                         .unwrap_or_default(),
                 ),
-                platforms,
             ),
             srcs: target.srcs.clone(),
             tags: {
@@ -805,10 +1097,7 @@ impl Renderer {
                 tags.insert(format!("crate-name={}", krate.name));
                 tags
             },
-            tools: SelectSet::new(
-                attrs.map(|attrs| attrs.tools.clone()).unwrap_or_default(),
-                platforms,
-            ),
+            tools: platforms.select_set(attrs.map(|attrs| attrs.tools.clone()).unwrap_or_default()),
             toolchains: attrs.map_or_else(BTreeSet::new, |attrs| attrs.toolchains.clone()),
             version: krate.common_attrs.version.clone(),
             visibility: BTreeSet::from(["//visibility:private".to_owned()]),
@@ -823,21 +1112,19 @@ impl Renderer {
     ) -> Result<RustProcMacro> {
         Ok(RustProcMacro {
             name: target.crate_name.clone(),
-            deps: SelectSet::new(
-                self.make_deps(
+            deps: platforms
+                .select_set(self.make_deps(
                     krate.common_attrs.deps.clone(),
                     krate.common_attrs.extra_deps.clone(),
-                ),
-                platforms,
-            ),
-            proc_macro_deps: SelectSet::new(
-                self.make_deps(
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.deps)?),
+            proc_macro_deps: platforms
+                .select_set(self.make_deps(
                     krate.common_attrs.proc_macro_deps.clone(),
                     krate.common_attrs.extra_proc_macro_deps.clone(),
-                ),
-                platforms,
-            ),
-            aliases: SelectDict::new(self.make_aliases(krate, false, false), platforms),
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.proc_macro_deps)?),
+            aliases: platforms.select_dict(self.make_aliases(krate, false, false)),
             common: self.make_common_attrs(platforms, krate, target)?,
         })
     }
@@ -850,27 +1137,137 @@ impl Renderer {
     ) -> Result<RustLibrary> {
         Ok(RustLibrary {
             name: target.crate_name.clone(),
-            deps: SelectSet::new(
-                self.make_deps(
+            crate_name: None,
+            deps: platforms
+                .select_set(self.make_deps(
                     krate.common_attrs.deps.clone(),
                     krate.common_attrs.extra_deps.clone(),
-                ),
-                platforms,
-            ),
-            proc_macro_deps: SelectSet::new(
-                self.make_deps(
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.deps)?),
+            proc_macro_deps: platforms
+                .select_set(self.make_deps(
                     krate.common_attrs.proc_macro_deps.clone(),
                     krate.common_attrs.extra_proc_macro_deps.clone(),
-                ),
-                platforms,
-            ),
-            link_deps: SelectSet::new(krate.common_attrs.extra_link_deps.clone(), platforms),
-            aliases: SelectDict::new(self.make_aliases(krate, false, false), platforms),
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.proc_macro_deps)?),
+            link_deps: platforms.select_set(krate.common_attrs.extra_link_deps.clone()),
+            aliases: platforms.select_dict(self.make_aliases(krate, false, false)),
             common: self.make_common_attrs(platforms, krate, target)?,
             disable_pipelining: krate.disable_pipelining,
         })
     }
 
+    /// The host library of a crate is built for build scripts and proc-macros with the
+    /// crate's host features and dependencies. Feature toggles only apply to the target.
+    fn make_rust_host_library(
+        &self,
+        platforms: &Platforms,
+        krate: &CrateContext,
+        target: &TargetAttributes,
+        host_attrs: &HostAttributes,
+    ) -> Result<RustLibrary> {
+        let mut host_krate = krate.clone();
+        host_krate.common_attrs.crate_features = host_attrs.crate_features.clone();
+        host_krate.common_attrs.deps = host_attrs.deps.clone();
+        host_krate.feature_toggles.clear();
+
+        Ok(RustLibrary {
+            name: host_library_name(&target.crate_name),
+            crate_name: Some(target.crate_name.clone()),
+            ..self.make_rust_library(platforms, &host_krate, target)?
+        })
+    }
+
+    /// `rust_shared_library` and `rust_static_library` targets are built from the
+    /// same sources as the crate's library, so their names are suffixed with the
+    /// crate type while the crate name is kept.
+    fn make_rust_native_library(
+        &self,
+        platforms: &Platforms,
+        krate: &CrateContext,
+        target: &TargetAttributes,
+        crate_type: &str,
+    ) -> Result<RustNativeLibrary> {
+        Ok(RustNativeLibrary {
+            name: format!("{}__{crate_type}", target.crate_name),
+            crate_name: target.crate_name.clone(),
+            deps: platforms
+                .select_set(self.make_deps(
+                    krate.common_attrs.deps.clone(),
+                    krate.common_attrs.extra_deps.clone(),
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.deps)?),
+            proc_macro_deps: platforms
+                .select_set(self.make_deps(
+                    krate.common_attrs.proc_macro_deps.clone(),
+                    krate.common_attrs.extra_proc_macro_deps.clone(),
+                ))
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.proc_macro_deps)?),
+            link_deps: platforms.select_set(krate.common_attrs.extra_link_deps.clone()),
+            aliases: platforms.select_dict(self.make_aliases(krate, false, false)),
+            common: self.make_common_attrs(platforms, krate, target)?,
+        })
+    }
+
+    /// Tests use the crate's dev dependencies in addition to its regular ones.
+    /// Integration tests additionally depend on the crate's library, whereas
+    /// unit tests are compiled from the library's own sources.
+    fn make_rust_test(
+        &self,
+        platforms: &Platforms,
+        krate: &CrateContext,
+        target: &TargetAttributes,
+        suffix: &str,
+        depends_on_library: bool,
+    ) -> Result<RustTest> {
+        let lib_is_proc_macro = krate
+            .targets
+            .iter()
+            .any(|rule| matches!(rule, Rule::ProcMacro(_)));
+
+        let mut deps = self.make_deps(
+            Select::merge(
+                krate.common_attrs.deps.clone(),
+                krate.common_attrs.deps_dev.clone(),
+            ),
+            krate.common_attrs.extra_deps.clone(),
+        );
+        let mut proc_macro_deps = self.make_deps(
+            Select::merge(
+                krate.common_attrs.proc_macro_deps.clone(),
+                krate.common_attrs.proc_macro_deps_dev.clone(),
+            ),
+            krate.common_attrs.extra_proc_macro_deps.clone(),
+        );
+
+        if let Some(library_target_name) = krate
+            .library_target_name
+            .as_ref()
+            .filter(|_| depends_on_library)
+        {
+            let lib_label = Label::from_str(&format!(":{library_target_name}")).unwrap();
+            if lib_is_proc_macro {
+                proc_macro_deps.insert(lib_label, None);
+            } else {
+                deps.insert(lib_label, None);
+            }
+        }
+
+        Ok(RustTest {
+            name: format!("{}__{suffix}", target.crate_name),
+            crate_name: target.crate_name.clone(),
+            deps: platforms
+                .select_set(deps)
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.deps)?),
+            proc_macro_deps: platforms
+                .select_set(proc_macro_deps)
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.proc_macro_deps)?),
+            link_deps: platforms.select_set(krate.common_attrs.extra_link_deps.clone()),
+            aliases: platforms.select_dict(self.make_aliases(krate, false, true)),
+            common: self.make_common_attrs(platforms, krate, target)?,
+        })
+    }
+
     fn make_rust_binary(
         &self,
         platforms: &Platforms,
@@ -904,10 +1301,14 @@ impl Renderer {
 
         Ok(RustBinary {
             name: format!("{}__bin", target.crate_name),
-            deps: SelectSet::new(deps, platforms),
-            proc_macro_deps: SelectSet::new(proc_macro_deps, platforms),
-            link_deps: SelectSet::new(krate.common_attrs.extra_link_deps.clone(), platforms),
-            aliases: SelectDict::new(self.make_aliases(krate, false, false), platforms),
+            deps: platforms
+                .select_set(deps)
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.deps)?),
+            proc_macro_deps: platforms
+                .select_set(proc_macro_deps)
+                .with_toggles(self.make_toggled_deps(krate, |toggle| &toggle.proc_macro_deps)?),
+            link_deps: platforms.select_set(krate.common_attrs.extra_link_deps.clone()),
+            aliases: platforms.select_dict(self.make_aliases(krate, false, false)),
             common: self.make_common_attrs(platforms, krate, target)?,
         })
     }
@@ -925,7 +1326,9 @@ impl Renderer {
                 krate.common_attrs.compile_data_glob_excludes.clone(),
                 krate.common_attrs.compile_data.clone(),
             ),
-            crate_features: SelectSet::new(krate.common_attrs.crate_features.clone(), platforms),
+            crate_features: platforms
+                .select_set(krate.common_attrs.crate_features.clone())
+                .with_toggles(self.make_toggles(krate, |toggle| toggle.crate_features.clone())?),
             crate_root: target.crate_root.clone(),
             data: make_data(
                 platforms,
@@ -935,9 +1338,9 @@ impl Renderer {
             ),
             edition: krate.common_attrs.edition.clone(),
             linker_script: krate.common_attrs.linker_script.clone(),
-            rustc_env: SelectDict::new(krate.common_attrs.rustc_env.clone(), platforms),
-            rustc_env_files: SelectSet::new(krate.common_attrs.rustc_env_files.clone(), platforms),
-            rustc_flags: SelectList::new(
+            rustc_env: platforms.select_dict(krate.common_attrs.rustc_env.clone()),
+            rustc_env_files: platforms.select_set(krate.common_attrs.rustc_env_files.clone()),
+            rustc_flags: platforms.select_list(
                 // In most cases, warnings in 3rd party crates are not
                 // interesting as they're out of the control of consumers. The
                 // flag here silences warnings. For more details see:
@@ -946,7 +1349,6 @@ impl Renderer {
                     Select::from_value(Vec::from(["--cap-lints=allow".to_owned()])),
                     krate.common_attrs.rustc_flags.clone(),
                 ),
-                platforms,
             ),
             srcs: target.srcs.clone(),
             tags: {
@@ -958,19 +1360,30 @@ impl Renderer {
                 tags.insert(format!("crate-name={}", krate.name));
                 tags
             },
-            target_compatible_with: self.config.generate_target_compatible_with.then(|| {
-                TargetCompatibleWith::new(
-                    self.supported_platform_triples
+            target_compatible_with: match &target.compatible_configurations {
+                // Targets limited to some configurations are always constrained.
+                Some(configurations) => Some(TargetCompatibleWith::new(
+                    configurations
                         .iter()
-                        .map(|target_triple| {
-                            render_platform_constraint_label(
-                                &self.config.platforms_template,
-                                target_triple,
-                            )
-                        })
+                        .filter_map(|configuration| platforms.get(configuration))
+                        .flatten()
+                        .cloned()
                         .collect(),
-                )
-            }),
+                )),
+                None => self.config.generate_target_compatible_with.then(|| {
+                    TargetCompatibleWith::new(
+                        self.supported_platform_triples
+                            .iter()
+                            .map(|target_triple| {
+                                render_platform_constraint_label(
+                                    &self.config.platforms_template,
+                                    target_triple,
+                                )
+                            })
+                            .collect(),
+                    )
+                }),
+            },
             version: krate.common_attrs.version.clone(),
         })
     }
@@ -1010,6 +1423,24 @@ impl Renderer {
                 }
             }
         }
+
+        // Aliases of toggled dependencies are harmless when the toggle is off.
+        if !build {
+            for dependency in krate
+                .feature_toggles
+                .values()
+                .flat_map(|toggle| toggle.deps.iter().chain(toggle.proc_macro_deps.iter()))
+            {
+                if let Some(alias) = &dependency.alias {
+                    let label = self.crate_label(
+                        &dependency.id.name,
+                        &dependency.id.version.to_string(),
+                        &dependency.target,
+                    );
+                    aliases.insert((label, alias.clone()), None);
+                }
+            }
+        }
         aliases
     }
 
@@ -1018,19 +1449,91 @@ impl Renderer {
         deps: Select<BTreeSet<CrateDependency>>,
         extra_deps: Select<BTreeSet<Label>>,
     ) -> Select<BTreeSet<Label>> {
-        Select::merge(
-            deps.map(|dep| {
-                match (dep.local_path, self.config.vendor_mode) {
-                    // In local vendor mode, we use paths within the the repo.
-                    (Some(path), Some(VendorMode::Local)) => {
-                        Label::from_str(&format!("//{}:{}", path, dep.target)).unwrap()
-                    }
-                    // If we're not vendoring source, or don't have a path for the dep, construct the label we expect.
-                    _ => self.crate_label(&dep.id.name, &dep.id.version.to_string(), &dep.target),
-                }
-            }),
-            extra_deps,
-        )
+        Select::merge(deps.map(|dep| self.make_dep_label(dep)), extra_deps)
+    }
+
+    fn make_dep_label(&self, dep: CrateDependency) -> Label {
+        match (dep.local_path, self.config.vendor_mode) {
+            // In local vendor mode, we use paths within the the repo.
+            (Some(path), Some(VendorMode::Local)) => {
+                Label::from_str(&format!("//{}:{}", path, dep.target)).unwrap()
+            }
+            // If we're not vendoring source, or don't have a path for the dep, construct the label we expect.
+            _ => self.crate_label(&dep.id.name, &dep.id.version.to_string(), &dep.target),
+        }
+    }
+
+    /// Collect the values each of a crate's feature toggles gates, keyed by the
+    /// label of the toggle's `config_setting`.
+    fn make_toggles<T, F>(
+        &self,
+        krate: &CrateContext,
+        values: F,
+    ) -> Result<BTreeMap<String, BTreeSet<T>>>
+    where
+        F: Fn(&FeatureToggle) -> BTreeSet<T>,
+    {
+        krate
+            .feature_toggles
+            .iter()
+            .map(|(feature, toggle)| {
+                let label = self.render_feature_toggle_label(&krate.name, feature)?;
+                Ok((label.repr(), values(toggle)))
+            })
+            .collect()
+    }
+
+    fn make_toggled_deps<F>(
+        &self,
+        krate: &CrateContext,
+        deps: F,
+    ) -> Result<BTreeMap<String, BTreeSet<Label>>>
+    where
+        F: Fn(&FeatureToggle) -> &BTreeSet<CrateDependency>,
+    {
+        self.make_toggles(krate, |toggle| {
+            deps(toggle)
+                .iter()
+                .map(|dep| self.make_dep_label(dep.clone()))
+                .collect()
+        })
+    }
+
+    /// The `config_setting` matching when a crate's feature toggle is on.
+    fn render_feature_toggle_label(&self, crate_name: &str, feature: &str) -> Result<Label> {
+        self.render_hub_label(Some(crate_name), format!("{feature}__enabled"))
+    }
+
+    /// A target in the hub, optionally within one of its subpackages. Crate
+    /// BUILD files usually live outside of the hub, so the hub's repository is
+    /// spelled out unless sources are vendored next to it.
+    fn render_hub_label(&self, subpackage: Option<&str>, target: String) -> Result<Label> {
+        let Label::Absolute {
+            repository,
+            package,
+            ..
+        } = render_module_label(&self.config.crates_module_template, "BUILD.bazel")?
+        else {
+            bail!("The crates module template must be an absolute label");
+        };
+
+        let repository = match (repository, self.config.vendor_mode) {
+            (Repository::Local, None) => Repository::Explicit(self.config.repository_name.clone()),
+            (Repository::Local, Some(VendorMode::Remote)) => Repository::Explicit(String::new()),
+            (repository, _) => repository,
+        };
+
+        let package = match (package.is_empty(), subpackage) {
+            (_, None) => package,
+            (true, Some(subpackage)) => subpackage.to_owned(),
+            (false, Some(subpackage)) => format!("{package}/{subpackage}"),
+        };
+
+        Ok(Label::Absolute {
+            repository,
+            package,
+            target,
+        })
     }
 
     fn label_to_path(label: &Label) -> PathBuf {
@@ -1054,7 +1557,9 @@ impl Renderer {
     }
 }
 
-/// Write a set of [crate::context::crate_context::CrateContext] to disk.
+/// Write a set of [crate::context::crate_context::CrateContext] to disk. Files
+/// which already have the expected content are left untouched so their
+/// modification times are preserved.
 pub(crate) fn write_outputs(outputs: BTreeMap<PathBuf, String>, dry_run: bool) -> Result<()> {
     if dry_run {
         for (path, content) in outputs {
@@ -1069,6 +1574,10 @@ pub(crate) fn write_outputs(outputs: BTreeMap<PathBuf, String>, dry_run: bool) -
         }
     } else {
         for (path, content) in outputs {
+            if fs::read(&path).is_ok_and(|existing| existing == content.as_bytes()) {
+                continue;
+            }
+
             // Ensure the output directory exists
             fs::create_dir_all(
                 path.parent()
@@ -1082,6 +1591,73 @@ pub(crate) fn write_outputs(outputs: BTreeMap<PathBuf, String>, dry_run: bool) -
     Ok(())
 }
 
+/// Delete any files in `dir` which are not part of `outputs`, such as the BUILD
+/// files of crates which are no longer dependencies, along with any directories
+/// left empty. The sources of crates vendored by `cargo vendor` are left in place.
+pub(crate) fn remove_stale_outputs(
+    dir: &Path,
+    outputs: &BTreeMap<PathBuf, String>,
+    dry_run: bool,
+) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let mut vendored_crates = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.join(".cargo-checksum.json").exists() {
+            vendored_crates.push(path);
+        }
+    }
+
+    // Visit directories after their contents so emptied ones can be removed.
+    for entry in walkdir::WalkDir::new(dir).contents_first(true) {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        let path = entry.path();
+        if vendored_crates
+            .iter()
+            .any(|vendored| path.starts_with(vendored))
+        {
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            let is_empty = fs::read_dir(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?
+                .next()
+                .is_none();
+            if is_empty && path != dir && !dry_run {
+                fs::remove_dir(path)
+                    .with_context(|| format!("Failed to delete {}", path.display()))?;
+            }
+        } else if !outputs.contains_key(path) {
+            if dry_run {
+                println!("Would delete stale file {}", path.display());
+            } else {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to delete {}", path.display()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Collect the toggleable features of every crate, keyed by crate name. All
+/// versions of a crate share the same flags.
+fn collect_feature_toggles(context: &Context) -> BTreeMap<String, BTreeSet<String>> {
+    let mut feature_toggles: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for krate in context.crates.values() {
+        if !krate.feature_toggles.is_empty() {
+            feature_toggles
+                .entry(krate.name.clone())
+                .or_default()
+                .extend(krate.feature_toggles.keys().cloned());
+        }
+    }
+    feature_toggles
+}
+
 /// Render the Bazel label of a crate
 pub(crate) fn render_crate_bazel_label(
     template: &str,
@@ -1160,7 +1736,7 @@ fn make_data_with_exclude(
                 .chain(exclude)
                 .collect(),
         },
-        select: SelectSet::new(select, platforms),
+        select: platforms.select_set(select),
     }
 }
 
@@ -1177,7 +1753,7 @@ fn make_data(
 mod test {
     use super::*;
 
-    use camino::Utf8Path;
+    use camino::{Utf8Path, Utf8PathBuf};
     use indoc::indoc;
 
     use crate::config::{Config, CrateId};
@@ -1247,6 +1823,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1255,6 +1832,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1271,8 +1849,154 @@ mod test {
     }
 
     #[test]
-    fn test_disable_pipelining() {
-        let mut context = Context::default();
+    fn render_template_overrides() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("render_template_overrides");
+        fs::create_dir_all(tempdir.join("partials/crate")).unwrap();
+        fs::write(
+            tempdir.join("crate_build_file.j2"),
+            indoc! {r#"
+                {% include "partials/crate/banner.j2" %}
+                {{ content | replace(from='"@rules_rust//rust:defs.bzl"', to='"//tools:rust.bzl"') }}"#},
+        )
+        .unwrap();
+        fs::write(
+            tempdir.join("partials/crate/banner.j2"),
+            "# {{ name }} {{ version }} in {{ repository_name }}",
+        )
+        .unwrap();
+
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+
+        let config = RenderConfig {
+            template_overrides: Some(Utf8PathBuf::try_from(tempdir).unwrap()),
+            ..(*mock_render_config(None)).clone()
+        };
+        let renderer = Renderer::new(Arc::new(config), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        assert!(build_file_content.starts_with("# mock_crate 0.1.0 in test_rendering\n"));
+        assert!(build_file_content.contains(r#"load("//tools:rust.bzl", "rust_library")"#));
+        assert!(!build_file_content.contains("@rules_rust//rust:defs.bzl"));
+    }
+
+    #[test]
+    fn render_invalid_template_overrides() {
+        let render = |name: &str, content: &str| {
+            let (_tempdir, tempdir) =
+                crate::test::test_tempdir("render_invalid_template_overrides");
+            let path = tempdir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+
+            let config = RenderConfig {
+                template_overrides: Some(Utf8PathBuf::try_from(tempdir).unwrap()),
+                ..(*mock_render_config(None)).clone()
+            };
+            Renderer::new(Arc::new(config), mock_supported_platform_triples())
+                .render(&Context::default(), None)
+                .map(|_| ())
+        };
+
+        let unknown_template = render("BUILD.j2", "").unwrap_err();
+        assert!(format!("{:?}", unknown_template).contains("Unknown template `BUILD.j2`"));
+
+        let unknown_variable = render("crate_build_file.j2", "{{ crate.name }}").unwrap_err();
+        assert!(format!("{:?}", unknown_variable).contains("Invalid template overrides"));
+
+        render("partials/header.j2", "# {{ regen_command }}").unwrap();
+    }
+
+    #[test]
+    fn write_outputs_skips_unchanged_files() {
+        let (_tempdir, tempdir) = crate::test::test_tempdir("write_outputs_skips_unchanged_files");
+        let unchanged = tempdir.join("BUILD.unchanged-1.0.0.bazel");
+        let changed = tempdir.join("BUILD.changed-1.0.0.bazel");
+        fs::write(&unchanged, "unchanged").unwrap();
+        fs::write(&changed, "old").unwrap();
+
+        let epoch = std::time::SystemTime::UNIX_EPOCH;
+        for path in [&unchanged, &changed] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(epoch)
+                .unwrap();
+        }
+
+        write_outputs(
+            BTreeMap::from([
+                (unchanged.clone(), "unchanged".to_owned()),
+                (changed.clone(), "new".to_owned()),
+            ]),
+            false,
+        )
+        .unwrap();
+
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(&unchanged), epoch);
+        assert_ne!(modified(&changed), epoch);
+        assert_eq!(fs::read_to_string(&changed).unwrap(), "new");
+    }
+
+    #[test]
+    fn remove_stale_outputs_keeps_rendered_files() {
+        let (_tempdir, tempdir) =
+            crate::test::test_tempdir("remove_stale_outputs_keeps_rendered_files");
+        fs::create_dir_all(tempdir.join("removed")).unwrap();
+        fs::create_dir_all(tempdir.join("kept")).unwrap();
+        fs::write(tempdir.join("BUILD.bazel"), "").unwrap();
+        fs::write(tempdir.join("BUILD.removed-1.0.0.bazel"), "").unwrap();
+        fs::write(tempdir.join("removed/BUILD.bazel"), "").unwrap();
+        fs::write(tempdir.join("kept/BUILD.bazel"), "").unwrap();
+        fs::create_dir_all(tempdir.join("vendored-1.0.0/src")).unwrap();
+        fs::write(tempdir.join("vendored-1.0.0/.cargo-checksum.json"), "").unwrap();
+        fs::write(tempdir.join("vendored-1.0.0/src/lib.rs"), "").unwrap();
+
+        let outputs = BTreeMap::from([
+            (tempdir.join("BUILD.bazel"), String::new()),
+            (tempdir.join("kept/BUILD.bazel"), String::new()),
+        ]);
+        remove_stale_outputs(&tempdir, &outputs, false).unwrap();
+
+        assert!(tempdir.join("BUILD.bazel").exists());
+        assert!(tempdir.join("kept/BUILD.bazel").exists());
+        assert!(!tempdir.join("BUILD.removed-1.0.0.bazel").exists());
+        assert!(!tempdir.join("removed").exists());
+        assert!(tempdir.join("vendored-1.0.0/src/lib.rs").exists());
+    }
+
+    #[test]
+    fn test_disable_pipelining() {
+        let mut context = Context::default();
         let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
         context.crates.insert(
             crate_id.clone(),
@@ -1285,6 +2009,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1293,6 +2018,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1327,6 +2053,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: Some(BuildScriptAttributes::default()),
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1335,6 +2062,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1419,6 +2147,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: Some(attrs),
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1427,6 +2156,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1512,6 +2242,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1520,6 +2251,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1550,6 +2282,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1558,6 +2291,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1573,6 +2307,555 @@ mod test {
         assert!(build_file_content.contains("\"crate-name=mock_crate\""));
     }
 
+    #[test]
+    fn render_native_libraries() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([
+                    Rule::SharedLibrary(mock_target_attributes()),
+                    Rule::StaticLibrary(mock_target_attributes()),
+                ]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        assert!(build_file_content.contains("\"rust_shared_library\","));
+        assert!(build_file_content.contains("\"rust_static_library\","));
+        assert!(build_file_content.contains("rust_shared_library(\n    name = \"mock_crate__cdylib\",\n    crate_name = \"mock_crate\","));
+        assert!(build_file_content.contains("rust_static_library(\n    name = \"mock_crate__staticlib\",\n    crate_name = \"mock_crate\","));
+    }
+
+    #[test]
+    fn render_overridden_native_library() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([
+                    Rule::Library(mock_target_attributes()),
+                    Rule::SharedLibrary(mock_target_attributes()),
+                    Rule::Test(TargetAttributes {
+                        crate_name: "integration".to_owned(),
+                        ..mock_target_attributes()
+                    }),
+                ]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::from([
+                    (
+                        "cdylib".to_owned(),
+                        Label::from_str("//native:mock_cdylib").unwrap(),
+                    ),
+                    (
+                        "test".to_owned(),
+                        Label::from_str("//tests:mock_test").unwrap(),
+                    ),
+                ]),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        assert!(build_file_content.contains("rust_library(\n    name = \"mock_crate\","));
+        assert!(build_file_content.contains(
+            "alias(\n    name = \"mock_crate__cdylib\",\n    actual = \"//native:mock_cdylib\","
+        ));
+        assert!(build_file_content.contains(
+            "alias(\n    name = \"integration__test\",\n    actual = \"//tests:mock_test\","
+        ));
+        assert!(!build_file_content.contains("rust_shared_library("));
+    }
+
+    #[test]
+    fn render_tests() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let dev_dep = CrateId::new("mock_dev_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let mut deps_dev = Select::new();
+        deps_dev.insert(
+            CrateDependency {
+                id: dev_dep.clone(),
+                target: dev_dep.name.clone(),
+                alias: None,
+                local_path: None,
+            },
+            None,
+        );
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([
+                    Rule::Library(mock_target_attributes()),
+                    Rule::UnitTest(mock_target_attributes()),
+                    Rule::Test(TargetAttributes {
+                        crate_name: "integration".to_owned(),
+                        crate_root: Some("tests/integration.rs".to_owned()),
+                        ..TargetAttributes::default()
+                    }),
+                ]),
+                library_target_name: Some("mock_crate".to_owned()),
+                common_attrs: CommonAttributes {
+                    deps_dev,
+                    ..CommonAttributes::default()
+                },
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        let expected_unit_test = indoc! {r#"
+            rust_test(
+                name = "mock_crate__unit_test",
+                crate_name = "mock_crate",
+                deps = [
+                    "@test_rendering__mock_dev_crate-0.1.0//:mock_dev_crate",
+                ],
+        "#};
+        let expected_integration_test = indoc! {r#"
+            rust_test(
+                name = "integration__test",
+                crate_name = "integration",
+                deps = [
+                    ":mock_crate",
+                    "@test_rendering__mock_dev_crate-0.1.0//:mock_dev_crate",
+                ],
+        "#};
+        assert!(
+            build_file_content.contains(expected_unit_test),
+            "{build_file_content}"
+        );
+        assert!(
+            build_file_content.contains(expected_integration_test),
+            "{build_file_content}"
+        );
+    }
+
+    #[test]
+    fn render_compressed_selects() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let unix_dep = CrateId::new("mock_unix_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let configuration = "cfg(unix)".to_owned();
+        let mut deps = Select::new();
+        deps.insert(
+            CrateDependency {
+                id: unix_dep.clone(),
+                target: unix_dep.name.clone(),
+                alias: None,
+                local_path: None,
+            },
+            Some(configuration.clone()),
+        );
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: Some("mock_crate".to_owned()),
+                common_attrs: CommonAttributes {
+                    deps,
+                    ..CommonAttributes::default()
+                },
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+        let linux = BTreeSet::from([
+            TargetTriple::from_bazel("aarch64-unknown-linux-gnu".to_owned()),
+            TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned()),
+        ]);
+        context.conditions.insert(configuration, linux.clone());
+
+        let mut supported_platform_triples = linux;
+        supported_platform_triples.insert(TargetTriple::from_bazel(
+            "x86_64-pc-windows-msvc".to_owned(),
+        ));
+        let mut config = RenderConfig::clone(&mock_render_config(None));
+        config.compress_selects = true;
+        let renderer = Renderer::new(Arc::new(config), Arc::new(supported_platform_triples));
+        let output = renderer.render(&context, None).unwrap();
+
+        let module_build_content = output.get(&PathBuf::from("BUILD.bazel")).unwrap();
+        let name = module_build_content
+            .split('"')
+            .find(|token| token.starts_with("platforms_"))
+            .unwrap();
+        let expected_group = indoc! {r#"
+            selects.config_setting_group(
+                name = "platforms_GROUP",
+                match_any = [
+                    "@rules_rust//rust/platform:aarch64-unknown-linux-gnu",
+                    "@rules_rust//rust/platform:x86_64-unknown-linux-gnu",
+                ],
+            )
+        "#}
+        .replace("platforms_GROUP", name);
+        assert!(
+            module_build_content.contains(&expected_group),
+            "{module_build_content}"
+        );
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+        let expected_deps = indoc! {r#"
+                deps = select({
+                    "@test_rendering//:platforms_GROUP": [
+                        "@test_rendering__mock_unix_crate-0.1.0//:mock_unix_crate",  # cfg(unix)
+                    ],
+                    "//conditions:default": [],
+                }),
+        "#}
+        .replace("platforms_GROUP", name);
+        assert!(
+            build_file_content
+                .replace(' ', "")
+                .contains(&expected_deps.replace(' ', "")),
+            "{build_file_content}"
+        );
+    }
+
+    #[test]
+    fn render_compound_conditions() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let simd_dep = CrateId::new("mock_simd_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let configuration = r#"cfg(target_feature = "avx2")"#.to_owned();
+        let mut deps = Select::new();
+        deps.insert(
+            CrateDependency {
+                id: simd_dep.clone(),
+                target: simd_dep.name.clone(),
+                alias: None,
+                local_path: None,
+            },
+            Some(configuration.clone()),
+        );
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: Some("mock_crate".to_owned()),
+                common_attrs: CommonAttributes {
+                    deps,
+                    ..CommonAttributes::default()
+                },
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+        context
+            .conditions
+            .insert(configuration.clone(), BTreeSet::new());
+        context.compound_conditions.insert(
+            configuration,
+            BTreeSet::from([CompoundCondition {
+                triple: TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned()),
+                settings: BTreeSet::from([Label::from_str("//:avx2").unwrap()]),
+            }]),
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+        let expected_deps = indoc! {r#"
+                deps = select({
+                    "@test_rendering//:x86_64-unknown-linux-gnu__avx2": [
+                        "@test_rendering__mock_simd_crate-0.1.0//:mock_simd_crate",  # cfg(target_feature = "avx2")
+                    ],
+                    "//conditions:default": [],
+                }),
+        "#};
+        assert!(
+            build_file_content
+                .replace(' ', "")
+                .contains(&expected_deps.replace(' ', "")),
+            "{build_file_content}"
+        );
+
+        let module_build_content = output.get(&PathBuf::from("BUILD.bazel")).unwrap();
+        let expected_group = indoc! {r#"
+            selects.config_setting_group(
+                name = "x86_64-unknown-linux-gnu__avx2",
+                match_all = [
+                    "//:avx2",
+                    "@rules_rust//rust/platform:x86_64-unknown-linux-gnu",
+                ],
+            )
+        "#};
+        assert!(
+            module_build_content.contains(expected_group),
+            "{module_build_content}"
+        );
+        assert!(
+            module_build_content.contains(r#"load("@bazel_skylib//lib:selects.bzl", "selects")"#)
+        );
+    }
+
+    #[test]
+    fn render_feature_toggles() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let optional_dep = CrateId::new("mock_optional_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let mut crate_features = Select::new();
+        crate_features.insert("std".to_owned(), None);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: Some("mock_crate".to_owned()),
+                common_attrs: CommonAttributes {
+                    crate_features,
+                    ..CommonAttributes::default()
+                },
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::from([(
+                    "extra".to_owned(),
+                    FeatureToggle {
+                        crate_features: BTreeSet::from(["extra".to_owned()]),
+                        deps: BTreeSet::from([CrateDependency {
+                            id: optional_dep.clone(),
+                            target: optional_dep.name.clone(),
+                            alias: None,
+                            local_path: None,
+                        }]),
+                        proc_macro_deps: BTreeSet::new(),
+                    },
+                )]),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render_hub(&context, None).unwrap();
+
+        let build_file_content = output
+            .files
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+        let expected_library = indoc! {r#"
+            rust_library(
+                name = "mock_crate",
+                deps = select({
+                    "@test_rendering//mock_crate:extra__enabled": [
+                        "@test_rendering__mock_optional_crate-0.1.0//:mock_optional_crate",
+                    ],
+                    "//conditions:default": [],
+                }),
+                compile_data = glob(
+        "#};
+        let expected_features = indoc! {r#"
+                crate_features = [
+                    "std",
+                ] + select({
+                    "@test_rendering//mock_crate:extra__enabled": [
+                        "extra",
+                    ],
+                    "//conditions:default": [],
+                }),
+        "#};
+        assert!(
+            build_file_content.contains(expected_library),
+            "{build_file_content}"
+        );
+        assert!(build_file_content
+            .replace(' ', "")
+            .contains(&expected_features.replace(' ', "")));
+
+        assert_eq!(output.hub_packages, vec!["mock_crate".to_owned()]);
+        let hub_package_content = output
+            .files
+            .get(&PathBuf::from("mock_crate/BUILD.bazel"))
+            .unwrap();
+        let expected_hub_package = indoc! {r#"
+            load("@bazel_skylib//rules:common_settings.bzl", "bool_flag")
+
+            package(default_visibility = ["//visibility:public"])
+
+            bool_flag(
+                name = "extra",
+                build_setting_default = True,
+            )
+
+            config_setting(
+                name = "extra__enabled",
+                flag_values = {
+                    ":extra": "true",
+                },
+            )
+        "#};
+        assert!(
+            hub_package_content.ends_with(expected_hub_package),
+            "{hub_package_content}"
+        );
+    }
+
+    #[test]
+    fn render_binary_with_required_features() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Binary(TargetAttributes {
+                    compatible_configurations: Some(BTreeSet::from(["cfg(unix)".to_owned()])),
+                    ..mock_target_attributes()
+                })]),
+                library_target_name: None,
+                common_attrs: CommonAttributes::default(),
+                build_script_attrs: None,
+                host_attrs: None,
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+        context.conditions.insert(
+            "cfg(unix)".to_owned(),
+            BTreeSet::from([
+                TargetTriple::from_bazel("aarch64-apple-darwin".to_owned()),
+                TargetTriple::from_bazel("x86_64-unknown-linux-gnu".to_owned()),
+            ]),
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap();
+
+        let expected = indoc! {r#"
+            target_compatible_with = select({
+                "@rules_rust//rust/platform:aarch64-apple-darwin": [],
+                "@rules_rust//rust/platform:x86_64-unknown-linux-gnu": [],
+                "//conditions:default": ["@platforms//:incompatible"],
+            }),
+        "#};
+        assert!(
+            build_file_content
+                .replace(' ', "")
+                .contains(&expected.replace(' ', "")),
+            "{build_file_content}"
+        );
+    }
+
     #[test]
     fn render_additive_build_contents() {
         let mut context = Context::default();
@@ -1588,6 +2871,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1598,6 +2882,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1651,6 +2936,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1659,6 +2945,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1695,6 +2982,7 @@ mod test {
                     ..CommonAttributes::default()
                 },
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1703,6 +2991,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1729,6 +3018,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1737,6 +3027,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1771,6 +3062,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1779,6 +3071,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1823,6 +3116,7 @@ mod test {
                     ..CommonAttributes::default()
                 },
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1831,6 +3125,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1945,6 +3240,7 @@ mod test {
                     ..CommonAttributes::default()
                 },
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -1953,6 +3249,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1977,6 +3274,87 @@ mod test {
             .contains(&expected.replace(' ', "")));
     }
 
+    #[test]
+    fn host_library() {
+        let mut context = Context::default();
+        let crate_id = CrateId::new("mock_crate".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let dep_id = CrateId::new("mock_dep".to_owned(), VERSION_ZERO_ONE_ZERO);
+        let dependency = |target: &str| CrateDependency {
+            id: dep_id.clone(),
+            target: target.to_owned(),
+            alias: None,
+            local_path: None,
+        };
+        context.crates.insert(
+            crate_id.clone(),
+            CrateContext {
+                name: crate_id.name,
+                version: crate_id.version,
+                package_url: None,
+                repository: None,
+                targets: BTreeSet::from([Rule::Library(mock_target_attributes())]),
+                library_target_name: None,
+                common_attrs: CommonAttributes {
+                    crate_features: Select::from_value(BTreeSet::from(["alloc".to_owned()])),
+                    deps: Select::from_value(BTreeSet::from([dependency("mock_dep")])),
+                    ..CommonAttributes::default()
+                },
+                build_script_attrs: None,
+                host_attrs: Some(HostAttributes {
+                    crate_features: Select::from_value(BTreeSet::from([
+                        "alloc".to_owned(),
+                        "std".to_owned(),
+                    ])),
+                    deps: Select::from_value(BTreeSet::from([dependency("mock_dep__host")])),
+                }),
+                license: None,
+                license_ids: BTreeSet::default(),
+                license_file: None,
+                additive_build_file_content: None,
+                disable_pipelining: false,
+                extra_aliased_targets: BTreeMap::default(),
+                alias_rule: None,
+                override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
+            },
+        );
+
+        let renderer = Renderer::new(mock_render_config(None), mock_supported_platform_triples());
+        let output = renderer.render(&context, None).unwrap();
+
+        let build_file_content = output
+            .get(&PathBuf::from("BUILD.mock_crate-0.1.0.bazel"))
+            .unwrap()
+            .replace(' ', "");
+
+        let expected_target = indoc! {r#"
+            rust_library(
+                name = "mock_crate",
+                deps = [
+                    "@test_rendering__mock_dep-0.1.0//:mock_dep",
+                ],
+        "#};
+        assert!(build_file_content.contains(&expected_target.replace(' ', "")));
+
+        let expected_host = indoc! {r#"
+            rust_library(
+                name = "mock_crate__host",
+                crate_name = "mock_crate",
+                deps = [
+                    "@test_rendering__mock_dep-0.1.0//:mock_dep__host",
+                ],
+        "#};
+        assert!(build_file_content.contains(&expected_host.replace(' ', "")));
+
+        let expected_host_features = indoc! {r#"
+            crate_features = [
+                "alloc",
+                "std",
+            ],
+        "#};
+        assert!(build_file_content.contains(&expected_host_features.replace(' ', "")));
+    }
+
     #[test]
     fn crate_package_metadata_without_license_ids() {
         let mut context = Context::default();
@@ -1992,6 +3370,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -2000,6 +3379,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2051,10 +3431,12 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2117,10 +3499,12 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2186,6 +3570,7 @@ mod test {
                 library_target_name: None,
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 license: None,
                 license_ids: BTreeSet::default(),
                 license_file: None,
@@ -2194,6 +3579,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2213,10 +3599,7 @@ mod test {
         // create tempdir to write to
         let outdir = tempfile::tempdir().unwrap();
 
-        // create dir to mimic cargo vendor
-        let _ = std::fs::create_dir_all(outdir.path().join("libbpf-sys-1.4.0+v1.4.0"));
-
-        let normalized_outputs = normalize_cargo_file_paths(output, outdir.path());
+        let normalized_outputs = normalize_cargo_file_paths(output, outdir.path(), true);
         eprintln!(
             "Normalized outputs are {:?}",
             normalized_outputs.clone().into_keys()
@@ -2270,10 +3653,12 @@ mod test {
                     ..Default::default()
                 },
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2365,10 +3750,12 @@ mod test {
                 library_target_name: Some("my_dependency".into()),
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2404,10 +3791,12 @@ mod test {
                     ..Default::default()
                 },
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -2511,6 +3900,7 @@ mod test {
                 library_target_name: Some("my_proc_macro".to_owned()),
                 common_attrs: CommonAttributes::default(),
                 build_script_attrs: None,
+                host_attrs: None,
                 repository: None,
                 license: None,
                 license_ids: BTreeSet::default(),
@@ -2520,6 +3910,7 @@ mod test {
                 extra_aliased_targets: BTreeMap::default(),
                 alias_rule: None,
                 override_targets: BTreeMap::default(),
+                feature_toggles: BTreeMap::default(),
             },
         );
 
@@ -1,9 +1,12 @@
 //! A template engine backed by [tera::Tera] for rendering Files.
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{Context as AnyhowContext, Result};
+use anyhow::{bail, Context as AnyhowContext, Result};
+use itertools::Itertools;
 use serde_json::{from_value, to_value, Value};
 
 use crate::config::RenderConfig;
@@ -12,11 +15,75 @@ use crate::rendering::{
     render_crate_bazel_label, render_crate_bazel_repository, render_crate_build_file,
     render_module_label, CrateContext, Platforms,
 };
-use crate::select::Select;
+use crate::select::{CompoundCondition, Select};
 use crate::utils::sanitize_repository_name;
 use crate::utils::starlark::Label;
 use crate::utils::target_triple::TargetTriple;
 
+/// The templates built into `cargo-bazel`, any of which may be replaced by
+/// [RenderConfig::template_overrides].
+const BUILTIN_TEMPLATES: [(&str, &str); 8] = [
+    (
+        "partials/module/aliases_map.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/partials/module/aliases_map.j2"
+        )),
+    ),
+    (
+        "partials/module/deps_map.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/partials/module/deps_map.j2"
+        )),
+    ),
+    (
+        "partials/module/repo_git.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/partials/module/repo_git.j2"
+        )),
+    ),
+    (
+        "partials/module/repo_http.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/partials/module/repo_http.j2"
+        )),
+    ),
+    (
+        "partials/header.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/partials/header.j2"
+        )),
+    ),
+    (
+        "crate_build_file.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/crate_build_file.j2"
+        )),
+    ),
+    (
+        "module_bzl.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/module_bzl.j2"
+        )),
+    ),
+    (
+        "defs_bzl_shim.j2",
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/rendering/templates/defs_bzl_shim.j2"
+        )),
+    ),
+];
+
+/// The prefix of templates which may be added by [RenderConfig::template_overrides].
+const PARTIALS_PREFIX: &str = "partials/";
+
 pub(crate) struct TemplateEngine {
     engine: tera::Tera,
     context: tera::Context,
@@ -27,60 +94,21 @@ impl TemplateEngine {
         render_config: Arc<RenderConfig>,
         supported_platform_triples: Arc<BTreeSet<TargetTriple>>,
         platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
-    ) -> Self {
+        compound_conditions: Arc<BTreeMap<String, BTreeSet<CompoundCondition>>>,
+    ) -> Result<Self> {
+        let mut templates: BTreeMap<String, String> = BUILTIN_TEMPLATES
+            .iter()
+            .map(|(name, content)| (name.to_string(), content.to_string()))
+            .collect();
+        let overrides = match &render_config.template_overrides {
+            Some(dir) => load_template_overrides(dir.as_std_path())?,
+            None => BTreeMap::new(),
+        };
+        templates.extend(overrides.clone());
+
         let mut tera = tera::Tera::default();
-        tera.add_raw_templates(vec![
-            (
-                "partials/module/aliases_map.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/partials/module/aliases_map.j2"
-                )),
-            ),
-            (
-                "partials/module/deps_map.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/partials/module/deps_map.j2"
-                )),
-            ),
-            (
-                "partials/module/repo_git.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/partials/module/repo_git.j2"
-                )),
-            ),
-            (
-                "partials/module/repo_http.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/partials/module/repo_http.j2"
-                )),
-            ),
-            (
-                "partials/header.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/partials/header.j2"
-                )),
-            ),
-            (
-                "module_bzl.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/module_bzl.j2"
-                )),
-            ),
-            (
-                "defs_bzl_shim.j2",
-                include_str!(concat!(
-                    env!("CARGO_MANIFEST_DIR"),
-                    "/src/rendering/templates/defs_bzl_shim.j2"
-                )),
-            ),
-        ])
-        .unwrap();
+        tera.add_raw_templates(templates)
+            .context("Failed to parse templates")?;
 
         tera.register_function(
             "crate_build_file",
@@ -117,6 +145,7 @@ impl TemplateEngine {
                 Arc::clone(&render_config),
                 supported_platform_triples,
                 platform_conditions,
+                compound_conditions,
             ),
         );
 
@@ -134,10 +163,31 @@ impl TemplateEngine {
             },
         );
 
-        Self {
+        let engine = Self {
             engine: tera,
             context,
+        };
+        if !overrides.is_empty() {
+            engine.validate().with_context(|| {
+                format!(
+                    "Invalid template overrides in {}",
+                    render_config.template_overrides.as_ref().unwrap()
+                )
+            })?;
         }
+
+        Ok(engine)
+    }
+
+    /// Render every top-level template with an empty lockfile so syntax errors in
+    /// overridden templates surface before any files are written. Parts of a
+    /// template which are only rendered for a crate are not checked here.
+    fn validate(&self) -> Result<()> {
+        self.render_header()?;
+        self.render_defs_bzl_shim()?;
+        self.render_crates_bzl(&Context::default(), &Platforms::default(), None)?;
+        self.render_crate_build_file("placeholder", "0.0.0", "")?;
+        Ok(())
     }
 
     fn new_tera_ctx(&self) -> tera::Context {
@@ -172,6 +222,23 @@ impl TemplateEngine {
             .context("Failed to render crates module")
     }
 
+    /// Render the final contents of a crate's BUILD file from the generated Starlark.
+    pub(crate) fn render_crate_build_file(
+        &self,
+        name: &str,
+        version: &str,
+        content: &str,
+    ) -> Result<String> {
+        let mut context = self.new_tera_ctx();
+        context.insert("name", name);
+        context.insert("version", version);
+        context.insert("content", content);
+
+        self.engine
+            .render("crate_build_file.j2", &context)
+            .context("Failed to render crate BUILD file")
+    }
+
     /// Render the deprecated `defs.bzl` re-export shim. Kept so consumers
     /// that still `load("@<repo>//:defs.bzl", ...)` keep working while
     /// `crates.bzl` is the source of truth.
@@ -182,6 +249,43 @@ impl TemplateEngine {
     }
 }
 
+/// Load the templates in a [RenderConfig::template_overrides] directory, keyed by
+/// their path relative to it.
+fn load_template_overrides(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut templates = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry
+            .with_context(|| format!("Failed to read template overrides in {}", dir.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let name = entry
+            .path()
+            .strip_prefix(dir)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .join("/");
+        let is_builtin = BUILTIN_TEMPLATES
+            .iter()
+            .any(|(builtin, _)| *builtin == name);
+        if !is_builtin && !name.starts_with(PARTIALS_PREFIX) {
+            bail!(
+                "Unknown template `{}` in {}. Overrides must replace one of [{}] or add a new partial under `{}`",
+                name,
+                dir.display(),
+                BUILTIN_TEMPLATES.iter().map(|(name, _)| *name).join(", "),
+                PARTIALS_PREFIX,
+            );
+        }
+
+        let content = fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        templates.insert(name, content);
+    }
+    Ok(templates)
+}
+
 /// A convenience wrapper for parsing parameters to tera functions
 macro_rules! parse_tera_param {
     ($param:literal, $param_type:ty, $args:ident) => {
@@ -307,17 +411,20 @@ fn local_crate_mirror_options_json_fn_generator(
     config: Arc<RenderConfig>,
     supported_platform_triples: Arc<BTreeSet<TargetTriple>>,
     platform_conditions: Arc<BTreeMap<String, BTreeSet<TargetTriple>>>,
+    compound_conditions: Arc<BTreeMap<String, BTreeSet<CompoundCondition>>>,
 ) -> impl tera::Function {
     Box::new(
         move |args: &HashMap<String, Value>| -> tera::Result<Value> {
             let config = Arc::clone(&config);
             let supported_platform_triples = Arc::clone(&supported_platform_triples);
             let platform_conditions = Arc::clone(&platform_conditions);
+            let compound_conditions = Arc::clone(&compound_conditions);
             let crate_context = Arc::new(parse_tera_param!("crate_context", CrateContext, args));
             let context = SingleBuildFileRenderContext {
                 config,
                 supported_platform_triples,
                 platform_conditions,
+                compound_conditions,
                 crate_context,
             };
             serde_json::to_string(&context)
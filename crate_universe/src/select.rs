@@ -3,6 +3,9 @@ use std::fmt::Debug;
 
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 
+use crate::utils::starlark::Label;
+use crate::utils::target_triple::TargetTriple;
+
 /// A wrapper around values where some values may be conditionally included (e.g. only on a certain platform), and others are unconditional.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct Select<T>
@@ -388,6 +391,18 @@ where
                 .collect(),
         }
     }
+
+    /// Remove and return the common (unconfigured) values matching `predicate`.
+    pub(crate) fn take_common<F>(&mut self, mut predicate: F) -> BTreeSet<T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let (taken, kept) = std::mem::take(&mut self.common)
+            .into_iter()
+            .partition(|value| predicate(value));
+        self.common = kept;
+        taken
+    }
 }
 
 // BTreeMap<U, T>
@@ -482,3 +497,30 @@ where
         result
     }
 }
+
+/// A configuration matched only when a platform and a set of `config_setting`s
+/// all match, such as a target triple built with a given target feature.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct CompoundCondition {
+    /// The platform the condition applies to.
+    pub(crate) triple: TargetTriple,
+
+    /// The labels of the `config_setting`s which must also match.
+    pub(crate) settings: BTreeSet<Label>,
+}
+
+impl CompoundCondition {
+    /// A target name unique to this condition.
+    pub(crate) fn name(&self) -> String {
+        std::iter::once(self.triple.to_bazel())
+            .chain(self.settings.iter().map(|setting| {
+                setting
+                    .to_string()
+                    .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+                    .trim_start_matches('_')
+                    .to_owned()
+            }))
+            .collect::<Vec<_>>()
+            .join("__")
+    }
+}
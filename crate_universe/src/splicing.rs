@@ -16,12 +16,16 @@ use cargo_toml::Manifest;
 use serde::{Deserialize, Serialize};
 
 use crate::config::CrateId;
-use crate::metadata::{Cargo, CargoUpdateRequest, LockGenerator, TreeResolverMetadata};
+use crate::metadata::{
+    BuildScriptOutputs, Cargo, CargoUpdateRequest, HostTreeMetadata, LockGenerator,
+    TreeResolverMetadata,
+};
 use crate::utils;
 use crate::utils::starlark::Label;
+use crate::utils::target_triple::{CustomTarget, TargetTriple};
 
 use self::cargo_config::CargoConfig;
-use self::crate_index_lookup::CrateIndexLookup;
+pub(crate) use self::crate_index_lookup::CrateIndexLookup;
 pub(crate) use self::splicer::*;
 
 type DirectPackageManifest = BTreeMap<String, cargo_toml::DependencyDetail>;
@@ -41,6 +45,10 @@ pub(crate) struct SplicingManifest {
 
     /// The Cargo resolver version to use for splicing
     pub(crate) resolver_version: cargo_toml::Resolver,
+
+    /// A mapping of platforms unknown to `rustc` to the files describing them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) custom_targets: BTreeMap<TargetTriple, CustomTarget>,
 }
 
 impl FromStr for SplicingManifest {
@@ -61,6 +69,7 @@ impl SplicingManifest {
         let Self {
             manifests,
             cargo_config,
+            custom_targets,
             ..
         } = self;
 
@@ -88,12 +97,44 @@ impl SplicingManifest {
             Utf8PathBuf::from(resolved_path)
         });
 
+        // Ensure custom target descriptions are located at absolute paths
+        let custom_targets = custom_targets
+            .into_iter()
+            .map(|(triple, target)| {
+                let target = target.map_path(|path| {
+                    let resolved_path = path
+                        .to_string()
+                        .replace("${build_workspace_directory}", &workspace_dir_str)
+                        .replace("${output_base}", &output_base_str);
+                    Utf8PathBuf::from(resolved_path)
+                });
+                (triple, target)
+            })
+            .collect();
+
         Self {
             manifests,
             cargo_config,
+            custom_targets,
             ..self
         }
     }
+
+    /// Determine the `cfg` values of all custom targets.
+    pub(crate) fn custom_target_cfgs(
+        &self,
+        rustc: &Path,
+    ) -> Result<BTreeMap<TargetTriple, Vec<String>>> {
+        self.custom_targets
+            .iter()
+            .map(|(triple, target)| {
+                let cfg = target
+                    .cfg(rustc)
+                    .with_context(|| format!("Failed to load custom target '{}'", triple))?;
+                Ok((triple.clone(), cfg))
+            })
+            .collect()
+    }
 }
 
 /// The result of fully resolving a [SplicingManifest] in preparation for splicing.
@@ -107,6 +148,10 @@ pub(crate) struct SplicingMetadata {
 
     /// The path of a Cargo config file
     pub(crate) cargo_config: Option<CargoConfig>,
+
+    /// The contents of the files describing custom targets
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) custom_targets: BTreeMap<TargetTriple, String>,
 }
 
 impl TryFrom<SplicingManifest> for SplicingMetadata {
@@ -141,10 +186,21 @@ impl TryFrom<SplicingManifest> for SplicingMetadata {
             None => None,
         };
 
+        let custom_targets = value
+            .custom_targets
+            .into_iter()
+            .map(|(triple, target)| {
+                let content = fs::read_to_string(target.path())
+                    .with_context(|| format!("Failed to load custom target '{}'", target.path()))?;
+                Ok((triple, content))
+            })
+            .collect::<Result<BTreeMap<TargetTriple, String>>>()?;
+
         Ok(Self {
             direct_packages,
             manifests,
             cargo_config,
+            custom_targets,
         })
     }
 }
@@ -175,6 +231,23 @@ pub(crate) struct WorkspaceMetadata {
     /// We store this here because it's computed during the splicing phase via
     /// calls to "cargo tree" which need the full spliced workspace.
     pub(crate) tree_metadata: TreeResolverMetadata,
+
+    /// Features and dependencies of crates built for the host which can't
+    /// share a library with the target. Gathered alongside `tree_metadata`.
+    #[serde(default, skip_serializing_if = "HostTreeMetadata::is_empty")]
+    pub(crate) host_tree_metadata: HostTreeMetadata,
+
+    /// The pinned outputs of build scripts which were run ahead of time for
+    /// each supported platform, in place of running them during the build.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) build_script_outputs: BuildScriptOutputs,
+
+    /// The `cfg` values of each custom target.
+    ///
+    /// Like `tree_metadata`, these are gathered during splicing where the
+    /// files describing the targets are available.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) target_cfgs: BTreeMap<TargetTriple, Vec<String>>,
 }
 
 impl TryFrom<toml::Value> for WorkspaceMetadata {
@@ -248,16 +321,23 @@ impl WorkspaceMetadata {
             workspace_prefix,
             package_prefixes,
             tree_metadata: TreeResolverMetadata::new(),
+            host_tree_metadata: HostTreeMetadata::default(),
+            build_script_outputs: BuildScriptOutputs::new(),
+            target_cfgs: BTreeMap::new(),
         })
     }
 
     /// Update an existing Cargo manifest with metadata about registry urls and target
     /// features that are needed in generator steps beyond splicing.
     #[tracing::instrument(skip_all)]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn write_registry_urls_and_feature_map(
         cargo: &Cargo,
         lockfile: &cargo_lock::Lockfile,
         resolver_data: TreeResolverMetadata,
+        host_resolver_data: HostTreeMetadata,
+        build_script_outputs: BuildScriptOutputs,
+        target_cfgs: BTreeMap<TargetTriple, Vec<String>>,
         input_manifest_path: &Utf8Path,
         output_manifest_path: &Utf8Path,
     ) -> Result<()> {
@@ -420,6 +500,9 @@ impl WorkspaceMetadata {
             .sources
             .extend(additional_sources.into_iter());
         workspace_metadata.tree_metadata = resolver_data;
+        workspace_metadata.host_tree_metadata = host_resolver_data;
+        workspace_metadata.build_script_outputs = build_script_outputs;
+        workspace_metadata.target_cfgs = target_cfgs;
         workspace_metadata.inject_into(&mut manifest)?;
 
         write_root_manifest(output_manifest_path.as_std_path(), manifest)?;
@@ -476,6 +559,7 @@ pub(crate) fn generate_lockfile(
     existing_lock: &Option<PathBuf>,
     cargo_bin: Cargo,
     update_request: &Option<CargoUpdateRequest>,
+    resolve_conflicts: bool,
 ) -> Result<cargo_lock::Lockfile> {
     let manifest_dir = manifest_path
         .as_path_buf()
@@ -490,11 +574,9 @@ pub(crate) fn generate_lockfile(
     }
 
     // Generate the new lockfile
-    let lockfile = LockGenerator::new(cargo_bin).generate(
-        manifest_path.as_path_buf(),
-        existing_lock,
-        update_request,
-    )?;
+    let lockfile = LockGenerator::new(cargo_bin)
+        .with_resolve_conflicts(resolve_conflicts)
+        .generate(manifest_path.as_path_buf(), existing_lock, update_request)?;
 
     // Write the lockfile to disk
     if !root_lockfile_path.exists() {
@@ -610,6 +692,12 @@ mod test {
         manifest.cargo_config = Some(Utf8PathBuf::from(
             "${build_workspace_directory}/.cargo/config.toml",
         ));
+        manifest.custom_targets = BTreeMap::from([(
+            TargetTriple::from_bazel("thumbv7em-acme-none-eabihf".to_owned()),
+            CustomTarget::TargetJson(Utf8PathBuf::from(
+                "${build_workspace_directory}/platforms/acme.json",
+            )),
+        )]);
         manifest = manifest.resolve(
             &PathBuf::from("/tmp/abs/path/workspace"),
             &PathBuf::from("/tmp/output_base"),
@@ -638,7 +726,18 @@ mod test {
         assert_eq!(
             manifest.cargo_config.unwrap(),
             PathBuf::from("/tmp/abs/path/workspace/.cargo/config.toml"),
-        )
+        );
+
+        // Check custom targets
+        assert_eq!(
+            manifest.custom_targets,
+            BTreeMap::from([(
+                TargetTriple::from_bazel("thumbv7em-acme-none-eabihf".to_owned()),
+                CustomTarget::TargetJson(Utf8PathBuf::from(
+                    "/tmp/abs/path/workspace/platforms/acme.json"
+                )),
+            )])
+        );
     }
 
     #[test]
@@ -678,6 +777,7 @@ mod test {
             ]),
             cargo_config: None,
             resolver_version: cargo_toml::Resolver::V2,
+            custom_targets: BTreeMap::new(),
         };
         let metadata = SplicingMetadata::try_from(manifest).unwrap();
         let metadata = serde_json::to_string(&metadata).unwrap();
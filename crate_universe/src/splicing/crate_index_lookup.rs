@@ -1,5 +1,8 @@
+use std::io;
+use std::path::Path;
+
 use crate::splicing::SourceInfo;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use crates_index::IndexConfig;
 use hex::ToHex;
 
@@ -15,6 +18,55 @@ pub(crate) enum CrateIndexLookup {
 }
 
 impl CrateIndexLookup {
+    /// Open a local mirror of the registry index at `url` without accessing the network.
+    /// The mirror is either a git checkout of the index or a sparse index cache in the
+    /// layout Cargo writes to `$CARGO_HOME/registry/index`.
+    pub(crate) fn from_local_mirror(path: &Path, url: &str) -> Result<Self> {
+        if path.join(".git").exists() || path.join("HEAD").is_file() {
+            let index = crates_index::GitIndex::try_with_path(path, url)
+                .with_context(|| format!("Failed to open git index at {}", path.display()))?
+                .ok_or_else(|| anyhow!("No git index found at {}", path.display()))?;
+            let index_config = index
+                .index_config()
+                .with_context(|| format!("`config.json` not found in index: {}", path.display()))?;
+            Ok(Self::Git {
+                index,
+                index_config,
+            })
+        } else {
+            let index = crates_index::SparseIndex::at_path(path.to_path_buf(), url.to_owned());
+            let index_config = index
+                .index_config()
+                .with_context(|| format!("`config.json` not found in index: {}", path.display()))?;
+            Ok(Self::Http {
+                index,
+                index_config,
+            })
+        }
+    }
+
+    /// Read every published version of the crate `name` from the local copy of the index.
+    /// Returns `None` if the crate is not in the index.
+    pub(crate) fn get_crate(&self, name: &str) -> Result<Option<crates_index::Crate>> {
+        match self {
+            Self::Http { index, .. } => match index.crate_from_cache(name) {
+                Ok(krate) => Ok(Some(krate)),
+                Err(crates_index::Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                    Ok(None)
+                }
+                Err(err) => {
+                    Err(err).with_context(|| format!("Failed to get crate from cache: {:?}", index))
+                }
+            },
+            Self::Git { index, .. } => Ok(index.crate_(name)),
+        }
+    }
+
+    /// The url the index says the crate `name` at `version` is downloaded from.
+    pub(crate) fn download_url(&self, name: &str, version: &str) -> Option<String> {
+        self.index_config().download_url(name, version)
+    }
+
     pub(crate) fn get_source_info(&self, pkg: &cargo_lock::Package) -> Result<SourceInfo> {
         let url = self
             .index_config()
@@ -718,8 +718,11 @@ mod test {
 
     /// Get cargo and rustc binaries the Cargo way
     #[cfg(feature = "cargo")]
-    fn get_cargo_and_rustc_paths() -> (PathBuf, PathBuf) {
-        (PathBuf::from("cargo"), PathBuf::from("rustc"))
+    fn get_cargo_and_rustc_paths() -> (std::path::PathBuf, std::path::PathBuf) {
+        (
+            std::path::PathBuf::from("cargo"),
+            std::path::PathBuf::from("rustc"),
+        )
     }
 
     fn cargo() -> Cargo {
@@ -197,6 +197,22 @@ pub(crate) mod metadata {
         .unwrap()
     }
 
+    pub(crate) fn target_cfg_features() -> cargo_metadata::Metadata {
+        serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/metadata/target_cfg_features/metadata.json"
+        )))
+        .unwrap()
+    }
+
+    pub(crate) fn host_specific_build_deps() -> cargo_metadata::Metadata {
+        serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/test_data/metadata/host_specific_build_deps/metadata.json"
+        )))
+        .unwrap()
+    }
+
     pub(crate) fn abspath() -> cargo_metadata::Metadata {
         serde_json::from_str(include_str!(concat!(
             env!("CARGO_MANIFEST_DIR"),
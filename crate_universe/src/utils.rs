@@ -1,8 +1,10 @@
 //! Common utilities
 
+pub(crate) mod merge_conflicts;
 pub(crate) mod starlark;
 pub(crate) mod symlink;
 pub(crate) mod target_triple;
+pub(crate) mod text_diff;
 
 pub(crate) const CRATES_IO_INDEX_URL: &str = "https://github.com/rust-lang/crates.io-index";
 
@@ -21,43 +23,29 @@ pub(crate) fn sanitize_repository_name(name: &str) -> String {
     name.replace('+', "-")
 }
 
-/// Vendored crates are generated by cargo itself in `src/metadata.rs` in the
-/// `VendorGenerator::generate()` method.  This means that the semver metadata will
-/// always contain a (+) symbol, which is not compatible with bazel's labels.
-/// This function will rename the cargo vendor generated file paths to be compatible with bazel
-/// labels by simply replacing the (+) with a (-). If this file is called by any other cli mod,
-/// it just simply joins the out dir to the path
+/// Crates vendored in `local` mode are extracted by `cargo vendor` into directories
+/// named after their version, the semver metadata of which contains a (+) symbol
+/// that is not compatible with bazel's labels. The directories are renamed as they
+/// are vendored by replacing the (+) with a (-), so when `sanitize_crate_dirs` is set
+/// the outputs within them are moved along. This only rewrites paths and never
+/// touches the filesystem. The out dir is then joined to each path.
 pub(crate) fn normalize_cargo_file_paths(
     outputs: BTreeMap<PathBuf, String>,
     out_dir: &Path,
+    sanitize_crate_dirs: bool,
 ) -> BTreeMap<PathBuf, String> {
     outputs
         .into_iter()
         .map(|(path, content)| {
-            // Get Path Str and Parent Path Str so we can rename the root file
-            let original_path_str = path.to_str().expect("All file paths should be strings");
-            let original_parent_path_str = path
-                .parent()
-                .expect("Should have parent")
-                .to_str()
-                .expect("All file paths should be strings");
-
-            // Vendor mode pre-creates the source directory (cargo's
-            // `+`-bearing layout) and we rewrite it to be label-friendly.
-            // Other render paths — e.g. the per-alias hub subpackage
-            // `BUILD.bazel`s whose parent name happens to contain a semver `+` —
-            // have nothing pre-created on disk; leave their paths alone so
+            // Other render paths — e.g. the per-alias hub subpackage `BUILD.bazel`s
+            // whose parent name happens to contain a semver `+` — are left alone so
             // the directory and the alias name stay in sync.
-            let src = out_dir.join(original_parent_path_str);
-            let path = if original_parent_path_str.contains('+') && src.exists() {
-                std::fs::rename(
-                    src,
-                    out_dir.join(sanitize_repository_name(original_parent_path_str)),
-                )
-                .expect("Could not rename paths");
-                PathBuf::from(&original_path_str.replace('+', "-"))
-            } else {
-                path
+            let path = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(file_name)) if sanitize_crate_dirs => {
+                    let parent = parent.to_str().expect("All file paths should be strings");
+                    PathBuf::from(sanitize_repository_name(parent)).join(file_name)
+                }
+                _ => path,
             };
 
             // In recent versions of Bazel, canonical repository paths may contain (+)
@@ -91,30 +79,38 @@ mod test {
     fn test_normalize_cargo_file_paths() {
         let mut outputs = BTreeMap::new();
         outputs.insert(
-            PathBuf::from("libbpf-sys-1.3.0+v1.3.0/BUILD.bazel"),
+            PathBuf::from("crates/libbpf-sys-1.3.0+v1.3.0/BUILD.bazel"),
             "contents".into(),
         );
 
         let outdir = tempfile::tempdir().unwrap();
-        // create dir to mimic cargo vendor
-        std::fs::create_dir_all(outdir.path().join("libbpf-sys-1.3.0+v1.3.0")).unwrap();
 
-        let got = normalize_cargo_file_paths(outputs, outdir.path());
-        for output in got.into_keys() {
-            assert!(!output.to_str().unwrap().contains('+'));
-        }
+        let got = normalize_cargo_file_paths(outputs, outdir.path(), true);
+        assert_eq!(
+            got.into_keys().collect::<Vec<_>>(),
+            vec![outdir
+                .path()
+                .join("crates/libbpf-sys-1.3.0-v1.3.0/BUILD.bazel")]
+        );
+
+        // Paths are rewritten without touching the filesystem.
+        assert!(!outdir.path().join("crates").exists());
     }
 
     #[test]
     fn test_normalize_cargo_file_paths_no_rename() {
         let mut outputs = BTreeMap::new();
-        outputs.insert(PathBuf::from("tokio-1.30.0/BUILD.bazel"), "contents".into());
+        outputs.insert(
+            PathBuf::from("libbpf-sys-1.3.0+v1.3.0/BUILD.bazel"),
+            "contents".into(),
+        );
 
         let outdir = tempfile::tempdir().unwrap();
 
-        let got = normalize_cargo_file_paths(outputs, outdir.path());
-        for output in got.into_keys() {
-            assert!(!output.to_str().unwrap().contains('+'));
-        }
+        let got = normalize_cargo_file_paths(outputs, outdir.path(), false);
+        assert_eq!(
+            got.into_keys().collect::<Vec<_>>(),
+            vec![outdir.path().join("libbpf-sys-1.3.0+v1.3.0/BUILD.bazel")]
+        );
     }
 }
@@ -0,0 +1,117 @@
+//! Utilities for reading files containing git merge conflict markers.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// The two sides of a file containing git merge conflicts.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ConflictSides {
+    /// The content with every conflict resolved to the current branch (`<<<<<<<`).
+    pub(crate) ours: String,
+
+    /// The content with every conflict resolved to the incoming branch (`>>>>>>>`).
+    pub(crate) theirs: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Both,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// Returns true if `line` is a conflict marker made of `marker` characters.
+fn is_marker(line: &str, marker: char) -> bool {
+    let line = line.trim_end_matches(['\r', '\n']);
+    line.len() >= 7
+        && line.chars().take(7).all(|c| c == marker)
+        && line[7..].chars().next().is_none_or(|c| c == ' ')
+}
+
+/// Returns true if `content` contains git merge conflict markers.
+pub(crate) fn has_merge_conflicts(content: &str) -> bool {
+    split_merge_conflicts(content).is_some()
+}
+
+/// Returns true if the file at `path` exists and contains git merge conflict markers.
+pub(crate) fn file_has_merge_conflicts(path: &Path) -> Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(has_merge_conflicts(&content))
+}
+
+/// Split content containing git merge conflicts into both of its sides, or `None`
+/// if there are no conflicts. The merge base of `diff3` style conflicts is dropped.
+pub(crate) fn split_merge_conflicts(content: &str) -> Option<ConflictSides> {
+    let mut sides = ConflictSides::default();
+    let mut section = Section::Both;
+    let mut found = false;
+
+    for line in content.split_inclusive('\n') {
+        section = match section {
+            Section::Both if is_marker(line, '<') => {
+                found = true;
+                Section::Ours
+            }
+            Section::Ours if is_marker(line, '|') => Section::Base,
+            Section::Ours | Section::Base if is_marker(line, '=') => Section::Theirs,
+            Section::Theirs if is_marker(line, '>') => Section::Both,
+            section => {
+                if matches!(section, Section::Both | Section::Ours) {
+                    sides.ours.push_str(line);
+                }
+                if matches!(section, Section::Both | Section::Theirs) {
+                    sides.theirs.push_str(line);
+                }
+                section
+            }
+        };
+    }
+
+    // An unterminated conflict is not something git would produce.
+    (found && section == Section::Both).then_some(sides)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_merge_conflicts_without_conflicts() {
+        assert_eq!(split_merge_conflicts("a\n=======\nb\n"), None);
+        assert!(!has_merge_conflicts("version = 3\n"));
+    }
+
+    #[test]
+    fn split_merge_conflicts_sides() {
+        let content = indoc::indoc! {"
+            common
+            <<<<<<< HEAD
+            ours
+            ||||||| base
+            base
+            =======
+            theirs
+            >>>>>>> feature
+            shared
+            <<<<<<< HEAD
+            =======
+            added
+            >>>>>>> feature
+        "};
+
+        assert_eq!(
+            split_merge_conflicts(content),
+            Some(ConflictSides {
+                ours: "common\nours\nshared\n".to_owned(),
+                theirs: "common\ntheirs\nshared\nadded\n".to_owned(),
+            })
+        );
+    }
+}
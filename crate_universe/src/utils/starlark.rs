@@ -10,7 +10,7 @@ mod select_set;
 mod serialize;
 mod target_compatible_with;
 
-use std::collections::BTreeSet as Set;
+use std::collections::{BTreeMap, BTreeSet as Set};
 
 use serde::{Serialize, Serializer};
 use serde_starlark::{Error as StarlarkError, FunctionCall};
@@ -34,6 +34,9 @@ pub(crate) enum Starlark {
     ExportsFiles(ExportsFiles),
     Filegroup(Filegroup),
     Alias(Alias),
+    BoolFlag(BoolFlag),
+    ConfigSetting(ConfigSetting),
+    ConfigSettingGroup(ConfigSettingGroup),
     CargoBuildScript(CargoBuildScript),
     CargoTomlEnvVars(CargoTomlEnvVars),
     #[serde(serialize_with = "serialize::rust_proc_macro")]
@@ -42,6 +45,12 @@ pub(crate) enum Starlark {
     RustLibrary(RustLibrary),
     #[serde(serialize_with = "serialize::rust_binary")]
     RustBinary(RustBinary),
+    #[serde(serialize_with = "serialize::rust_shared_library")]
+    RustSharedLibrary(RustNativeLibrary),
+    #[serde(serialize_with = "serialize::rust_static_library")]
+    RustStaticLibrary(RustNativeLibrary),
+    #[serde(serialize_with = "serialize::rust_test")]
+    RustTest(RustTest),
 
     #[serde(skip_serializing)]
     Verbatim(String),
@@ -82,6 +91,30 @@ pub(crate) struct Filegroup {
     pub(crate) srcs: Glob,
 }
 
+#[derive(Serialize)]
+#[serde(rename = "bool_flag")]
+pub(crate) struct BoolFlag {
+    pub(crate) name: String,
+    pub(crate) build_setting_default: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "config_setting")]
+pub(crate) struct ConfigSetting {
+    pub(crate) name: String,
+    pub(crate) flag_values: BTreeMap<Label, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "selects.config_setting_group")]
+pub(crate) struct ConfigSettingGroup {
+    pub(crate) name: String,
+    #[serde(skip_serializing_if = "Set::is_empty")]
+    pub(crate) match_any: Set<String>,
+    #[serde(skip_serializing_if = "Set::is_empty")]
+    pub(crate) match_all: Set<String>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Alias {
     pub(crate) rule: String,
@@ -173,6 +206,8 @@ pub(crate) struct RustProcMacro {
 #[derive(Serialize)]
 pub(crate) struct RustLibrary {
     pub(crate) name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) crate_name: Option<String>,
     #[serde(skip_serializing_if = "SelectSet::is_empty")]
     pub(crate) deps: SelectSet<Label>,
     #[serde(skip_serializing_if = "SelectSet::is_empty")]
@@ -202,6 +237,39 @@ pub(crate) struct RustBinary {
     pub(crate) common: CommonAttrs,
 }
 
+/// A `rust_shared_library` or `rust_static_library`.
+#[derive(Serialize)]
+pub(crate) struct RustNativeLibrary {
+    pub(crate) name: String,
+    pub(crate) crate_name: String,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) proc_macro_deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) link_deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectDict::is_empty")]
+    pub(crate) aliases: SelectDict<Label, String>,
+    #[serde(flatten)]
+    pub(crate) common: CommonAttrs,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RustTest {
+    pub(crate) name: String,
+    pub(crate) crate_name: String,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) proc_macro_deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectSet::is_empty")]
+    pub(crate) link_deps: SelectSet<Label>,
+    #[serde(skip_serializing_if = "SelectDict::is_empty")]
+    pub(crate) aliases: SelectDict<Label, String>,
+    #[serde(flatten)]
+    pub(crate) common: CommonAttrs,
+}
+
 #[derive(Serialize)]
 pub(crate) struct CommonAttrs {
     #[serde(skip_serializing_if = "Data::is_empty")]
@@ -1,8 +1,12 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
 
 use serde::ser::Serializer;
 use serde::Serialize;
 use serde_starlark::LineComment;
+use sha2::{Digest, Sha256};
+
+use crate::utils::starlark::Label;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub(crate) struct WithOriginalConfigurations<T> {
@@ -38,3 +42,128 @@ where
 pub(crate) fn looks_like_bazel_configuration_label(configuration: &str) -> bool {
     configuration.contains("//")
 }
+
+/// Canonicalizes the platform branches of rendered `select`s. Values selected
+/// by every supported platform are hoisted out of the `select`, and platforms
+/// with identical branches are matched by a single `config_setting_group`.
+#[derive(Debug)]
+pub(crate) struct SelectCompression {
+    // The labels of every supported platform.
+    platforms: BTreeSet<String>,
+    // The package the `config_setting_group`s are defined in.
+    package: Label,
+    // Groups referenced by compressed `select`s, keyed by target name.
+    groups: Mutex<BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl SelectCompression {
+    pub(crate) fn new(platforms: BTreeSet<String>, package: Label) -> Self {
+        Self {
+            platforms,
+            package,
+            groups: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The `config_setting_group`s referenced so far, keyed by target name and
+    /// mapped to the platforms they match.
+    pub(crate) fn groups(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.groups.lock().unwrap().clone()
+    }
+
+    /// Whether every supported platform has a branch in the `select`.
+    pub(crate) fn covers_all_platforms<V>(&self, selects: &BTreeMap<String, V>) -> bool {
+        !self.platforms.is_empty()
+            && self
+                .platforms
+                .iter()
+                .all(|platform| selects.contains_key(platform))
+    }
+
+    /// Whether the configuration is the label of a supported platform.
+    pub(crate) fn is_platform(&self, configuration: &str) -> bool {
+        self.platforms.contains(configuration)
+    }
+
+    /// Collapses the branches of supported platforms which are the `same` into
+    /// one branch matched by a group of those platforms. Other branches are
+    /// returned untouched.
+    pub(crate) fn group_branches<V>(
+        &self,
+        selects: BTreeMap<String, V>,
+        same: impl Fn(&V, &V) -> bool,
+        merge: impl Fn(&mut V, V),
+    ) -> BTreeMap<String, V> {
+        let mut result = BTreeMap::new();
+        let mut branches: Vec<(BTreeSet<String>, V)> = Vec::new();
+        for (configuration, value) in selects {
+            if !self.is_platform(&configuration) {
+                result.insert(configuration, value);
+                continue;
+            }
+            match branches
+                .iter_mut()
+                .find(|(_, existing)| same(existing, &value))
+            {
+                Some((platforms, existing)) => {
+                    platforms.insert(configuration);
+                    merge(existing, value);
+                }
+                None => branches.push((BTreeSet::from([configuration]), value)),
+            }
+        }
+
+        for (platforms, value) in branches {
+            let configuration = if platforms.len() == 1 {
+                platforms.into_iter().next().unwrap()
+            } else {
+                self.group(platforms)
+            };
+            result.insert(configuration, value);
+        }
+
+        result
+    }
+
+    // Registers a group matching any of the given platforms, returning its
+    // label. Names are derived from the platforms so they are stable between
+    // renders.
+    fn group(&self, platforms: BTreeSet<String>) -> String {
+        let mut hasher = Sha256::new();
+        for platform in &platforms {
+            hasher.update(platform.as_bytes());
+            hasher.update(b"\n");
+        }
+        let name = format!("platforms_{}", &hex::encode(hasher.finalize())[..12]);
+
+        let label = match &self.package {
+            Label::Absolute {
+                repository,
+                package,
+                ..
+            } => Label::Absolute {
+                repository: repository.clone(),
+                package: package.clone(),
+                target: name.clone(),
+            },
+            Label::Relative { .. } => Label::Relative {
+                target: name.clone(),
+            },
+        };
+
+        self.groups.lock().unwrap().insert(name, platforms);
+        label.to_string()
+    }
+}
+
+/// Whether two sequences of values are the same, disregarding the
+/// configurations they originated from.
+pub(crate) fn same_values<'a, T>(
+    lhs: impl ExactSizeIterator<Item = &'a WithOriginalConfigurations<T>>,
+    rhs: impl ExactSizeIterator<Item = &'a WithOriginalConfigurations<T>>,
+) -> bool
+where
+    T: PartialEq + 'a,
+{
+    lhs.len() == rhs.len() && lhs.zip(rhs).all(|(lhs, rhs)| lhs.value == rhs.value)
+}
@@ -7,7 +7,8 @@ use serde_starlark::{FunctionCall, MULTILINE};
 
 use crate::select::{Select, SelectableOrderedValue, SelectableValue};
 use crate::utils::starlark::{
-    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, WithOriginalConfigurations,
+    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, SelectCompression,
+    WithOriginalConfigurations,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -95,6 +96,55 @@ where
         }
     }
 
+    /// Hoists entries selected by every supported platform into the common
+    /// part and groups platforms whose entries are identical.
+    pub(crate) fn compress(mut self, compression: &SelectCompression) -> Self {
+        if compression.covers_all_platforms(&self.selects) {
+            let mut branches = self
+                .selects
+                .iter()
+                .filter(|(configuration, _)| compression.is_platform(configuration))
+                .map(|(_, entries)| entries);
+            let shared: BTreeMap<U, T> = match branches.next() {
+                Some(first) => first
+                    .iter()
+                    .filter(|(key, value)| {
+                        branches.clone().all(|entries| {
+                            entries
+                                .get(key)
+                                .is_some_and(|other| other.value == value.value)
+                        })
+                    })
+                    .map(|(key, value)| (key.clone(), value.value.clone()))
+                    .collect(),
+                None => BTreeMap::new(),
+            };
+            for entries in self.selects.values_mut() {
+                entries.retain(|key, _| !shared.contains_key(key));
+            }
+            self.selects.retain(|_, entries| !entries.is_empty());
+            self.common.extend(shared);
+        }
+
+        self.selects = compression.group_branches(
+            std::mem::take(&mut self.selects),
+            |lhs, rhs| {
+                lhs.len() == rhs.len()
+                    && lhs.iter().zip(rhs).all(|((lhs_key, lhs), (rhs_key, rhs))| {
+                        lhs_key == rhs_key && lhs.value == rhs.value
+                    })
+            },
+            |existing, other| {
+                for (existing, other) in existing.values_mut().zip(other.into_values()) {
+                    existing
+                        .original_configurations
+                        .extend(other.original_configurations);
+                }
+            },
+        );
+        self
+    }
+
     pub(crate) fn is_empty(&self) -> bool {
         self.common.is_empty() && self.selects.is_empty() && self.unmapped.is_empty()
     }
@@ -230,8 +280,12 @@ where
 mod test {
     use super::*;
 
+    use std::str::FromStr;
+
     use indoc::indoc;
 
+    use crate::utils::starlark::Label;
+
     #[test]
     fn empty_select_dict() {
         let select_dict: SelectDict<String, String> =
@@ -330,6 +384,61 @@ mod test {
         );
     }
 
+    #[test]
+    fn compress_select_dict() {
+        let mut select: Select<BTreeMap<String, String>> = Select::default();
+        for cfg in ["cfg(unix)", "cfg(windows)"] {
+            select.insert(
+                ("shared".to_owned(), "value".to_owned()),
+                Some(cfg.to_owned()),
+            );
+        }
+        select.insert(
+            ("unix".to_owned(), "value".to_owned()),
+            Some("cfg(unix)".to_owned()),
+        );
+
+        let platforms = BTreeMap::from([
+            (
+                "cfg(unix)".to_owned(),
+                BTreeSet::from(["//platform:linux".to_owned(), "//platform:macos".to_owned()]),
+            ),
+            (
+                "cfg(windows)".to_owned(),
+                BTreeSet::from(["//platform:windows".to_owned()]),
+            ),
+        ]);
+
+        let compression = SelectCompression::new(
+            BTreeSet::from([
+                "//platform:linux".to_owned(),
+                "//platform:macos".to_owned(),
+                "//platform:windows".to_owned(),
+            ]),
+            Label::from_str("@crates//:BUILD.bazel").unwrap(),
+        );
+
+        let select_dict = SelectDict::new(select, &platforms).compress(&compression);
+
+        let (name, _) = compression.groups().into_iter().next().unwrap();
+        let expected_starlark = indoc! {r#"
+            select({
+                "@crates//:platforms_GROUP": {
+                    "shared": "value",
+                    "unix": "value",  # cfg(unix)
+                },
+                "//conditions:default": {
+                    "shared": "value",
+                },
+            })
+        "#};
+
+        assert_eq!(
+            select_dict.serialize(serde_starlark::Serializer).unwrap(),
+            expected_starlark.replace("platforms_GROUP", &name),
+        );
+    }
+
     #[test]
     fn remap_select_dict_configurations() {
         let mut select: Select<BTreeMap<String, String>> = Select::default();
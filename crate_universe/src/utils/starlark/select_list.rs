@@ -8,7 +8,8 @@ use serde_starlark::{FunctionCall, MULTILINE};
 use crate::select::{Select, SelectableValue};
 use crate::utils::starlark::serialize::MultilineArray;
 use crate::utils::starlark::{
-    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, WithOriginalConfigurations,
+    looks_like_bazel_configuration_label, same_values, NoMatchingPlatformTriples,
+    SelectCompression, WithOriginalConfigurations,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -86,6 +87,23 @@ where
         }
     }
 
+    /// Groups platforms whose elements are identical. Elements are not
+    /// hoisted into the common part as that would change their order.
+    pub(crate) fn compress(mut self, compression: &SelectCompression) -> Self {
+        self.selects = compression.group_branches(
+            std::mem::take(&mut self.selects),
+            |lhs, rhs| same_values(lhs.iter(), rhs.iter()),
+            |existing, other| {
+                for (existing, other) in existing.iter_mut().zip(other) {
+                    existing
+                        .original_configurations
+                        .extend(other.original_configurations);
+                }
+            },
+        );
+        self
+    }
+
     /// Determine whether or not the select should be serialized
     pub(crate) fn is_empty(&self) -> bool {
         self.common.is_empty() && self.selects.is_empty() && self.unmapped.is_empty()
@@ -7,7 +7,8 @@ use serde_starlark::{FunctionCall, MULTILINE};
 
 use crate::select::{Select, SelectableScalar};
 use crate::utils::starlark::{
-    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, WithOriginalConfigurations,
+    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, SelectCompression,
+    WithOriginalConfigurations,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -76,6 +77,37 @@ where
         }
     }
 
+    /// Hoists a value selected by every supported platform into the common
+    /// part, drops branches identical to it and groups platforms whose values
+    /// are identical.
+    pub(crate) fn compress(mut self, compression: &SelectCompression) -> Self {
+        if self.common.is_none() && compression.covers_all_platforms(&self.selects) {
+            let mut values = self
+                .selects
+                .iter()
+                .filter(|(configuration, _)| compression.is_platform(configuration))
+                .map(|(_, value)| &value.value);
+            let first = values.next().cloned();
+            if values.all(|value| Some(value) == first.as_ref()) {
+                self.common = first;
+            }
+        }
+        if let Some(common) = &self.common {
+            self.selects.retain(|_, value| &value.value != common);
+        }
+
+        self.selects = compression.group_branches(
+            std::mem::take(&mut self.selects),
+            |lhs, rhs| lhs.value == rhs.value,
+            |existing, other| {
+                existing
+                    .original_configurations
+                    .extend(other.original_configurations)
+            },
+        );
+        self
+    }
+
     /// Determine whether or not the select should be serialized
     pub(crate) fn is_empty(&self) -> bool {
         self.common.is_none() && self.selects.is_empty() && self.unmapped.is_empty()
@@ -183,8 +215,12 @@ where
 mod test {
     use super::*;
 
+    use std::str::FromStr;
+
     use indoc::indoc;
 
+    use crate::utils::starlark::Label;
+
     #[test]
     fn empty_select_value() {
         let select_value: SelectScalar<String> =
@@ -267,6 +303,60 @@ mod test {
         );
     }
 
+    #[test]
+    fn compress_select_value() {
+        let mut select: Select<String> = Select::default();
+        select.insert("unix".to_owned(), Some("cfg(unix)".to_owned()));
+        select.insert("windows".to_owned(), Some("cfg(windows)".to_owned()));
+
+        let platforms = BTreeMap::from([
+            (
+                "cfg(unix)".to_owned(),
+                BTreeSet::from(["//platform:linux".to_owned(), "//platform:macos".to_owned()]),
+            ),
+            (
+                "cfg(windows)".to_owned(),
+                BTreeSet::from(["//platform:windows".to_owned()]),
+            ),
+        ]);
+
+        let compression = SelectCompression::new(
+            BTreeSet::from([
+                "//platform:linux".to_owned(),
+                "//platform:macos".to_owned(),
+                "//platform:windows".to_owned(),
+            ]),
+            Label::from_str("@crates//:BUILD.bazel").unwrap(),
+        );
+
+        let select_value = SelectScalar::new(select, &platforms).compress(&compression);
+
+        let (name, _) = compression.groups().into_iter().next().unwrap();
+        let expected_starlark = indoc! {r#"
+            select({
+                "//platform:windows": "windows",  # cfg(windows)
+                "@crates//:platforms_GROUP": "unix",  # cfg(unix)
+            })
+        "#};
+
+        assert_eq!(
+            select_value.serialize(serde_starlark::Serializer).unwrap(),
+            expected_starlark.replace("platforms_GROUP", &name),
+        );
+
+        // A value shared by every platform is no longer selected.
+        let mut select: Select<String> = Select::default();
+        select.insert("unix".to_owned(), Some("cfg(unix)".to_owned()));
+        select.insert("unix".to_owned(), Some("cfg(windows)".to_owned()));
+
+        let select_value = SelectScalar::new(select, &platforms).compress(&compression);
+
+        assert_eq!(
+            select_value.serialize(serde_starlark::Serializer).unwrap(),
+            "\"unix\"\n",
+        );
+    }
+
     #[test]
     fn remap_select_value_configurations() {
         let mut select: Select<String> = Select::default();
@@ -8,7 +8,8 @@ use serde_starlark::{FunctionCall, MULTILINE};
 use crate::select::{Select, SelectableOrderedValue};
 use crate::utils::starlark::serialize::MultilineArray;
 use crate::utils::starlark::{
-    looks_like_bazel_configuration_label, NoMatchingPlatformTriples, WithOriginalConfigurations,
+    looks_like_bazel_configuration_label, same_values, NoMatchingPlatformTriples,
+    SelectCompression, WithOriginalConfigurations,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -24,6 +25,14 @@ where
     // new configuration. They could be ignored, but are preserved here to
     // generate comments that help the user understand what happened.
     unmapped: BTreeMap<String, BTreeSet<T>>,
+    // Elements gated behind a single build setting each, keyed by the label of
+    // the setting's `config_setting`. These are rendered in separate `select`s
+    // so they never conflict with platform configurations.
+    toggles: BTreeMap<String, BTreeSet<T>>,
+    // Elements keyed by a compound condition which refines a platform in
+    // `selects`. A `config_setting_group` does not specialize the platform it
+    // refines, so these are also rendered in separate `select`s.
+    compound: BTreeMap<String, BTreeSet<WithOriginalConfigurations<T>>>,
 }
 
 impl<T> SelectSet<T>
@@ -98,12 +107,92 @@ where
                 })
                 .collect(),
             unmapped,
+            toggles: BTreeMap::new(),
+            compound: BTreeMap::new(),
         }
     }
 
+    /// Adds elements which are only present when the `config_setting` they are
+    /// keyed by matches.
+    pub(crate) fn with_toggles(mut self, toggles: BTreeMap<String, BTreeSet<T>>) -> Self {
+        for (config_setting, values) in toggles {
+            if !values.is_empty() {
+                self.toggles
+                    .entry(config_setting)
+                    .or_default()
+                    .extend(values);
+            }
+        }
+        self
+    }
+
+    /// Moves elements keyed by compound conditions out of the platform
+    /// `select`. The mapping is from the label of each compound condition to
+    /// the label of the platform it refines. Elements already present for
+    /// that platform are dropped.
+    pub(crate) fn with_compound_conditions(mut self, compound: &BTreeMap<String, String>) -> Self {
+        for (condition, platform) in compound {
+            let Some(values) = self.selects.remove(condition) else {
+                continue;
+            };
+            let values: BTreeSet<_> = match self.selects.get(platform) {
+                Some(existing) => values
+                    .into_iter()
+                    .filter(|value| !existing.iter().any(|e| e.value == value.value))
+                    .collect(),
+                None => values,
+            };
+            if !values.is_empty() {
+                self.compound.insert(condition.clone(), values);
+            }
+        }
+        self
+    }
+
+    /// Hoists elements selected by every supported platform into the common
+    /// part and groups platforms whose elements are identical.
+    pub(crate) fn compress(mut self, compression: &SelectCompression) -> Self {
+        if compression.covers_all_platforms(&self.selects) {
+            let shared: BTreeSet<T> = self
+                .selects
+                .iter()
+                .filter(|(configuration, _)| compression.is_platform(configuration))
+                .map(|(_, values)| values.iter().map(|v| v.value.clone()).collect())
+                .reduce(|shared: BTreeSet<T>, values| &shared & &values)
+                .unwrap_or_default();
+            for values in self.selects.values_mut() {
+                values.retain(|v| !shared.contains(&v.value));
+            }
+            self.selects.retain(|_, values| !values.is_empty());
+            self.common.extend(shared);
+        }
+
+        self.selects = compression.group_branches(
+            std::mem::take(&mut self.selects),
+            |lhs, rhs| same_values(lhs.iter(), rhs.iter()),
+            |existing, other| {
+                *existing = std::mem::take(existing)
+                    .into_iter()
+                    .zip(other)
+                    .map(|(mut existing, other)| {
+                        existing
+                            .original_configurations
+                            .extend(other.original_configurations);
+                        existing
+                    })
+                    .collect();
+            },
+        );
+        self
+    }
+
     /// Determine whether or not the select should be serialized
     pub(crate) fn is_empty(&self) -> bool {
-        self.common.is_empty() && self.selects.is_empty() && self.unmapped.is_empty()
+        self.common.is_empty()
+            && self.selects.is_empty()
+            && self.unmapped.is_empty()
+            && self.toggles.is_empty()
+            && self.compound.is_empty()
     }
 }
 
@@ -145,10 +234,20 @@ where
         //             ],
         //         },
         //     })
+        //
+        // Elements of compound conditions, then toggled elements, follow in
+        // one select per `config_setting`:
+        //
+        //     + select({
+        //         "@crates//serde:derive__enabled": [
+        //             "value...",
+        //         ],
+        //         "//conditions:default": [],
+        //     })
 
         let mut plus = serializer.serialize_tuple_struct("+", MULTILINE)?;
 
-        if !self.common.is_empty() || self.selects.is_empty() && self.unmapped.is_empty() {
+        if !self.common.is_empty() || self.is_empty() {
             plus.serialize_field(&MultilineArray(&self.common))?;
         }
 
@@ -209,6 +308,37 @@ where
             plus.serialize_field(&FunctionCall::new(function, [SelectInner(self)]))?;
         }
 
+        struct ToggleInner<'a, V>(&'a str, &'a BTreeSet<V>);
+
+        impl<V> Serialize for ToggleInner<'_, V>
+        where
+            V: Serialize,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let mut map = serializer.serialize_map(Some(MULTILINE))?;
+                map.serialize_entry(self.0, &MultilineArray(self.1))?;
+                map.serialize_entry("//conditions:default", &[] as &[V])?;
+                map.end()
+            }
+        }
+
+        for (condition, values) in &self.compound {
+            plus.serialize_field(&FunctionCall::new(
+                "select",
+                [ToggleInner(condition, values)],
+            ))?;
+        }
+
+        for (config_setting, values) in &self.toggles {
+            plus.serialize_field(&FunctionCall::new(
+                "select",
+                [ToggleInner(config_setting, values)],
+            ))?;
+        }
+
         plus.end()
     }
 }
@@ -217,8 +347,12 @@ where
 mod test {
     use super::*;
 
+    use std::str::FromStr;
+
     use indoc::indoc;
 
+    use crate::utils::starlark::Label;
+
     #[test]
     fn empty_select_set() {
         let select_set: SelectSet<String> = SelectSet::new(Default::default(), &Default::default());
@@ -309,6 +443,148 @@ mod test {
         );
     }
 
+    #[test]
+    fn toggled_select_set() {
+        let mut select: Select<BTreeSet<String>> = Select::default();
+        select.insert("Hello".to_owned(), Some("platform".to_owned()));
+        select.insert("Goodbye".to_owned(), None);
+
+        let platforms = BTreeMap::from([(
+            "platform".to_owned(),
+            BTreeSet::from(["platform".to_owned()]),
+        )]);
+
+        let select_set = SelectSet::new(select, &platforms).with_toggles(BTreeMap::from([
+            (
+                "@crates//a:b__enabled".to_owned(),
+                BTreeSet::from(["Toggled".to_owned()]),
+            ),
+            ("@crates//a:c__enabled".to_owned(), BTreeSet::new()),
+        ]));
+
+        let expected_starlark = indoc! {r#"
+            [
+                "Goodbye",
+            ] + select({
+                "platform": [
+                    "Hello",  # platform
+                ],
+                "//conditions:default": [],
+            }) + select({
+                "@crates//a:b__enabled": [
+                    "Toggled",
+                ],
+                "//conditions:default": [],
+            })
+        "#};
+
+        assert_eq!(
+            select_set.serialize(serde_starlark::Serializer).unwrap(),
+            expected_starlark,
+        );
+    }
+
+    #[test]
+    fn compound_condition_select_set() {
+        let mut select: Select<BTreeSet<String>> = Select::default();
+        select.insert("dep-a".to_owned(), Some("cfg(unix)".to_owned()));
+        select.insert("dep-a".to_owned(), Some("cfg(avx2)".to_owned()));
+        select.insert("dep-b".to_owned(), Some("cfg(avx2)".to_owned()));
+
+        let platforms = BTreeMap::from([
+            (
+                "cfg(unix)".to_owned(),
+                BTreeSet::from(["//platform:linux".to_owned()]),
+            ),
+            (
+                "cfg(avx2)".to_owned(),
+                BTreeSet::from(["@crates//:linux__avx2".to_owned()]),
+            ),
+        ]);
+
+        let select_set =
+            SelectSet::new(select, &platforms).with_compound_conditions(&BTreeMap::from([(
+                "@crates//:linux__avx2".to_owned(),
+                "//platform:linux".to_owned(),
+            )]));
+
+        let expected_starlark = indoc! {r#"
+            select({
+                "//platform:linux": [
+                    "dep-a",  # cfg(unix)
+                ],
+                "//conditions:default": [],
+            }) + select({
+                "@crates//:linux__avx2": [
+                    "dep-b",  # cfg(avx2)
+                ],
+                "//conditions:default": [],
+            })
+        "#};
+
+        assert_eq!(
+            select_set.serialize(serde_starlark::Serializer).unwrap(),
+            expected_starlark,
+        );
+    }
+
+    #[test]
+    fn compress_select_set() {
+        let mut select: Select<BTreeSet<String>> = Select::default();
+        select.insert("dep-a".to_owned(), Some("cfg(unix)".to_owned()));
+        select.insert("dep-a".to_owned(), Some("cfg(windows)".to_owned()));
+        select.insert("dep-b".to_owned(), Some("cfg(unix)".to_owned()));
+        select.insert("dep-c".to_owned(), Some("cfg(windows)".to_owned()));
+
+        let platforms = BTreeMap::from([
+            (
+                "cfg(unix)".to_owned(),
+                BTreeSet::from(["//platform:linux".to_owned(), "//platform:macos".to_owned()]),
+            ),
+            (
+                "cfg(windows)".to_owned(),
+                BTreeSet::from(["//platform:windows".to_owned()]),
+            ),
+        ]);
+
+        let compression = SelectCompression::new(
+            BTreeSet::from([
+                "//platform:linux".to_owned(),
+                "//platform:macos".to_owned(),
+                "//platform:windows".to_owned(),
+            ]),
+            Label::from_str("@crates//:BUILD.bazel").unwrap(),
+        );
+
+        let select_set = SelectSet::new(select, &platforms).compress(&compression);
+
+        let expected_starlark = indoc! {r#"
+            [
+                "dep-a",
+            ] + select({
+                "//platform:windows": [
+                    "dep-c",  # cfg(windows)
+                ],
+                "@crates//:platforms_GROUP": [
+                    "dep-b",  # cfg(unix)
+                ],
+                "//conditions:default": [],
+            })
+        "#};
+
+        let groups = compression.groups();
+        assert_eq!(groups.len(), 1);
+        let (name, members) = groups.into_iter().next().unwrap();
+        assert_eq!(
+            members,
+            BTreeSet::from(["//platform:linux".to_owned(), "//platform:macos".to_owned()])
+        );
+        assert_eq!(
+            select_set.serialize(serde_starlark::Serializer).unwrap(),
+            expected_starlark.replace("platforms_GROUP", &name),
+        );
+    }
+
     #[test]
     fn remap_select_set_configurations() {
         let mut select: Select<BTreeSet<String>> = Select::default();
@@ -405,6 +681,8 @@ mod test {
                 "cfg(pdp11)".to_owned(),
                 BTreeSet::from(["dep-e".to_owned()]),
             )]),
+            toggles: BTreeMap::new(),
+            compound: BTreeMap::new(),
         };
 
         assert_eq!(select_set, expected);
@@ -3,7 +3,8 @@ use serde::Serialize;
 use serde_starlark::{FunctionCall, MULTILINE, ONELINE};
 
 use super::{
-    Data, ExportsFiles, License, Load, Package, PackageInfo, RustBinary, RustLibrary, RustProcMacro,
+    Data, ExportsFiles, License, Load, Package, PackageInfo, RustBinary, RustLibrary,
+    RustNativeLibrary, RustProcMacro, RustTest,
 };
 
 // For structs that contain #[serde(flatten)], a quirk of how Serde processes
@@ -31,6 +32,33 @@ where
     FunctionCall::new("rust_binary", rule).serialize(serializer)
 }
 
+pub(crate) fn rust_shared_library<S>(
+    rule: &RustNativeLibrary,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    FunctionCall::new("rust_shared_library", rule).serialize(serializer)
+}
+
+pub(crate) fn rust_static_library<S>(
+    rule: &RustNativeLibrary,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    FunctionCall::new("rust_static_library", rule).serialize(serializer)
+}
+
+pub(crate) fn rust_test<S>(rule: &RustTest, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    FunctionCall::new("rust_test", rule).serialize(serializer)
+}
+
 // Serialize an array with each element on its own line, even if there is just a
 // single element which serde_starlark would ordinarily place on the same line
 // as the array brackets.
@@ -1,5 +1,15 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter, Result};
+use std::path::Path;
+use std::process::Command;
 
+use anyhow::Context;
+use camino::Utf8PathBuf;
+use cfg_expr::targets::{
+    get_builtin_target_by_triple, Abi, Arch, Endian, Env, Families, Family, HasAtomic, HasAtomics,
+    Os, Panic, TargetInfo, Triple, Vendor,
+};
+use cfg_expr::{Expression, Predicate};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -35,3 +45,198 @@ impl Display for TargetTriple {
         }
     }
 }
+
+/// A platform unknown to `rustc` which is described by the user instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CustomTarget {
+    /// The path to a [custom target specification](https://doc.rust-lang.org/rustc/targets/custom.html).
+    TargetJson(Utf8PathBuf),
+
+    /// The path to the captured output of `rustc --print cfg` for the target.
+    RustcCfg(Utf8PathBuf),
+}
+
+impl CustomTarget {
+    /// The path of the file describing the target.
+    pub(crate) fn path(&self) -> &Utf8PathBuf {
+        match self {
+            Self::TargetJson(path) | Self::RustcCfg(path) => path,
+        }
+    }
+
+    /// Apply `f` to the path of the file describing the target.
+    pub(crate) fn map_path<F: FnOnce(Utf8PathBuf) -> Utf8PathBuf>(self, f: F) -> Self {
+        match self {
+            Self::TargetJson(path) => Self::TargetJson(f(path)),
+            Self::RustcCfg(path) => Self::RustcCfg(f(path)),
+        }
+    }
+
+    /// The value to pass to Cargo's `--target` flag for this target.
+    pub(crate) fn cargo_target(&self, triple: &TargetTriple) -> String {
+        match self {
+            Self::TargetJson(path) => path.to_string(),
+            Self::RustcCfg(_) => triple.to_cargo(),
+        }
+    }
+
+    /// Determine the `cfg` values active for this target.
+    pub(crate) fn cfg(&self, rustc: &Path) -> anyhow::Result<Vec<String>> {
+        let text = match self {
+            Self::TargetJson(path) => {
+                let output = Command::new(rustc)
+                    .arg("--print=cfg")
+                    .arg("--target")
+                    .arg(path)
+                    .output()
+                    .with_context(|| format!("Failed to spawn rustc for target spec '{}'", path))?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "Failed to query `cfg` for target spec '{}':\n{}",
+                        path,
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+                String::from_utf8(output.stdout)?
+            }
+            Self::RustcCfg(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read rustc cfg file '{}'", path))?,
+        };
+
+        Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
+/// A platform against which `cfg` expressions are evaluated.
+pub(crate) struct CfgTarget {
+    /// The target information understood by `cfg-expr`.
+    info: TargetInfo,
+
+    /// The raw `cfg` values of a custom target. These answer predicates `info`
+    /// has no notion of, such as `target_feature`.
+    cfg: BTreeSet<(String, Option<String>)>,
+}
+
+impl CfgTarget {
+    /// Build a target for `triple`. Triples unknown to `rustc` are described by
+    /// their `cfg` values in `target_cfgs`.
+    pub(crate) fn new(
+        triple: &TargetTriple,
+        target_cfgs: &BTreeMap<TargetTriple, Vec<String>>,
+    ) -> anyhow::Result<Self> {
+        if let Some(cfg) = target_cfgs.get(triple) {
+            return Self::from_cfg(triple, cfg)
+                .with_context(|| format!("Invalid cfg for custom target: {}", triple));
+        }
+        match get_builtin_target_by_triple(&triple.to_cargo()) {
+            Some(info) => Ok(Self {
+                info: info.clone(),
+                cfg: BTreeSet::new(),
+            }),
+            None => Err(anyhow::anyhow!(
+                "Invalid platform triple in supported platforms: {}",
+                triple
+            )),
+        }
+    }
+
+    /// Build a target from the output of `rustc --print cfg`.
+    fn from_cfg(triple: &TargetTriple, lines: &[String]) -> anyhow::Result<Self> {
+        let cfg: BTreeSet<(String, Option<String>)> = lines
+            .iter()
+            .map(|line| match line.split_once('=') {
+                Some((key, val)) => (
+                    key.trim().to_owned(),
+                    Some(val.trim().trim_matches('"').to_owned()),
+                ),
+                None => (line.trim().to_owned(), None),
+            })
+            .collect();
+
+        let values = |key: &str| -> Vec<String> {
+            cfg.iter()
+                .filter(|(k, _)| k == key)
+                .filter_map(|(_, v)| v.clone())
+                .collect()
+        };
+        let value = |key: &str| -> Option<String> { values(key).into_iter().next() };
+
+        let arch =
+            value("target_arch").ok_or_else(|| anyhow::anyhow!("`target_arch` is not set"))?;
+        let pointer_width = match value("target_pointer_width") {
+            Some(width) => width
+                .parse::<u8>()
+                .with_context(|| format!("Invalid `target_pointer_width`: {}", width))?,
+            None => anyhow::bail!("`target_pointer_width` is not set"),
+        };
+        let endian = match value("target_endian").as_deref() {
+            Some("big") => Endian::big,
+            Some("little") => Endian::little,
+            Some(endian) => anyhow::bail!("Invalid `target_endian`: {}", endian),
+            None => anyhow::bail!("`target_endian` is not set"),
+        };
+        let has_atomics = values("target_has_atomic")
+            .iter()
+            .map(|atomic| {
+                atomic
+                    .parse::<HasAtomic>()
+                    .map_err(|_| anyhow::anyhow!("Invalid `target_has_atomic`: {}", atomic))
+            })
+            .collect::<anyhow::Result<Vec<HasAtomic>>>()?;
+
+        let info = TargetInfo {
+            triple: Triple::new(triple.to_cargo()),
+            os: value("target_os").map(Os::new),
+            abi: value("target_abi").filter(|v| !v.is_empty()).map(Abi::new),
+            arch: Arch::new(arch),
+            env: value("target_env").filter(|v| !v.is_empty()).map(Env::new),
+            vendor: value("target_vendor").map(Vendor::new),
+            families: Families::new(values("target_family").into_iter().map(Family::new)),
+            pointer_width,
+            endian,
+            has_atomics: HasAtomics::new(has_atomics),
+            panic: Panic::new(value("panic").unwrap_or_else(|| "unwind".to_owned())),
+        };
+
+        Ok(Self { info, cfg })
+    }
+
+    /// Evaluate a platform specification (either a `cfg` expression or a target
+    /// triple) such as the ones used for target specific dependencies.
+    pub(crate) fn eval(&self, platform: &str) -> anyhow::Result<bool> {
+        // `cfg-expr` only understands `cfg` expressions, so a target triple is
+        // checked as `cfg(target = "{triple}")` instead.
+        let expression = match platform.starts_with("cfg(") {
+            true => Expression::parse(platform),
+            false => Expression::parse(&format!("cfg(target = \"{platform}\")")),
+        }
+        .with_context(|| format!("Failed to parse expression: '{}'", platform))?;
+        Ok(expression.eval(|p| self.matches(p)))
+    }
+
+    /// Evaluate a predicate of a `cfg` expression against this target.
+    pub(crate) fn matches(&self, predicate: &Predicate) -> bool {
+        match predicate {
+            Predicate::Target(tp) => tp.matches(&self.info),
+            Predicate::KeyValue { key, val } if *key == "target" => {
+                val == &self.info.triple.as_str()
+            }
+            Predicate::KeyValue { key, val } => self.has(key, Some(val)),
+            Predicate::TargetFeature(feature) => self.has("target_feature", Some(feature)),
+            Predicate::Flag(flag) => self.has(flag, None),
+            // For now there is no other kind of matching
+            _ => false,
+        }
+    }
+
+    /// Check whether the raw `cfg` of a custom target contains a value.
+    fn has(&self, key: &str, val: Option<&str>) -> bool {
+        self.cfg.contains(&(key.to_owned(), val.map(str::to_owned)))
+    }
+}
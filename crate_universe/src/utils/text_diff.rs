@@ -0,0 +1,212 @@
+//! A minimal line based unified diff for reporting differences in generated files.
+
+/// The number of unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// The largest number of cells of the longest common subsequence table which will
+/// be computed. Beyond this, the changed region is reported as replaced wholesale.
+const MAX_LCS_CELLS: usize = 16_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal { old: usize, new: usize },
+    Delete { old: usize, new: usize },
+    Insert { old: usize, new: usize },
+}
+
+impl Op {
+    fn is_change(&self) -> bool {
+        !matches!(self, Op::Equal { .. })
+    }
+
+    fn positions(&self) -> (usize, usize) {
+        match *self {
+            Op::Equal { old, new } | Op::Delete { old, new } | Op::Insert { old, new } => {
+                (old, new)
+            }
+        }
+    }
+}
+
+/// Render a unified diff of `old` and `new`, or an empty string if they're equal.
+pub(crate) fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| op.is_change())
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group changes into hunks, merging those whose context would overlap.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changes {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut output = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let (old_start, new_start) = hunk[0].positions();
+        let old_count = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert { .. }))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete { .. }))
+            .count();
+        let range = |start: usize, count: usize| match count {
+            0 => format!("{},0", start),
+            _ => format!("{},{}", start + 1, count),
+        };
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_count),
+            range(new_start, new_count)
+        ));
+
+        for op in hunk {
+            let line = match *op {
+                Op::Equal { old, .. } => format!(" {}", old_lines[old]),
+                Op::Delete { old, .. } => format!("-{}", old_lines[old]),
+                Op::Insert { new, .. } => format!("+{}", new_lines[new]),
+            };
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// Compute the edit script between two sets of lines.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<Op> = (0..prefix)
+        .map(|index| Op::Equal {
+            old: index,
+            new: index,
+        })
+        .collect();
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if (n + 1) * (m + 1) <= MAX_LCS_CELLS {
+        // lcs[i][j] is the length of the longest common subsequence of
+        // `old_middle[i..]` and `new_middle[j..]`.
+        let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_middle[i] == new_middle[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            let (old, new) = (prefix + i, prefix + j);
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                ops.push(Op::Equal { old, new });
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push(Op::Delete { old, new });
+                i += 1;
+            } else {
+                ops.push(Op::Insert { old, new });
+                j += 1;
+            }
+        }
+    } else {
+        ops.extend((0..n).map(|i| Op::Delete {
+            old: prefix + i,
+            new: prefix,
+        }));
+        ops.extend((0..m).map(|j| Op::Insert {
+            old: prefix + n,
+            new: prefix + j,
+        }));
+    }
+
+    ops.extend((0..suffix).map(|index| Op::Equal {
+        old: prefix + n + index,
+        new: prefix + m + index,
+    }));
+    ops
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unified_diff_equal() {
+        assert_eq!(unified_diff("a", "b", "one\ntwo\n", "one\ntwo\n"), "");
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n14\n15\n16\n";
+
+        assert_eq!(
+            unified_diff("a/BUILD.bazel", "b/BUILD.bazel", old, new),
+            indoc::indoc! {"
+                --- a/BUILD.bazel
+                +++ b/BUILD.bazel
+                @@ -1,6 +1,6 @@
+                 1
+                 2
+                -3
+                +three
+                 4
+                 5
+                 6
+                @@ -10,6 +10,6 @@
+                 10
+                 11
+                 12
+                -13
+                 14
+                 15
+                +16
+            "}
+        );
+    }
+
+    #[test]
+    fn unified_diff_new_file() {
+        assert_eq!(
+            unified_diff("a/x", "b/x", "", "one\n"),
+            "--- a/x\n+++ b/x\n@@ -0,0 +1,1 @@\n+one\n"
+        );
+    }
+}
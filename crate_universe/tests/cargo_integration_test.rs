@@ -117,6 +117,7 @@ fn run(repository_name: &str, manifests: HashMap<String, String>, lockfile: &str
         config,
         cargo,
         rustc,
+        cargo_tree_cache_dir: None,
         repository_name: String::from("crates_index"),
         skip_cargo_lockfile_overwrite: false,
         nonhermetic_root_bazel_workspace_dir: Utf8PathBuf::from("/doesnotexist/unused/repo/root"),